@@ -0,0 +1,332 @@
+// ---------------- BULK OPERATIONS ----------------
+//
+// Commands over a selection of files: trash, move or copy to a folder,
+// rotate and change marks. Every file is handled on its own, one failing
+// doesn't stop the others, and the outcome of each is returned. Progress
+// is emitted as `bulk-progress` with `{ done, total, path }`.
+//
+// Trashing, moving and copying go into the journal as one step, so a
+// single undo brings the whole selection back. Rotating only changes the
+// EXIF orientation, the pixels are never recompressed.
+
+use crate::file_ops::{self, ConflictPolicy, Sent};
+use crate::journal::{self, JournalState, Operation};
+use crate::metadata;
+use crate::metadata_edit;
+use crate::ratings::{self, Flag};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tauri::{AppHandle, Emitter, Manager};
+
+#[derive(Serialize, Clone)]
+struct BulkProgress {
+    done: usize,
+    total: usize,
+    path: String,
+}
+
+struct Progress<'a> {
+    app: &'a AppHandle,
+    done: AtomicUsize,
+    total: usize,
+}
+
+impl<'a> Progress<'a> {
+    fn new(app: &'a AppHandle, total: usize) -> Self {
+        Progress {
+            app,
+            done: AtomicUsize::new(0),
+            total,
+        }
+    }
+
+    fn step(&self, path: &str) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        let progress = BulkProgress {
+            done,
+            total: self.total,
+            path: path.to_string(),
+        };
+        let _ = self.app.emit("bulk-progress", progress);
+    }
+}
+
+/// Outcome for one file.
+#[derive(Serialize, Default)]
+pub struct FileResult {
+    path: String,
+    /// Where a moved or copied file is now, `None` if it was skipped.
+    new_path: Option<String>,
+    error: Option<String>,
+    /// `FileOpError` kind, "exists" when the name was taken.
+    kind: Option<String>,
+    /// What went wrong on the side, sidecars left behind.
+    warning: Option<String>,
+}
+
+impl FileResult {
+    fn from_result(path: &str, result: Result<(), String>) -> Self {
+        FileResult {
+            path: path.to_string(),
+            error: result.err(),
+            ..Default::default()
+        }
+    }
+}
+
+// ----- Trash, move and copy ------
+
+/// Move `paths` to the trash.
+#[tauri::command]
+pub async fn bulk_trash(app: AppHandle, paths: Vec<String>) -> Result<Vec<FileResult>, String> {
+    // one file after the other, which adds up for large selections
+    tauri::async_runtime::spawn_blocking(move || {
+        let progress = Progress::new(&app, paths.len());
+        let mut ops = Vec::new();
+
+        let results: Vec<FileResult> = paths
+            .iter()
+            .map(|path| {
+                let result = journal::trash(Path::new(path)).map(|op| ops.push(op));
+                progress.step(path);
+                FileResult::from_result(path, result)
+            })
+            .collect();
+
+        if let Some(step) = Operation::group(ops) {
+            let journal = app.state::<JournalState>();
+            journal.0.lock().unwrap().record(step);
+        }
+        Ok(results)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Move or copy `paths` into `folder`, keeping their names.
+#[tauri::command]
+pub async fn bulk_send(
+    app: AppHandle,
+    paths: Vec<String>,
+    folder: String,
+    copy: bool,
+    policy: Option<ConflictPolicy>,
+) -> Result<Vec<FileResult>, String> {
+    // copies and moves across drives can take a while
+    tauri::async_runtime::spawn_blocking(move || {
+        let folder = PathBuf::from(folder);
+        if !folder.is_dir() {
+            return Err(format!("{} is not a folder", folder.display()));
+        }
+        let policy = policy.unwrap_or_default();
+        let progress = Progress::new(&app, paths.len());
+        let mut ops = Vec::new();
+
+        let results: Vec<FileResult> = paths
+            .iter()
+            .map(|path| {
+                let from = Path::new(path);
+                let result = match from.file_name() {
+                    None => Err(file_ops::FileOpError::NotFound(from.to_path_buf())),
+                    Some(name) if copy => file_ops::copy_path(from, &folder.join(name), policy),
+                    Some(name) => file_ops::move_path(from, &folder.join(name), policy),
+                };
+                progress.step(path);
+
+                match result {
+                    Ok(sent) => {
+                        let sent = sent.map(|placed| {
+                            let sent = Sent::from(&placed);
+                            let to = sent.path.clone();
+                            if to != *path {
+                                // the files it replaced are trashed just before it
+                                ops.extend(placed.replaced);
+                                let (from, to) = (path.clone(), to.clone());
+                                ops.push(match copy {
+                                    true => Operation::Copy { from, to },
+                                    false => Operation::Move { from, to },
+                                });
+                            }
+                            sent
+                        });
+                        FileResult {
+                            path: path.clone(),
+                            new_path: sent.as_ref().map(|s| s.path.clone()),
+                            warning: sent.and_then(|s| s.warning),
+                            ..Default::default()
+                        }
+                    }
+                    Err(e) => FileResult {
+                        path: path.clone(),
+                        kind: Some(e.kind().to_string()),
+                        error: Some(e.to_string()),
+                        ..Default::default()
+                    },
+                }
+            })
+            .collect();
+
+        if let Some(step) = Operation::group(ops) {
+            let journal = app.state::<JournalState>();
+            journal.0.lock().unwrap().record(step);
+        }
+        Ok(results)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// ----- Rotation ------
+
+/// EXIF orientation as (mirrored, clockwise degrees), the mirroring first.
+const ORIENTATIONS: [(bool, u32); 8] = [
+    (false, 0),
+    (true, 0),
+    (false, 180),
+    (true, 180),
+    (true, 270),
+    (false, 90),
+    (true, 90),
+    (false, 270),
+];
+
+/// The orientation that shows `orientation` turned a further `degrees`
+/// clockwise.
+fn rotated(orientation: u16, degrees: u32) -> u16 {
+    let (mirrored, turn) = ORIENTATIONS[(orientation.clamp(1, 8) - 1) as usize];
+    let wanted = (mirrored, (turn + degrees) % 360);
+    ORIENTATIONS.iter().position(|&o| o == wanted).unwrap() as u16 + 1
+}
+
+fn rotate_file(path: &str, degrees: u32) -> Result<(), String> {
+    let current = metadata::read_raw_quick(path)
+        .ok()
+        .and_then(|raw| raw.exif)
+        .and_then(|exif| metadata_edit::exif_orientation(&exif))
+        .unwrap_or(1);
+    metadata_edit::set_orientation(path, rotated(current, degrees))
+}
+
+/// Turn `paths` clockwise by 90, 180 or 270 degrees.
+#[tauri::command]
+pub async fn bulk_rotate(
+    app: AppHandle,
+    paths: Vec<String>,
+    degrees: u32,
+) -> Result<Vec<FileResult>, String> {
+    if !matches!(degrees, 90 | 180 | 270) {
+        return Err(format!("Invalid rotation: {} degrees", degrees));
+    }
+    // rayon holds the thread it is called on until every file is done
+    tauri::async_runtime::spawn_blocking(move || {
+        let progress = Progress::new(&app, paths.len());
+
+        Ok(paths
+            .par_iter()
+            .map(|path| {
+                let result = rotate_file(path, degrees);
+                progress.step(path);
+                FileResult::from_result(path, result)
+            })
+            .collect())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// ----- Marks ------
+
+/// Changes to the marks of every file, `None` and empty lists keep what
+/// each file has.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct MarksChange {
+    rating: Option<u8>,
+    /// An empty label removes it.
+    label: Option<String>,
+    flag: Option<Flag>,
+    add_tags: Vec<String>,
+    remove_tags: Vec<String>,
+}
+
+fn change_marks(path: &str, change: &MarksChange, sidecar: bool) -> Result<(), String> {
+    let mut marks = ratings::read_marks(path);
+
+    if let Some(rating) = change.rating {
+        marks.rating = rating;
+    }
+    if let Some(label) = &change.label {
+        marks.label = Some(label.trim())
+            .filter(|l| !l.is_empty())
+            .map(str::to_string);
+    }
+    if let Some(flag) = change.flag {
+        marks.flag = flag;
+    }
+
+    let has = |tags: &[String], tag: &str| tags.iter().any(|t| t.eq_ignore_ascii_case(tag));
+    marks.tags.retain(|t| !has(&change.remove_tags, t.trim()));
+    for tag in change.add_tags.iter().map(|t| t.trim()) {
+        if !tag.is_empty() && !has(&marks.tags, tag) {
+            marks.tags.push(tag.to_string());
+        }
+    }
+
+    ratings::write_marks(path, &marks, sidecar).map(|_| ())
+}
+
+/// Apply `change` to the marks of `paths`. `sidecar` as in `set_image_marks`.
+#[tauri::command]
+pub async fn bulk_set_marks(
+    app: AppHandle,
+    paths: Vec<String>,
+    change: MarksChange,
+    sidecar: bool,
+) -> Result<Vec<FileResult>, String> {
+    if change.rating.is_some_and(|r| r > 5) {
+        return Err("Invalid rating".to_string());
+    }
+    // off the async runtime, as in bulk_rotate
+    tauri::async_runtime::spawn_blocking(move || {
+        let progress = Progress::new(&app, paths.len());
+
+        Ok(paths
+            .par_iter()
+            .map(|path| {
+                let result = change_marks(path, &change, sidecar);
+                progress.step(path);
+                FileResult::from_result(path, result)
+            })
+            .collect())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_turns_and_keeps_the_mirroring() {
+        assert_eq!(rotated(1, 90), 6);
+        assert_eq!(rotated(6, 90), 3);
+        assert_eq!(rotated(3, 180), 1);
+        assert_eq!(rotated(8, 270), 3);
+        // mirrored orientations stay mirrored
+        assert_eq!(rotated(2, 90), 7);
+        assert_eq!(rotated(5, 180), 7);
+        assert_eq!(rotated(4, 270), 7);
+        assert_eq!(rotated(7, 180), 5);
+        // unknown values count as normal
+        assert_eq!(rotated(0, 90), 6);
+        for orientation in 1..=8 {
+            let turned = [90, 90, 90, 90]
+                .iter()
+                .fold(orientation, |o, &d| rotated(o, d));
+            assert_eq!(turned, orientation);
+        }
+    }
+}
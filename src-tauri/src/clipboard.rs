@@ -0,0 +1,163 @@
+// ---------------- CLIPBOARD ----------------
+//
+// Copying the image puts its decoded pixels on the system clipboard, so it
+// pastes into chats and documents. Copying the file puts a reference to it
+// there instead (text/uri-list on Linux, a file drop on Windows), which file
+// managers paste as a copy of the file.
+//
+// The clipboard lives on a thread of its own for as long as the app runs:
+// on Linux whatever was copied is served by this process, and is gone as
+// soon as the clipboard handle is dropped.
+
+use crate::color::{self, ColorSettings};
+use crate::decode::{self, DecodeState};
+use crate::metadata;
+use crate::metadata_edit;
+use arboard::{Clipboard, ImageData};
+use image::metadata::Orientation;
+use image::{ImageFormat, RgbaImage};
+use std::borrow::Cow;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+
+type Job = Box<dyn FnOnce(Result<&mut Clipboard, arboard::Error>) + Send>;
+
+pub struct ClipboardState(Mutex<Sender<Job>>);
+
+impl ClipboardState {
+    /// Start the clipboard thread. The clipboard is opened on first use,
+    /// and again on the next one if that failed.
+    pub fn start() -> ClipboardState {
+        let (sender, jobs) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            let mut clipboard: Option<Clipboard> = None;
+            for job in jobs {
+                match clipboard.take().map_or_else(Clipboard::new, Ok) {
+                    Ok(mut opened) => {
+                        job(Ok(&mut opened));
+                        clipboard = Some(opened);
+                    }
+                    Err(e) => job(Err(e)),
+                }
+            }
+        });
+        ClipboardState(Mutex::new(sender))
+    }
+
+    /// Run `work` on the clipboard thread and wait for it.
+    fn run<T, F>(&self, work: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Clipboard) -> Result<T, arboard::Error> + Send + 'static,
+    {
+        let (reply, result) = mpsc::channel();
+        let job: Job = Box::new(move |clipboard| {
+            let _ = reply.send(clipboard.and_then(work).map_err(describe_error));
+        });
+
+        let gone = || "The clipboard is not available".to_string();
+        self.0.lock().unwrap().send(job).map_err(|_| gone())?;
+        result.recv().map_err(|_| gone())?
+    }
+}
+
+fn describe_error(e: arboard::Error) -> String {
+    match e {
+        arboard::Error::ContentNotAvailable => "There is no image on the clipboard".to_string(),
+        arboard::Error::ClipboardNotSupported => {
+            "The clipboard is not supported on this system".to_string()
+        }
+        e => format!("Clipboard error: {}", e),
+    }
+}
+
+/// Put the image at `path` on the clipboard as it is shown: upright and in
+/// sRGB, which is what other apps take clipboard images to be.
+#[tauri::command]
+pub async fn copy_image_to_clipboard(
+    path: String,
+    limits: State<'_, DecodeState>,
+    clipboard: State<'_, ClipboardState>,
+) -> Result<(), String> {
+    let limits = *limits.0.lock().unwrap();
+
+    let (mut img, icc) = decode::decode_with_profile(&path, &limits)?;
+    let orientation = metadata::read_raw_quick(&path)
+        .ok()
+        .and_then(|raw| raw.exif)
+        .and_then(|exif| metadata_edit::exif_orientation(&exif))
+        .and_then(|o| Orientation::from_exif(o as u8));
+    if let Some(orientation) = orientation {
+        img.apply_orientation(orientation);
+    }
+    let rgba = color::convert(img, icc.as_deref(), &ColorSettings::default())?.to_rgba8();
+
+    let image = ImageData {
+        width: rgba.width() as usize,
+        height: rgba.height() as usize,
+        bytes: Cow::Owned(rgba.into_raw()),
+    };
+    clipboard.run(move |c| c.set_image(image))
+}
+
+/// Put references to `paths` on the clipboard, to paste as files.
+#[tauri::command]
+pub async fn copy_files_to_clipboard(
+    paths: Vec<String>,
+    clipboard: State<'_, ClipboardState>,
+) -> Result<(), String> {
+    if let Some(missing) = paths.iter().find(|p| !Path::new(p).exists()) {
+        return Err(format!("Path does not exist: {}", missing));
+    }
+    clipboard.run(move |c| c.set().file_list(&paths))
+}
+
+/// Save the image on the clipboard as a PNG in the app cache and return
+/// its path, `None` when there is no image on the clipboard.
+#[tauri::command]
+pub async fn paste_image_from_clipboard(
+    app: AppHandle,
+    clipboard: State<'_, ClipboardState>,
+) -> Result<Option<String>, String> {
+    let pasted = clipboard.run(|c| match c.get_image() {
+        Ok(image) => Ok(Some(image)),
+        Err(arboard::Error::ContentNotAvailable) => Ok(None),
+        Err(e) => Err(e),
+    })?;
+    let Some(image) = pasted else {
+        return Ok(None);
+    };
+
+    let rgba = RgbaImage::from_raw(
+        image.width as u32,
+        image.height as u32,
+        image.bytes.into_owned(),
+    )
+    .ok_or("The clipboard image is damaged")?;
+
+    let mut png = Vec::new();
+    rgba.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+
+    let dir = app
+        .path()
+        .app_cache_dir()
+        .map_err(|e| e.to_string())?
+        .join("pasted");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to save the pasted image: {}", e))?;
+
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let file = dir.join(format!("Pasted {}.png", stamp));
+    fs::write(&file, png).map_err(|e| format!("Failed to save the pasted image: {}", e))?;
+
+    Ok(Some(file.to_string_lossy().to_string()))
+}
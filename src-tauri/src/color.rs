@@ -0,0 +1,275 @@
+// ---------------- COLOR MANAGEMENT ----------------
+//
+// Embedded ICC profiles come from the decoders and the pixels are converted
+// to the display profile (sRGB unless another one was chosen) before they
+// are sent to the webview. Untagged images are taken as sRGB.
+//
+// Only RGB and grayscale source profiles are converted. CMYK and Lab files
+// are already turned into RGB by the decoders without their profile.
+
+use crate::decode::{self, DecodeState};
+use crate::tiles::TileState;
+use image::{ColorType, DynamicImage, ImageBuffer};
+use moxcms::{ColorProfile, DataColorSpace, Layout, ProfileText, TransformOptions, Xyzd};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::Mutex;
+use tauri::State;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "path", rename_all = "snake_case")]
+pub enum DisplayProfile {
+    Srgb,
+    DisplayP3,
+    AdobeRgb,
+    /// An .icc / .icm file, usually the monitor's calibration profile.
+    File(String),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ColorSettings {
+    /// False shows the stored pixel values as they are, for comparison.
+    pub managed: bool,
+    pub display: DisplayProfile,
+}
+
+impl Default for ColorSettings {
+    fn default() -> Self {
+        ColorSettings {
+            managed: true,
+            display: DisplayProfile::Srgb,
+        }
+    }
+}
+
+pub struct ColorState(pub Mutex<ColorSettings>);
+
+#[derive(Serialize, Clone)]
+pub struct ProfileInfo {
+    pub description: String,
+    color_space: String,
+    version: String,
+    size: usize,
+    is_srgb: bool,
+}
+
+fn text(value: &ProfileText) -> Option<String> {
+    let s = match value {
+        ProfileText::PlainString(s) => s.clone(),
+        ProfileText::Description(d) if !d.unicode_string.is_empty() => d.unicode_string.clone(),
+        ProfileText::Description(d) => d.ascii_string.clone(),
+        ProfileText::Localizable(list) => list
+            .iter()
+            .find(|l| l.language.eq_ignore_ascii_case("en"))
+            .or_else(|| list.first())?
+            .value
+            .clone(),
+    };
+
+    let s = s.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+fn close(a: Xyzd, b: Xyzd) -> bool {
+    (a.x - b.x).abs() < 0.002 && (a.y - b.y).abs() < 0.002 && (a.z - b.z).abs() < 0.002
+}
+
+/// sRGB primaries, whatever the profile calls itself.
+fn is_srgb(profile: &ColorProfile) -> bool {
+    let srgb = ColorProfile::new_srgb();
+
+    profile.color_space == DataColorSpace::Rgb
+        && close(profile.red_colorant, srgb.red_colorant)
+        && close(profile.green_colorant, srgb.green_colorant)
+        && close(profile.blue_colorant, srgb.blue_colorant)
+}
+
+fn is_convertible(profile: &ColorProfile) -> bool {
+    matches!(
+        profile.color_space,
+        DataColorSpace::Rgb | DataColorSpace::Gray
+    )
+}
+
+/// Description and basics of an embedded profile, `None` if it can't be parsed.
+pub fn describe(icc: &[u8]) -> Option<ProfileInfo> {
+    let profile = ColorProfile::new_from_slice(icc).ok()?;
+
+    // Major version in byte 8, minor in the high nibble of byte 9
+    let version = match icc.get(8..10) {
+        Some(v) => format!("{}.{}", v[0], v[1] >> 4),
+        None => "?".to_string(),
+    };
+
+    let color_space = match profile.color_space {
+        DataColorSpace::Rgb => "RGB".to_string(),
+        DataColorSpace::Gray => "Gray".to_string(),
+        DataColorSpace::Cmyk => "CMYK".to_string(),
+        DataColorSpace::Lab => "Lab".to_string(),
+        other => format!("{:?}", other),
+    };
+
+    Some(ProfileInfo {
+        description: profile
+            .description
+            .as_ref()
+            .and_then(text)
+            .unwrap_or_else(|| "Unnamed profile".to_string()),
+        color_space,
+        version,
+        size: icc.len(),
+        is_srgb: is_srgb(&profile),
+    })
+}
+
+fn display_profile(display: &DisplayProfile) -> Result<ColorProfile, String> {
+    match display {
+        DisplayProfile::Srgb => Ok(ColorProfile::new_srgb()),
+        DisplayProfile::DisplayP3 => Ok(ColorProfile::new_display_p3()),
+        DisplayProfile::AdobeRgb => Ok(ColorProfile::new_adobe_rgb()),
+        DisplayProfile::File(path) => {
+            let bytes = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let profile = ColorProfile::new_from_slice(&bytes)
+                .map_err(|e| format!("Not a usable ICC profile: {}", e))?;
+
+            if profile.color_space != DataColorSpace::Rgb {
+                return Err("The display profile must be an RGB profile".to_string());
+            }
+            Ok(profile)
+        }
+    }
+}
+
+/// Source profile of an image, sRGB when there is none or it can't be used.
+fn source_profile(icc: Option<&[u8]>) -> ColorProfile {
+    icc.and_then(|bytes| ColorProfile::new_from_slice(bytes).ok())
+        .filter(is_convertible)
+        .unwrap_or_else(ColorProfile::new_srgb)
+}
+
+/// Whether the image has to be decoded here instead of by the webview.
+/// Unmanaged viewing needs it as well, since the webview may apply the
+/// embedded profile on its own.
+pub fn needs_conversion(icc: Option<&[u8]>, settings: &ColorSettings) -> bool {
+    let source_srgb = is_srgb(&source_profile(icc));
+
+    if !settings.managed {
+        return !source_srgb;
+    }
+    !(source_srgb && settings.display == DisplayProfile::Srgb)
+}
+
+fn is_high_depth(color: ColorType) -> bool {
+    !matches!(
+        color,
+        ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8
+    )
+}
+
+/// Convert `img` from its embedded profile to the display profile.
+/// Returns the image unchanged when unmanaged, when nothing has to be done
+/// or when the profile can't be applied.
+pub fn convert(
+    img: DynamicImage,
+    icc: Option<&[u8]>,
+    settings: &ColorSettings,
+) -> Result<DynamicImage, String> {
+    if !settings.managed {
+        return Ok(img);
+    }
+
+    let source = source_profile(icc);
+    let target = display_profile(&settings.display)?;
+    if is_srgb(&source) && is_srgb(&target) {
+        return Ok(img);
+    }
+
+    let gray = source.color_space == DataColorSpace::Gray;
+    let src_layout = if gray {
+        Layout::GrayAlpha
+    } else {
+        Layout::Rgba
+    };
+    let (width, height) = (img.width(), img.height());
+    let options = TransformOptions::default();
+
+    let converted = if is_high_depth(img.color()) {
+        let Ok(transform) =
+            source.create_transform_16bit(src_layout, &target, Layout::Rgba, options)
+        else {
+            return Ok(img);
+        };
+
+        let src = if gray {
+            img.to_luma_alpha16().into_raw()
+        } else {
+            img.to_rgba16().into_raw()
+        };
+        let mut dst = vec![0u16; width as usize * height as usize * 4];
+        transform
+            .transform(&src, &mut dst)
+            .map_err(|e| e.to_string())?;
+
+        ImageBuffer::from_raw(width, height, dst).map(DynamicImage::ImageRgba16)
+    } else {
+        let Ok(transform) =
+            source.create_transform_8bit(src_layout, &target, Layout::Rgba, options)
+        else {
+            return Ok(img);
+        };
+
+        let src = if gray {
+            img.to_luma_alpha8().into_raw()
+        } else {
+            img.to_rgba8().into_raw()
+        };
+        let mut dst = vec![0u8; width as usize * height as usize * 4];
+        transform
+            .transform(&src, &mut dst)
+            .map_err(|e| e.to_string())?;
+
+        ImageBuffer::from_raw(width, height, dst).map(DynamicImage::ImageRgba8)
+    };
+
+    converted.ok_or_else(|| "Color conversion produced a wrong buffer size".to_string())
+}
+
+#[tauri::command]
+pub fn get_color_settings(state: State<ColorState>) -> ColorSettings {
+    state.0.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn set_color_settings(
+    settings: ColorSettings,
+    state: State<ColorState>,
+    tiles: State<TileState>,
+) -> Result<(), String> {
+    // Fail early on a bad profile file instead of on every image
+    display_profile(&settings.display)?;
+
+    *state.0.lock().unwrap() = settings;
+
+    // Tiles were built with the old settings
+    tiles.0.lock().unwrap().clear();
+    Ok(())
+}
+
+/// True when `path` must be shown through `load_image` for its colors to
+/// be right (or deliberately unmanaged).
+#[tauri::command]
+pub async fn needs_color_conversion(
+    path: String,
+    state: State<'_, ColorState>,
+    limits: State<'_, DecodeState>,
+) -> Result<bool, String> {
+    let settings = state.0.lock().unwrap().clone();
+    let limits = *limits.0.lock().unwrap();
+
+    // Formats the Rust decoders can't read are left to the webview
+    let Ok(header) = decode::read_header(&path, &limits) else {
+        return Ok(false);
+    };
+
+    Ok(needs_conversion(header.icc.as_deref(), &settings))
+}
@@ -0,0 +1,191 @@
+// ---------------- IMAGE COMPARISON ----------------
+//
+// Difference of two images for the compare view: absolute per-channel
+// difference, a heatmap of the pixels that differ by more than a threshold,
+// and PSNR / SSIM. Works on the stored 8-bit RGBA values, before color
+// management, so two renders of the same thing compare equal. Both images
+// are turned upright by their EXIF orientation first.
+
+use crate::decode::{self, DecodeLimits, DecodeState};
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::io::Cursor;
+use tauri::State;
+
+/// SSIM window size and step, in pixels.
+const SSIM_WINDOW: u32 = 8;
+const SSIM_STEP: u32 = 4;
+
+#[derive(Serialize)]
+pub struct Comparison {
+    width: u32,
+    height: u32,
+    /// PNG of |a - b| per channel.
+    difference: Vec<u8>,
+    /// PNG with the pixels over the threshold in yellow to red, the rest
+    /// as a dimmed gray copy of `a`.
+    heatmap: Vec<u8>,
+    /// `None` when the images are identical (infinite PSNR).
+    psnr: Option<f64>,
+    ssim: f64,
+    /// Pixels whose largest channel difference is above the threshold.
+    changed_pixels: u64,
+    max_difference: u8,
+}
+
+fn luma(p: &Rgba<u8>) -> f64 {
+    0.2126 * p[0] as f64 + 0.7152 * p[1] as f64 + 0.0722 * p[2] as f64
+}
+
+/// Mean SSIM of the luma over `SSIM_WINDOW` windows.
+fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let (width, height) = a.dimensions();
+    let window = SSIM_WINDOW.min(width).min(height);
+    let n = (window * window) as f64;
+
+    let ys: Vec<u32> = (0..=height - window).step_by(SSIM_STEP as usize).collect();
+
+    let (sum, count) = ys
+        .par_iter()
+        .map(|&y0| {
+            let mut sum = 0.0;
+            let mut count = 0u64;
+
+            for x0 in (0..=width - window).step_by(SSIM_STEP as usize) {
+                let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+
+                for y in y0..y0 + window {
+                    for x in x0..x0 + window {
+                        let la = luma(a.get_pixel(x, y));
+                        let lb = luma(b.get_pixel(x, y));
+                        sa += la;
+                        sb += lb;
+                        saa += la * la;
+                        sbb += lb * lb;
+                        sab += la * lb;
+                    }
+                }
+
+                let (ma, mb) = (sa / n, sb / n);
+                let va = saa / n - ma * ma;
+                let vb = sbb / n - mb * mb;
+                let cov = sab / n - ma * mb;
+
+                sum += ((2.0 * ma * mb + C1) * (2.0 * cov + C2))
+                    / ((ma * ma + mb * mb + C1) * (va + vb + C2));
+                count += 1;
+            }
+            (sum, count)
+        })
+        .reduce(|| (0.0, 0), |x, y| (x.0 + y.0, x.1 + y.1));
+
+    if count == 0 {
+        1.0
+    } else {
+        sum / count as f64
+    }
+}
+
+fn encode_png(img: RgbaImage) -> Result<Vec<u8>, String> {
+    let mut buf = Vec::new();
+    DynamicImage::ImageRgba8(img)
+        .write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(buf)
+}
+
+/// Compare `a` with `b`. `threshold` (0-255) decides which pixels count as
+/// changed in the heatmap and `changed_pixels`.
+#[tauri::command]
+pub async fn compare_images(
+    a: String,
+    b: String,
+    threshold: u8,
+    limits: State<'_, DecodeState>,
+) -> Result<Comparison, String> {
+    let limits = *limits.0.lock().unwrap();
+
+    // rayon holds the thread it is called on until both are decoded
+    tauri::async_runtime::spawn_blocking(move || compare(&a, &b, threshold, &limits))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn compare(a: &str, b: &str, threshold: u8, limits: &DecodeLimits) -> Result<Comparison, String> {
+    // upright, as the compare view shows them
+    let upright = |path: &str| {
+        let (mut img, _) = decode::decode_with_profile(path, limits)?;
+        if let Some(orientation) = decode::orientation(path) {
+            img.apply_orientation(orientation);
+        }
+        Ok::<_, String>(img.to_rgba8())
+    };
+    let (img_a, img_b) = rayon::join(|| upright(a), || upright(b));
+    let (img_a, img_b) = (img_a?, img_b?);
+
+    if img_a.dimensions() != img_b.dimensions() {
+        return Err(format!(
+            "The images have different sizes ({} × {} and {} × {})",
+            img_a.width(),
+            img_a.height(),
+            img_b.width(),
+            img_b.height()
+        ));
+    }
+
+    let (width, height) = img_a.dimensions();
+    let with_alpha = img_a.pixels().chain(img_b.pixels()).any(|p| p[3] != 255);
+    let channels = if with_alpha { 4 } else { 3 };
+
+    let mut difference = RgbaImage::new(width, height);
+    let mut heatmap = RgbaImage::new(width, height);
+    let mut squared_error = 0u64;
+    let mut changed_pixels = 0u64;
+    let mut max_difference = 0u8;
+
+    for ((pa, pb), (diff, heat)) in img_a
+        .pixels()
+        .zip(img_b.pixels())
+        .zip(difference.pixels_mut().zip(heatmap.pixels_mut()))
+    {
+        let mut largest = 0u8;
+        for c in 0..4 {
+            let d = pa[c].abs_diff(pb[c]);
+            diff[c] = d;
+            if c < channels {
+                squared_error += d as u64 * d as u64;
+                largest = largest.max(d);
+            }
+        }
+        diff[3] = 255;
+        max_difference = max_difference.max(largest);
+
+        *heat = if largest > threshold {
+            changed_pixels += 1;
+            let t = (largest - threshold) as f64 / (255 - threshold) as f64;
+            Rgba([255, (255.0 * (1.0 - t)) as u8, 0, 255])
+        } else {
+            let gray = (luma(pa) * 0.35) as u8;
+            Rgba([gray, gray, gray, 255])
+        };
+    }
+
+    let mse = squared_error as f64 / (width as f64 * height as f64 * channels as f64);
+    let psnr = (mse > 0.0).then(|| 10.0 * (255.0 * 255.0 / mse).log10());
+    let ssim = ssim(&img_a, &img_b);
+
+    Ok(Comparison {
+        width,
+        height,
+        difference: encode_png(difference)?,
+        heatmap: encode_png(heatmap)?,
+        psnr,
+        ssim,
+        changed_pixels,
+        max_difference,
+    })
+}
@@ -0,0 +1,366 @@
+// ---------------- BATCH CONVERSION ----------------
+//
+// Converts a list of images to another format, in parallel. Every file is
+// decoded through decode.rs, turned upright, optionally shrunk to fit a
+// box and encoded again. Outputs go through a temporary file and never
+// replace an existing file unless asked to.
+//
+// JPEG, PNG and WebP outputs keep the EXIF and XMP of the source unless
+// metadata is stripped (TIFF sources only their XMP); the color profile is
+// always kept. WebP is written lossless, the only WebP encoder the image
+// crate has.
+
+use crate::decode::{self, DecodeState};
+use crate::file_ops;
+use crate::library::{self, LibraryState, Record};
+use crate::metadata;
+use crate::metadata_edit;
+use crate::rename;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::tiff::TiffEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageEncoder, Rgb, RgbImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, State};
+
+/// Cancel flag of the running job, `None` when idle.
+pub struct ConvertState(pub Mutex<Option<Arc<AtomicBool>>>);
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ConvertOptions {
+    /// jpg, png, webp, tif or bmp.
+    format: String,
+    /// JPEG quality, 1 to 100.
+    quality: u8,
+    /// Fit within this box, never enlarging.
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+    /// Drop EXIF and XMP.
+    strip_metadata: bool,
+    /// Absolute path of the folder the outputs go to.
+    output_dir: String,
+    /// Output name without extension, see `rename::expand_template`.
+    template: String,
+    /// Replace files that already exist in the output folder.
+    overwrite: bool,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            format: "jpg".to_string(),
+            quality: 90,
+            max_width: None,
+            max_height: None,
+            strip_metadata: false,
+            output_dir: String::new(),
+            template: "{name}".to_string(),
+            overwrite: false,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct ConvertProgress {
+    done: usize,
+    total: usize,
+    path: String,
+}
+
+#[derive(Serialize)]
+pub struct ConvertedFile {
+    source: String,
+    output: String,
+}
+
+#[derive(Serialize)]
+pub struct FileError {
+    path: String,
+    error: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct ConvertReport {
+    converted: Vec<ConvertedFile>,
+    failed: Vec<FileError>,
+    /// Files left alone because the job was cancelled.
+    cancelled: usize,
+}
+
+fn extension(format: &str) -> Result<&'static str, String> {
+    match format.to_lowercase().as_str() {
+        "jpg" | "jpeg" => Ok("jpg"),
+        "png" => Ok("png"),
+        "webp" => Ok("webp"),
+        "tif" | "tiff" => Ok("tif"),
+        "bmp" => Ok("bmp"),
+        other => Err(format!("Unsupported output format: {}", other)),
+    }
+}
+
+/// JPEG has no alpha, transparent pixels end up white.
+fn flatten(img: &DynamicImage) -> RgbImage {
+    let rgba = img.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+fn encode(
+    img: &DynamicImage,
+    ext: &str,
+    quality: u8,
+    icc: Option<Vec<u8>>,
+) -> Result<Vec<u8>, String> {
+    let mut buf = Vec::new();
+    let out = Cursor::new(&mut buf);
+
+    let img = match ext {
+        "jpg" => DynamicImage::ImageRgb8(flatten(img)),
+        // 16 bit and float images don't go into WebP or BMP
+        "webp" | "bmp" if img.color().has_alpha() => DynamicImage::ImageRgba8(img.to_rgba8()),
+        "webp" | "bmp" => DynamicImage::ImageRgb8(img.to_rgb8()),
+        // nor float into PNG or TIFF
+        _ if matches!(
+            img,
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        ) =>
+        {
+            DynamicImage::ImageRgba16(img.to_rgba16())
+        }
+        _ => img.clone(),
+    };
+
+    let (w, h, color) = (img.width(), img.height(), img.color().into());
+    let bytes = img.as_bytes();
+    let result = match ext {
+        "jpg" => {
+            let mut encoder = JpegEncoder::new_with_quality(out, quality.clamp(1, 100));
+            if let Some(icc) = icc {
+                let _ = encoder.set_icc_profile(icc);
+            }
+            encoder.write_image(bytes, w, h, color)
+        }
+        "png" => {
+            let mut encoder = PngEncoder::new(out);
+            if let Some(icc) = icc {
+                let _ = encoder.set_icc_profile(icc);
+            }
+            encoder.write_image(bytes, w, h, color)
+        }
+        "webp" => {
+            let mut encoder = WebPEncoder::new_lossless(out);
+            if let Some(icc) = icc {
+                let _ = encoder.set_icc_profile(icc);
+            }
+            encoder.write_image(bytes, w, h, color)
+        }
+        "tif" => {
+            let mut encoder = TiffEncoder::new(out);
+            if let Some(icc) = icc {
+                let _ = encoder.set_icc_profile(icc);
+            }
+            encoder.write_image(bytes, w, h, color)
+        }
+        _ => img.write_to(out, image::ImageFormat::Bmp),
+    };
+
+    result.map_err(|e| format!("Encoding failed: {}", e))?;
+    Ok(buf)
+}
+
+fn convert_file(
+    source: &str,
+    output: &Path,
+    ext: &str,
+    options: &ConvertOptions,
+    limits: &decode::DecodeLimits,
+) -> Result<(), String> {
+    if !options.overwrite && output.exists() {
+        return Err(format!("{} already exists", output.display()));
+    }
+
+    let (mut img, icc) = decode::decode_with_profile(source, limits)?;
+    let raw = metadata::read_raw_quick(source).unwrap_or_default();
+
+    let orientation = raw
+        .exif
+        .as_deref()
+        .and_then(metadata_edit::exif_orientation)
+        .and_then(|o| Orientation::from_exif(o as u8));
+    if let Some(orientation) = orientation {
+        img.apply_orientation(orientation);
+    }
+
+    let (max_w, max_h) = (
+        options.max_width.unwrap_or(u32::MAX),
+        options.max_height.unwrap_or(u32::MAX),
+    );
+    if img.width() > max_w || img.height() > max_h {
+        img = img.resize(max_w, max_h, FilterType::Lanczos3);
+    }
+
+    let mut data = encode(&img, ext, options.quality, icc)?;
+    if !options.strip_metadata {
+        data = metadata_edit::embed_metadata(data, &raw)?;
+    }
+
+    let tmp = output.with_extension(format!("{}.tmp", ext));
+    fs::write(&tmp, &data).map_err(|e| format!("Failed to write: {}", e))?;
+    // a file that turned up since the check above is not replaced either
+    let placed = match options.overwrite {
+        true => fs::rename(&tmp, output).map_err(|e| format!("Failed to write: {}", e)),
+        false => file_ops::move_no_replace(&tmp, output).map_err(|e| e.to_string()),
+    };
+    if placed.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    placed
+}
+
+/// Convert `paths` as `options` say. Emits `batch-convert-progress` with
+/// `{ done, total, path }` after each file.
+#[tauri::command]
+pub async fn batch_convert(
+    app: AppHandle,
+    paths: Vec<String>,
+    options: ConvertOptions,
+    state: State<'_, ConvertState>,
+    library: State<'_, LibraryState>,
+    limits: State<'_, DecodeState>,
+) -> Result<ConvertReport, String> {
+    let limits = *limits.0.lock().unwrap();
+    let ext = extension(&options.format)?;
+
+    // the template may use EXIF fields
+    let records: HashMap<String, Record> = library::records_of(&library, paths.clone(), &limits)
+        .into_iter()
+        .map(|r| (r.path.clone(), r))
+        .collect();
+    if let Some(record) = records.values().next() {
+        rename::expand_template(&options.template, record, 1)?;
+    }
+
+    let dir = PathBuf::from(&options.output_dir);
+    if options.output_dir.trim().is_empty() {
+        return Err("Choose an output folder".to_string());
+    }
+    if !dir.is_absolute() {
+        return Err(format!(
+            "The output folder is not a full path: {}",
+            options.output_dir
+        ));
+    }
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create the output folder: {}", e))?;
+
+    let mut report = ConvertReport::default();
+
+    // Outputs are decided up front, so two sources never race for one name
+    let mut claimed: HashMap<PathBuf, &str> = HashMap::new();
+    let mut jobs = Vec::new();
+    for (i, source) in paths.iter().enumerate() {
+        let name = match records.get(source) {
+            Some(record) => rename::expand_template(&options.template, record, i + 1)
+                .and_then(|name| rename::check_name(&name).map(|_| name)),
+            None => Err("The file is gone".to_string()),
+        };
+        let output = match name {
+            Ok(name) => dir.join(format!("{}.{}", name.trim(), ext)),
+            Err(error) => {
+                report.failed.push(FileError {
+                    path: source.clone(),
+                    error,
+                });
+                continue;
+            }
+        };
+
+        if file_ops::same_file(Path::new(source), &output) {
+            report.failed.push(FileError {
+                path: source.clone(),
+                error: "The output would replace the original".to_string(),
+            });
+        } else if let Some(other) = claimed.get(&output) {
+            report.failed.push(FileError {
+                path: source.clone(),
+                error: format!("Same output name as {}", other),
+            });
+        } else {
+            claimed.insert(output.clone(), source);
+            jobs.push((source.clone(), output));
+        }
+    }
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let mut running = state.0.lock().unwrap();
+        if running.is_some() {
+            return Err("A conversion is already running".to_string());
+        }
+        *running = Some(cancel.clone());
+    }
+
+    let total = jobs.len();
+
+    // rayon holds the thread it is called on until every file is done
+    let results = tauri::async_runtime::spawn_blocking(move || {
+        let done = AtomicUsize::new(0);
+        jobs.into_par_iter()
+            .map(|(source, output)| {
+                if cancel.load(Ordering::Relaxed) {
+                    return (source, None);
+                }
+                let result = convert_file(&source, &output, ext, &options, &limits).map(|_| output);
+
+                let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+                let progress = ConvertProgress {
+                    done: n,
+                    total,
+                    path: source.clone(),
+                };
+                let _ = app.emit("batch-convert-progress", progress);
+                (source, Some(result))
+            })
+            .collect::<Vec<(String, Option<Result<PathBuf, String>>)>>()
+    })
+    .await;
+
+    *state.0.lock().unwrap() = None;
+    let results = results.map_err(|e| format!("The conversion failed: {}", e))?;
+
+    for (source, result) in results {
+        match result {
+            Some(Ok(output)) => report.converted.push(ConvertedFile {
+                source,
+                output: output.to_string_lossy().to_string(),
+            }),
+            Some(Err(error)) => report.failed.push(FileError {
+                path: source,
+                error,
+            }),
+            None => report.cancelled += 1,
+        }
+    }
+    Ok(report)
+}
+
+/// Stop the running conversion after the files already being converted.
+#[tauri::command]
+pub fn cancel_batch_convert(state: State<ConvertState>) {
+    if let Some(cancel) = state.0.lock().unwrap().as_ref() {
+        cancel.store(true, Ordering::Relaxed);
+    }
+}
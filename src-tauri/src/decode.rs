@@ -0,0 +1,198 @@
+// ---------------- SAFE DECODING ----------------
+//
+// Every Rust decode path goes through here. Files come from untrusted
+// sources, so the decoder runs with `image::Limits` applied, on a worker
+// thread, and gives up after a timeout instead of freezing the app.
+//
+// A decoder that times out cannot be killed; its thread keeps running in the
+// background until it finishes, bounded by `max_alloc`.
+
+use crate::metadata;
+use crate::metadata_edit;
+use image::metadata::Orientation;
+use image::{ColorType, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader, Limits};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::State;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct DecodeLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Largest single allocation the decoder may make, in bytes.
+    pub max_alloc: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_width: 65_535,
+            max_height: 65_535,
+            max_alloc: 1024 * 1024 * 1024,
+            timeout_secs: 30,
+        }
+    }
+}
+
+impl DecodeLimits {
+    pub fn to_image_limits(self) -> Limits {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(self.max_width);
+        limits.max_image_height = Some(self.max_height);
+        limits.max_alloc = Some(self.max_alloc);
+        limits
+    }
+
+    pub fn check_dimensions(&self, width: u32, height: u32) -> Result<(), String> {
+        if width > self.max_width || height > self.max_height {
+            return Err(format!(
+                "Image is {} × {}, above the {} × {} decoder limit",
+                width, height, self.max_width, self.max_height
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub struct DecodeState(pub Mutex<DecodeLimits>);
+
+fn describe_error(e: ImageError) -> String {
+    match e {
+        ImageError::Limits(l) => format!("Image exceeds the decoder limits: {}", l),
+        other => format!("Decode failed: {}", other),
+    }
+}
+
+/// Open `path` with its format guessed from the content and `limits` applied.
+pub fn open_reader(
+    path: &str,
+    limits: &DecodeLimits,
+) -> Result<ImageReader<BufReader<File>>, String> {
+    let mut reader = ImageReader::open(path)
+        .map_err(|e| format!("Failed to open: {}", e))?
+        .with_guessed_format()
+        .map_err(|e| format!("Failed to open: {}", e))?;
+
+    reader.limits(limits.to_image_limits());
+    Ok(reader)
+}
+
+/// Dimensions from the file header without any limit applied, `None` if
+/// the format is not readable here.
+pub fn header_dimensions(path: &str) -> Option<(u32, u32)> {
+    let mut reader = ImageReader::open(path).ok()?.with_guessed_format().ok()?;
+    reader.no_limits();
+    reader.into_dimensions().ok()
+}
+
+/// What can be learned from the file header without decoding pixels.
+pub struct HeaderInfo {
+    pub format: Option<ImageFormat>,
+    pub width: u32,
+    pub height: u32,
+    /// Color type the decoder would produce.
+    pub color: ColorType,
+    /// Embedded ICC profile.
+    pub icc: Option<Vec<u8>>,
+}
+
+/// Read the header only, on a worker thread with `limits` applied.
+pub fn read_header(path: &str, limits: &DecodeLimits) -> Result<HeaderInfo, String> {
+    let path = path.to_string();
+    let limits = *limits;
+
+    run_with_timeout(limits.timeout_secs, move || {
+        let reader = open_reader(&path, &limits)?;
+        let format = reader.format();
+        let mut decoder = reader.into_decoder().map_err(describe_error)?;
+        let (width, height) = decoder.dimensions();
+
+        Ok(HeaderInfo {
+            format,
+            width,
+            height,
+            color: decoder.color_type(),
+            icc: decoder.icc_profile().ok().flatten(),
+        })
+    })
+}
+
+/// Run `work` on a worker thread, giving up after `timeout_secs`.
+/// A panicking decoder is reported as an error as well.
+pub fn run_with_timeout<T, F>(timeout_secs: u64, work: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+
+    thread::Builder::new()
+        .name("decode".to_string())
+        .spawn(move || {
+            let _ = tx.send(work());
+        })
+        .map_err(|e| format!("Failed to start decoder: {}", e))?;
+
+    match rx.recv_timeout(Duration::from_secs(timeout_secs)) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(format!(
+            "Decoding took longer than {} seconds and was abandoned",
+            timeout_secs
+        )),
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            Err("The decoder crashed on this file".to_string())
+        }
+    }
+}
+
+/// Fully decode `path` with `limits` applied, together with its embedded
+/// ICC profile.
+pub fn decode_with_profile(
+    path: &str,
+    limits: &DecodeLimits,
+) -> Result<(DynamicImage, Option<Vec<u8>>), String> {
+    let path = path.to_string();
+    let limits = *limits;
+
+    run_with_timeout(limits.timeout_secs, move || {
+        let mut decoder = open_reader(&path, &limits)?
+            .into_decoder()
+            .map_err(describe_error)?;
+
+        // Same allocation check `ImageReader::decode` does
+        limits
+            .to_image_limits()
+            .reserve(decoder.total_bytes())
+            .map_err(describe_error)?;
+
+        let icc = decoder.icc_profile().ok().flatten();
+        let img = DynamicImage::from_decoder(decoder).map_err(describe_error)?;
+        Ok((img, icc))
+    })
+}
+
+/// The EXIF orientation of `path`, which turns its decoded pixels upright
+/// the way the webview shows the file.
+pub fn orientation(path: &str) -> Option<Orientation> {
+    metadata::read_raw_quick(path)
+        .ok()?
+        .exif
+        .as_deref()
+        .and_then(metadata_edit::exif_orientation)
+        .and_then(|o| Orientation::from_exif(o as u8))
+}
+
+#[tauri::command]
+pub fn get_decode_limits(state: State<DecodeState>) -> DecodeLimits {
+    *state.0.lock().unwrap()
+}
+
+#[tauri::command]
+pub fn set_decode_limits(limits: DecodeLimits, state: State<DecodeState>) {
+    *state.0.lock().unwrap() = limits;
+}
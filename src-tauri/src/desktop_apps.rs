@@ -0,0 +1,758 @@
+// ---------------- LINUX DESKTOP APPLICATIONS ----------------
+//
+// "Open with" on Linux, the way file managers do it. The applications are
+// the desktop entries (.desktop files) registered for the MIME type of the
+// image, put in order and amended by the mimeapps.list files, with their
+// icons looked up in the icon theme. Launching one expands its Exec line
+// as the Desktop Entry Specification says.
+
+use resvg::{tiny_skia, usvg};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+
+/// Size of the icons in the menu.
+const ICON_SIZE: u32 = 32;
+
+type Group = HashMap<String, String>;
+
+pub struct DesktopApp {
+    /// Desktop file id, e.g. `org.gimp.GIMP.desktop`.
+    id: String,
+    name: String,
+    icon: Option<String>,
+    exec: String,
+    mime_types: Vec<String>,
+    /// Only meant to open files, not listed in menus.
+    no_display: bool,
+    file: PathBuf,
+}
+
+// ----- Key files ------
+
+/// The groups of a desktop entry style file. Of keys given twice, the
+/// first counts.
+fn parse_key_file(text: &str) -> HashMap<String, Group> {
+    let mut groups: HashMap<String, Group> = HashMap::new();
+    let mut current = None;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(name.to_string());
+            continue;
+        }
+        let (Some(group), Some((key, value))) = (&current, line.split_once('=')) else {
+            continue;
+        };
+        groups
+            .entry(group.clone())
+            .or_default()
+            .entry(key.trim().to_string())
+            .or_insert_with(|| value.trim().to_string());
+    }
+    groups
+}
+
+/// Undo the escapes of a string value (`\s`, `\n`, `\t`, `\r`, `\\`).
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn list(value: Option<&String>) -> Vec<String> {
+    value
+        .map(|v| {
+            v.split(';')
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// `key` in the language of the session, e.g. `Name[de_DE]`, then
+/// `Name[de]`, then `Name`.
+fn localized(group: &Group, key: &str) -> Option<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|var| env::var(var).ok().filter(|l| !l.is_empty()))
+        .unwrap_or_default();
+    let lang = locale.split(['.', '@']).next().unwrap_or_default();
+    let short = lang.split('_').next().unwrap_or_default();
+
+    [lang, short]
+        .iter()
+        .filter(|l| !l.is_empty())
+        .find_map(|l| group.get(&format!("{}[{}]", key, l)))
+        .or_else(|| group.get(key))
+        .map(|v| unescape(v))
+}
+
+// ----- XDG directories ------
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME").map(PathBuf::from)
+}
+
+/// `$var` as a directory, `default` in the home folder without it.
+fn xdg_home(var: &str, default: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home().map(|h| h.join(default)))
+}
+
+/// `$var` as a list of directories, `default` without it.
+fn xdg_dirs(var: &str, default: &str) -> Vec<PathBuf> {
+    let value = env::var(var).ok().filter(|v| !v.is_empty());
+    value
+        .as_deref()
+        .unwrap_or(default)
+        .split(':')
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Data directories, the more important first.
+fn data_dirs() -> Vec<PathBuf> {
+    xdg_home("XDG_DATA_HOME", ".local/share")
+        .into_iter()
+        .chain(xdg_dirs("XDG_DATA_DIRS", "/usr/local/share:/usr/share"))
+        .collect()
+}
+
+/// Config directories, the more important first.
+fn config_dirs() -> Vec<PathBuf> {
+    xdg_home("XDG_CONFIG_HOME", ".config")
+        .into_iter()
+        .chain(xdg_dirs("XDG_CONFIG_DIRS", "/etc/xdg"))
+        .collect()
+}
+
+/// The running desktop in lower case, e.g. `["ubuntu", "gnome"]`.
+fn current_desktops() -> Vec<String> {
+    env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|d| !d.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn find_program(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+// ----- Desktop entries ------
+
+impl DesktopApp {
+    /// The application in `file`, `None` when it isn't one that can be
+    /// started here.
+    fn load(id: String, file: PathBuf) -> Option<DesktopApp> {
+        let text = fs::read_to_string(&file).ok()?;
+        let groups = parse_key_file(&text);
+        let entry = groups.get("Desktop Entry")?;
+        let is_true = |key: &str| entry.get(key).is_some_and(|v| v == "true");
+
+        if entry.get("Type").map(String::as_str) != Some("Application")
+            || is_true("Hidden")
+            || is_true("Terminal")
+        {
+            return None;
+        }
+
+        let desktops = current_desktops();
+        let shown_in = |key: &str| {
+            entry.get(key).map(|v| {
+                list(Some(v))
+                    .iter()
+                    .any(|d| desktops.contains(&d.to_lowercase()))
+            })
+        };
+        if shown_in("OnlyShowIn") == Some(false) || shown_in("NotShowIn") == Some(true) {
+            return None;
+        }
+        if let Some(try_exec) = entry.get("TryExec") {
+            find_program(&unescape(try_exec))?;
+        }
+
+        Some(DesktopApp {
+            id,
+            name: localized(entry, "Name")?,
+            icon: entry
+                .get("Icon")
+                .map(|i| unescape(i))
+                .filter(|i| !i.is_empty()),
+            exec: unescape(entry.get("Exec")?),
+            mime_types: list(entry.get("MimeType")),
+            no_display: is_true("NoDisplay"),
+            file,
+        })
+    }
+
+    /// Whether the Exec line takes files or URLs.
+    fn takes_files(&self) -> bool {
+        ["%f", "%F", "%u", "%U"]
+            .iter()
+            .any(|code| self.exec.contains(code))
+    }
+
+    /// Whether this is an entry for the viewer itself.
+    fn is_this_viewer(&self) -> bool {
+        let program = split_exec(&self.exec).and_then(|args| args.into_iter().next());
+        let own = env::current_exe().ok();
+        match (program, own) {
+            (Some(program), Some(own)) => Path::new(&program).file_name() == own.file_name(),
+            _ => false,
+        }
+    }
+}
+
+/// The id, name and icon as a PNG of `apps`, what the Open With menu shows.
+pub fn menu_entries(apps: Vec<DesktopApp>) -> Vec<(String, String, Option<Vec<u8>>)> {
+    let icons = IconTheme::current();
+    apps.into_iter()
+        .map(|app| {
+            let icon = app.icon.as_deref().and_then(|name| icons.png(name));
+            (app.id, app.name, icon)
+        })
+        .collect()
+}
+
+fn desktop_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|e| e.path()) {
+        if path.is_dir() {
+            desktop_files(&path, files);
+        } else if path.extension().is_some_and(|e| e == "desktop") {
+            files.push(path);
+        }
+    }
+}
+
+/// Every installed application, by desktop file id. Of two entries with
+/// the same id, the one in the more important directory counts, even when
+/// it hides the application.
+fn all_apps() -> HashMap<String, DesktopApp> {
+    let mut apps = HashMap::new();
+    let mut seen = HashSet::new();
+
+    for dir in data_dirs().iter().map(|d| d.join("applications")) {
+        let mut files = Vec::new();
+        desktop_files(&dir, &mut files);
+
+        for file in files {
+            let Ok(relative) = file.strip_prefix(&dir) else {
+                continue;
+            };
+            // applications/kde/app.desktop is kde-app.desktop
+            let id = relative.to_string_lossy().replace('/', "-");
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            if let Some(app) = DesktopApp::load(id.clone(), file) {
+                apps.insert(id, app);
+            }
+        }
+    }
+    apps
+}
+
+// ----- MIME associations ------
+
+/// MIME type of an image, by its extension.
+fn mime_type(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    Some(match ext.as_str() {
+        "jpg" | "jpeg" | "jpe" | "jfif" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "bmp" => "image/bmp",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "tif" | "tiff" => "image/tiff",
+        "svg" => "image/svg+xml",
+        "ico" => "image/vnd.microsoft.icon",
+        "cur" => "image/x-win-bitmap",
+        _ => return None,
+    })
+}
+
+#[derive(Default)]
+struct Associations {
+    defaults: Vec<String>,
+    added: Vec<String>,
+    removed: HashSet<String>,
+}
+
+/// What the mimeapps.list files say about `mime`. They are read the more
+/// important first, so an association removed in one stays removed
+/// whatever the ones after it add.
+fn associations(mime: &str) -> Associations {
+    let desktops = current_desktops();
+    let dirs = config_dirs()
+        .into_iter()
+        .chain(data_dirs().into_iter().map(|d| d.join("applications")));
+
+    let mut found = Associations::default();
+    for dir in dirs {
+        let names = desktops
+            .iter()
+            .map(|d| format!("{}-mimeapps.list", d))
+            .chain(Some("mimeapps.list".to_string()));
+
+        for name in names {
+            let Ok(text) = fs::read_to_string(dir.join(name)) else {
+                continue;
+            };
+            let groups = parse_key_file(&text);
+            let ids = |group: &str| list(groups.get(group).and_then(|g| g.get(mime)));
+
+            found.defaults.extend(ids("Default Applications"));
+            for id in ids("Added Associations") {
+                if !found.removed.contains(&id) {
+                    found.added.push(id);
+                }
+            }
+            found.removed.extend(ids("Removed Associations"));
+        }
+    }
+    found
+}
+
+/// The applications for the image at `path`, the default one first.
+pub fn apps_for(path: &str) -> Vec<DesktopApp> {
+    let Some(mime) = mime_type(Path::new(path)) else {
+        return Vec::new();
+    };
+    let mut all = all_apps();
+    let found = associations(mime);
+
+    let mut registered: Vec<(String, String)> = all
+        .values()
+        .filter(|app| app.mime_types.iter().any(|m| m == mime))
+        .filter(|app| !found.removed.contains(&app.id))
+        .map(|app| (app.name.to_lowercase(), app.id.clone()))
+        .collect();
+    registered.sort();
+
+    found
+        .defaults
+        .into_iter()
+        .chain(found.added)
+        .chain(registered.into_iter().map(|(_, id)| id))
+        .filter_map(|id| all.remove(&id))
+        .filter(|app| !app.is_this_viewer())
+        .collect()
+}
+
+/// Applications that open files but aren't registered for the type of
+/// `path`, by name. What to choose from when none of those will do.
+pub fn other_apps(path: &str) -> Vec<DesktopApp> {
+    let listed: HashSet<String> = apps_for(path).into_iter().map(|app| app.id).collect();
+
+    let mut others: Vec<DesktopApp> = all_apps()
+        .into_values()
+        .filter(|app| !app.no_display && app.takes_files() && !listed.contains(&app.id))
+        .filter(|app| !app.is_this_viewer())
+        .collect();
+    others.sort_by_key(|app| app.name.to_lowercase());
+    others
+}
+
+// ----- Launching ------
+
+/// Split an Exec line into arguments, undoing its quoting.
+fn split_exec(exec: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let (mut in_arg, mut quoted) = (false, false);
+
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            '\\' if quoted => current.push(chars.next()?),
+            c if c.is_whitespace() && !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if quoted {
+        return None;
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Some(args)
+}
+
+fn file_url(path: &str) -> String {
+    let mut url = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
+/// The command line that opens `path` with `app`. Without a file field
+/// code, the file goes last.
+fn command_line(app: &DesktopApp, path: &str) -> Result<Vec<String>, String> {
+    let invalid = || format!("Invalid Exec line in {}", app.file.display());
+    let args = split_exec(&app.exec).ok_or_else(invalid)?;
+
+    let mut line = Vec::new();
+    let mut took_file = false;
+    for arg in args {
+        if arg == "%i" {
+            if let Some(icon) = &app.icon {
+                line.extend(["--icon".to_string(), icon.clone()]);
+            }
+            continue;
+        }
+
+        let mut out = String::new();
+        let mut dropped = false;
+        let mut chars = arg.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('%') => out.push('%'),
+                Some('f' | 'F') => {
+                    out.push_str(path);
+                    took_file = true;
+                }
+                Some('u' | 'U') => {
+                    out.push_str(&file_url(path));
+                    took_file = true;
+                }
+                Some('c') => out.push_str(&app.name),
+                Some('k') => out.push_str(&app.file.to_string_lossy()),
+                // deprecated and unknown codes
+                _ => dropped = true,
+            }
+        }
+        if !(dropped && out.is_empty()) {
+            line.push(out);
+        }
+    }
+
+    if !took_file {
+        line.push(path.to_string());
+    }
+    if line.is_empty() {
+        return Err(invalid());
+    }
+    Ok(line)
+}
+
+/// Open `path` with the application `id`.
+pub fn launch(id: &str, path: &str) -> Result<Child, String> {
+    let app = all_apps()
+        .remove(id)
+        .ok_or_else(|| format!("Application not found: {}", id))?;
+    let line = command_line(&app, path)?;
+
+    Command::new(&line[0])
+        .args(&line[1..])
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", app.name, e))
+}
+
+/// Open `path` with the default application for its type, or let
+/// xdg-open pick one when there is none.
+pub fn open_default(path: &str) -> Result<(), String> {
+    match apps_for(path).first() {
+        Some(app) => launch(&app.id, path).map(|_| ()),
+        None => Command::new("xdg-open")
+            .arg(path)
+            .spawn()
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+    }
+}
+
+// ----- Icons ------
+
+/// The icon theme the desktop uses: GTK settings, then GNOME's, then
+/// KDE's, hicolor without any.
+fn theme_name() -> String {
+    let config = xdg_home("XDG_CONFIG_HOME", ".config");
+    let setting = |file: &str, group: &str, key: &str| {
+        let text = fs::read_to_string(config.as_ref()?.join(file)).ok()?;
+        parse_key_file(&text).get(group)?.get(key).cloned()
+    };
+
+    setting("gtk-4.0/settings.ini", "Settings", "gtk-icon-theme-name")
+        .or_else(|| setting("gtk-3.0/settings.ini", "Settings", "gtk-icon-theme-name"))
+        .or_else(|| {
+            let output = Command::new("gsettings")
+                .args(["get", "org.gnome.desktop.interface", "icon-theme"])
+                .output()
+                .ok()
+                .filter(|o| o.status.success())?;
+            Some(
+                String::from_utf8_lossy(&output.stdout)
+                    .trim()
+                    .trim_matches('\'')
+                    .to_string(),
+            )
+        })
+        .or_else(|| setting("kdeglobals", "Icons", "Theme"))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "hicolor".to_string())
+}
+
+/// Directories icon themes are in, the more important first.
+fn icon_bases() -> Vec<PathBuf> {
+    home()
+        .map(|h| h.join(".icons"))
+        .into_iter()
+        .chain(data_dirs().into_iter().map(|d| d.join("icons")))
+        .collect()
+}
+
+struct Theme {
+    name: String,
+    /// Subdirectories, those closest to `ICON_SIZE` first.
+    dirs: Vec<String>,
+}
+
+fn load_theme(name: &str, bases: &[PathBuf]) -> Option<(Theme, Vec<String>)> {
+    let text = bases
+        .iter()
+        .find_map(|b| fs::read_to_string(b.join(name).join("index.theme")).ok())?;
+    let groups = parse_key_file(&text);
+    let about = groups.get("Icon Theme")?;
+    let split = |key: &str| -> Vec<String> {
+        about
+            .get(key)
+            .map(|v| v.split(',').map(|s| s.trim().to_string()).collect())
+            .unwrap_or_default()
+    };
+
+    let size = ICON_SIZE as i64;
+    let distance = |dir: &String| {
+        let group = groups.get(dir);
+        let number = |key: &str, default: i64| {
+            group
+                .and_then(|g| g.get(key))
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        let dir_size = number("Size", 0);
+        let (min, max) = match group.and_then(|g| g.get("Type")).map(String::as_str) {
+            Some("Scalable") => (number("MinSize", dir_size), number("MaxSize", dir_size)),
+            Some("Fixed") => (dir_size, dir_size),
+            _ => {
+                let threshold = number("Threshold", 2);
+                (dir_size - threshold, dir_size + threshold)
+            }
+        };
+        if size < min {
+            min - size
+        } else {
+            (size - max).max(0)
+        }
+    };
+
+    let mut dirs = split("Directories");
+    dirs.sort_by_key(distance);
+    let theme = Theme {
+        name: name.to_string(),
+        dirs,
+    };
+    Some((theme, split("Inherits")))
+}
+
+/// `name` and the themes it inherits from, hicolor last.
+fn theme_chain(name: &str, bases: &[PathBuf], chain: &mut Vec<Theme>) {
+    if chain.iter().any(|t| t.name == name) {
+        return;
+    }
+    let Some((theme, parents)) = load_theme(name, bases) else {
+        return;
+    };
+    chain.push(theme);
+    for parent in parents {
+        theme_chain(&parent, bases, chain);
+    }
+}
+
+/// The icon theme of the desktop and those it inherits from.
+struct IconTheme {
+    bases: Vec<PathBuf>,
+    chain: Vec<Theme>,
+}
+
+impl IconTheme {
+    fn current() -> IconTheme {
+        let bases = icon_bases();
+        let mut chain = Vec::new();
+        theme_chain(&theme_name(), &bases, &mut chain);
+        theme_chain("hicolor", &bases, &mut chain);
+        IconTheme { bases, chain }
+    }
+
+    /// File of the icon `name`, where the icon theme spec looks for it.
+    fn find(&self, name: &str) -> Option<PathBuf> {
+        let extensions = ["png", "svg"];
+        for theme in &self.chain {
+            for dir in &theme.dirs {
+                for base in &self.bases {
+                    let folder = base.join(&theme.name).join(dir);
+                    for ext in extensions {
+                        let file = folder.join(format!("{}.{}", name, ext));
+                        if file.is_file() {
+                            return Some(file);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.bases
+            .iter()
+            .cloned()
+            .chain(Some(PathBuf::from("/usr/share/pixmaps")))
+            .flat_map(|dir| extensions.map(|ext| dir.join(format!("{}.{}", name, ext))))
+            .find(|file| file.is_file())
+    }
+
+    /// The icon `name` of a desktop entry as a PNG. It's a theme icon, or
+    /// a file when it's a path.
+    fn png(&self, name: &str) -> Option<Vec<u8>> {
+        let file = match Path::new(name).is_absolute() {
+            true => PathBuf::from(name),
+            false => self.find(name)?,
+        };
+        let data = fs::read(&file).ok()?;
+        match file.extension()?.to_str()? {
+            "svg" | "svgz" => render_svg(&data),
+            _ => scale_raster(&data),
+        }
+    }
+}
+
+fn render_svg(data: &[u8]) -> Option<Vec<u8>> {
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default()).ok()?;
+    let size = tree.size();
+    let scale = ICON_SIZE as f32 / size.width().max(size.height());
+    let offset = |side: f32| (ICON_SIZE as f32 - side * scale) / 2.0;
+
+    let mut pixmap = tiny_skia::Pixmap::new(ICON_SIZE, ICON_SIZE)?;
+    let transform = tiny_skia::Transform::from_scale(scale, scale)
+        .post_translate(offset(size.width()), offset(size.height()));
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+    pixmap.encode_png().ok()
+}
+
+fn scale_raster(data: &[u8]) -> Option<Vec<u8>> {
+    let img = image::load_from_memory(data).ok()?;
+    let mut png = Vec::new();
+    img.thumbnail(ICON_SIZE, ICON_SIZE)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .ok()?;
+    Some(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(exec: &str) -> DesktopApp {
+        DesktopApp {
+            id: "paint.desktop".to_string(),
+            name: "Paint".to_string(),
+            icon: Some("paint".to_string()),
+            exec: exec.to_string(),
+            mime_types: Vec::new(),
+            no_display: false,
+            file: PathBuf::from("/usr/share/applications/paint.desktop"),
+        }
+    }
+
+    #[test]
+    fn splits_quoted_exec_lines() {
+        assert_eq!(
+            split_exec(r#"paint  --new "my \"file\" \\ here" "" %F"#).unwrap(),
+            ["paint", "--new", r#"my "file" \ here"#, "", "%F"]
+        );
+        assert_eq!(split_exec("a\"b c\"d").unwrap(), ["ab cd"]);
+        assert!(split_exec("paint \"%f").is_none());
+        assert!(split_exec("paint \"\\").is_none());
+    }
+
+    #[test]
+    fn fills_in_field_codes() {
+        let line = |exec: &str| command_line(&app(exec), "/photos/a b%.jpg").unwrap();
+
+        assert_eq!(line("paint %f"), ["paint", "/photos/a b%.jpg"]);
+        assert_eq!(
+            line("paint --open=%U"),
+            ["paint", "--open=file:///photos/a%20b%25.jpg"]
+        );
+        assert_eq!(
+            line("paint %i --title=%c %k 100%%"),
+            [
+                "paint",
+                "--icon",
+                "paint",
+                "--title=Paint",
+                "/usr/share/applications/paint.desktop",
+                "100%",
+                "/photos/a b%.jpg",
+            ]
+        );
+        // deprecated codes are dropped, the file goes last without a code
+        assert_eq!(line("paint %d %m"), ["paint", "/photos/a b%.jpg"]);
+        assert!(command_line(&app("paint \"%f"), "/photos/a.jpg").is_err());
+    }
+}
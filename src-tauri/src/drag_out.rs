@@ -0,0 +1,80 @@
+// ---------------- DRAG OUT ----------------
+//
+// Dragging images out of the window as files, into file managers, browsers
+// and other apps. The webview can only drag data inside itself, so the
+// native drag is started from here, on the main thread as the OS wants it.
+//
+// `file-drag-end` is emitted once the files were dropped or the drag was
+// cancelled, so the window can tell its own files dropped back on it.
+
+use crate::decode::{self, DecodeState};
+use image::ImageFormat;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use tauri::{AppHandle, Emitter, State, WebviewWindow};
+
+/// Longest side of the picture under the cursor.
+const ICON_SIZE: u32 = 128;
+
+/// A PNG preview of `path`, for when the window has none to give.
+fn icon_of(path: &str, limits: &decode::DecodeLimits) -> Result<Vec<u8>, String> {
+    let (img, _) = decode::decode_with_profile(path, limits)?;
+    let mut png = Vec::new();
+    img.thumbnail(ICON_SIZE, ICON_SIZE)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+/// Start dragging `paths` as files, with `icon` (PNG) under the cursor or a
+/// preview of the first file.
+#[tauri::command]
+pub async fn start_file_drag(
+    app: AppHandle,
+    window: WebviewWindow,
+    paths: Vec<String>,
+    icon: Option<Vec<u8>>,
+    limits: State<'_, DecodeState>,
+) -> Result<(), String> {
+    let Some(first) = paths.first() else {
+        return Ok(());
+    };
+    let limits = *limits.0.lock().unwrap();
+    let icon = match icon {
+        Some(icon) => icon,
+        None => icon_of(first, &limits)?,
+    };
+    let files: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+
+    let (started, result) = mpsc::channel();
+    let target = window.clone();
+    window
+        .run_on_main_thread(move || {
+            let item = drag::DragItem::Files(files);
+            let image = drag::Image::Raw(icon);
+            let on_end = move |_: drag::DragResult, _: drag::CursorPosition| {
+                let _ = app.emit("file-drag-end", ());
+            };
+
+            #[cfg(target_os = "linux")]
+            let outcome = target
+                .gtk_window()
+                .map_err(|e| e.to_string())
+                .and_then(|gtk| {
+                    drag::start_drag(&gtk, item, image, on_end, Default::default())
+                        .map_err(|e| e.to_string())
+                });
+            #[cfg(not(target_os = "linux"))]
+            let outcome = drag::start_drag(&target, item, image, on_end, Default::default())
+                .map_err(|e| e.to_string());
+
+            let _ = started.send(outcome);
+        })
+        .map_err(|e| e.to_string())?;
+
+    result
+        .recv()
+        .map_err(|_| "The drag didn't start".to_string())?
+        .map_err(|e| format!("Failed to drag the file: {}", e))
+}
@@ -0,0 +1,375 @@
+// ---------------- DUPLICATE FINDER ----------------
+//
+// Finds copies of the same image in a folder tree. Exact duplicates share a
+// SHA-256 of the file content; near duplicates (re-exports, resized or
+// recompressed copies) are matched by perceptual hashes of the pixels:
+// a difference hash (dHash) and a DCT hash (pHash), both 64 bits, compared
+// by their combined Hamming distance. An image joins the group of the first
+// image it is similar to, compared with that image and not with any other
+// member, so a chain of slowly drifting edits doesn't pull unrelated images
+// into one group. The hashes are kept in the library index, so scanning a
+// folder again only hashes new or changed files.
+
+use crate::decode::{self, DecodeLimits, DecodeState};
+use crate::library::{self, LibraryState, Record};
+use image::imageops::FilterType;
+use image::DynamicImage;
+use rayon::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+#[derive(Serialize, Clone)]
+struct ScanProgress {
+    done: usize,
+    total: usize,
+}
+
+#[derive(Serialize, Clone)]
+pub struct DuplicateFile {
+    path: String,
+    size: u64,
+    width: Option<u32>,
+    height: Option<u32>,
+    /// Seconds since the Unix epoch.
+    modified: Option<u64>,
+    /// Hex SHA-256 of the file, equal for exact copies.
+    content_hash: String,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateGroup {
+    /// All files in the group are byte-identical.
+    exact: bool,
+    /// Index of the file suggested to keep: the most pixels, then the
+    /// largest file, then the oldest.
+    keep: usize,
+    files: Vec<DuplicateFile>,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateReport {
+    scanned: usize,
+    /// Files that couldn't be decoded, compared by content only.
+    undecodable: usize,
+    groups: Vec<DuplicateGroup>,
+}
+
+struct Hashed {
+    file: DuplicateFile,
+    /// (dHash, pHash), `None` when the pixels couldn't be read.
+    perceptual: Option<(u64, u64)>,
+}
+
+impl From<Record> for Hashed {
+    fn from(record: Record) -> Self {
+        Hashed {
+            file: DuplicateFile {
+                path: record.path,
+                size: record.stamp.size,
+                width: record.width,
+                height: record.height,
+                modified: u64::try_from(record.stamp.modified / 1000).ok(),
+                content_hash: record.content_hash.unwrap_or_default(),
+            },
+            perceptual: record.perceptual,
+        }
+    }
+}
+
+pub fn collect_images(dir: &Path, recursive: bool, out: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let Ok(kind) = entry.file_type() else {
+            continue;
+        };
+
+        // file_type doesn't follow symlinks, so linked folders can't loop
+        if kind.is_dir() {
+            if recursive {
+                collect_images(&path, recursive, out);
+            }
+        } else if crate::is_image_file(&path) {
+            out.push(path.to_string_lossy().to_string());
+        }
+    }
+}
+
+/// 8x8 grayscale gradient: each bit tells whether a pixel is brighter than
+/// its right neighbour.
+fn dhash(img: &DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+
+    for y in 0..8 {
+        for x in 0..8 {
+            let bit = small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | bit as u64;
+        }
+    }
+    hash
+}
+
+/// Lowest 8x8 DCT frequencies of a 32x32 grayscale copy, each bit telling
+/// whether the coefficient is above the median. The DC term is left out.
+fn phash(img: &DynamicImage) -> u64 {
+    const N: usize = 32;
+    let small = img
+        .resize_exact(N as u32, N as u32, FilterType::Triangle)
+        .to_luma8();
+    let pixels: Vec<f64> = small.pixels().map(|p| p[0] as f64).collect();
+
+    let cos: Vec<f64> = (0..8 * N)
+        .map(|i| {
+            let (u, x) = (i / N, i % N);
+            ((2 * x + 1) as f64 * u as f64 * std::f64::consts::PI / (2 * N) as f64).cos()
+        })
+        .collect();
+
+    let mut coefficients = Vec::with_capacity(64);
+    for v in 0..8 {
+        for u in 0..8 {
+            let mut sum = 0.0;
+            for y in 0..N {
+                for x in 0..N {
+                    sum += pixels[y * N + x] * cos[u * N + x] * cos[v * N + y];
+                }
+            }
+            coefficients.push(sum);
+        }
+    }
+
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+
+    coefficients
+        .iter()
+        .fold(0u64, |hash, &c| (hash << 1) | (c > median) as u64)
+}
+
+/// Hex SHA-256 of a file's bytes.
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// (dHash, pHash) of the image at `path`, `None` when it can't be decoded.
+pub fn perceptual_hashes(path: &str, limits: &decode::DecodeLimits) -> Option<(u64, u64)> {
+    let (img, _) = decode::decode_with_profile(path, limits).ok()?;
+    // the hashes only need a small copy, this keeps the resizes cheap
+    let thumb = img.thumbnail(256, 256);
+    Some((dhash(&thumb), phash(&thumb)))
+}
+
+fn best_to_keep(files: &[DuplicateFile]) -> usize {
+    let pixels = |f: &DuplicateFile| f.width.unwrap_or(0) as u64 * f.height.unwrap_or(0) as u64;
+
+    (0..files.len())
+        .max_by(|&a, &b| {
+            let (fa, fb) = (&files[a], &files[b]);
+            pixels(fa)
+                .cmp(&pixels(fb))
+                .then(fa.size.cmp(&fb.size))
+                .then(fb.modified.cmp(&fa.modified))
+        })
+        .unwrap_or(0)
+}
+
+/// Scan `folder` (and its subfolders when `recursive`) for duplicates.
+/// Images count as near duplicates when their two perceptual hashes differ
+/// in at most `max_distance` bits on average; 0 finds exact copies only. Emits
+/// `duplicate-scan-progress` with `{ done, total }` while hashing.
+#[tauri::command]
+pub async fn find_duplicates(
+    app: AppHandle,
+    folder: String,
+    recursive: bool,
+    max_distance: u32,
+    limits: State<'_, DecodeState>,
+) -> Result<DuplicateReport, String> {
+    let limits = *limits.0.lock().unwrap();
+
+    // hashing a folder tree takes a while, and rayon holds the thread it
+    // is called on until every file is done
+    tauri::async_runtime::spawn_blocking(move || {
+        let library = app.state::<LibraryState>();
+        scan(&app, &folder, recursive, max_distance, &library, &limits)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn scan(
+    app: &AppHandle,
+    folder: &str,
+    recursive: bool,
+    max_distance: u32,
+    library: &LibraryState,
+    limits: &DecodeLimits,
+) -> Result<DuplicateReport, String> {
+    if !Path::new(folder).is_dir() {
+        return Err(format!("Not a folder: {}", folder));
+    }
+
+    let mut paths = Vec::new();
+    collect_images(Path::new(folder), recursive, &mut paths);
+    paths.sort_by(|a, b| natord::compare(a, b));
+
+    // hashes already in the library index are reused
+    let hashed: Vec<Hashed> = library::records(library, paths, true, limits, &|done, total| {
+        let _ = app.emit("duplicate-scan-progress", ScanProgress { done, total });
+    })
+    .into_iter()
+    .filter(|r| r.content_hash.is_some())
+    .map(Hashed::from)
+    .collect();
+
+    Ok(DuplicateReport {
+        scanned: hashed.len(),
+        undecodable: hashed.iter().filter(|h| h.perceptual.is_none()).count(),
+        groups: group(&hashed, max_distance),
+    })
+}
+
+/// Groups of exact copies and, unless `max_distance` is 0, near duplicates.
+fn group(hashed: &[Hashed], max_distance: u32) -> Vec<DuplicateGroup> {
+    // exact copies
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    let mut by_content: HashMap<&str, usize> = HashMap::new();
+    for (i, h) in hashed.iter().enumerate() {
+        match by_content.get(h.file.content_hash.as_str()) {
+            Some(&cluster) => clusters[cluster].push(i),
+            None => {
+                by_content.insert(&h.file.content_hash, clusters.len());
+                clusters.push(vec![i]);
+            }
+        }
+    }
+
+    // near duplicates, each cluster against the first image of the earlier
+    // ones it could join
+    if max_distance > 0 {
+        let similar = |(da, pa): (u64, u64), (db, pb): (u64, u64)| {
+            // one hash alone is easily fooled by flat or smooth images
+            (da ^ db).count_ones() + (pa ^ pb).count_ones() <= 2 * max_distance
+        };
+
+        // (cluster, hashes of its first image)
+        let mut leaders: Vec<(usize, (u64, u64))> = Vec::new();
+        for cluster in 0..clusters.len() {
+            let Some(hashes) = hashed[clusters[cluster][0]].perceptual else {
+                continue;
+            };
+            match leaders.par_iter().find_first(|(_, h)| similar(*h, hashes)) {
+                Some(&(leader, _)) => {
+                    let members = std::mem::take(&mut clusters[cluster]);
+                    clusters[leader].extend(members);
+                }
+                None => leaders.push((cluster, hashes)),
+            }
+        }
+    }
+
+    let mut groups: Vec<DuplicateGroup> = clusters
+        .into_iter()
+        .filter(|members| members.len() > 1)
+        .map(|members| {
+            let files: Vec<DuplicateFile> =
+                members.iter().map(|&i| hashed[i].file.clone()).collect();
+            DuplicateGroup {
+                exact: files
+                    .iter()
+                    .all(|f| f.content_hash == files[0].content_hash),
+                keep: best_to_keep(&files),
+                files,
+            }
+        })
+        .collect();
+
+    // exact copies first, then by the first path
+    groups.sort_by(|a, b| {
+        b.exact
+            .cmp(&a.exact)
+            .then_with(|| natord::compare(&a.files[0].path, &b.files[0].path))
+    });
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashed(path: &str, content: &str, perceptual: Option<(u64, u64)>) -> Hashed {
+        Hashed {
+            file: DuplicateFile {
+                path: path.to_string(),
+                size: 100,
+                width: Some(10),
+                height: Some(10),
+                modified: None,
+                content_hash: content.to_string(),
+            },
+            perceptual,
+        }
+    }
+
+    fn paths(group: &DuplicateGroup) -> Vec<&str> {
+        group.files.iter().map(|f| f.path.as_str()).collect()
+    }
+
+    #[test]
+    fn groups_exact_and_near_duplicates() {
+        let files = [
+            hashed("a.jpg", "1", Some((0, 0))),
+            hashed("b.jpg", "2", Some((0b111, 0b1))),
+            hashed("c.jpg", "1", Some((0, 0))),
+            // close to b, too far from a
+            hashed("d.jpg", "3", Some((0b1_1111, 0b111))),
+            hashed("e.jpg", "4", Some((u64::MAX, u64::MAX))),
+            hashed("f.png", "5", None),
+        ];
+
+        let groups = group(&files, 0);
+        assert_eq!(groups.len(), 1);
+        assert!(groups[0].exact);
+        assert_eq!(paths(&groups[0]), ["a.jpg", "c.jpg"]);
+
+        // a chain a ~ b ~ d doesn't pull d in, it is only compared with a
+        let groups = group(&files, 2);
+        assert_eq!(groups.len(), 1);
+        assert!(!groups[0].exact);
+        assert_eq!(paths(&groups[0]), ["a.jpg", "c.jpg", "b.jpg"]);
+
+        let groups = group(&files, 4);
+        assert_eq!(paths(&groups[0]), ["a.jpg", "c.jpg", "b.jpg", "d.jpg"]);
+    }
+
+    #[test]
+    fn perceptual_hashes_survive_resizing() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(320, 240, |x, y| {
+            image::Rgb([
+                (x * 255 / 320) as u8,
+                (y * 255 / 240) as u8,
+                ((x + y) % 256) as u8,
+            ])
+        }));
+        let small = img.resize_exact(160, 120, FilterType::Lanczos3);
+        let flipped = img.fliph();
+
+        let distance = |a: &DynamicImage, b: &DynamicImage| {
+            (dhash(a) ^ dhash(b)).count_ones() + (phash(a) ^ phash(b)).count_ones()
+        };
+        assert!(distance(&img, &small) <= 2 * 4);
+        assert!(distance(&img, &flipped) > 2 * 10);
+    }
+}
@@ -0,0 +1,115 @@
+// ---------------- EDITOR WATCH ----------------
+//
+// An image opened with an app from Open With is watched while the app
+// runs, so the viewer shows what was saved in it. `image-edited` is
+// emitted with `{ path, changed, closed }`: after each save, once the size
+// and time of the file stop changing, and when the app exits.
+//
+// Some editors pass the file to an instance that is already running and
+// exit at once. There's no process to wait for then, and the file is
+// watched for a while instead. Opening the same image again takes over
+// the watch.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::process::Child;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tauri::{AppHandle, Emitter, Manager};
+
+const POLL: Duration = Duration::from_secs(1);
+
+/// An app that exits sooner than this has passed the file on.
+const HAND_OFF: Duration = Duration::from_secs(5);
+
+/// How long a file that was passed on is watched.
+const PASSED_ON_WATCH: Duration = Duration::from_secs(60 * 60);
+
+/// The current watch of each path.
+#[derive(Default)]
+pub struct EditorWatchState(pub Mutex<HashMap<String, u64>>);
+
+#[derive(Serialize, Clone)]
+struct ImageEdited {
+    path: String,
+    /// The file is not what the viewer last showed.
+    changed: bool,
+    /// The app exited.
+    closed: bool,
+}
+
+/// Size and modification time, `None` while there is no file (an editor
+/// replacing it).
+fn stamp(path: &str) -> Option<(u64, SystemTime)> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+
+/// Watch `path` while `editor`, just started on it, runs.
+pub fn watch(app: AppHandle, mut editor: Child, path: String) {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let state = app.state::<EditorWatchState>();
+    state.0.lock().unwrap().insert(path.clone(), id);
+
+    thread::spawn(move || {
+        let watches = || app.state::<EditorWatchState>();
+        let current = || watches().0.lock().unwrap().get(&path) == Some(&id);
+        let emit = |changed: bool, closed: bool| {
+            let path = path.clone();
+            let _ = app.emit(
+                "image-edited",
+                ImageEdited {
+                    path,
+                    changed,
+                    closed,
+                },
+            );
+        };
+
+        let started = Instant::now();
+        let mut shown = stamp(&path);
+        let mut last = shown;
+        let mut running = true;
+
+        while current() {
+            thread::sleep(POLL);
+
+            // saved, and done saving
+            let now = stamp(&path);
+            if now.is_some() && now == last && now != shown {
+                shown = now;
+                emit(true, false);
+            }
+            last = now;
+
+            if !running || matches!(editor.try_wait(), Ok(None)) {
+                if !running && started.elapsed() > PASSED_ON_WATCH {
+                    break;
+                }
+                continue;
+            }
+            running = false;
+            if started.elapsed() < HAND_OFF {
+                continue;
+            }
+
+            let changed = now.is_some() && now != shown;
+            emit(changed, true);
+            break;
+        }
+
+        let state = watches();
+        let mut watching = state.0.lock().unwrap();
+        if watching.get(&path) == Some(&id) {
+            watching.remove(&path);
+        }
+        drop(watching);
+
+        // taken over, the app may still be running
+        let _ = editor.wait();
+    });
+}
@@ -0,0 +1,297 @@
+// ---------------- EXTERNAL APPS ----------------
+//
+// Programs the user adds to Open With, each with a label, the program, an
+// argument template and maybe a shortcut. They come before the system's
+// applications, in the user's order, and are kept as JSON in the app
+// config folder.
+//
+// The template is split into arguments where there are spaces, quotes
+// ("" or '') keep them together. `{path}` in it is the image, without one
+// the image is the last argument. Backslashes are left alone, they are
+// Windows paths more often than escapes.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::Mutex;
+use tauri::State;
+
+/// Open With ids of external apps are this and the app id.
+pub const ID_PREFIX: &str = "external:";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExternalApp {
+    /// Given when the app is saved the first time.
+    #[serde(default)]
+    pub id: String,
+    pub label: String,
+    /// The program, or its name on the PATH.
+    pub path: String,
+    /// e.g. `--layer {path}`
+    #[serde(default)]
+    pub args: String,
+    /// e.g. `Ctrl+Alt+G`, handled by the window.
+    #[serde(default)]
+    pub shortcut: Option<String>,
+}
+
+pub struct ExternalApps {
+    file: Option<PathBuf>,
+    apps: Vec<ExternalApp>,
+}
+
+pub struct ExternalAppsState(pub Mutex<ExternalApps>);
+
+impl ExternalApps {
+    /// The apps saved in `dir`, none that are ever saved without.
+    pub fn load(dir: Option<&Path>) -> ExternalApps {
+        let file = dir.map(|d| d.join("external_apps.json"));
+        let apps = file
+            .as_ref()
+            .and_then(|f| fs::read(f).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        ExternalApps { file, apps }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec_pretty(&self.apps).map_err(|e| e.to_string())?;
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let tmp = file.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, file).map_err(|e| e.to_string())
+    }
+
+    pub fn list(&self) -> &[ExternalApp] {
+        &self.apps
+    }
+
+    pub fn find(&self, id: &str) -> Option<&ExternalApp> {
+        self.apps.iter().find(|app| app.id == id)
+    }
+}
+
+/// Split an argument template at spaces outside quotes.
+fn split_args(template: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+
+    for c in template.chars() {
+        match (c, quote) {
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (c, None) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (c, _) => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("Missing closing {} in the arguments", q));
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+/// The arguments that open `image` with `app`.
+fn arguments(app: &ExternalApp, image: &str) -> Result<Vec<String>, String> {
+    let mut args = split_args(&app.args)?;
+    if args.iter().any(|a| a.contains("{path}")) {
+        for arg in &mut args {
+            *arg = arg.replace("{path}", image);
+        }
+    } else {
+        args.push(image.to_string());
+    }
+    Ok(args)
+}
+
+/// Open `image` with `app`.
+pub fn launch(app: &ExternalApp, image: &str) -> Result<Child, String> {
+    let mut command = Command::new(&app.path);
+    command.args(arguments(app, image)?);
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    command
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", app.label, e))
+}
+
+/// Tidy `apps` up and give new ones an id. Fails on an app without a
+/// label or program, a template that doesn't parse, or a shortcut used
+/// twice.
+fn check(apps: &mut [ExternalApp]) -> Result<(), String> {
+    let mut shortcuts: HashMap<String, String> = HashMap::new();
+
+    for app in apps.iter_mut() {
+        app.label = app.label.trim().to_string();
+        app.path = app.path.trim().to_string();
+        app.args = app.args.trim().to_string();
+        app.shortcut = app
+            .shortcut
+            .take()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+
+        if app.label.is_empty() {
+            return Err("Every app needs a name".to_string());
+        }
+        if app.path.is_empty() {
+            return Err(format!("{} has no program", app.label));
+        }
+        split_args(&app.args).map_err(|e| format!("{}: {}", app.label, e))?;
+
+        if let Some(shortcut) = &app.shortcut {
+            if let Some(other) = shortcuts.insert(shortcut.clone(), app.label.clone()) {
+                return Err(format!(
+                    "{} is the shortcut of both {} and {}",
+                    shortcut, other, app.label
+                ));
+            }
+        }
+    }
+
+    // numbers no app has, for the new ones
+    let taken: HashSet<String> = apps.iter().map(|a| a.id.clone()).collect();
+    let mut free = (1..)
+        .map(|n: u32| n.to_string())
+        .filter(|id| !taken.contains(id));
+    let mut kept = HashSet::new();
+    for app in apps.iter_mut() {
+        if app.id.is_empty() || !kept.insert(app.id.clone()) {
+            app.id = free.next().unwrap();
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_external_apps(state: State<ExternalAppsState>) -> Vec<ExternalApp> {
+    state.0.lock().unwrap().apps.clone()
+}
+
+/// Replace the external apps with `apps`, in that order. Returns them as
+/// saved, with their ids.
+#[tauri::command]
+pub fn set_external_apps(
+    mut apps: Vec<ExternalApp>,
+    state: State<ExternalAppsState>,
+) -> Result<Vec<ExternalApp>, String> {
+    check(&mut apps)?;
+
+    let mut saved = state.0.lock().unwrap();
+    let previous = std::mem::replace(&mut saved.apps, apps);
+    if let Err(e) = saved.save() {
+        saved.apps = previous;
+        return Err(format!("Failed to save the apps: {}", e));
+    }
+    Ok(saved.apps.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(id: &str, label: &str, args: &str, shortcut: Option<&str>) -> ExternalApp {
+        ExternalApp {
+            id: id.to_string(),
+            label: label.to_string(),
+            path: "gimp".to_string(),
+            args: args.to_string(),
+            shortcut: shortcut.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn splits_quoted_templates() {
+        assert_eq!(
+            split_args(r#"--layer  "my file" '' 'say "hi"' C:\images\{path}"#).unwrap(),
+            ["--layer", "my file", "", r#"say "hi""#, r"C:\images\{path}"]
+        );
+        assert_eq!(split_args("a\"b c\"d").unwrap(), ["ab cd"]);
+        assert!(split_args("   ").unwrap().is_empty());
+        assert_eq!(
+            split_args("--title 'Photo").unwrap_err(),
+            "Missing closing ' in the arguments"
+        );
+    }
+
+    #[test]
+    fn places_the_image() {
+        let args = |template: &str| arguments(&app("1", "GIMP", template, None), "/photos/a b.jpg");
+
+        assert_eq!(
+            args("--layer={path} -n").unwrap(),
+            ["--layer=/photos/a b.jpg", "-n"]
+        );
+        assert_eq!(
+            args("{path} {path}").unwrap(),
+            ["/photos/a b.jpg", "/photos/a b.jpg"]
+        );
+        // without {path} the image goes last
+        assert_eq!(args("-n").unwrap(), ["-n", "/photos/a b.jpg"]);
+        assert_eq!(args("").unwrap(), ["/photos/a b.jpg"]);
+        assert!(args("\"{path}").is_err());
+    }
+
+    #[test]
+    fn checks_apps_and_gives_ids() {
+        let mut apps = vec![
+            app("2", " GIMP ", " -n ", Some(" Ctrl+G ")),
+            app("", "Krita", "", Some("  ")),
+            app("2", "Darktable", "", None),
+            app("", "Inkscape", "", None),
+        ];
+        check(&mut apps).unwrap();
+        assert_eq!(apps[0].label, "GIMP");
+        assert_eq!(apps[0].args, "-n");
+        assert_eq!(apps[0].shortcut.as_deref(), Some("Ctrl+G"));
+        assert_eq!(apps[1].shortcut, None);
+        // saved ids are kept, new and repeated ones get numbers no app has
+        let ids: Vec<&str> = apps.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, ["2", "1", "3", "4"]);
+
+        let mut apps = vec![
+            app("1", "GIMP", "", Some("Ctrl+G")),
+            app("2", "Krita", "", Some("Ctrl+G ")),
+        ];
+        assert_eq!(
+            check(&mut apps).unwrap_err(),
+            "Ctrl+G is the shortcut of both GIMP and Krita"
+        );
+        assert_eq!(
+            check(&mut [app("", " ", "", None)]).unwrap_err(),
+            "Every app needs a name"
+        );
+        assert_eq!(
+            check(&mut [app("", "GIMP", "'{path}", None)]).unwrap_err(),
+            "GIMP: Missing closing ' in the arguments"
+        );
+    }
+}
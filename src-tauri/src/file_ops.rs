@@ -0,0 +1,661 @@
+// ---------------- SAFE FILE OPERATIONS ----------------
+//
+// Renaming and copying that never replace a file by accident. A file
+// already at the destination is a conflict: the caller either gets
+// `FileOpError::Exists` back or picks a policy up front (skip, overwrite,
+// add a number, keep the newer file). A file that is overwritten goes to
+// the trash, and its trash step is returned so that undo can bring it back.
+//
+// Moves are a rename that refuses to replace (on Linux) or go through a
+// hard link, which fails instead of replacing, and copies through a
+// temporary file in the destination folder, so a failed copy never leaves
+// half a file behind. A move to another drive is a copy that is checked
+// before the original goes. XMP sidecars follow their image.
+
+use crate::journal::{self, Operation};
+use crate::ratings;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum FileOpError {
+    /// The destination is taken and the policy doesn't say what to do.
+    Exists(PathBuf),
+    InvalidName(String),
+    NotFound(PathBuf),
+    Io(String),
+}
+
+impl fmt::Display for FileOpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileOpError::Exists(path) => write!(f, "{} already exists", path.display()),
+            FileOpError::InvalidName(reason) => write!(f, "{}", reason),
+            FileOpError::NotFound(path) => write!(f, "{} doesn't exist", path.display()),
+            FileOpError::Io(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl FileOpError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FileOpError::Exists(_) => "exists",
+            FileOpError::InvalidName(_) => "invalid_name",
+            FileOpError::NotFound(_) => "not_found",
+            FileOpError::Io(_) => "io",
+        }
+    }
+}
+
+/// Reaches the frontend as `{ kind, path, message }`.
+impl Serialize for FileOpError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let path = match self {
+            FileOpError::Exists(path) | FileOpError::NotFound(path) => Some(path),
+            _ => None,
+        };
+        let mut s = serializer.serialize_struct("FileOpError", 3)?;
+        s.serialize_field("kind", self.kind())?;
+        s.serialize_field("path", &path.map(|p| p.to_string_lossy()))?;
+        s.serialize_field("message", &self.to_string())?;
+        s.end()
+    }
+}
+
+fn io_error(action: &str, path: &Path, e: io::Error) -> FileOpError {
+    FileOpError::Io(format!("Failed to {} {}: {}", action, path.display(), e))
+}
+
+/// What to do when the destination already exists.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Return `FileOpError::Exists`.
+    #[default]
+    Fail,
+    Skip,
+    Overwrite,
+    /// "name (2).ext", "name (3).ext", ...
+    AutoSuffix,
+    /// Overwrite only if the source was modified later.
+    KeepNewer,
+}
+
+// ----- Names ------
+
+const WINDOWS_FORBIDDEN: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+const WINDOWS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// A file name, extension included, that this platform accepts.
+pub fn check_file_name(name: &str) -> Result<(), FileOpError> {
+    let invalid = |reason: String| Err(FileOpError::InvalidName(reason));
+
+    if name.trim().is_empty() || name == "." || name == ".." {
+        return invalid("The name is empty".to_string());
+    }
+    if name.len() > 255 {
+        return invalid("The name is longer than 255 bytes".to_string());
+    }
+    if name.contains('/') || name.contains('\0') {
+        return invalid(format!(
+            "The name contains {:?}",
+            if name.contains('/') { '/' } else { '\0' }
+        ));
+    }
+
+    if cfg!(windows) {
+        if let Some(c) = name
+            .chars()
+            .find(|c| WINDOWS_FORBIDDEN.contains(c) || c.is_control())
+        {
+            return invalid(format!("The name contains {:?}", c));
+        }
+        if name.ends_with('.') || name.ends_with(' ') {
+            return invalid("The name ends with a dot or a space".to_string());
+        }
+        // "nul.txt" is as reserved as "nul"
+        let stem = name.split('.').next().unwrap_or("").trim_end();
+        if WINDOWS_RESERVED
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(stem))
+        {
+            return invalid(format!("{} is a reserved name", stem));
+        }
+    } else if cfg!(target_os = "macos") && name.contains(':') {
+        return invalid("The name contains ':'".to_string());
+    }
+    Ok(())
+}
+
+/// `new_name` in the folder of `path`. The extension of `path` is added
+/// unless `new_name` already ends with it.
+pub fn renamed_path(path: &Path, new_name: &str) -> Result<PathBuf, FileOpError> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| FileOpError::InvalidName("Invalid path".to_string()))?;
+    let new_name = new_name.trim();
+    check_file_name(new_name)?;
+
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext)
+            if !new_name
+                .to_lowercase()
+                .ends_with(&format!(".{}", ext.to_lowercase())) =>
+        {
+            format!("{}.{}", new_name, ext)
+        }
+        _ => new_name.to_string(),
+    };
+    check_file_name(&name)?;
+    Ok(parent.join(name))
+}
+
+// ----- Conflicts ------
+
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// `path` with " (n)" added to its name, the first n that is free.
+fn free_name(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path.extension().map(|e| e.to_string_lossy().to_string());
+
+    (2..)
+        .map(|n| {
+            let name = match &ext {
+                Some(ext) => format!("{} ({}).{}", stem, n, ext),
+                None => format!("{} ({})", stem, n),
+            };
+            path.with_file_name(name)
+        })
+        .find(|p| fs::symlink_metadata(p).is_err())
+        .unwrap()
+}
+
+enum Target {
+    Skip,
+    /// Nothing there, or only `source` itself under another case.
+    Free(PathBuf),
+    Replace(PathBuf),
+}
+
+fn resolve(source: &Path, dest: &Path, policy: ConflictPolicy) -> Result<Target, FileOpError> {
+    if fs::symlink_metadata(dest).is_err() {
+        return Ok(Target::Free(dest.to_path_buf()));
+    }
+
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    Ok(match policy {
+        ConflictPolicy::Fail => return Err(FileOpError::Exists(dest.to_path_buf())),
+        ConflictPolicy::Skip => Target::Skip,
+        ConflictPolicy::Overwrite => Target::Replace(dest.to_path_buf()),
+        ConflictPolicy::AutoSuffix => Target::Free(free_name(dest)),
+        ConflictPolicy::KeepNewer => match (modified(source), modified(dest)) {
+            (Some(s), Some(d)) if s > d => Target::Replace(dest.to_path_buf()),
+            _ => Target::Skip,
+        },
+    })
+}
+
+// ----- Moving and copying ------
+
+/// Move `from` to `to`, failing with `Exists` rather than replacing a file.
+/// A case-only rename of the same file is allowed.
+pub fn move_no_replace(from: &Path, to: &Path) -> Result<(), FileOpError> {
+    if same_file(from, to) {
+        return fs::rename(from, to).map_err(|e| io_error("rename", from, e));
+    }
+
+    #[cfg(target_os = "linux")]
+    match rename_no_replace(from, to) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(FileOpError::Exists(to.to_path_buf()))
+        }
+        // the kernel or the file system doesn't take the flag
+        Err(e) if matches!(e.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) => {}
+        Err(e) => return Err(io_error("rename", from, e)),
+    }
+
+    match fs::hard_link(from, to) {
+        Ok(()) => fs::remove_file(from).map_err(|e| {
+            let _ = fs::remove_file(to);
+            io_error("rename", from, e)
+        }),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            Err(FileOpError::Exists(to.to_path_buf()))
+        }
+        Err(_) => move_over_placeholder(from, to),
+    }
+}
+
+/// An atomic rename that fails with `AlreadyExists` instead of replacing.
+#[cfg(target_os = "linux")]
+fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = |p: &Path| {
+        CString::new(p.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    };
+    let (from, to) = (path(from)?, path(to)?);
+    // SAFETY: both paths are NUL terminated and live across the call
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            from.as_ptr(),
+            libc::AT_FDCWD,
+            to.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// The move for file systems without hard links (FAT, exFAT, many network
+/// shares), where a plain rename would replace a file. The name is taken
+/// first with a new empty file, which fails if it exists, and `from` is
+/// renamed over that. A file that replaces the placeholder in between
+/// would still be lost, so the placeholder is checked right before the
+/// rename and the move stops if it has changed.
+fn move_over_placeholder(from: &Path, to: &Path) -> Result<(), FileOpError> {
+    let placeholder = match fs::OpenOptions::new().write(true).create_new(true).open(to) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(FileOpError::Exists(to.to_path_buf()))
+        }
+        Err(e) => return Err(io_error("create", to, e)),
+    };
+    let created = placeholder.metadata().and_then(|m| m.modified()).ok();
+    drop(placeholder);
+
+    let unchanged = fs::symlink_metadata(to)
+        .map(|m| m.is_file() && m.len() == 0 && m.modified().ok() == created)
+        .unwrap_or(false);
+    if !unchanged {
+        return Err(FileOpError::Exists(to.to_path_buf()));
+    }
+    fs::rename(from, to).map_err(|e| {
+        let _ = fs::remove_file(to);
+        io_error("rename", from, e)
+    })
+}
+
+/// Where a renamed, moved or copied file ended up.
+pub struct Placed {
+    pub path: PathBuf,
+    /// Trash steps of the files it and its sidecars replaced.
+    pub replaced: Vec<Operation>,
+    /// Sidecars that didn't come along, and why.
+    pub warnings: Vec<String>,
+}
+
+impl Placed {
+    fn at(path: &Path, replaced: Vec<Operation>) -> Placed {
+        Placed {
+            path: path.to_path_buf(),
+            replaced,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// Where a copied or moved file is now, for the frontend. `warning` names
+/// the sidecars that were left behind.
+#[derive(Serialize)]
+pub struct Sent {
+    pub path: String,
+    pub warning: Option<String>,
+}
+
+impl From<&Placed> for Sent {
+    fn from(placed: &Placed) -> Sent {
+        Sent {
+            path: placed.path.to_string_lossy().to_string(),
+            warning: match placed.warnings.is_empty() {
+                true => None,
+                false => Some(placed.warnings.join("; ")),
+            },
+        }
+    }
+}
+
+/// Put `from` at `target`, as resolved. Returns where it ended up and the
+/// trash step of the file it replaced.
+fn place(from: &Path, target: &Target) -> Result<Option<Placed>, FileOpError> {
+    let to = match target {
+        Target::Skip => return Ok(None),
+        Target::Free(to) => {
+            move_no_replace(from, to)?;
+            return Ok(Some(Placed::at(to, Vec::new())));
+        }
+        Target::Replace(to) => to,
+    };
+
+    let replaced = match to.exists() {
+        true => vec![journal::trash(to).map_err(FileOpError::Io)?],
+        false => Vec::new(),
+    };
+    if let Err(e) = fs::rename(from, to) {
+        for op in &replaced {
+            let _ = journal::restore(op);
+        }
+        return Err(io_error("replace", to, e));
+    }
+    Ok(Some(Placed::at(to, replaced)))
+}
+
+/// The sidecars of `from` that exist, paired with those of `to`.
+fn sidecar_moves(from: &Path, to: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let (from, to) = (from.to_string_lossy(), to.to_string_lossy());
+    ratings::sidecar_paths(&from)
+        .into_iter()
+        .zip(ratings::sidecar_paths(&to))
+        .filter(|(old, _)| old.is_file())
+        .collect()
+}
+
+/// Rename `from` to `to`. Returns the new path, `None` if skipped.
+pub fn rename_path(
+    from: &Path,
+    to: &Path,
+    policy: ConflictPolicy,
+) -> Result<Option<Placed>, FileOpError> {
+    if !from.exists() {
+        return Err(FileOpError::NotFound(from.to_path_buf()));
+    }
+    if from == to {
+        return Ok(Some(Placed::at(to, Vec::new())));
+    }
+
+    // a case-only rename finds the file itself at `to`
+    let target = match same_file(from, to) {
+        true => Target::Free(to.to_path_buf()),
+        false => resolve(from, to, policy)?,
+    };
+    let Some(mut placed) = place(from, &target)? else {
+        return Ok(None);
+    };
+
+    let replace = matches!(target, Target::Replace(_));
+    let mut done = Vec::new();
+    for (old, new) in sidecar_moves(from, &placed.path) {
+        let target = match replace {
+            true => Target::Replace(new.clone()),
+            false => Target::Free(new.clone()),
+        };
+        match place(&old, &target) {
+            Ok(moved) => placed
+                .replaced
+                .extend(moved.into_iter().flat_map(|m| m.replaced)),
+            Err(e) => {
+                // back to how it was, the image and what it replaced included
+                for (old, new) in done.iter().rev() {
+                    let _ = fs::rename(new, old);
+                }
+                let _ = fs::rename(&placed.path, from);
+                for op in placed.replaced.iter().rev() {
+                    let _ = journal::restore(op);
+                }
+                return Err(e);
+            }
+        }
+        done.push((old, new));
+    }
+    Ok(Some(placed))
+}
+
+/// Copy `from` to `to`, through a temporary file next to `to`. With
+/// `verify` the copy is compared with `from` before it takes its place.
+fn copy_one(from: &Path, target: &Target, verify: bool) -> Result<Option<Placed>, FileOpError> {
+    let to = match target {
+        Target::Skip => return Ok(None),
+        Target::Free(to) | Target::Replace(to) => to,
+    };
+    let tmp = to.with_file_name(format!(
+        ".{}.{}.copy",
+        to.file_name().unwrap_or_default().to_string_lossy(),
+        std::process::id()
+    ));
+
+    fs::copy(from, &tmp).map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        io_error("copy", from, e)
+    })?;
+    if verify && !same_content(from, &tmp) {
+        let _ = fs::remove_file(&tmp);
+        return Err(FileOpError::Io(format!(
+            "The copy of {} differs from the original, it was not moved",
+            from.display()
+        )));
+    }
+    place(&tmp, target).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// Copy `from` to `to`. Returns the path of the copy, `None` if skipped.
+pub fn copy_path(
+    from: &Path,
+    to: &Path,
+    policy: ConflictPolicy,
+) -> Result<Option<Placed>, FileOpError> {
+    copy_checked(from, to, policy, false)
+}
+
+fn copy_checked(
+    from: &Path,
+    to: &Path,
+    policy: ConflictPolicy,
+    verify: bool,
+) -> Result<Option<Placed>, FileOpError> {
+    if !from.is_file() {
+        return Err(FileOpError::NotFound(from.to_path_buf()));
+    }
+
+    // a file is never copied onto itself, whatever the policy
+    let target = match resolve(from, to, policy)? {
+        Target::Replace(to) if same_file(from, &to) => Target::Skip,
+        target => target,
+    };
+    let Some(mut placed) = copy_one(from, &target, verify)? else {
+        return Ok(None);
+    };
+
+    let replace = matches!(target, Target::Replace(_));
+    for (old, new) in sidecar_moves(from, &placed.path) {
+        let target = match replace {
+            true => Target::Replace(new),
+            false => Target::Free(new),
+        };
+        match copy_one(&old, &target, verify) {
+            Ok(copied) => placed
+                .replaced
+                .extend(copied.into_iter().flat_map(|c| c.replaced)),
+            Err(e) => placed.warnings.push(format!("Sidecar not copied: {}", e)),
+        }
+    }
+    Ok(Some(placed))
+}
+
+/// Whether `path` and the folder `dir` are on one drive, where a rename
+/// can move between them.
+fn same_device(path: &Path, dir: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (fs::metadata(path), fs::metadata(dir)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        // the drive letter or network share
+        let root = |p: &Path| {
+            fs::canonicalize(p).ok().and_then(|p| {
+                p.components()
+                    .next()
+                    .map(|c| c.as_os_str().to_ascii_lowercase())
+            })
+        };
+        matches!((root(path), root(dir)), (Some(a), Some(b)) if a == b)
+    }
+}
+
+/// Whether the files at `a` and `b` hold the same bytes, read side by side.
+fn same_content(a: &Path, b: &Path) -> bool {
+    let open = |p: &Path| fs::File::open(p).map(io::BufReader::new);
+    let (Ok(mut a), Ok(mut b)) = (open(a), open(b)) else {
+        return false;
+    };
+    let len = |f: &io::BufReader<fs::File>| f.get_ref().metadata().map(|m| m.len()).ok();
+    if len(&a).is_none() || len(&a) != len(&b) {
+        return false;
+    }
+
+    loop {
+        let (Ok(da), Ok(db)) = (a.fill_buf(), b.fill_buf()) else {
+            return false;
+        };
+        let n = da.len().min(db.len());
+        if n == 0 {
+            return da.is_empty() && db.is_empty();
+        }
+        if da[..n] != db[..n] {
+            return false;
+        }
+        a.consume(n);
+        b.consume(n);
+    }
+}
+
+/// Move `from` to `to`, on this drive or another. Across drives the file
+/// is copied, the copy compared with the original before it takes the
+/// place of anything, and only then the original deleted. Returns the new
+/// path, `None` if skipped.
+pub fn move_path(
+    from: &Path,
+    to: &Path,
+    policy: ConflictPolicy,
+) -> Result<Option<Placed>, FileOpError> {
+    let dir = to.parent().unwrap_or(Path::new(""));
+    if !dir.is_dir() {
+        return Err(FileOpError::NotFound(dir.to_path_buf()));
+    }
+    if same_device(from, dir) {
+        return rename_path(from, to, policy);
+    }
+
+    let Some(copy) = copy_checked(from, to, policy, true)? else {
+        return Ok(None);
+    };
+
+    fs::remove_file(from).map_err(|e| io_error("delete", from, e))?;
+    for (old, new) in sidecar_moves(from, &copy.path) {
+        if same_content(&old, &new) {
+            let _ = fs::remove_file(&old);
+        }
+    }
+    Ok(Some(copy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh folder for one test.
+    fn folder(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file-ops-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn moves_without_replacing() {
+        let dir = folder("move");
+        fs::write(dir.join("a.jpg"), "a").unwrap();
+        fs::write(dir.join("b.jpg"), "b").unwrap();
+
+        move_no_replace(&dir.join("a.jpg"), &dir.join("c.jpg")).unwrap();
+        assert!(!dir.join("a.jpg").exists());
+        assert!(matches!(
+            move_no_replace(&dir.join("c.jpg"), &dir.join("b.jpg")),
+            Err(FileOpError::Exists(_))
+        ));
+        assert!(matches!(
+            move_over_placeholder(&dir.join("c.jpg"), &dir.join("b.jpg")),
+            Err(FileOpError::Exists(_))
+        ));
+        assert_eq!(fs::read_to_string(dir.join("b.jpg")).unwrap(), "b");
+        assert_eq!(fs::read_to_string(dir.join("c.jpg")).unwrap(), "a");
+
+        move_over_placeholder(&dir.join("c.jpg"), &dir.join("d.jpg")).unwrap();
+        assert!(!dir.join("c.jpg").exists());
+        assert_eq!(fs::read_to_string(dir.join("d.jpg")).unwrap(), "a");
+        // a failed rename takes the placeholder away again
+        assert!(move_over_placeholder(&dir.join("c.jpg"), &dir.join("e.jpg")).is_err());
+        assert!(!dir.join("e.jpg").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compares_content() {
+        let dir = folder("content");
+        let big: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        let mut other = big.clone();
+        other[99_999] ^= 1;
+
+        fs::write(dir.join("a"), &big).unwrap();
+        fs::write(dir.join("b"), &big).unwrap();
+        fs::write(dir.join("c"), &other).unwrap();
+        fs::write(dir.join("d"), &big[..50_000]).unwrap();
+
+        assert!(same_content(&dir.join("a"), &dir.join("b")));
+        assert!(!same_content(&dir.join("a"), &dir.join("c")));
+        assert!(!same_content(&dir.join("a"), &dir.join("d")));
+        assert!(!same_content(&dir.join("a"), &dir.join("missing")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checks_file_names() {
+        for name in ["photo.jpg", "Ünïcode ✓.png", ".hidden", "a..b"] {
+            assert!(check_file_name(name).is_ok(), "{}", name);
+        }
+        let long = "a".repeat(256);
+        for name in ["", "  ", ".", "..", "a/b", "a\0b", long.as_str()] {
+            assert!(check_file_name(name).is_err(), "{:?}", name);
+        }
+
+        for name in ["a:b", "a?b", "what.", "nul.txt", "COM1", "con .jpg"] {
+            assert_eq!(check_file_name(name).is_err(), cfg!(windows), "{}", name);
+        }
+        assert_eq!(
+            check_file_name("a:b").is_err(),
+            cfg!(any(windows, target_os = "macos"))
+        );
+        assert!(check_file_name("console.jpg").is_ok());
+    }
+}
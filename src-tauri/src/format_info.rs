@@ -0,0 +1,491 @@
+// ---------------- FORMAT DETAILS ----------------
+//
+// How the pixels are stored in the file, read from the format headers:
+// the decoders only tell what they turn the data into (a 1-bit palette PNG
+// and a CMYK JPEG both come out as 8-bit RGB(A)).
+
+use crate::metadata::{self, Tiff, PNG_SIGNATURE};
+use image::ColorType;
+use std::fs;
+use std::io::Read;
+
+#[derive(Default)]
+pub struct FormatDetails {
+    /// Color model in the file: "YCbCr", "CMYK", "Indexed", "Grayscale"...
+    pub color_model: Option<String>,
+    pub channels: Option<u8>,
+    pub bits_per_channel: Option<u8>,
+    /// Chroma subsampling of YCbCr data, e.g. "4:2:0".
+    pub subsampling: Option<String>,
+    /// PNG Adam7 or GIF interlacing.
+    pub interlaced: bool,
+    /// Progressive JPEG.
+    pub progressive: bool,
+    pub palette_size: Option<u32>,
+    /// Estimated from the quantization tables, 1-100.
+    pub jpeg_quality: Option<u8>,
+}
+
+/// Readable name of what the decoder produces.
+pub fn color_type_name(color: ColorType) -> &'static str {
+    match color {
+        ColorType::L8 | ColorType::L16 => "Grayscale",
+        ColorType::La8 | ColorType::La16 => "Grayscale + Alpha",
+        ColorType::Rgb8 | ColorType::Rgb16 => "RGB",
+        ColorType::Rgba8 | ColorType::Rgba16 => "RGBA",
+        ColorType::Rgb32F => "RGB (float)",
+        ColorType::Rgba32F => "RGBA (float)",
+        _ => "Unknown",
+    }
+}
+
+/// Details from the file header, empty for formats that aren't parsed here.
+pub fn read(path: &str) -> FormatDetails {
+    const HEAD: u64 = 256 * 1024;
+
+    let mut head = Vec::new();
+    if fs::File::open(path)
+        .and_then(|f| f.take(HEAD).read_to_end(&mut head))
+        .is_err()
+    {
+        return FormatDetails::default();
+    }
+
+    // The headers are normally at the start, large metadata blocks or a
+    // TIFF directory at the end of the file need the whole thing
+    match parse(&head) {
+        Some(details) => details,
+        None if head.len() as u64 == HEAD => fs::read(path)
+            .ok()
+            .and_then(|data| parse(&data))
+            .unwrap_or_default(),
+        None => FormatDetails::default(),
+    }
+}
+
+fn parse(data: &[u8]) -> Option<FormatDetails> {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        jpeg(data)
+    } else if data.starts_with(PNG_SIGNATURE) {
+        png(data)
+    } else if data.starts_with(b"GIF8") {
+        gif(data)
+    } else if data.len() > 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        webp(data)
+    } else if data.starts_with(b"BM") {
+        bmp(data)
+    } else if Tiff::new(data).is_some() {
+        tiff(data)
+    } else {
+        Some(FormatDetails::default())
+    }
+}
+
+// ----- JPEG ------
+
+/// Zigzag position to natural (row-major) position.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Luminance table from Annex K of the JPEG standard, natural order. This
+/// is what libjpeg scales by the quality setting.
+const STD_LUMINANCE: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// The luminance table libjpeg writes for `quality`, in zigzag order as
+/// it is stored.
+fn libjpeg_table(quality: u8) -> [u16; 64] {
+    let quality = quality.clamp(1, 100) as u32;
+    let scale = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    };
+
+    let mut table = [0u16; 64];
+    for (q, &n) in table.iter_mut().zip(ZIGZAG.iter()) {
+        *q = ((STD_LUMINANCE[n] as u32 * scale + 50) / 100).clamp(1, 255) as u16;
+    }
+    table
+}
+
+/// The libjpeg quality whose table comes closest to `table`: exact for
+/// files written by libjpeg and a fair estimate for other encoders.
+fn estimate_quality(table: &[u16; 64]) -> u8 {
+    let distance = |quality: u8| -> u32 {
+        libjpeg_table(quality)
+            .iter()
+            .zip(table)
+            .map(|(&a, &b)| (a as i32 - b as i32).unsigned_abs())
+            .sum()
+    };
+    (1..=100).min_by_key(|&q| distance(q)).unwrap_or(100)
+}
+
+/// Table 0 from the DQT segments, which encoders use for luminance.
+fn luminance_table(body: &[u8]) -> Option<[u16; 64]> {
+    let mut pos = 0;
+
+    while pos < body.len() {
+        let precision = body[pos] >> 4;
+        let id = body[pos] & 0x0F;
+        let size = if precision == 0 { 64 } else { 128 };
+        let values = body.get(pos + 1..pos + 1 + size)?;
+
+        if id == 0 {
+            let mut table = [0u16; 64];
+            for (i, q) in table.iter_mut().enumerate() {
+                *q = if precision == 0 {
+                    values[i] as u16
+                } else {
+                    u16::from_be_bytes([values[i * 2], values[i * 2 + 1]])
+                };
+            }
+            return Some(table);
+        }
+        pos += 1 + size;
+    }
+    None
+}
+
+fn subsampling_name(h: u8, v: u8) -> String {
+    match (h, v) {
+        (1, 1) => "4:4:4".to_string(),
+        (2, 1) => "4:2:2".to_string(),
+        (2, 2) => "4:2:0".to_string(),
+        (1, 2) => "4:4:0".to_string(),
+        (4, 1) => "4:1:1".to_string(),
+        (4, 2) => "4:1:0".to_string(),
+        _ => format!("{}×{}", h, v),
+    }
+}
+
+fn jpeg(data: &[u8]) -> Option<FormatDetails> {
+    let mut details = FormatDetails::default();
+    let mut adobe_transform = None;
+    let mut jfif = false;
+    let mut frame = None;
+
+    for seg in metadata::jpeg_segments(data).0 {
+        let body = &data[seg.body];
+        match seg.kind[0] {
+            0xE0 if body.starts_with(b"JFIF\0") => jfif = true,
+            0xEE if body.starts_with(b"Adobe") => adobe_transform = body.get(11).copied(),
+            0xDB if details.jpeg_quality.is_none() => {
+                details.jpeg_quality = luminance_table(body).map(|t| estimate_quality(&t));
+            }
+            // Start of frame, except DHT (C4), JPG (C8) and DAC (CC)
+            marker @ (0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF) => {
+                details.progressive = matches!(marker, 0xC2 | 0xC6 | 0xCA | 0xCE);
+                frame = Some(body);
+            }
+            _ => {}
+        }
+    }
+
+    // Without a frame header the segments were cut off
+    let frame = frame?;
+    let components = *frame.get(5)? as usize;
+    let sampling: Vec<(u8, u8, u8)> = (0..components)
+        .filter_map(|i| {
+            let c = frame.get(6 + i * 3..9 + i * 3)?;
+            Some((c[0], c[1] >> 4, c[1] & 0x0F))
+        })
+        .collect();
+
+    details.bits_per_channel = Some(frame[0]);
+    details.channels = Some(components as u8);
+
+    let rgb_ids = sampling.iter().map(|c| c.0).eq(*b"RGB");
+    let model = match components {
+        1 => "Grayscale",
+        3 if adobe_transform == Some(0) || (!jfif && adobe_transform.is_none() && rgb_ids) => "RGB",
+        3 => "YCbCr",
+        4 if adobe_transform == Some(2) => "YCCK",
+        4 => "CMYK",
+        _ => "Unknown",
+    };
+    details.color_model = Some(model.to_string());
+
+    if matches!(model, "YCbCr" | "YCCK") && sampling.len() >= 3 {
+        let (_, yh, yv) = sampling[0];
+        let (_, ch, cv) = sampling[1];
+        if ch > 0 && cv > 0 {
+            details.subsampling = Some(subsampling_name(yh / ch, yv / cv));
+        }
+    }
+
+    Some(details)
+}
+
+// ----- PNG ------
+
+fn png(data: &[u8]) -> Option<FormatDetails> {
+    let mut details = FormatDetails::default();
+    let chunks = metadata::png_chunks(data);
+
+    let ihdr = chunks.iter().find(|c| &c.kind == b"IHDR")?;
+    let ihdr = data.get(ihdr.body.clone())?;
+    let bits = *ihdr.get(8)?;
+    let color_type = *ihdr.get(9)?;
+
+    let (model, channels) = match color_type {
+        0 => ("Grayscale", 1),
+        2 => ("RGB", 3),
+        3 => ("Indexed", 1),
+        4 => ("Grayscale + Alpha", 2),
+        6 => ("RGBA", 4),
+        _ => ("Unknown", 0),
+    };
+    details.color_model = Some(model.to_string());
+    details.channels = Some(channels);
+    details.bits_per_channel = Some(bits);
+    details.interlaced = ihdr.get(12) == Some(&1);
+
+    if let Some(plte) = chunks.iter().find(|c| &c.kind == b"PLTE") {
+        details.palette_size = Some(plte.body.len() as u32 / 3);
+    }
+    Some(details)
+}
+
+// ----- GIF ------
+
+/// Skip a chain of data sub-blocks, returning the position after it.
+fn skip_sub_blocks(data: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *data.get(pos)? as usize;
+        pos += 1 + len;
+        if len == 0 {
+            return Some(pos);
+        }
+    }
+}
+
+fn gif(data: &[u8]) -> Option<FormatDetails> {
+    let mut details = FormatDetails {
+        color_model: Some("Indexed".to_string()),
+        channels: Some(1),
+        bits_per_channel: Some(8),
+        ..Default::default()
+    };
+
+    let packed = *data.get(10)?;
+    let mut pos = 13;
+    if packed & 0x80 != 0 {
+        let size = 2u32 << (packed & 0x07);
+        details.palette_size = Some(size);
+        pos += size as usize * 3;
+    }
+
+    // First image descriptor, past any extensions
+    loop {
+        match *data.get(pos)? {
+            0x21 => pos = skip_sub_blocks(data, pos + 2)?,
+            0x2C => {
+                let flags = *data.get(pos + 9)?;
+                details.interlaced = flags & 0x40 != 0;
+                if flags & 0x80 != 0 {
+                    details.palette_size = Some(2 << (flags & 0x07));
+                }
+                break;
+            }
+            _ => break,
+        }
+    }
+
+    Some(details)
+}
+
+// ----- WEBP ------
+
+fn webp(data: &[u8]) -> Option<FormatDetails> {
+    let mut details = FormatDetails::default();
+    let mut alpha = false;
+    let mut pos = 12;
+
+    // Chunk headers only, the image data itself may be cut off
+    while pos + 8 <= data.len() {
+        let kind = &data[pos..pos + 4];
+        let len = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+
+        match kind {
+            b"VP8X" => alpha = data.get(pos + 8).is_some_and(|f| f & 0x10 != 0),
+            b"ALPH" => alpha = true,
+            b"VP8 " => {
+                details.color_model = Some("YCbCr".to_string());
+                details.subsampling = Some("4:2:0".to_string());
+                details.channels = Some(if alpha { 4 } else { 3 });
+                break;
+            }
+            b"VP8L" => {
+                // Lossless stores ARGB, the alpha hint is bit 28 of the header
+                let header = data.get(pos + 9..pos + 13)?;
+                let has_alpha = u32::from_le_bytes(header.try_into().unwrap()) & (1 << 28) != 0;
+                details.color_model = Some(if has_alpha { "RGBA" } else { "RGB" }.to_string());
+                details.channels = Some(if has_alpha { 4 } else { 3 });
+                break;
+            }
+            // Animation frames carry their own VP8/VP8L chunk
+            b"ANMF" => {
+                pos += 8 + 16;
+                continue;
+            }
+            _ => {}
+        }
+        pos += 8 + len + (len & 1);
+    }
+
+    details.color_model.as_ref()?;
+    details.bits_per_channel = Some(8);
+    Some(details)
+}
+
+// ----- BMP ------
+
+fn bmp(data: &[u8]) -> Option<FormatDetails> {
+    let le16 = |pos: usize| Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?));
+    let le32 = |pos: usize| Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?));
+
+    let header_size = le32(14)?;
+    let (bpp, colors_used, alpha_mask) = if header_size == 12 {
+        (le16(24)?, 0, 0)
+    } else {
+        let alpha_mask = if header_size >= 56 { le32(66)? } else { 0 };
+        (le16(28)?, le32(46)?, alpha_mask)
+    };
+
+    let mut details = FormatDetails::default();
+    match bpp {
+        1 | 2 | 4 | 8 => {
+            details.color_model = Some("Indexed".to_string());
+            details.channels = Some(1);
+            details.bits_per_channel = Some(bpp as u8);
+            details.palette_size = Some(if colors_used > 0 {
+                colors_used
+            } else {
+                1 << bpp
+            });
+        }
+        16 => {
+            details.color_model = Some("RGB".to_string());
+            details.channels = Some(3);
+            details.bits_per_channel = Some(5);
+        }
+        24 | 32 => {
+            let alpha = bpp == 32 && alpha_mask != 0;
+            details.color_model = Some(if alpha { "RGBA" } else { "RGB" }.to_string());
+            details.channels = Some(if alpha { 4 } else { 3 });
+            details.bits_per_channel = Some(8);
+        }
+        _ => {}
+    }
+    Some(details)
+}
+
+// ----- TIFF ------
+
+const TIFF_BITS_PER_SAMPLE: u16 = 0x0102;
+const TIFF_PHOTOMETRIC: u16 = 0x0106;
+const TIFF_SAMPLES_PER_PIXEL: u16 = 0x0115;
+const TIFF_COLOR_MAP: u16 = 0x0140;
+const TIFF_EXTRA_SAMPLES: u16 = 0x0152;
+const TIFF_YCBCR_SUBSAMPLING: u16 = 0x0212;
+
+fn tiff(data: &[u8]) -> Option<FormatDetails> {
+    let tiff = Tiff::new(data)?;
+    let ifd = tiff.read_ifd(tiff.first_ifd()?)?;
+    let find = |tag: u16| ifd.entries.iter().find(|e| e.tag == tag);
+    let value = |tag: u16| find(tag).and_then(|e| tiff.value_u32(e));
+
+    let mut details = FormatDetails::default();
+    let photometric = value(TIFF_PHOTOMETRIC);
+
+    let model = match photometric {
+        Some(0) => "Grayscale (white is zero)",
+        Some(1) => "Grayscale",
+        Some(2) => "RGB",
+        Some(3) => "Indexed",
+        Some(4) => "Transparency mask",
+        Some(5) => "CMYK",
+        Some(6) => "YCbCr",
+        Some(8) | Some(9) | Some(10) => "CIELab",
+        Some(32803) => "Color filter array",
+        Some(32844) | Some(32845) => "LogLuv",
+        Some(34892) => "Linear raw",
+        _ => "Unknown",
+    };
+
+    let samples = value(TIFF_SAMPLES_PER_PIXEL).unwrap_or(1);
+    let color_samples = match photometric {
+        Some(2) | Some(6) | Some(8) | Some(9) | Some(10) => 3,
+        Some(5) => 4,
+        _ => 1,
+    };
+
+    // Associated (1) or unassociated (2) alpha, 0 is some other extra data.
+    // Some writers leave the tag out, the decoders then take it as alpha.
+    let alpha = match value(TIFF_EXTRA_SAMPLES) {
+        Some(extra) => extra == 1 || extra == 2,
+        None => samples > color_samples,
+    };
+    details.color_model = Some(match (model, alpha) {
+        ("RGB", true) => "RGBA".to_string(),
+        (model, true) => format!("{} + Alpha", model),
+        (model, false) => model.to_string(),
+    });
+    details.channels = Some(samples as u8);
+    details.bits_per_channel = Some(value(TIFF_BITS_PER_SAMPLE).unwrap_or(1) as u8);
+
+    if photometric == Some(3) {
+        // Three entries (R, G, B) per color
+        details.palette_size = find(TIFF_COLOR_MAP).map(|e| e.count / 3);
+    }
+
+    if photometric == Some(6) {
+        // Defaults to 2×2 when the tag is missing
+        let (h, v) = find(TIFF_YCBCR_SUBSAMPLING)
+            .and_then(|e| {
+                let pos = tiff.value_pos(e)?;
+                Some((tiff.u16_at(pos)?, tiff.u16_at(pos + 2)?))
+            })
+            .unwrap_or((2, 2));
+        details.subsampling = Some(subsampling_name(h as u8, v as u8));
+    }
+
+    Some(details)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn libjpeg_qualities_are_estimated_exactly() {
+        for quality in 1..=100 {
+            assert_eq!(estimate_quality(&libjpeg_table(quality)), quality);
+        }
+        // another encoder's tables land on the closest one
+        let mut table = libjpeg_table(80);
+        table[63] += 3;
+        assert_eq!(estimate_quality(&table), 80);
+    }
+
+    #[test]
+    fn reads_the_quality_of_an_encoded_jpeg() {
+        let img =
+            image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8 * 4, y as u8 * 4, 0]));
+        let mut data = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut data, 85)
+            .encode_image(&img)
+            .unwrap();
+
+        let details = parse(&data).unwrap();
+        assert_eq!(details.jpeg_quality, Some(85));
+    }
+}
@@ -0,0 +1,213 @@
+// ---------------- GEOTAGS ----------------
+//
+// GPS position from the EXIF GPS IFD, converted to decimal degrees
+// (south and west negative), and a folder scan for the map view.
+
+use crate::metadata::{self, Tiff, TAG_GPS_IFD};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fs;
+
+#[derive(Serialize, Clone, Copy)]
+pub struct GpsPosition {
+    latitude: f64,
+    longitude: f64,
+    /// Meters, negative below sea level.
+    altitude: Option<f64>,
+    /// Degrees from north the camera was facing.
+    direction: Option<f64>,
+    /// True for magnetic north, false for true north.
+    direction_magnetic: bool,
+}
+
+#[derive(Serialize)]
+pub struct GeotaggedImage {
+    path: String,
+    position: GpsPosition,
+}
+
+const GPS_LATITUDE_REF: u16 = 0x0001;
+const GPS_LATITUDE: u16 = 0x0002;
+const GPS_LONGITUDE_REF: u16 = 0x0003;
+const GPS_LONGITUDE: u16 = 0x0004;
+const GPS_ALTITUDE_REF: u16 = 0x0005;
+const GPS_ALTITUDE: u16 = 0x0006;
+const GPS_IMG_DIRECTION_REF: u16 = 0x0010;
+const GPS_IMG_DIRECTION: u16 = 0x0011;
+
+/// Degrees, minutes and seconds as three rationals.
+fn dms(tiff: &Tiff, pos: usize, count: u32) -> Option<f64> {
+    let part = |i: u32| {
+        if i < count {
+            tiff.rational_at(pos + i as usize * 8, false)
+        } else {
+            Some(0.0)
+        }
+    };
+    let value = part(0)? + part(1)? / 60.0 + part(2)? / 3600.0;
+    value.is_finite().then_some(value)
+}
+
+pub fn read_position(exif: &[u8]) -> Option<GpsPosition> {
+    let tiff = Tiff::new(exif)?;
+    let ifd0 = tiff.read_ifd(tiff.first_ifd()?)?;
+    let pointer = ifd0.entries.iter().find(|e| e.tag == TAG_GPS_IFD)?;
+    let gps = tiff.read_ifd(tiff.value_u32(pointer)? as usize)?;
+
+    let mut latitude = None;
+    let mut longitude = None;
+    let mut lat_ref = b'N';
+    let mut lon_ref = b'E';
+    let mut altitude = None;
+    let mut below_sea_level = false;
+    let mut direction = None;
+    let mut direction_magnetic = false;
+
+    for entry in &gps.entries {
+        let first_byte = || tiff.value_bytes(entry).and_then(|b| b.first().copied());
+        let rational = || {
+            (entry.format == 5)
+                .then(|| tiff.value_pos(entry))
+                .flatten()
+                .and_then(|pos| tiff.rational_at(pos, false))
+        };
+
+        match entry.tag {
+            GPS_LATITUDE_REF => lat_ref = first_byte().unwrap_or(b'N'),
+            GPS_LONGITUDE_REF => lon_ref = first_byte().unwrap_or(b'E'),
+            GPS_LATITUDE if entry.format == 5 => {
+                latitude = dms(&tiff, tiff.value_pos(entry)?, entry.count);
+            }
+            GPS_LONGITUDE if entry.format == 5 => {
+                longitude = dms(&tiff, tiff.value_pos(entry)?, entry.count);
+            }
+            GPS_ALTITUDE_REF => below_sea_level = first_byte() == Some(1),
+            GPS_ALTITUDE => altitude = rational(),
+            GPS_IMG_DIRECTION_REF => direction_magnetic = first_byte() == Some(b'M'),
+            GPS_IMG_DIRECTION => direction = rational(),
+            _ => {}
+        }
+    }
+
+    let mut latitude = latitude?;
+    let mut longitude = longitude?;
+    if lat_ref == b'S' {
+        latitude = -latitude;
+    }
+    if lon_ref == b'W' {
+        longitude = -longitude;
+    }
+
+    // Cameras without a fix often write 0/0, which is not a real position
+    if !(-90.0..=90.0).contains(&latitude)
+        || !(-180.0..=180.0).contains(&longitude)
+        || (latitude == 0.0 && longitude == 0.0)
+    {
+        return None;
+    }
+
+    Some(GpsPosition {
+        latitude,
+        longitude,
+        altitude: altitude.map(|a| if below_sea_level { -a } else { a }),
+        direction,
+        direction_magnetic,
+    })
+}
+
+pub fn position_of(path: &str) -> Option<GpsPosition> {
+    let raw = metadata::read_raw_quick(path).ok()?;
+    read_position(raw.exif.as_deref()?)
+}
+
+/// Every geotagged image directly inside `folder`.
+#[tauri::command]
+pub async fn get_geotagged_images(folder: String) -> Result<Vec<GeotaggedImage>, String> {
+    // rayon holds the thread it is called on until every file is read
+    tauri::async_runtime::spawn_blocking(move || geotagged_images(&folder))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn geotagged_images(folder: &str) -> Result<Vec<GeotaggedImage>, String> {
+    let files: Vec<String> = fs::read_dir(folder)
+        .map_err(|e| e.to_string())?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| crate::is_image_file(p))
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+
+    let mut found: Vec<GeotaggedImage> = files
+        .into_par_iter()
+        .filter_map(|path| {
+            let position = position_of(&path)?;
+            Some(GeotaggedImage { path, position })
+        })
+        .collect();
+
+    found.sort_by(|a, b| natord::compare(&a.path, &b.path));
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Little endian EXIF with a GPS IFD at 26 and its rationals from 104.
+    fn exif(
+        lat: (u8, [(u32, u32); 3]),
+        lon: (u8, [(u32, u32); 3]),
+        alt: (u8, (u32, u32)),
+    ) -> Vec<u8> {
+        let entry = |out: &mut Vec<u8>, tag: u16, format: u16, count: u32, value: [u8; 4]| {
+            out.extend(tag.to_le_bytes());
+            out.extend(format.to_le_bytes());
+            out.extend(count.to_le_bytes());
+            out.extend(value);
+        };
+        let mut out = b"II*\0".to_vec();
+        out.extend(8u32.to_le_bytes());
+        out.extend(1u16.to_le_bytes());
+        entry(&mut out, TAG_GPS_IFD, 4, 1, 26u32.to_le_bytes());
+        out.extend(0u32.to_le_bytes());
+
+        out.extend(6u16.to_le_bytes());
+        entry(&mut out, GPS_LATITUDE_REF, 2, 2, [lat.0, 0, 0, 0]);
+        entry(&mut out, GPS_LATITUDE, 5, 3, 104u32.to_le_bytes());
+        entry(&mut out, GPS_LONGITUDE_REF, 2, 2, [lon.0, 0, 0, 0]);
+        entry(&mut out, GPS_LONGITUDE, 5, 3, 128u32.to_le_bytes());
+        entry(&mut out, GPS_ALTITUDE_REF, 1, 1, [alt.0, 0, 0, 0]);
+        entry(&mut out, GPS_ALTITUDE, 5, 1, 152u32.to_le_bytes());
+        out.extend(0u32.to_le_bytes());
+
+        for (n, d) in lat.1.iter().chain(&lon.1).chain([&alt.1]) {
+            out.extend(n.to_le_bytes());
+            out.extend(d.to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn reads_positions_in_decimal_degrees() {
+        let lat = [(35, 1), (39, 1), (3600, 100)];
+        let lon = [(139, 1), (45, 1), (1800, 100)];
+
+        let north_east = read_position(&exif((b'N', lat), (b'E', lon), (0, (125, 2)))).unwrap();
+        assert!((north_east.latitude - 35.66).abs() < 1e-9);
+        assert!((north_east.longitude - 139.755).abs() < 1e-9);
+        assert_eq!(north_east.altitude, Some(62.5));
+
+        let south_west = read_position(&exif((b'S', lat), (b'W', lon), (1, (125, 2)))).unwrap();
+        assert!((south_west.latitude + 35.66).abs() < 1e-9);
+        assert!((south_west.longitude + 139.755).abs() < 1e-9);
+        assert_eq!(south_west.altitude, Some(-62.5));
+
+        // no fix
+        let zero = [(0, 1), (0, 1), (0, 1)];
+        assert!(read_position(&exif((b'N', zero), (b'E', zero), (0, (0, 1)))).is_none());
+        // out of range
+        let far = [(95, 1), (0, 1), (0, 1)];
+        assert!(read_position(&exif((b'N', far), (b'E', lon), (0, (0, 1)))).is_none());
+    }
+}
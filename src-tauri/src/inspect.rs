@@ -0,0 +1,225 @@
+// ---------------- PIXEL INSPECTION ----------------
+//
+// Histogram and pixel values for QA. Both work on the pixels as stored in
+// the file, before any color management, so the numbers match what other
+// tools report. The image is turned upright by its EXIF orientation, as it
+// is shown, so sampled positions match the view. The last decoded image is
+// kept so that sampling under the cursor doesn't decode the file again for
+// every mouse move.
+
+use crate::decode::{self, DecodeState};
+use crate::metadata;
+use crate::metadata_edit;
+use image::metadata::Orientation;
+use image::{DynamicImage, GenericImageView};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tauri::State;
+
+#[derive(Default)]
+pub struct InspectCache {
+    /// Path and modification time of the cached image.
+    image: Option<(String, Option<SystemTime>, Arc<DynamicImage>)>,
+}
+
+pub struct InspectState(pub Mutex<InspectCache>);
+
+/// The decoded image for `path`, from the cache when the file hasn't changed.
+fn inspected(
+    path: &str,
+    state: &State<'_, InspectState>,
+    limits: &State<'_, DecodeState>,
+) -> Result<Arc<DynamicImage>, String> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    if let Some((cached, cached_modified, img)) = &state.0.lock().unwrap().image {
+        if cached == path && *cached_modified == modified {
+            return Ok(img.clone());
+        }
+    }
+
+    let limits = *limits.0.lock().unwrap();
+    let (mut img, _) = decode::decode_with_profile(path, &limits)?;
+
+    let orientation = metadata::read_raw_quick(path)
+        .ok()
+        .and_then(|raw| raw.exif)
+        .and_then(|exif| metadata_edit::exif_orientation(&exif))
+        .and_then(|o| Orientation::from_exif(o as u8));
+    if let Some(orientation) = orientation {
+        img.apply_orientation(orientation);
+    }
+    let img = Arc::new(img);
+
+    state.0.lock().unwrap().image = Some((path.to_string(), modified, img.clone()));
+    Ok(img)
+}
+
+#[derive(Serialize, Default, Clone, Copy)]
+pub struct Clipping {
+    /// Pixels at 0.
+    shadows: u64,
+    /// Pixels at 255.
+    highlights: u64,
+}
+
+#[derive(Serialize)]
+pub struct Histogram {
+    red: Vec<u64>,
+    green: Vec<u64>,
+    blue: Vec<u64>,
+    /// Rec. 709 luma of the 8-bit values.
+    luminance: Vec<u64>,
+    alpha: Option<Vec<u64>>,
+    clipping_red: Clipping,
+    clipping_green: Clipping,
+    clipping_blue: Clipping,
+    clipping_luminance: Clipping,
+    pixels: u64,
+}
+
+/// Per-chunk counts, merged after the parallel pass.
+#[derive(Clone)]
+struct Counts([[u64; 256]; 5]);
+
+impl Counts {
+    fn new() -> Self {
+        Counts([[0; 256]; 5])
+    }
+
+    fn add(&mut self, px: &[u8]) {
+        let (r, g, b, a) = (px[0], px[1], px[2], px[3]);
+        let luma = (2126 * r as u32 + 7152 * g as u32 + 722 * b as u32 + 5000) / 10000;
+
+        self.0[0][r as usize] += 1;
+        self.0[1][g as usize] += 1;
+        self.0[2][b as usize] += 1;
+        self.0[3][luma as usize] += 1;
+        self.0[4][a as usize] += 1;
+    }
+
+    fn merge(mut self, other: Counts) -> Counts {
+        for (mine, theirs) in self.0.iter_mut().zip(other.0.iter()) {
+            for (m, t) in mine.iter_mut().zip(theirs.iter()) {
+                *m += t;
+            }
+        }
+        self
+    }
+}
+
+fn clipping(bins: &[u64; 256]) -> Clipping {
+    Clipping {
+        shadows: bins[0],
+        highlights: bins[255],
+    }
+}
+
+/// 8-bit histograms of R, G, B, luminance and (if present) alpha.
+/// Higher bit depths are scaled down to 8 bits.
+#[tauri::command]
+pub async fn compute_histogram(
+    path: String,
+    state: State<'_, InspectState>,
+    limits: State<'_, DecodeState>,
+) -> Result<Histogram, String> {
+    let img = inspected(&path, &state, &limits)?;
+    let has_alpha = img.color().has_alpha();
+    let rgba = img.to_rgba8();
+
+    let counts = rgba
+        .as_raw()
+        .par_chunks(4 * 64 * 1024)
+        .fold(Counts::new, |mut counts, chunk| {
+            chunk.chunks_exact(4).for_each(|px| counts.add(px));
+            counts
+        })
+        .reduce(Counts::new, Counts::merge);
+
+    let [red, green, blue, luminance, alpha] = counts.0;
+
+    Ok(Histogram {
+        clipping_red: clipping(&red),
+        clipping_green: clipping(&green),
+        clipping_blue: clipping(&blue),
+        clipping_luminance: clipping(&luminance),
+        red: red.to_vec(),
+        green: green.to_vec(),
+        blue: blue.to_vec(),
+        luminance: luminance.to_vec(),
+        alpha: has_alpha.then(|| alpha.to_vec()),
+        pixels: rgba.width() as u64 * rgba.height() as u64,
+    })
+}
+
+#[derive(Serialize)]
+pub struct PixelSample {
+    x: u32,
+    y: u32,
+    rgba: [u8; 4],
+    /// Channel names of `original`: "R", "G", "B", "A" or "L", "A".
+    channels: Vec<&'static str>,
+    /// Values at the file's own depth (16-bit integers, floats).
+    original: Vec<f64>,
+    /// 8, 16 or 32 (float).
+    original_bits: u8,
+}
+
+/// Exact value of pixel `x`, `y`.
+#[tauri::command]
+pub async fn sample_pixel(
+    path: String,
+    x: u32,
+    y: u32,
+    state: State<'_, InspectState>,
+    limits: State<'_, DecodeState>,
+) -> Result<PixelSample, String> {
+    let img = inspected(&path, &state, &limits)?;
+
+    if x >= img.width() || y >= img.height() {
+        return Err(format!("Pixel {}, {} is outside the image", x, y));
+    }
+
+    let (channels, original, original_bits): (Vec<&str>, Vec<f64>, u8) = match &*img {
+        DynamicImage::ImageLuma8(i) => (vec!["L"], to_f64(&i.get_pixel(x, y).0), 8),
+        DynamicImage::ImageLumaA8(i) => (vec!["L", "A"], to_f64(&i.get_pixel(x, y).0), 8),
+        DynamicImage::ImageRgb8(i) => (vec!["R", "G", "B"], to_f64(&i.get_pixel(x, y).0), 8),
+        DynamicImage::ImageRgba8(i) => (vec!["R", "G", "B", "A"], to_f64(&i.get_pixel(x, y).0), 8),
+        DynamicImage::ImageLuma16(i) => (vec!["L"], to_f64(&i.get_pixel(x, y).0), 16),
+        DynamicImage::ImageLumaA16(i) => (vec!["L", "A"], to_f64(&i.get_pixel(x, y).0), 16),
+        DynamicImage::ImageRgb16(i) => (vec!["R", "G", "B"], to_f64(&i.get_pixel(x, y).0), 16),
+        DynamicImage::ImageRgba16(i) => {
+            (vec!["R", "G", "B", "A"], to_f64(&i.get_pixel(x, y).0), 16)
+        }
+        DynamicImage::ImageRgb32F(i) => (vec!["R", "G", "B"], to_f64(&i.get_pixel(x, y).0), 32),
+        DynamicImage::ImageRgba32F(i) => {
+            (vec!["R", "G", "B", "A"], to_f64(&i.get_pixel(x, y).0), 32)
+        }
+        _ => {
+            let p = img.get_pixel(x, y).0;
+            (vec!["R", "G", "B", "A"], to_f64(&p), 8)
+        }
+    };
+
+    Ok(PixelSample {
+        x,
+        y,
+        rgba: img.get_pixel(x, y).0,
+        channels,
+        original,
+        original_bits,
+    })
+}
+
+fn to_f64<T: Copy + Into<f64>>(values: &[T]) -> Vec<f64> {
+    values.iter().map(|&v| v.into()).collect()
+}
+
+/// Drop the cached image once the histogram and loupe are closed.
+#[tauri::command]
+pub fn release_inspected_image(state: State<InspectState>) {
+    state.0.lock().unwrap().image = None;
+}
@@ -0,0 +1,549 @@
+// ---------------- FILE OPERATION JOURNAL ----------------
+//
+// Every trash, move / rename and copy the viewer does is written down, so
+// it can be undone and redone. The journal is kept as JSON in the app data
+// folder and survives a restart. When it can't be saved, a
+// "journal-error" event tells the frontend.
+//
+// Undoing a trash restores the file from the trash (Windows and Linux, the
+// trash crate can't on macOS), a move goes back and a copy goes to the
+// trash. Nothing is replaced on the way: if the way back is taken, the
+// step fails and stays where it was. A file that a move or copy replaced
+// was trashed in the same step and comes back out of the trash after it.
+// The moves of a batch rename can swap names or chain them: a move onto a
+// name that another move of the step frees waits at a temporary name.
+
+use crate::file_ops::{self, ConflictPolicy};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Steps kept, older ones are forgotten.
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    Trash {
+        path: String,
+        /// Id of the item in the trash, what restores it.
+        trash_id: Option<String>,
+    },
+    Move {
+        from: String,
+        to: String,
+    },
+    Copy {
+        from: String,
+        to: String,
+    },
+    /// Operations on a selection, undone as one step.
+    Group {
+        ops: Vec<Operation>,
+    },
+}
+
+impl Operation {
+    /// One step of `ops`, `None` if there are none.
+    pub fn group(mut ops: Vec<Operation>) -> Option<Operation> {
+        match ops.len() {
+            0 => None,
+            1 => ops.pop(),
+            _ => Some(Operation::Group { ops }),
+        }
+    }
+
+    fn into_ops(self) -> Vec<Operation> {
+        match self {
+            Operation::Group { ops } => ops,
+            op => vec![op],
+        }
+    }
+
+    fn describe(&self) -> String {
+        let name = |p: &str| {
+            Path::new(p)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| p.to_string())
+        };
+        match self {
+            Operation::Trash { path, .. } => format!("trash {}", name(path)),
+            Operation::Move { from, to } if Path::new(from).parent() == Path::new(to).parent() => {
+                format!("rename {} to {}", name(from), name(to))
+            }
+            Operation::Move { from, to } => format!("move {} to {}", name(from), to),
+            Operation::Copy { from, to } => format!("copy {} to {}", name(from), to),
+            Operation::Group { ops } => {
+                let folder = |p: &str| {
+                    Path::new(p)
+                        .parent()
+                        .map(|f| f.to_string_lossy().to_string())
+                        .unwrap_or_default()
+                };
+                // files replaced by the moves or copies are trashed with them
+                let sent: Vec<&Operation> = ops
+                    .iter()
+                    .filter(|op| !matches!(op, Operation::Trash { .. }))
+                    .collect();
+                let renamed = |op: &&Operation| match op {
+                    Operation::Move { from, to } => folder(from) == folder(to),
+                    _ => false,
+                };
+                match sent.as_slice() {
+                    [op] => op.describe(),
+                    [_, ..] if sent.iter().all(renamed) => format!("rename {} files", sent.len()),
+                    [Operation::Move { to, .. }, ..] => {
+                        format!("move {} files to {}", sent.len(), folder(to))
+                    }
+                    [Operation::Copy { to, .. }, ..] => {
+                        format!("copy {} files to {}", sent.len(), folder(to))
+                    }
+                    _ => format!("trash {} files", ops.len()),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Entries {
+    done: Vec<Operation>,
+    undone: Vec<Operation>,
+}
+
+pub struct Journal {
+    file: Option<PathBuf>,
+    entries: Entries,
+    app: AppHandle,
+}
+
+pub struct JournalState(pub Mutex<Journal>);
+
+impl Journal {
+    /// The journal saved in `dir`, an empty one that is never saved without.
+    pub fn load(dir: Option<&Path>, app: AppHandle) -> Journal {
+        let file = dir.map(|d| d.join("journal.json"));
+        let entries = file
+            .as_ref()
+            .and_then(|f| fs::read(f).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Journal { file, entries, app }
+    }
+
+    fn save(&self) {
+        let Some(file) = &self.file else { return };
+        let result = serde_json::to_vec(&self.entries)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                if let Some(dir) = file.parent() {
+                    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                let tmp = file.with_extension("json.tmp");
+                fs::write(&tmp, json).map_err(|e| e.to_string())?;
+                fs::rename(&tmp, file).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            let message = format!("The undo history could not be saved: {}", e);
+            let _ = self.app.emit("journal-error", message);
+        }
+    }
+
+    /// Note a step that was just done. Whatever was undone can no longer
+    /// be redone.
+    pub fn record(&mut self, op: Operation) {
+        self.entries.done.push(op);
+        if self.entries.done.len() > MAX_ENTRIES {
+            self.entries.done.remove(0);
+        }
+        self.entries.undone.clear();
+        self.save();
+    }
+}
+
+// ----- Trash ------
+
+#[cfg(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+))]
+mod bin {
+    use std::collections::HashSet;
+    use std::path::Path;
+    use trash::os_limited;
+
+    pub fn ids_of(path: &Path) -> HashSet<String> {
+        os_limited::list()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|item| item.original_path() == path)
+            .map(|item| item.id.to_string_lossy().to_string())
+            .collect()
+    }
+
+    pub fn restore(path: &Path, id: Option<&str>) -> Result<(), String> {
+        let items = os_limited::list().map_err(|e| format!("Failed to read the trash: {}", e))?;
+        let by_id = |item: &trash::TrashItem| Some(item.id.to_string_lossy().as_ref()) == id;
+
+        // the exact item, else any of that name
+        let item = items
+            .iter()
+            .find(|item| by_id(item))
+            .or_else(|| items.iter().find(|item| item.original_path() == path))
+            .cloned()
+            .ok_or_else(|| format!("{} is no longer in the trash", path.display()))?;
+
+        os_limited::restore_all([item]).map_err(|e| match e {
+            trash::Error::RestoreCollision { path, .. } => {
+                format!("{} already exists", path.display())
+            }
+            e => format!("Failed to restore from the trash: {}", e),
+        })
+    }
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+)))]
+mod bin {
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    pub fn ids_of(_path: &Path) -> HashSet<String> {
+        HashSet::new()
+    }
+
+    pub fn restore(_path: &Path, _id: Option<&str>) -> Result<(), String> {
+        Err("Restoring from the trash isn't supported on this system".to_string())
+    }
+}
+
+/// Move `path` to the trash. Returns the operation, with the id the trash
+/// gave it.
+pub fn trash(path: &Path) -> Result<Operation, String> {
+    if !path.exists() {
+        return Err(format!("Path does not exist: {}", path.display()));
+    }
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+    let before = bin::ids_of(&path);
+    trash::delete(&path).map_err(|e| format!("Failed to move to trash: {}", e))?;
+    let trash_id = bin::ids_of(&path).difference(&before).next().cloned();
+
+    Ok(Operation::Trash {
+        path: path.to_string_lossy().to_string(),
+        trash_id,
+    })
+}
+
+/// Take the file of a trash step out of the trash again.
+pub fn restore(op: &Operation) -> Result<(), String> {
+    match op {
+        Operation::Trash { path, trash_id } => bin::restore(Path::new(path), trash_id.as_deref()),
+        _ => Ok(()),
+    }
+}
+
+// ----- Undo and redo ------
+
+/// What a step changed in the folder list.
+#[derive(Serialize, Default)]
+pub struct Applied {
+    message: String,
+    /// Files that appeared, to show.
+    added: Vec<String>,
+    /// Files that are gone.
+    removed: Vec<String>,
+}
+
+fn undo_op(op: &Operation, applied: &mut Applied) -> Result<(), String> {
+    match op {
+        Operation::Trash { path, trash_id } => {
+            bin::restore(Path::new(path), trash_id.as_deref())?;
+            applied.added.push(path.clone());
+        }
+        Operation::Move { from, to } => {
+            file_ops::move_path(Path::new(to), Path::new(from), ConflictPolicy::Fail)
+                .map_err(|e| e.to_string())?;
+            applied.added.push(from.clone());
+            applied.removed.push(to.clone());
+        }
+        Operation::Copy { to, .. } => {
+            trash::delete(to).map_err(|e| format!("Failed to move to trash: {}", e))?;
+            applied.removed.push(to.clone());
+        }
+        Operation::Group { .. } => unreachable!("groups are undone one by one"),
+    }
+    Ok(())
+}
+
+/// Do `op` again. A trash gets a new trash id.
+fn redo_op(op: &mut Operation, applied: &mut Applied) -> Result<(), String> {
+    match op {
+        Operation::Trash { path, .. } => {
+            let removed = path.clone();
+            *op = trash(Path::new(path))?;
+            applied.removed.push(removed);
+        }
+        Operation::Move { from, to } => {
+            file_ops::move_path(Path::new(from), Path::new(to), ConflictPolicy::Fail)
+                .map_err(|e| e.to_string())?;
+            applied.added.push(to.clone());
+            applied.removed.push(from.clone());
+        }
+        Operation::Copy { from, to } => {
+            file_ops::copy_path(Path::new(from), Path::new(to), ConflictPolicy::Fail)
+                .map_err(|e| e.to_string())?;
+            applied.added.push(to.clone());
+        }
+        Operation::Group { .. } => unreachable!("groups are redone one by one"),
+    }
+    Ok(())
+}
+
+/// (source, destination) of a move, the way undo or redo takes it.
+fn direction(op: &Operation, undo: bool) -> Option<(&String, &String)> {
+    match op {
+        Operation::Move { from, to } if undo => Some((to, from)),
+        Operation::Move { from, to } => Some((from, to)),
+        _ => None,
+    }
+}
+
+/// Move the file of `op` out of the way, to a temporary name next to it.
+fn park(op: &Operation, undo: bool, i: usize) -> Result<PathBuf, String> {
+    let (source, _) = direction(op, undo).expect("only moves are parked");
+    let source = Path::new(source);
+    let tmp = source.with_file_name(format!(".undo-{}-{}.tmp", std::process::id(), i));
+    file_ops::move_path(source, &tmp, ConflictPolicy::Fail).map_err(|e| e.to_string())?;
+    Ok(tmp)
+}
+
+/// Finish the parked move of `op`, from `tmp` to its destination, or back
+/// where it was when that fails.
+fn unpark(op: &Operation, tmp: &Path, undo: bool, applied: &mut Applied) -> Result<(), String> {
+    let (source, dest) = direction(op, undo).expect("only moves are parked");
+    if let Err(e) = file_ops::move_path(tmp, Path::new(dest), ConflictPolicy::Fail) {
+        return match file_ops::move_path(tmp, Path::new(source), ConflictPolicy::Fail) {
+            Ok(_) => Err(e.to_string()),
+            Err(back) => Err(format!("{}, it is left at {}: {}", e, tmp.display(), back)),
+        };
+    }
+    applied.added.push(dest.clone());
+    applied.removed.push(source.clone());
+    Ok(())
+}
+
+/// Undo or redo every operation of `step`. Those that fail are returned,
+/// to stay where they were.
+fn apply_step(
+    step: Operation,
+    undo: bool,
+) -> (Applied, Option<Operation>, Option<Operation>, Vec<String>) {
+    let mut ops = step.into_ops();
+    if undo {
+        ops.reverse();
+    }
+
+    // moves onto a name that another move frees go last, from a temporary name
+    let chained: Vec<bool> = {
+        let sources: HashSet<&String> = ops
+            .iter()
+            .filter_map(|op| direction(op, undo))
+            .map(|(source, _)| source)
+            .collect();
+        ops.iter()
+            .map(|op| direction(op, undo).is_some_and(|(_, dest)| sources.contains(dest)))
+            .collect()
+    };
+    let parked: Vec<Option<Result<PathBuf, String>>> = ops
+        .iter()
+        .zip(&chained)
+        .enumerate()
+        .map(|(i, (op, &chained))| chained.then(|| park(op, undo, i)))
+        .collect();
+    let order: Vec<usize> = (0..ops.len())
+        .filter(|&i| !chained[i])
+        .chain((0..ops.len()).filter(|&i| chained[i]))
+        .collect();
+
+    let mut applied = Applied::default();
+    let mut results = vec![Ok(()); ops.len()];
+    for i in order {
+        results[i] = match &parked[i] {
+            None if undo => undo_op(&ops[i], &mut applied),
+            None => redo_op(&mut ops[i], &mut applied),
+            Some(Ok(tmp)) => unpark(&ops[i], tmp, undo, &mut applied),
+            Some(Err(e)) => Err(e.clone()),
+        };
+    }
+
+    let (mut moved, mut failed, mut errors) = (Vec::new(), Vec::new(), Vec::new());
+    for (op, result) in ops.into_iter().zip(results) {
+        match result {
+            Ok(()) => moved.push(op),
+            Err(e) => {
+                errors.push(e);
+                failed.push(op);
+            }
+        }
+    }
+    if undo {
+        moved.reverse();
+        failed.reverse();
+    }
+    (
+        applied,
+        Operation::group(moved),
+        Operation::group(failed),
+        errors,
+    )
+}
+
+/// Undo the last step. `None` when there is nothing to undo.
+#[tauri::command]
+pub async fn undo_file_operation(app: AppHandle) -> Result<Option<Applied>, String> {
+    // moves across drives and restores from the trash take a while, the
+    // journal is only locked to take the step and to put it back
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<JournalState>();
+        let Some(step) = state.0.lock().unwrap().entries.done.pop() else {
+            return Ok(None);
+        };
+        let description = step.describe();
+
+        let (mut applied, undone, failed, errors) = apply_step(step, true);
+        let mut journal = state.0.lock().unwrap();
+        // still done, it may work once the way back is clear
+        if let Some(failed) = failed {
+            journal.entries.done.push(failed);
+        }
+        let Some(undone) = undone else {
+            return Err(format!("Can't undo {}: {}", description, errors.join("; ")));
+        };
+
+        journal.entries.undone.push(undone);
+        journal.save();
+        applied.message = match errors.len() {
+            0 => format!("Undone: {}", description),
+            n => format!(
+                "Undone: {}, except {}: {}",
+                description,
+                n,
+                errors.join("; ")
+            ),
+        };
+        Ok(Some(applied))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Redo the last undone step. `None` when there is nothing to redo.
+#[tauri::command]
+pub async fn redo_file_operation(app: AppHandle) -> Result<Option<Applied>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<JournalState>();
+        let Some(step) = state.0.lock().unwrap().entries.undone.pop() else {
+            return Ok(None);
+        };
+        let description = step.describe();
+
+        let (mut applied, redone, failed, errors) = apply_step(step, false);
+        let mut journal = state.0.lock().unwrap();
+        if let Some(failed) = failed {
+            journal.entries.undone.push(failed);
+        }
+        let Some(redone) = redone else {
+            return Err(format!("Can't redo {}: {}", description, errors.join("; ")));
+        };
+
+        journal.entries.done.push(redone);
+        journal.save();
+        applied.message = match errors.len() {
+            0 => format!("Redone: {}", description),
+            n => format!(
+                "Redone: {}, except {}: {}",
+                description,
+                n,
+                errors.join("; ")
+            ),
+        };
+        Ok(Some(applied))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What each of `names` in `dir` holds, `None` when it's missing.
+    fn contents(dir: &Path, names: &[&str]) -> Vec<Option<String>> {
+        names
+            .iter()
+            .map(|name| fs::read_to_string(dir.join(name)).ok())
+            .collect()
+    }
+
+    #[test]
+    fn undoes_swaps_and_chains_of_renames() {
+        let dir = std::env::temp_dir().join(format!("journal-renames-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let moved = |from: &str, to: &str| Operation::Move {
+            from: path(from),
+            to: path(to),
+        };
+
+        // a and b swapped, c renamed to d and d to e
+        fs::write(dir.join("a.jpg"), "b").unwrap();
+        fs::write(dir.join("b.jpg"), "a").unwrap();
+        fs::write(dir.join("d.jpg"), "c").unwrap();
+        fs::write(dir.join("e.jpg"), "d").unwrap();
+        let step = Operation::Group {
+            ops: vec![
+                moved("a.jpg", "b.jpg"),
+                moved("b.jpg", "a.jpg"),
+                moved("c.jpg", "d.jpg"),
+                moved("d.jpg", "e.jpg"),
+            ],
+        };
+        let names = ["a.jpg", "b.jpg", "c.jpg", "d.jpg", "e.jpg"];
+        let text = |s: &[Option<&str>]| -> Vec<Option<String>> {
+            s.iter().map(|c| c.map(str::to_string)).collect()
+        };
+
+        let (_, undone, failed, errors) = apply_step(step, true);
+        assert!(failed.is_none(), "{:?}", errors);
+        assert_eq!(
+            contents(&dir, &names),
+            text(&[Some("a"), Some("b"), Some("c"), Some("d"), None])
+        );
+
+        let (_, _, failed, errors) = apply_step(undone.unwrap(), false);
+        assert!(failed.is_none(), "{:?}", errors);
+        assert_eq!(
+            contents(&dir, &names),
+            text(&[Some("b"), Some("a"), None, Some("c"), Some("d")])
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,750 @@
+// ---------------- LIBRARY INDEX ----------------
+//
+// SQLite cache, in the app data dir, of what searching, filtering and the
+// duplicate finder need to know about a file: size, modification time,
+// dimensions, key EXIF fields, which metadata blocks it has, its ratings
+// and tags and, once computed, its content and perceptual hashes.
+//
+// A row is trusted while the file's size and modification time, and the
+// modification time of its XMP sidecar, are unchanged. Folders are brought
+// up to date whenever they are listed, so there is no watcher: a stat per
+// file is far cheaper than reading its metadata again. The index is only
+// a cache, without it (or with a newer schema) everything is read from the
+// files.
+//
+// Filtering and sorting a folder run as SQL on the index: `Selection`
+// collects the conditions and the order, `select` brings the rows up to
+// date and runs them. Without the index the rows go to a table in memory
+// for the one query.
+
+use crate::decode::{DecodeLimits, DecodeState};
+use crate::geo;
+use crate::metadata::{self, Tiff, TAG_EXIF_IFD, TAG_MAKE};
+use crate::ratings::{self, Flag, Marks};
+use rayon::prelude::*;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, Row};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+pub struct LibraryState(pub Mutex<Option<Connection>>);
+
+const DATABASE_NAME: &str = "library.sqlite3";
+
+/// Bumped when the table changes; older tables are dropped and rebuilt.
+const SCHEMA_VERSION: i32 = 2;
+
+const SCHEMA: &str = "
+    CREATE TABLE images (
+        path TEXT PRIMARY KEY,
+        folder TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        modified INTEGER NOT NULL,
+        sidecar_modified INTEGER,
+        width INTEGER,
+        height INTEGER,
+        camera TEXT,
+        lens TEXT,
+        date TEXT,
+        iso REAL,
+        has_exif INTEGER NOT NULL,
+        has_xmp INTEGER NOT NULL,
+        has_iptc INTEGER NOT NULL,
+        has_gps INTEGER NOT NULL,
+        rating INTEGER NOT NULL,
+        label TEXT,
+        flag TEXT NOT NULL,
+        tags TEXT NOT NULL,
+        sidecar INTEGER NOT NULL,
+        content_hash TEXT,
+        dhash INTEGER,
+        phash INTEGER
+    );
+    CREATE INDEX images_folder ON images(folder);
+    CREATE INDEX images_rating ON images(folder, rating);
+    CREATE INDEX images_flag ON images(folder, flag);
+    CREATE INDEX images_label ON images(folder, label);
+    CREATE INDEX images_date ON images(folder, date);
+    CREATE INDEX images_camera ON images(folder, camera COLLATE NOCASE);
+    CREATE INDEX images_size ON images(folder, size);
+";
+
+const COLUMNS: &str = "path, size, modified, sidecar_modified, width, height, camera, lens, \
+    date, iso, has_exif, has_xmp, has_iptc, has_gps, rating, label, flag, tags, sidecar, \
+    content_hash, dhash, phash";
+
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ISO: u16 = 0x8827;
+const TAG_DATE_TAKEN: u16 = 0x9003;
+const TAG_LENS_MODEL: u16 = 0xA434;
+
+/// Progress is reported every this many files.
+const PROGRESS_STEP: usize = 16;
+
+/// Open (or create) the index in `dir`.
+pub fn open(dir: &Path) -> Result<Connection, String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let conn = Connection::open(dir.join(DATABASE_NAME)).map_err(|e| e.to_string())?;
+
+    let version: i32 = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+
+    if version != SCHEMA_VERSION {
+        conn.execute_batch("DROP TABLE IF EXISTS images;")
+            .and_then(|_| conn.execute_batch(SCHEMA))
+            .and_then(|_| conn.pragma_update(None, "user_version", SCHEMA_VERSION))
+            .map_err(|e| e.to_string())?;
+    }
+
+    // a cache, losing the last writes in a crash is fine
+    conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")
+        .and_then(|_| prepare(&conn))
+        .map_err(|e| e.to_string())?;
+    Ok(conn)
+}
+
+/// Table for one query when there is no index.
+fn open_in_memory() -> rusqlite::Result<Connection> {
+    let conn = Connection::open_in_memory()?;
+    conn.execute_batch(SCHEMA)?;
+    prepare(&conn)?;
+    Ok(conn)
+}
+
+/// What the queries of `Selection` need on every connection.
+fn prepare(conn: &Connection) -> rusqlite::Result<()> {
+    // natural order of the file names, for sort:name
+    conn.create_collation("file_name", |a, b| natord::compare(name_of(a), name_of(b)))
+}
+
+/// What tells whether a row is still current.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Stamp {
+    pub size: u64,
+    /// Milliseconds since the Unix epoch.
+    pub modified: i64,
+    pub sidecar_modified: Option<i64>,
+}
+
+fn millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+pub fn stamp(path: &str) -> Option<Stamp> {
+    let meta = fs::metadata(path).ok()?;
+    let sidecar_modified = ratings::existing_sidecar(path)
+        .and_then(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+        .map(millis);
+
+    Some(Stamp {
+        size: meta.len(),
+        modified: meta.modified().map(millis).unwrap_or(0),
+        sidecar_modified,
+    })
+}
+
+#[derive(Clone)]
+pub struct Record {
+    pub path: String,
+    pub stamp: Stamp,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Make and model.
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+    /// EXIF date taken as "YYYY-MM-DD HH:MM:SS", without one the
+    /// modification date (UTC) as "YYYY-MM-DD".
+    pub date: Option<String>,
+    pub iso: Option<f64>,
+    pub has_exif: bool,
+    pub has_xmp: bool,
+    pub has_iptc: bool,
+    pub has_gps: bool,
+    pub marks: Marks,
+    /// Hex SHA-256 of the file, once hashed.
+    pub content_hash: Option<String>,
+    /// (dHash, pHash), once hashed and if the pixels could be read.
+    pub perceptual: Option<(u64, u64)>,
+}
+
+// ----- Reading a file ------
+
+/// YYYY-MM-DD of a Unix time, in UTC.
+pub fn civil_date(secs: i64) -> String {
+    // Howard Hinnant's civil_from_days
+    let z = secs.div_euclid(86_400) + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Camera, lens, date taken and ISO from IFD0 and the Exif IFD.
+fn read_exif(record: &mut Record, exif: &[u8]) {
+    let Some(tiff) = Tiff::new(exif) else {
+        return;
+    };
+
+    let ifd0 = tiff.first_ifd().and_then(|o| tiff.read_ifd(o));
+    let exif_ifd = ifd0
+        .iter()
+        .flat_map(|ifd| &ifd.entries)
+        .find(|e| e.tag == TAG_EXIF_IFD)
+        .and_then(|e| tiff.value_u32(e))
+        .and_then(|o| tiff.read_ifd(o as usize));
+
+    let mut make = None;
+    let mut model = None;
+    for entry in ifd0
+        .iter()
+        .chain(exif_ifd.iter())
+        .flat_map(|ifd| &ifd.entries)
+    {
+        let text = || Some(tiff.format_value(entry)).filter(|v| !v.is_empty());
+        match entry.tag {
+            TAG_MAKE => make = text(),
+            TAG_MODEL => model = text(),
+            TAG_LENS_MODEL => record.lens = text(),
+            TAG_ISO => record.iso = tiff.value_u32(entry).map(|v| v as f64),
+            // "2026:09:14 18:03:22"
+            TAG_DATE_TAKEN => record.date = text().map(|d| d.replacen(':', "-", 2)),
+            _ => {}
+        }
+    }
+
+    record.camera = match (make, model) {
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+}
+
+/// Read `path` again. The hashes of `previous` are kept when only the
+/// sidecar changed.
+fn index_file(
+    path: String,
+    stamp: Stamp,
+    previous: Option<&Record>,
+    hashes: bool,
+    limits: &DecodeLimits,
+) -> Record {
+    let (width, height) = crate::decode::header_dimensions(&path).unzip();
+    let raw = metadata::read_raw_quick(&path).unwrap_or_default();
+
+    let mut record = Record {
+        stamp,
+        width,
+        height,
+        camera: None,
+        lens: None,
+        date: None,
+        iso: None,
+        has_exif: raw.exif.is_some(),
+        has_xmp: raw.xmp.is_some(),
+        has_iptc: raw.iptc.is_some(),
+        has_gps: raw.exif.as_deref().and_then(geo::read_position).is_some(),
+        marks: ratings::read_marks(&path),
+        content_hash: None,
+        perceptual: None,
+        path,
+    };
+
+    if let Some(exif) = &raw.exif {
+        read_exif(&mut record, exif);
+    }
+    if record.date.is_none() {
+        record.date = Some(civil_date(stamp.modified.div_euclid(1000)));
+    }
+
+    let unchanged =
+        previous.filter(|p| p.stamp.size == stamp.size && p.stamp.modified == stamp.modified);
+    if let Some(previous) = unchanged {
+        record.content_hash = previous.content_hash.clone();
+        record.perceptual = previous.perceptual;
+    }
+
+    if hashes && record.content_hash.is_none() {
+        if let Ok(bytes) = fs::read(&record.path) {
+            record.content_hash = Some(crate::duplicates::content_hash(&bytes));
+            record.perceptual = crate::duplicates::perceptual_hashes(&record.path, limits);
+        }
+    }
+    record
+}
+
+// ----- Rows ------
+
+pub fn flag_name(flag: Flag) -> &'static str {
+    match flag {
+        Flag::None => "none",
+        Flag::Pick => "pick",
+        Flag::Reject => "reject",
+    }
+}
+
+fn from_row(row: &Row) -> rusqlite::Result<Record> {
+    let flag = match row.get::<_, String>(16)?.as_str() {
+        "pick" => Flag::Pick,
+        "reject" => Flag::Reject,
+        _ => Flag::None,
+    };
+    let tags: String = row.get(17)?;
+    let dhash: Option<i64> = row.get(20)?;
+    let phash: Option<i64> = row.get(21)?;
+
+    Ok(Record {
+        path: row.get(0)?,
+        stamp: Stamp {
+            size: row.get::<_, i64>(1)? as u64,
+            modified: row.get(2)?,
+            sidecar_modified: row.get(3)?,
+        },
+        width: row.get(4)?,
+        height: row.get(5)?,
+        camera: row.get(6)?,
+        lens: row.get(7)?,
+        date: row.get(8)?,
+        iso: row.get(9)?,
+        has_exif: row.get(10)?,
+        has_xmp: row.get(11)?,
+        has_iptc: row.get(12)?,
+        has_gps: row.get(13)?,
+        marks: Marks {
+            rating: row.get(14)?,
+            label: row.get(15)?,
+            flag,
+            tags: serde_json::from_str(&tags).unwrap_or_default(),
+            sidecar: row.get(18)?,
+        },
+        content_hash: row.get(19)?,
+        perceptual: dhash.zip(phash).map(|(d, p)| (d as u64, p as u64)),
+    })
+}
+
+fn name_of(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path)
+}
+
+fn folder_of(path: &str) -> String {
+    Path::new(path)
+        .parent()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn load_folders(
+    conn: &Connection,
+    folders: &HashSet<String>,
+) -> rusqlite::Result<HashMap<String, Record>> {
+    let mut stmt =
+        conn.prepare_cached(&format!("SELECT {} FROM images WHERE folder = ?1", COLUMNS))?;
+    let mut records = HashMap::new();
+
+    for folder in folders {
+        for record in stmt.query_map([folder], from_row)? {
+            let record = record?;
+            records.insert(record.path.clone(), record);
+        }
+    }
+    Ok(records)
+}
+
+/// Write `changed`, and drop the rows of `folders` that aren't in `listed`.
+fn store(
+    conn: &mut Connection,
+    changed: &[Record],
+    folders: &HashSet<String>,
+    listed: &HashSet<&str>,
+) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut insert = tx.prepare_cached(&format!(
+            "INSERT OR REPLACE INTO images (folder, {}) VALUES \
+             (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, \
+             ?18, ?19, ?20, ?21, ?22, ?23)",
+            COLUMNS
+        ))?;
+
+        for r in changed {
+            let (dhash, phash) = r.perceptual.map(|(d, p)| (d as i64, p as i64)).unzip();
+            insert.execute(params![
+                folder_of(&r.path),
+                r.path,
+                r.stamp.size as i64,
+                r.stamp.modified,
+                r.stamp.sidecar_modified,
+                r.width,
+                r.height,
+                r.camera,
+                r.lens,
+                r.date,
+                r.iso,
+                r.has_exif,
+                r.has_xmp,
+                r.has_iptc,
+                r.has_gps,
+                r.marks.rating,
+                r.marks.label,
+                flag_name(r.marks.flag),
+                serde_json::to_string(&r.marks.tags).unwrap_or_default(),
+                r.marks.sidecar,
+                r.content_hash,
+                dhash,
+                phash,
+            ])?;
+        }
+
+        let mut paths = tx.prepare_cached("SELECT path FROM images WHERE folder = ?1")?;
+        let mut delete = tx.prepare_cached("DELETE FROM images WHERE path = ?1")?;
+        for folder in folders {
+            let gone: Vec<String> = paths
+                .query_map([folder], |row| row.get::<_, String>(0))?
+                .filter_map(|p| p.ok())
+                .filter(|p| !listed.contains(p.as_str()))
+                .collect();
+            for path in gone {
+                delete.execute([path])?;
+            }
+        }
+    }
+    tx.commit()
+}
+
+/// Records of `files`, which must be complete folder listings: from the
+/// index where they are current, read from the files otherwise. With
+/// `hashes` the content and perceptual hashes are filled in as well.
+/// `progress` gets (done, total) while files are read.
+pub fn records(
+    state: &LibraryState,
+    files: Vec<String>,
+    hashes: bool,
+    limits: &DecodeLimits,
+    progress: &(dyn Fn(usize, usize) + Sync),
+) -> Vec<Record> {
+    lookup(state, files, true, hashes, limits, progress)
+}
+
+/// Like `records` for any list of files, without hashes. Rows of files
+/// missing from the list are kept.
+pub fn records_of(state: &LibraryState, files: Vec<String>, limits: &DecodeLimits) -> Vec<Record> {
+    lookup(state, files, false, false, limits, &|_, _| {})
+}
+
+fn lookup(
+    state: &LibraryState,
+    files: Vec<String>,
+    listings: bool,
+    hashes: bool,
+    limits: &DecodeLimits,
+    progress: &(dyn Fn(usize, usize) + Sync),
+) -> Vec<Record> {
+    refresh(state, files, listings, hashes, limits, progress).0
+}
+
+/// `lookup`, and whether the records are all in the index.
+fn refresh(
+    state: &LibraryState,
+    files: Vec<String>,
+    listings: bool,
+    hashes: bool,
+    limits: &DecodeLimits,
+    progress: &(dyn Fn(usize, usize) + Sync),
+) -> (Vec<Record>, bool) {
+    let stamped: Vec<(String, Stamp)> = files
+        .into_par_iter()
+        .filter_map(|path| {
+            let stamp = stamp(&path)?;
+            Some((path, stamp))
+        })
+        .collect();
+
+    let folders: HashSet<String> = stamped.iter().map(|(p, _)| folder_of(p)).collect();
+
+    let mut cached = match &*state.0.lock().unwrap() {
+        Some(conn) => load_folders(conn, &folders).unwrap_or_else(|e| {
+            eprintln!("Library index read failed: {}", e);
+            HashMap::new()
+        }),
+        None => HashMap::new(),
+    };
+
+    let current = |path: &str, stamp: &Stamp| {
+        cached
+            .get(path)
+            .is_some_and(|r| r.stamp == *stamp && (!hashes || r.content_hash.is_some()))
+    };
+    let stale: Vec<(String, Stamp)> = stamped
+        .iter()
+        .filter(|(path, stamp)| !current(path, stamp))
+        .cloned()
+        .collect();
+
+    let total = stale.len();
+    let done = AtomicUsize::new(0);
+    progress(0, total);
+
+    let changed: Vec<Record> = stale
+        .into_par_iter()
+        .map(|(path, stamp)| {
+            let previous = cached.get(&path);
+            let record = index_file(path, stamp, previous, hashes, limits);
+
+            let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+            if n.is_multiple_of(PROGRESS_STEP) || n == total {
+                progress(n, total);
+            }
+            record
+        })
+        .collect();
+
+    let mut stored = false;
+    if let Some(conn) = state.0.lock().unwrap().as_mut() {
+        let listed: HashSet<&str> = stamped.iter().map(|(p, _)| p.as_str()).collect();
+        let pruned = if listings { folders } else { HashSet::new() };
+        match store(conn, &changed, &pruned, &listed) {
+            Ok(()) => stored = true,
+            Err(e) => eprintln!("Library index write failed: {}", e),
+        }
+    }
+
+    for record in changed {
+        cached.insert(record.path.clone(), record);
+    }
+    let records = stamped
+        .into_iter()
+        .filter_map(|(path, _)| cached.remove(&path))
+        .collect();
+    (records, stored)
+}
+
+// ----- Queries ------
+
+/// Which rows `select` keeps and in what order, as SQL on `images`.
+#[derive(Default)]
+pub struct Selection {
+    conditions: Vec<String>,
+    params: Vec<Value>,
+    order: Vec<String>,
+}
+
+impl Selection {
+    /// Keep the rows where `sql` holds, its `?` taking `params` in turn.
+    pub fn filter(&mut self, sql: String, params: Vec<Value>) {
+        self.conditions.push(sql);
+        self.params.extend(params);
+    }
+
+    /// Order by `sql` after the orders added before.
+    pub fn order_by(&mut self, sql: String) {
+        self.order.push(sql);
+    }
+}
+
+/// Run `selection` on the rows of `records`, ties in their order.
+fn query(
+    conn: &mut Connection,
+    records: &[Record],
+    selection: &Selection,
+) -> rusqlite::Result<Vec<String>> {
+    // the listing only lives for this query, it is rolled back
+    let tx = conn.transaction()?;
+    tx.execute_batch(
+        "CREATE TEMP TABLE IF NOT EXISTS listing (path TEXT PRIMARY KEY, position INTEGER);",
+    )?;
+    {
+        let mut insert = tx.prepare_cached("INSERT OR IGNORE INTO temp.listing VALUES (?1, ?2)")?;
+        for (position, record) in records.iter().enumerate() {
+            insert.execute(params![record.path, position as i64])?;
+        }
+    }
+
+    let mut sql = "SELECT images.path FROM images JOIN temp.listing USING (path)".to_string();
+    if !selection.conditions.is_empty() {
+        sql += &format!(" WHERE ({})", selection.conditions.join(") AND ("));
+    }
+    let mut order = selection.order.clone();
+    order.push("position".to_string());
+    sql += &format!(" ORDER BY {}", order.join(", "));
+
+    let paths = tx
+        .prepare(&sql)?
+        .query_map(params_from_iter(&selection.params), |row| row.get(0))?
+        .collect();
+    tx.rollback()?;
+    paths
+}
+
+/// The images of `files` that `selection` keeps, in its order and else in
+/// the order of `files`. `listings` as for `records` and `records_of`.
+pub fn select(
+    state: &LibraryState,
+    files: Vec<String>,
+    listings: bool,
+    selection: &Selection,
+    limits: &DecodeLimits,
+) -> Result<Vec<String>, String> {
+    let (records, stored) = refresh(state, files, listings, false, limits, &|_, _| {});
+
+    let mut guard = state.0.lock().unwrap();
+    let result = match guard.as_mut().filter(|_| stored) {
+        Some(conn) => query(conn, &records, selection),
+        None => open_in_memory().and_then(|mut conn| {
+            store(&mut conn, &records, &HashSet::new(), &HashSet::new())?;
+            query(&mut conn, &records, selection)
+        }),
+    };
+    result.map_err(|e| format!("Filtering the images failed: {}", e))
+}
+
+// ----- Commands ------
+
+#[derive(Serialize, Clone)]
+struct IndexProgress {
+    done: usize,
+    total: usize,
+}
+
+#[derive(Serialize)]
+pub struct LibraryInfo {
+    /// False when the database couldn't be opened.
+    available: bool,
+    images: u64,
+    folders: u64,
+    /// Database size in bytes.
+    size: u64,
+    path: Option<String>,
+}
+
+#[tauri::command]
+pub fn get_library_info(state: State<LibraryState>) -> Result<LibraryInfo, String> {
+    let guard = state.0.lock().unwrap();
+    let Some(conn) = guard.as_ref() else {
+        return Ok(LibraryInfo {
+            available: false,
+            images: 0,
+            folders: 0,
+            size: 0,
+            path: None,
+        });
+    };
+
+    let (images, folders): (i64, i64) = conn
+        .query_row(
+            "SELECT COUNT(*), COUNT(DISTINCT folder) FROM images",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    let path = conn.path().map(str::to_string);
+    let size = path
+        .iter()
+        .flat_map(|p| [p.clone(), format!("{}-wal", p)])
+        .filter_map(|p| fs::metadata(p).ok())
+        .map(|m| m.len())
+        .sum();
+
+    Ok(LibraryInfo {
+        available: true,
+        images: images as u64,
+        folders: folders as u64,
+        size,
+        path,
+    })
+}
+
+/// Bring the index of `folder` (and its subfolders when `recursive`) up to
+/// date, hashes included when `hashes`. Emits `library-index-progress`
+/// with `{ done, total }`. Returns the number of files read again.
+#[tauri::command]
+pub async fn index_folder(
+    app: AppHandle,
+    folder: String,
+    recursive: bool,
+    hashes: bool,
+    limits: State<'_, DecodeState>,
+) -> Result<usize, String> {
+    let limits = *limits.0.lock().unwrap();
+
+    if !Path::new(&folder).is_dir() {
+        return Err(format!("Not a folder: {}", folder));
+    }
+
+    // rayon holds the thread it is called on until every file is read
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut files = Vec::new();
+        crate::duplicates::collect_images(Path::new(&folder), recursive, &mut files);
+
+        let read = AtomicUsize::new(0);
+        let state = app.state::<LibraryState>();
+        records(&state, files, hashes, &limits, &|done, total| {
+            read.store(total, Ordering::Relaxed);
+            let _ = app.emit("library-index-progress", IndexProgress { done, total });
+        });
+        Ok(read.into_inner())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn clear_library_index(state: State<LibraryState>) -> Result<(), String> {
+    if let Some(conn) = state.0.lock().unwrap().as_ref() {
+        conn.execute_batch("DELETE FROM images; VACUUM;")
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_in_sql_without_an_index() {
+        let dir = std::env::temp_dir().join(format!("library-select-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<String> = [("img10.jpg", 3), ("img9.jpg", 2), ("img1.jpg", 1)]
+            .iter()
+            .map(|(name, size)| {
+                let path = dir.join(name);
+                fs::write(&path, vec![0u8; *size]).unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+        let state = LibraryState(Mutex::new(None));
+        let limits = DecodeLimits::default();
+        let names = |paths: Vec<String>| -> Vec<String> {
+            paths.iter().map(|p| name_of(p).to_string()).collect()
+        };
+
+        let mut selection = Selection::default();
+        selection.filter("size >= ?".to_string(), vec![Value::from(2)]);
+        selection.order_by("size".to_string());
+        let selected = select(&state, files.clone(), true, &selection, &limits).unwrap();
+        assert_eq!(names(selected), ["img9.jpg", "img10.jpg"]);
+
+        let mut selection = Selection::default();
+        selection.order_by("path COLLATE file_name DESC".to_string());
+        let selected = select(&state, files, true, &selection, &limits).unwrap();
+        assert_eq!(names(selected), ["img10.jpg", "img9.jpg", "img1.jpg"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
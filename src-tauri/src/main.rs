@@ -7,10 +7,49 @@ use std::io::Cursor;
 use std::{fs, path::Path, sync::Mutex};
 use tauri::{Manager, State};
 struct OpenedImage(Mutex<Option<String>>);
+/// Files dropped on the window together, listed in place of their folder
+/// while one of them is open.
+struct DroppedImages(Mutex<Option<Vec<String>>>);
 //use tauri::AppHandle;
 //use tauri_plugin_dialog::DialogExt;
 use std::path::PathBuf;
 
+mod bulk;
+mod clipboard;
+mod color;
+mod compare;
+mod convert;
+mod decode;
+#[cfg(target_os = "linux")]
+mod desktop_apps;
+mod drag_out;
+mod editor_watch;
+mod duplicates;
+mod external_apps;
+mod file_ops;
+mod format_info;
+mod geo;
+mod inspect;
+mod journal;
+mod library;
+mod metadata;
+mod metadata_edit;
+mod ratings;
+mod rename;
+mod search;
+mod tiles;
+use clipboard::ClipboardState;
+use color::ColorState;
+use convert::ConvertState;
+use decode::DecodeState;
+use editor_watch::EditorWatchState;
+use external_apps::{ExternalApps, ExternalAppsState};
+use file_ops::{ConflictPolicy, FileOpError, Sent};
+use inspect::InspectState;
+use journal::{Journal, JournalState, Operation};
+use library::LibraryState;
+use tiles::TileState;
+
 /* use serde::Serialize;
 use std::time::UNIX_EPOCH; */
 
@@ -20,43 +59,50 @@ fn get_opened_image(state: State<OpenedImage>) -> Option<String> {
     state.0.lock().unwrap().clone()
 }
 
+/// `list` is a set of dropped files to go through instead of the folder.
 #[tauri::command]
-fn set_opened_image(path: String, state: State<OpenedImage>) {
+fn set_opened_image(
+    path: String,
+    list: Option<Vec<String>>,
+    state: State<OpenedImage>,
+    dropped: State<DroppedImages>,
+) {
     *state.0.lock().unwrap() = Some(path);
+    *dropped.0.lock().unwrap() = list;
 }
 
-#[tauri::command]
-fn get_folder_images(current_path: String) -> (Vec<String>, usize) {
-    let path = Path::new(&current_path);
-    let dir = path.parent().unwrap();
+fn is_image_file(p: &Path) -> bool {
+    p.extension()
+        .and_then(|e| e.to_str())
+        .map(|ext| {
+            matches!(
+                ext.to_lowercase().as_str(),
+                "jpg"
+                    | "jpeg"
+                    | "png"
+                    | "bmp"
+                    | "gif"
+                    | "webp"
+                    | "ico"
+                    | "avif"
+                    | "cur"
+                    | "tiff"
+                    | "tif"
+                    | "svg"
+                    | "jfif"
+            )
+        })
+        .unwrap_or(false)
+}
 
+/// Images directly inside `dir`, in Explorer order on Windows.
+fn folder_image_list(dir: &Path) -> Vec<String> {
     let mut images: Vec<String> = fs::read_dir(dir)
-        .unwrap()
+        .into_iter()
+        .flatten()
         .filter_map(|e| e.ok())
         .map(|e| e.path())
-        .filter(|p| {
-            p.extension()
-                .and_then(|e| e.to_str())
-                .map(|ext| {
-                    matches!(
-                        ext.to_lowercase().as_str(),
-                        "jpg"
-                            | "jpeg"
-                            | "png"
-                            | "bmp"
-                            | "gif"
-                            | "webp"
-                            | "ico"
-                            | "avif"
-                            | "cur"
-                            | "tiff"
-                            | "tif"
-                            | "svg"
-                            | "jfif"
-                    )
-                })
-                .unwrap_or(false)
-        })
+        .filter(|p| is_image_file(p))
         .map(|p| p.to_string_lossy().to_string())
         .collect();
 
@@ -67,9 +113,84 @@ fn get_folder_images(current_path: String) -> (Vec<String>, usize) {
         explorer_compare(&na, &nb)
     });
 
-    let index = images.iter().position(|p| p == &current_path).unwrap_or(0);
+    images
+}
+
+/// The images among dropped `paths`, a folder standing for the images
+/// directly inside it. Each file is listed once.
+#[tauri::command]
+fn list_dropped_images(paths: Vec<String>) -> Vec<String> {
+    let mut images = Vec::new();
+    for path in paths {
+        let p = Path::new(&path);
+        if p.is_dir() {
+            images.extend(folder_image_list(p));
+        } else if is_image_file(p) {
+            images.push(path);
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    images.retain(|p| seen.insert(p.clone()));
+    images
+}
+
+/// Images in the folder of `current_path`, or the dropped files it is one
+/// of, and the index of that file. `filter` and `sort` narrow and reorder
+/// the list by rating, label, flag and tags; the index is 0 when the file
+/// itself is filtered out.
+#[tauri::command]
+async fn get_folder_images(
+    app: tauri::AppHandle,
+    current_path: String,
+    filter: Option<ratings::MarksFilter>,
+    sort: Option<ratings::MarksSort>,
+) -> Result<(Vec<String>, usize), String> {
+    // listing a large folder and reading its index rows takes a while
+    tauri::async_runtime::spawn_blocking(move || {
+        folder_images(&app, &current_path, filter.as_ref(), sort)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn folder_images(
+    app: &tauri::AppHandle,
+    current_path: &str,
+    filter: Option<&ratings::MarksFilter>,
+    sort: Option<ratings::MarksSort>,
+) -> Result<(Vec<String>, usize), String> {
+    let path = Path::new(current_path);
+
+    let dropped = app
+        .state::<DroppedImages>()
+        .0
+        .lock()
+        .unwrap()
+        .clone()
+        .filter(|list| list.iter().any(|p| p == current_path));
+    let is_dropped = dropped.is_some();
+    let mut images = match dropped {
+        Some(list) => list.into_iter().filter(|p| Path::new(p).exists()).collect(),
+        None => folder_image_list(path.parent().unwrap()),
+    };
+    if filter.is_some() || sort.is_some() {
+        let library = app.state::<LibraryState>();
+        let limits = *app.state::<DecodeState>().0.lock().unwrap();
+        let mut selection = library::Selection::default();
+        if let Some(filter) = filter {
+            filter.select(&mut selection);
+        }
+        if let Some(sort) = sort {
+            sort.select(&mut selection);
+        }
+        // a dropped list isn't the whole folder, the index keeps the rest
+        images = library::select(&library, images, !is_dropped, &selection, &limits)?;
+    }
+
+    let index = images.iter().position(|p| p == current_path).unwrap_or(0);
 
-    (images, index)
+    Ok((images, index))
 }
 
 #[cfg(target_os = "windows")]
@@ -99,6 +220,14 @@ fn main() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .manage(OpenedImage(Mutex::new(None)))
+        .manage(DroppedImages(Mutex::new(None)))
+        .manage(TileState(Mutex::new(Default::default())))
+        .manage(DecodeState(Mutex::new(Default::default())))
+        .manage(ColorState(Mutex::new(Default::default())))
+        .manage(InspectState(Mutex::new(Default::default())))
+        .manage(ConvertState(Mutex::new(None)))
+        .manage(ClipboardState::start())
+        .manage(EditorWatchState::default())
         .setup(|app| {
             let args: Vec<String> = std::env::args().collect();
 
@@ -108,6 +237,27 @@ fn main() {
                 *state.0.lock().unwrap() = Some(path);
             }
 
+            // without the index everything is read from the files
+            let library = app
+                .path()
+                .app_data_dir()
+                .map_err(|e| e.to_string())
+                .and_then(|dir| library::open(&dir))
+                .map_err(|e| eprintln!("Library index unavailable: {}", e))
+                .ok();
+            app.manage(LibraryState(Mutex::new(library)));
+
+            let data_dir = app.path().app_data_dir().ok();
+            app.manage(JournalState(Mutex::new(Journal::load(
+                data_dir.as_deref(),
+                app.handle().clone(),
+            ))));
+
+            let config_dir = app.path().app_config_dir().ok();
+            app.manage(ExternalAppsState(Mutex::new(ExternalApps::load(
+                config_dir.as_deref(),
+            ))));
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -115,9 +265,24 @@ fn main() {
             set_opened_image,
             get_folder_images,
             load_image,
+            get_image_dimensions,
+            tiles::open_tiled_image,
+            tiles::wait_tiled_image,
+            tiles::get_tile,
+            tiles::close_tiled_image,
+            tiles::set_tile_memory_limit,
+            tiles::get_tile_memory_limit,
+            decode::get_decode_limits,
+            decode::set_decode_limits,
+            color::get_color_settings,
+            color::set_color_settings,
+            color::needs_color_conversion,
             load_ico_frames,
             open_with,
             get_open_with_apps,
+            get_other_open_with_apps,
+            external_apps::get_external_apps,
+            external_apps::set_external_apps,
             open_with_app,
             open_with_dialog,
             open_native_print_dialog,
@@ -126,7 +291,39 @@ fn main() {
             set_desktop_background,
             open_in_explorer,
             copy_file,
+            send_to_folder,
+            bulk::bulk_trash,
+            bulk::bulk_send,
+            bulk::bulk_rotate,
+            bulk::bulk_set_marks,
+            clipboard::copy_image_to_clipboard,
+            clipboard::copy_files_to_clipboard,
+            clipboard::paste_image_from_clipboard,
+            drag_out::start_file_drag,
+            list_dropped_images,
             load_image_metadata,
+            metadata::load_metadata_tree,
+            metadata_edit::strip_metadata,
+            metadata_edit::edit_metadata,
+            metadata_edit::load_editable_metadata,
+            geo::get_geotagged_images,
+            compare::compare_images,
+            duplicates::find_duplicates,
+            inspect::compute_histogram,
+            inspect::sample_pixel,
+            inspect::release_inspected_image,
+            ratings::get_image_marks,
+            ratings::set_image_marks,
+            search::search_images,
+            library::index_folder,
+            library::get_library_info,
+            library::clear_library_index,
+            convert::batch_convert,
+            convert::cancel_batch_convert,
+            rename::plan_batch_rename,
+            rename::batch_rename,
+            journal::undo_file_operation,
+            journal::redo_file_operation,
             rename_file,
             show_file_properties
         ])
@@ -134,6 +331,7 @@ fn main() {
         .expect("error running tauri app");
 }
 
+#[cfg(not(target_os = "linux"))]
 fn trusted_apps() -> Vec<&'static str> {
     vec![
         "mspaint.exe",
@@ -200,50 +398,6 @@ fn resolve_app_path(exe: &str) -> Option<String> {
     None
 }
 
-#[cfg(target_os = "linux")]
-fn resolve_app_path(exe: &str) -> Option<String> {
-    use std::process::Command;
-    
-    // Check if it's already an absolute path that exists
-    let path = std::path::Path::new(exe);
-    if path.is_absolute() && path.exists() {
-        return Some(exe.to_string());
-    }
-    
-    // Use `which` to find the executable in PATH
-    let output = Command::new("which")
-        .arg(exe)
-        .output()
-        .ok()?;
-    
-    if output.status.success() {
-        let path_str = String::from_utf8_lossy(&output.stdout)
-            .trim()
-            .to_string();
-        if !path_str.is_empty() {
-            return Some(path_str);
-        }
-    }
-    
-    // Check common Linux application directories
-    let common_paths = [
-        "/usr/bin",
-        "/usr/local/bin",
-        "/opt",
-        "/snap/bin",
-        "/var/lib/flatpak/exports/bin",
-    ];
-    
-    for dir in common_paths {
-        let full_path = format!("{}/{}", dir, exe);
-        if std::path::Path::new(&full_path).exists() {
-            return Some(full_path);
-        }
-    }
-    
-    None
-}
-
 #[cfg(target_os = "windows")]
 fn exe_friendly_name(exe_path: &str) -> Option<String> {
     use std::path::Path;
@@ -352,33 +506,8 @@ fn exe_friendly_name(exe_path: &str) -> Option<String> {
     }
 }
 
-#[cfg(target_os = "linux")]
-fn exe_friendly_name(exe_path: &str) -> Option<String> {
-    use std::path::Path;
-    
-    // For Linux, just return the file stem (filename without extension)
-    Path::new(exe_path)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .map(|s| {
-            // Capitalize first letter for display
-            let mut chars = s.chars();
-            match chars.next() {
-                None => String::new(),
-                Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
-            }
-        })
-}
-
-#[cfg(target_os = "linux")]
-fn extract_icon_fast(_exe_path: &str) -> Option<Vec<u8>> {
-    // Icon extraction is not implemented for Linux
-    // This would require reading .desktop files and icon themes
-    None
-}
-
-#[tauri::command]
-fn get_open_with_apps(_path: String) -> Vec<(String, String, Option<Vec<u8>>)> {
+#[cfg(not(target_os = "linux"))]
+fn system_open_with_apps(_path: &str) -> Vec<(String, String, Option<Vec<u8>>)> {
     let mut apps = Vec::new();
 
     for exe in trusted_apps() {
@@ -407,12 +536,75 @@ fn get_open_with_apps(_path: String) -> Vec<(String, String, Option<Vec<u8>>)> {
     apps
 }
 
+/// The applications registered for the type of `path` in the desktop
+/// entries, the default one first.
+#[cfg(target_os = "linux")]
+fn system_open_with_apps(path: &str) -> Vec<(String, String, Option<Vec<u8>>)> {
+    desktop_apps::menu_entries(desktop_apps::apps_for(path))
+}
+
+/// Icon of a program added to Open With by hand.
+#[cfg(target_os = "windows")]
+fn program_icon(path: &str) -> Option<Vec<u8>> {
+    extract_icon_fast(&resolve_app_path(path).unwrap_or_else(|| path.to_string()))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn program_icon(_path: &str) -> Option<Vec<u8>> {
+    None
+}
+
+/// The Open With menu: the external apps, then the system's.
 #[tauri::command]
-fn load_image(path: String) -> Result<Vec<u8>, String> {
-    let img = ImageReader::open(&path)
-        .map_err(|e| e.to_string())?
-        .decode()
-        .map_err(|e| e.to_string())?;
+async fn get_open_with_apps(
+    path: String,
+    external: State<'_, ExternalAppsState>,
+) -> Result<Vec<(String, String, Option<Vec<u8>>)>, String> {
+    let added = external.0.lock().unwrap().list().to_vec();
+    let mut apps: Vec<_> = added
+        .into_iter()
+        .map(|app| {
+            let icon = program_icon(&app.path);
+            (format!("{}{}", external_apps::ID_PREFIX, app.id), app.label, icon)
+        })
+        .collect();
+    apps.extend(system_open_with_apps(&path));
+    Ok(apps)
+}
+
+/// Applications to choose from where the system has no Open With dialog
+/// of its own, `None` where it has.
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn get_other_open_with_apps(
+    path: String,
+) -> Option<Vec<(String, String, Option<Vec<u8>>)>> {
+    Some(desktop_apps::menu_entries(desktop_apps::other_apps(&path)))
+}
+
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn get_other_open_with_apps(
+    _path: String,
+) -> Option<Vec<(String, String, Option<Vec<u8>>)>> {
+    None
+}
+
+#[tauri::command]
+async fn load_image(
+    path: String,
+    limits: State<'_, DecodeState>,
+    colors: State<'_, ColorState>,
+) -> Result<Vec<u8>, String> {
+    let limits = *limits.0.lock().unwrap();
+    let colors = colors.0.lock().unwrap().clone();
+
+    // Shown as a PNG, which has no EXIF for the webview to turn it by
+    let (mut img, icc) = decode::decode_with_profile(&path, &limits)?;
+    if let Some(orientation) = decode::orientation(&path) {
+        img.apply_orientation(orientation);
+    }
+    let img = color::convert(img, icc.as_deref(), &colors)?;
 
     let mut buf = Vec::new();
     img.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)
@@ -421,6 +613,23 @@ fn load_image(path: String) -> Result<Vec<u8>, String> {
     Ok(buf)
 }
 
+/// Header dimensions, or `None` when the Rust decoders can't read the file
+/// (SVG, AVIF...) and the webview has to deal with it.
+#[tauri::command]
+fn get_image_dimensions(
+    path: String,
+    limits: State<DecodeState>,
+) -> Result<Option<(u32, u32)>, String> {
+    let limits = *limits.0.lock().unwrap();
+
+    let Some((width, height)) = decode::header_dimensions(&path) else {
+        return Ok(None);
+    };
+
+    limits.check_dimensions(width, height)?;
+    Ok(Some((width, height)))
+}
+
 // ----- ICO ------
 
 #[derive(serde::Serialize)]
@@ -441,8 +650,16 @@ fn has_smooth_alpha(rgba: &[u8]) -> bool {
 }
 
 #[tauri::command]
-fn load_ico_frames(path: String) -> Result<Vec<IcoFrame>, String> {
-    let file = File::open(&path).map_err(|e| e.to_string())?;
+async fn load_ico_frames(
+    path: String,
+    limits: State<'_, DecodeState>,
+) -> Result<Vec<IcoFrame>, String> {
+    let limits = *limits.0.lock().unwrap();
+    decode::run_with_timeout(limits.timeout_secs, move || decode_ico_frames(&path, &limits))
+}
+
+fn decode_ico_frames(path: &str, limits: &decode::DecodeLimits) -> Result<Vec<IcoFrame>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
     let icon_dir = IconDir::read(file).map_err(|e| e.to_string())?;
 
     let mut frames = Vec::new();
@@ -450,6 +667,18 @@ fn load_ico_frames(path: String) -> Result<Vec<IcoFrame>, String> {
     for entry in icon_dir.entries() {
         let is_png = entry.is_png();
 
+        // PNG entries can be any size, whatever the directory says
+        if is_png {
+            let mut reader =
+                ImageReader::with_format(Cursor::new(entry.data()), ImageFormat::Png);
+            reader.limits(limits.to_image_limits());
+            let (w, h) = reader.into_dimensions().map_err(|e| e.to_string())?;
+            limits.check_dimensions(w, h)?;
+        } else {
+            // the size from the BMP header, which the decoder allocates for
+            limits.check_dimensions(entry.width(), entry.height())?;
+        }
+
         let icon_image = entry.decode().map_err(|e| e.to_string())?;
 
         let width = icon_image.width();
@@ -509,20 +738,37 @@ fn open_with(path: String) -> Result<(), String> {
 #[cfg(target_os = "linux")]
 #[tauri::command]
 fn open_with(path: String) -> Result<(), String> {
-    use std::process::Command;
-
-    // On Linux, use xdg-open which opens with the default application
-    // or shows an application chooser if configured
-    Command::new("xdg-open")
-        .arg(&path)
-        .spawn()
-        .map_err(|e| e.to_string())?;
+    desktop_apps::open_default(&path)
+}
 
+/// Open `path` with an app of the Open With menu, and watch it for edits
+/// while the app runs.
+#[tauri::command]
+fn open_with_app(
+    handle: tauri::AppHandle,
+    app: String,
+    path: String,
+    external: State<ExternalAppsState>,
+) -> Result<(), String> {
+    let editor = match app.strip_prefix(external_apps::ID_PREFIX) {
+        Some(id) => {
+            let found = external.0.lock().unwrap().find(id).cloned();
+            let found = found.ok_or_else(|| format!("App not found: {}", app))?;
+            external_apps::launch(&found, &path)?
+        }
+        None => open_with_system_app(app, &path)?,
+    };
+    editor_watch::watch(handle, editor, path);
     Ok(())
 }
 
-#[tauri::command]
-fn open_with_app(app: String, path: String) -> Result<(), String> {
+#[cfg(target_os = "linux")]
+fn open_with_system_app(app: String, path: &str) -> Result<std::process::Child, String> {
+    desktop_apps::launch(&app, path)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_with_system_app(app: String, path: &str) -> Result<std::process::Child, String> {
     use std::process::Command;
 
     #[cfg(windows)]
@@ -536,9 +782,7 @@ fn open_with_app(app: String, path: String) -> Result<(), String> {
         Command::new("explorer")
             .arg(format!("shell:AppsFolder\\{}", app))
             .spawn()
-            .map_err(|e| e.to_string())?;
-
-        Ok(())
+            .map_err(|e| e.to_string())
     } 
     else {
         // ── Win32 app (.exe)
@@ -553,8 +797,7 @@ fn open_with_app(app: String, path: String) -> Result<(), String> {
             cmd.creation_flags(CREATE_NO_WINDOW);
         }
 
-        cmd.spawn().map_err(|e| e.to_string())?;
-        Ok(())
+        cmd.spawn().map_err(|e| e.to_string())
     }
 }
 
@@ -601,19 +844,12 @@ fn open_with_dialog(path: String) -> Result<(), String> {
     Ok(())
 }
 
+/// There's no Open With dialog common to Linux desktops, the window lists
+/// `get_other_open_with_apps` instead. This opens the default application.
 #[cfg(target_os = "linux")]
 #[tauri::command]
 fn open_with_dialog(path: String) -> Result<(), String> {
-    use std::process::Command;
-
-    // Try to open with a file manager that supports "Open With" dialogs
-    // First try xdg-open, which may show an app chooser on some desktop environments
-    Command::new("xdg-open")
-        .arg(&path)
-        .spawn()
-        .map_err(|e| e.to_string())?;
-
-    Ok(())
+    desktop_apps::open_default(&path)
 }
 
 #[cfg(target_os = "windows")]
@@ -771,16 +1007,10 @@ fn open_native_print_dialog(path: String) -> Result<(), String> {
 }
 
 #[tauri::command]
-async fn trash_file(path: String) -> Result<(), String> {
-    let path = Path::new(&path);
-    
-    // Validate path exists
-    if !path.exists() {
-        return Err(format!("Path does not exist: {}", path.display()));
-    }
-    
-    trash::delete(path)
-        .map_err(|e| format!("Failed to move to trash: {}", e))
+async fn trash_file(path: String, journal: State<'_, JournalState>) -> Result<(), String> {
+    let op = journal::trash(Path::new(&path))?;
+    journal.0.lock().unwrap().record(op);
+    Ok(())
 }
 
 
@@ -856,10 +1086,78 @@ fn save_file_as(app: AppHandle, path: String) -> Result<(), String> {
     Ok(())
 } */
 
+/// Returns the copy, with the sidecars that weren't copied, `None` when
+/// the policy skipped it.
 #[tauri::command]
-fn copy_file(src: String, dest: String) -> Result<(), String> {
-    std::fs::copy(&src, &dest).map_err(|e| e.to_string())?;
-    Ok(())
+fn copy_file(
+    src: String,
+    dest: String,
+    policy: Option<ConflictPolicy>,
+    journal: State<JournalState>,
+) -> Result<Option<Sent>, FileOpError> {
+    let dest = PathBuf::from(dest);
+    if let Some(name) = dest.file_name() {
+        file_ops::check_file_name(&name.to_string_lossy())?;
+    }
+    let Some(copied) = file_ops::copy_path(Path::new(&src), &dest, policy.unwrap_or_default())?
+    else {
+        return Ok(None);
+    };
+
+    let sent = Sent::from(&copied);
+    let mut ops = copied.replaced;
+    ops.push(Operation::Copy {
+        from: src,
+        to: sent.path.clone(),
+    });
+    if let Some(step) = Operation::group(ops) {
+        journal.0.lock().unwrap().record(step);
+    }
+    Ok(Some(sent))
+}
+
+/// Move or copy `path` into `folder`, keeping its name. Returns the new
+/// path and the sidecars left behind, `None` when the policy skipped it.
+#[tauri::command]
+async fn send_to_folder(
+    path: String,
+    folder: String,
+    copy: bool,
+    policy: Option<ConflictPolicy>,
+    journal: State<'_, JournalState>,
+) -> Result<Option<Sent>, FileOpError> {
+    let from = PathBuf::from(&path);
+    let name = from
+        .file_name()
+        .ok_or_else(|| FileOpError::InvalidName("Invalid path".to_string()))?;
+    let to = Path::new(&folder).join(name);
+    if !copy && file_ops::same_file(&from, &to) {
+        return Ok(Some(Sent {
+            path,
+            warning: None,
+        }));
+    }
+
+    let policy = policy.unwrap_or_default();
+    let placed = match copy {
+        true => file_ops::copy_path(&from, &to, policy)?,
+        false => file_ops::move_path(&from, &to, policy)?,
+    };
+    let Some(placed) = placed else {
+        return Ok(None);
+    };
+
+    let sent = Sent::from(&placed);
+    let to = sent.path.clone();
+    let mut ops = placed.replaced;
+    ops.push(match copy {
+        true => Operation::Copy { from: path, to },
+        false => Operation::Move { from: path, to },
+    });
+    if let Some(step) = Operation::group(ops) {
+        journal.0.lock().unwrap().record(step);
+    }
+    Ok(Some(sent))
 }
 
 /* #[tauri::command]
@@ -894,27 +1192,40 @@ fn rename_file(app: AppHandle, path: String) -> Result<(), String> {
 }
  */
 
+/// Returns the new path, `None` when the policy skipped the rename.
 #[tauri::command]
-fn rename_file(path: String, new_name: String) -> Result<(), String> {
+fn rename_file(
+    path: String,
+    new_name: String,
+    policy: Option<ConflictPolicy>,
+    journal: State<JournalState>,
+) -> Result<Option<String>, FileOpError> {
     let old_path = PathBuf::from(&path);
-    let parent = old_path.parent().ok_or("Invalid path")?;
-    let ext = old_path.extension().and_then(|e| e.to_str()).unwrap_or("");
-
-    let mut new_path = parent.join(&new_name);
-    if !ext.is_empty() {
-        new_path.set_extension(ext);
-    }
+    let new_path = file_ops::renamed_path(&old_path, &new_name)?;
 
-    std::fs::rename(&old_path, &new_path).map_err(|e| e.to_string())?;
+    let Some(renamed) = file_ops::rename_path(&old_path, &new_path, policy.unwrap_or_default())?
+    else {
+        return Ok(None);
+    };
 
-    Ok(())
+    let to = renamed.path.to_string_lossy().to_string();
+    if to != path {
+        let mut ops = renamed.replaced;
+        ops.push(Operation::Move {
+            from: path,
+            to: to.clone(),
+        });
+        if let Some(step) = Operation::group(ops) {
+            journal.0.lock().unwrap().record(step);
+        }
+    }
+    Ok(Some(to))
 }
 
 // ---------------- IMAGE INFO ----------------
 
 use serde::Serialize;
-use rexif::parse_file;
-use image::GenericImageView;
+use rexif::{parse_file, ExifTag};
 
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -933,9 +1244,16 @@ pub struct ImageInfoBackend {
     height: u32,
     file_size: u64,
 
-    color_mode: String,
-    bit_depth: u8,
+    /// Color model stored in the file, what the decoder produces otherwise.
+    color_model: String,
+    channels: u8,
+    bits_per_channel: u8,
     alpha: bool,
+    subsampling: Option<String>,
+    interlaced: bool,
+    progressive: bool,
+    palette_size: Option<u32>,
+    jpeg_quality: Option<u8>,
 
     date_taken: Option<String>,
     camera: Option<String>,
@@ -945,6 +1263,8 @@ pub struct ImageInfoBackend {
     focal: Option<String>,
     flash: Option<String>,
     color_profile: Option<String>,
+    icc_profile: Option<color::ProfileInfo>,
+    gps: Option<geo::GpsPosition>,
 
     full_path: String,
     created: u64,
@@ -952,21 +1272,36 @@ pub struct ImageInfoBackend {
 }
 
 #[tauri::command]
-fn load_image_metadata(path: String) -> Result<ImageInfoBackend, String> {
-
-    let reader = ImageReader::open(&path)
-        .map_err(|e| format!("Failed to open: {}", e))?;
+async fn load_image_metadata(
+    path: String,
+    limits: State<'_, DecodeState>,
+) -> Result<ImageInfoBackend, String> {
+    let limits = *limits.0.lock().unwrap();
+
+    // reads the header, the EXIF and the format details of the file
+    tauri::async_runtime::spawn_blocking(move || image_metadata(path, limits))
+        .await
+        .map_err(|e| e.to_string())?
+}
 
-    let format = reader.format().unwrap_or(ImageFormat::Png);
+fn image_metadata(path: String, limits: decode::DecodeLimits) -> Result<ImageInfoBackend, String> {
+    // Header only, the pixels are not needed for any of this
+    let header = decode::read_header(&path, &limits)?;
 
-    let img = reader.decode()
-        .map_err(|e| format!("Decode failed: {}", e))?;
+    let format = header.format.unwrap_or(ImageFormat::Png);
+    let (width, height) = (header.width, header.height);
+    let color = header.color;
 
-    let (width, height) = img.dimensions();
-    let color = img.color();
+    let details = format_info::read(&path);
+    let decoded_channels = color.channel_count();
 
-    let bit_depth = color.bits_per_pixel() as u8;
-    let color_mode = format!("{:?}", color);
+    let color_model = details
+        .color_model
+        .unwrap_or_else(|| format_info::color_type_name(color).to_string());
+    let channels = details.channels.unwrap_or(decoded_channels);
+    let bits_per_channel = details
+        .bits_per_channel
+        .unwrap_or((color.bits_per_pixel() / decoded_channels as u16) as u8);
     let alpha = color.has_alpha();
 
     let meta = fs::metadata(&path).map_err(|_| "meta fail".to_string())?;
@@ -990,19 +1325,22 @@ fn load_image_metadata(path: String) -> Result<ImageInfoBackend, String> {
     let mut flash = None;
     let mut color_profile = None;
 
+    // An embedded ICC profile says more than the EXIF ColorSpace tag
+    let icc_profile = header.icc.as_deref().and_then(color::describe);
+
     if let Ok(exif) = parse_file(&path) {
         for entry in exif.entries {
             let v = entry.value_more_readable.to_string();
 
-            match entry.tag.to_string().as_str() {
-                "DateTimeOriginal" => date_taken = Some(v),
-                "Model"            => camera = Some(v),
-                "FNumber"          => aperture = Some(v),
-                "ExposureTime"     => shutter = Some(v),
-                "ISOSpeedRatings"  => iso = Some(v),
-                "FocalLength"      => focal = Some(v),
-                "Flash"            => flash = Some(v),
-                "ColorSpace"       => color_profile = Some(v),
+            match entry.tag {
+                ExifTag::DateTimeOriginal => date_taken = Some(v),
+                ExifTag::Model            => camera = Some(v),
+                ExifTag::FNumber          => aperture = Some(v),
+                ExifTag::ExposureTime     => shutter = Some(v),
+                ExifTag::ISOSpeedRatings  => iso = Some(v),
+                ExifTag::FocalLength      => focal = Some(v),
+                ExifTag::Flash            => flash = Some(v),
+                ExifTag::ColorSpace       => color_profile = Some(v),
                 _ => {}
             }
         }
@@ -1015,9 +1353,15 @@ fn load_image_metadata(path: String) -> Result<ImageInfoBackend, String> {
         height,
         file_size: meta.len(),
 
-        color_mode,
-        bit_depth,
+        color_model,
+        channels,
+        bits_per_channel,
         alpha,
+        subsampling: details.subsampling,
+        interlaced: details.interlaced,
+        progressive: details.progressive,
+        palette_size: details.palette_size,
+        jpeg_quality: details.jpeg_quality,
 
         date_taken,
         camera,
@@ -1026,7 +1370,12 @@ fn load_image_metadata(path: String) -> Result<ImageInfoBackend, String> {
         iso,
         focal,
         flash,
-        color_profile,
+        color_profile: icc_profile
+            .as_ref()
+            .map(|p| p.description.clone())
+            .or(color_profile),
+        icc_profile,
+        gps: geo::position_of(&path),
 
         full_path: path,
         created,
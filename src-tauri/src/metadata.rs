@@ -0,0 +1,1492 @@
+// ---------------- METADATA TREE ----------------
+//
+// Full EXIF / IPTC / XMP view for the image info panel. The raw blocks are
+// pulled out of the container (JPEG, PNG, WebP, TIFF) here, EXIF IFDs are
+// walked directly so that IFD1, Interoperability and maker notes show up as
+// well, and rexif supplies names and readable values for the standard tags.
+
+use rexif::{ExifEntry, ExifTag, IfdKind};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::ops::Range;
+
+#[derive(Serialize, Clone)]
+pub struct MetadataNode {
+    name: String,
+    /// Tag or dataset number, shown next to the name and searchable.
+    id: Option<String>,
+    value: Option<String>,
+    children: Vec<MetadataNode>,
+}
+
+impl MetadataNode {
+    fn group(name: impl Into<String>, children: Vec<MetadataNode>) -> Self {
+        MetadataNode {
+            name: name.into(),
+            id: None,
+            value: None,
+            children,
+        }
+    }
+
+    fn leaf(name: impl Into<String>, id: Option<String>, value: impl Into<String>) -> Self {
+        MetadataNode {
+            name: name.into(),
+            id,
+            value: Some(value.into()),
+            children: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct MetadataTree {
+    sections: Vec<MetadataNode>,
+    xmp_raw: Option<String>,
+}
+
+// ----- Containers ------
+
+/// Metadata blocks as stored in the file.
+#[derive(Default)]
+pub struct RawMetadata {
+    /// TIFF structure (starts with `II*\0` or `MM\0*`).
+    pub exif: Option<Vec<u8>>,
+    /// IPTC-IIM datasets.
+    pub iptc: Option<Vec<u8>>,
+    pub xmp: Option<String>,
+}
+
+pub const JPEG_EXIF: &[u8] = b"Exif\0\0";
+pub const JPEG_XMP: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+pub const JPEG_PHOTOSHOP: &[u8] = b"Photoshop 3.0\0";
+pub const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+pub const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+
+pub fn read_raw(path: &str) -> Result<RawMetadata, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to open: {}", e))?;
+    Ok(extract_raw(&data))
+}
+
+/// Like `read_raw`, but only reads the start of JPEG files, where all of
+/// their metadata lives. Meant for scanning whole folders.
+pub fn read_raw_quick(path: &str) -> Result<RawMetadata, String> {
+    const HEAD: u64 = 256 * 1024;
+
+    let mut head = Vec::new();
+    fs::File::open(path)
+        .and_then(|f| f.take(HEAD).read_to_end(&mut head))
+        .map_err(|e| format!("Failed to open: {}", e))?;
+
+    if head.starts_with(&[0xFF, 0xD8]) || (head.len() as u64) < HEAD {
+        return Ok(extract_raw(&head));
+    }
+    read_raw(path)
+}
+
+/// The JPEG thumbnail in IFD1 of an EXIF block, if there is one.
+pub fn exif_thumbnail(exif: &[u8]) -> Option<&[u8]> {
+    const TAG_THUMBNAIL_OFFSET: u16 = 0x0201;
+    const TAG_THUMBNAIL_LENGTH: u16 = 0x0202;
+
+    let tiff = Tiff::new(exif)?;
+    let ifd0 = tiff.read_ifd(tiff.first_ifd()?)?;
+    let ifd1 = Some(ifd0.next)
+        .filter(|&next| next != 0)
+        .and_then(|next| tiff.read_ifd(next as usize))?;
+    let value = |tag| {
+        let entry = ifd1.entries.iter().find(|e| e.tag == tag)?;
+        tiff.value_u32(entry).map(|v| v as usize)
+    };
+
+    let offset = value(TAG_THUMBNAIL_OFFSET)?;
+    exif.get(offset..offset.checked_add(value(TAG_THUMBNAIL_LENGTH)?)?)
+}
+
+pub fn extract_raw(data: &[u8]) -> RawMetadata {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        raw_from_jpeg(data)
+    } else if data.starts_with(PNG_SIGNATURE) {
+        raw_from_png(data)
+    } else if data.len() > 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        raw_from_webp(data)
+    } else if Tiff::new(data).is_some() {
+        raw_from_tiff(data)
+    } else {
+        RawMetadata::default()
+    }
+}
+
+fn strip_exif_prefix(data: &[u8]) -> Vec<u8> {
+    data.strip_prefix(JPEG_EXIF).unwrap_or(data).to_vec()
+}
+
+/// A JPEG marker segment, PNG / RIFF chunk or Photoshop resource. `range`
+/// covers the whole block including its header, `body` only the payload.
+pub struct Block {
+    pub kind: [u8; 4],
+    pub range: Range<usize>,
+    pub body: Range<usize>,
+}
+
+/// Marker segments before the image data (the marker is `kind[0]`), and the
+/// position of the first byte that is not part of them.
+pub fn jpeg_segments(data: &[u8]) -> (Vec<Block>, usize) {
+    let mut blocks = Vec::new();
+    let mut pos = 2;
+
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            break;
+        }
+        let marker = data[pos + 1];
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        if (0xD0..=0xD7).contains(&marker) || marker == 0x01 {
+            pos += 2;
+            continue;
+        }
+
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if len < 2 || pos + 2 + len > data.len() {
+            break;
+        }
+        blocks.push(Block {
+            kind: [marker, 0, 0, 0],
+            range: pos..pos + 2 + len,
+            body: pos + 4..pos + 2 + len,
+        });
+        pos += 2 + len;
+    }
+
+    (blocks, pos)
+}
+
+fn raw_from_jpeg(data: &[u8]) -> RawMetadata {
+    let mut raw = RawMetadata::default();
+
+    for seg in jpeg_segments(data).0 {
+        let body = &data[seg.body];
+        match seg.kind[0] {
+            0xE1 if body.starts_with(JPEG_EXIF) && raw.exif.is_none() => {
+                raw.exif = Some(body[JPEG_EXIF.len()..].to_vec());
+            }
+            0xE1 if body.starts_with(JPEG_XMP) && raw.xmp.is_none() => {
+                raw.xmp = Some(String::from_utf8_lossy(&body[JPEG_XMP.len()..]).into_owned());
+            }
+            0xED if body.starts_with(JPEG_PHOTOSHOP) => {
+                let resources = &body[JPEG_PHOTOSHOP.len()..];
+                if let Some(iptc) = photoshop_resources(resources)
+                    .into_iter()
+                    .find(|r| r.kind[..2] == PHOTOSHOP_IPTC.to_be_bytes())
+                {
+                    raw.iptc = Some(resources[iptc.body].to_vec());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    raw
+}
+
+pub const PHOTOSHOP_IPTC: u16 = 0x0404;
+
+/// Photoshop image resources ("8BIM" blocks), the id is in `kind[..2]`.
+pub fn photoshop_resources(data: &[u8]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut pos = 0;
+
+    while pos + 12 <= data.len() && &data[pos..pos + 4] == b"8BIM" {
+        // Pascal string name, padded to an even length
+        let name_len = data[pos + 6] as usize;
+        let size_pos = pos + 6 + ((1 + name_len + 1) & !1);
+
+        let Some(size) = data.get(size_pos..size_pos + 4) else {
+            break;
+        };
+        let size = u32::from_be_bytes(size.try_into().unwrap()) as usize;
+        let start = size_pos + 4;
+        if start + size > data.len() {
+            break;
+        }
+
+        let end = (start + ((size + 1) & !1)).min(data.len());
+        blocks.push(Block {
+            kind: [data[pos + 4], data[pos + 5], 0, 0],
+            range: pos..end,
+            body: start..start + size,
+        });
+        pos = end;
+    }
+
+    blocks
+}
+
+/// Every PNG chunk up to and including IEND.
+pub fn png_chunks(data: &[u8]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+
+    while pos + 12 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = data[pos + 4..pos + 8].try_into().unwrap();
+        if pos + 12 + len > data.len() {
+            break;
+        }
+        blocks.push(Block {
+            kind,
+            range: pos..pos + 12 + len,
+            body: pos + 8..pos + 8 + len,
+        });
+        if &kind == b"IEND" {
+            break;
+        }
+        pos += 12 + len;
+    }
+
+    blocks
+}
+
+fn raw_from_png(data: &[u8]) -> RawMetadata {
+    let mut raw = RawMetadata::default();
+
+    for chunk in png_chunks(data) {
+        let body = &data[chunk.body];
+        match &chunk.kind {
+            b"eXIf" => raw.exif = Some(strip_exif_prefix(body)),
+            b"iTXt" => {
+                if let Some(text) = png_xmp(body) {
+                    raw.xmp = Some(text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    raw
+}
+
+/// XMP packet of an iTXt chunk, if it is the (uncompressed) XMP one.
+pub fn png_xmp(body: &[u8]) -> Option<String> {
+    // keyword\0 flag method language\0 translated\0 text
+    let rest = body.strip_prefix(PNG_XMP_KEYWORD)?;
+    if rest.len() < 3 || rest[0] != 0 || rest[1] != 0 {
+        return None; // compressed XMP is not supported
+    }
+    let mut parts = rest[3..].splitn(3, |&b| b == 0);
+    let (_, _, text) = (parts.next(), parts.next(), parts.next());
+    text.map(|t| String::from_utf8_lossy(t).into_owned())
+}
+
+/// Every RIFF chunk of a WebP file.
+pub fn webp_chunks(data: &[u8]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut pos = 12;
+
+    while pos + 8 <= data.len() {
+        let kind: [u8; 4] = data[pos..pos + 4].try_into().unwrap();
+        let len = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        if pos + 8 + len > data.len() {
+            break;
+        }
+        let end = (pos + 8 + len + (len & 1)).min(data.len());
+        blocks.push(Block {
+            kind,
+            range: pos..end,
+            body: pos + 8..pos + 8 + len,
+        });
+        pos = end;
+    }
+
+    blocks
+}
+
+fn raw_from_webp(data: &[u8]) -> RawMetadata {
+    let mut raw = RawMetadata::default();
+
+    for chunk in webp_chunks(data) {
+        let body = &data[chunk.body];
+        match &chunk.kind {
+            b"EXIF" => raw.exif = Some(strip_exif_prefix(body)),
+            b"XMP " => raw.xmp = Some(String::from_utf8_lossy(body).into_owned()),
+            _ => {}
+        }
+    }
+
+    raw
+}
+
+pub const TAG_XMP: u16 = 0x02BC;
+pub const TAG_IPTC: u16 = 0x83BB;
+
+/// TIFF tags that describe how the image data is stored. They are kept
+/// when stripping a TIFF file and left out of its EXIF block.
+pub const STRUCTURAL_TAGS: [u16; 37] = [
+    0x00FE, 0x00FF, 0x0100, 0x0101, 0x0102, 0x0103, 0x0106, 0x0107, 0x010A, 0x0111, 0x0112, 0x0115,
+    0x0116, 0x0117, 0x0118, 0x0119, 0x011A, 0x011B, 0x011C, 0x0122, 0x0123, 0x0128, 0x013D, 0x013E,
+    0x013F, 0x0140, 0x0142, 0x0143, 0x0144, 0x0145, 0x014A, 0x0152, 0x0153, 0x015B, 0x0212, 0x0213,
+    0x8773,
+];
+
+/// IFD0 tags of a TIFF file that are no EXIF: XMP and IPTC, read as
+/// blocks of their own, and Photoshop's resources and layers.
+const NON_EXIF_TAGS: [u16; 4] = [TAG_XMP, TAG_IPTC, 0x8649, 0x935C];
+
+const TAG_ORIENTATION: u16 = 0x0112;
+
+fn raw_from_tiff(data: &[u8]) -> RawMetadata {
+    let mut raw = RawMetadata::default();
+
+    let Some(tiff) = Tiff::new(data) else {
+        return raw;
+    };
+    let Some(ifd0) = tiff.first_ifd().and_then(|o| tiff.read_ifd(o)) else {
+        return raw;
+    };
+
+    let mut make = None;
+    for entry in &ifd0.entries {
+        match entry.tag {
+            TAG_XMP => {
+                raw.xmp = tiff
+                    .value_bytes(entry)
+                    .map(|b| String::from_utf8_lossy(b).into_owned());
+            }
+            TAG_IPTC => raw.iptc = tiff.value_bytes(entry).map(|b| b.to_vec()),
+            TAG_MAKE => make = Some(tiff.format_value(entry)),
+            _ => {}
+        }
+    }
+
+    raw.exif = exif_from_tiff(&tiff, make.as_deref());
+    raw
+}
+
+/// The metadata of IFD0 and the Exif, GPS and Interoperability IFDs of a
+/// TIFF file as an EXIF block of its own, without the image data.
+fn exif_from_tiff(tiff: &Tiff, make: Option<&str>) -> Option<Vec<u8>> {
+    let mut out = if tiff.le { b"II*\0" } else { b"MM\0*" }.to_vec();
+    out.extend([0; 4]);
+
+    let mut visited = HashSet::new();
+    let ifd0 = copy_ifd(tiff, tiff.first_ifd()?, 0, make, &mut visited, &mut out)?;
+    out[4..8].copy_from_slice(&tiff.u32_bytes(ifd0));
+    Some(out)
+}
+
+/// Append the IFD at `offset` to `out`, its values and the IFDs it points
+/// to first. `depth` is 0 for IFD0. Returns where the IFD starts, `None`
+/// if none of its entries are kept.
+fn copy_ifd(
+    tiff: &Tiff,
+    offset: usize,
+    depth: usize,
+    make: Option<&str>,
+    visited: &mut HashSet<usize>,
+    out: &mut Vec<u8>,
+) -> Option<u32> {
+    if !visited.insert(offset) {
+        return None;
+    }
+    let ifd = tiff.read_ifd(offset)?;
+    let mut entries = Vec::new();
+
+    for entry in &ifd.entries {
+        let image_data = (STRUCTURAL_TAGS.contains(&entry.tag) && entry.tag != TAG_ORIENTATION)
+            || NON_EXIF_TAGS.contains(&entry.tag);
+        if depth == 0 && image_data {
+            continue;
+        }
+        let Some(bytes) = tiff.value_bytes(entry) else {
+            continue;
+        };
+
+        let mut field = [0u8; 4];
+        if matches!(entry.tag, TAG_EXIF_IFD | TAG_GPS_IFD | TAG_INTEROP_IFD) {
+            // IFD0 > Exif > Interoperability
+            let Some(sub) = tiff.value_u32(entry).filter(|_| depth < 2) else {
+                continue;
+            };
+            let Some(sub) = copy_ifd(tiff, sub as usize, depth + 1, make, visited, out) else {
+                continue;
+            };
+            field = tiff.u32_bytes(sub);
+        } else if bytes.len() <= 4 {
+            field[..bytes.len()].copy_from_slice(bytes);
+        } else {
+            if out.len() % 2 == 1 {
+                out.push(0);
+            }
+            let at = out.len();
+            out.extend_from_slice(bytes);
+            if entry.tag == TAG_MAKER_NOTE {
+                relocate_maker_note(tiff, tiff.value_pos(entry)?, at, bytes, make, out);
+            }
+            field = tiff.u32_bytes(u32::try_from(at).ok()?);
+        }
+
+        let mut copy = [0u8; 12];
+        copy[..8].copy_from_slice(&tiff.data[entry.pos..entry.pos + 8]);
+        copy[8..].copy_from_slice(&field);
+        entries.push(copy);
+    }
+
+    if entries.is_empty() {
+        return None;
+    }
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+    let at = u32::try_from(out.len()).ok()?;
+    out.extend(&tiff.u16_bytes(entries.len() as u16));
+    for entry in &entries {
+        out.extend(entry);
+    }
+    out.extend([0; 4]);
+    Some(at)
+}
+
+/// Maker notes whose offsets count from the start of the file (Canon, Sony,
+/// Panasonic...) are moved with the copy at `to` of the note at `from`.
+fn relocate_maker_note(
+    tiff: &Tiff,
+    from: usize,
+    to: usize,
+    note: &[u8],
+    make: Option<&str>,
+    out: &mut [u8],
+) {
+    let Some(layout) = maker_note_layout(tiff, from, note, make) else {
+        return;
+    };
+    if layout.base != 0 {
+        return;
+    }
+    let Some(ifd) = layout.tiff.read_ifd(layout.ifd_offset) else {
+        return;
+    };
+    let inside = from..from + note.len();
+
+    for entry in &ifd.entries {
+        let size = format_size(entry.format).saturating_mul(entry.count as usize);
+        let Some(value) = tiff.u32_at(entry.pos + 8).map(|v| v as usize) else {
+            continue;
+        };
+        if size <= 4 || entry.pos + 12 > inside.end || !inside.contains(&value) {
+            continue;
+        }
+        let Ok(moved) = u32::try_from(value - from + to) else {
+            continue;
+        };
+        let pos = entry.pos - from + to + 8;
+        out[pos..pos + 4].copy_from_slice(&tiff.u32_bytes(moved));
+    }
+}
+
+// ----- TIFF / IFD walking ------
+
+pub struct Tiff<'a> {
+    data: &'a [u8],
+    le: bool,
+}
+
+pub struct IfdEntry {
+    pub tag: u16,
+    pub format: u16,
+    pub count: u32,
+    /// Position of the 12 byte entry itself.
+    pub pos: usize,
+}
+
+pub struct Ifd {
+    pub entries: Vec<IfdEntry>,
+    pub next: u32,
+}
+
+/// IFDs with more entries than this are treated as garbage.
+const MAX_IFD_ENTRIES: usize = 1000;
+
+pub fn format_size(format: u16) -> usize {
+    match format {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 | 13 => 4,
+        5 | 10 | 12 => 8,
+        _ => 0,
+    }
+}
+
+impl<'a> Tiff<'a> {
+    pub fn new(data: &'a [u8]) -> Option<Self> {
+        match data.get(..4)? {
+            b"II*\0" => Some(Tiff { data, le: true }),
+            b"MM\0*" => Some(Tiff { data, le: false }),
+            _ => None,
+        }
+    }
+
+    pub fn with_order(data: &'a [u8], le: bool) -> Self {
+        Tiff { data, le }
+    }
+
+    pub fn little_endian(&self) -> bool {
+        self.le
+    }
+
+    pub fn u16_at(&self, pos: usize) -> Option<u16> {
+        let b: [u8; 2] = self.data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if self.le {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    }
+
+    pub fn u32_at(&self, pos: usize) -> Option<u32> {
+        let b: [u8; 4] = self.data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.le {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    fn u16_bytes(&self, value: u16) -> [u8; 2] {
+        if self.le {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    }
+
+    fn u32_bytes(&self, value: u32) -> [u8; 4] {
+        if self.le {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    }
+
+    pub fn first_ifd(&self) -> Option<usize> {
+        self.u32_at(4).map(|o| o as usize)
+    }
+
+    pub fn read_ifd(&self, offset: usize) -> Option<Ifd> {
+        let count = self.u16_at(offset)? as usize;
+        if count == 0 || count > MAX_IFD_ENTRIES {
+            return None;
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for i in 0..count {
+            let pos = offset + 2 + i * 12;
+            entries.push(IfdEntry {
+                tag: self.u16_at(pos)?,
+                format: self.u16_at(pos + 2)?,
+                count: self.u32_at(pos + 4)?,
+                pos,
+            });
+        }
+
+        let next = self.u32_at(offset + 2 + count * 12).unwrap_or(0);
+        Some(Ifd { entries, next })
+    }
+
+    /// Position of the value, inline or behind the offset.
+    pub fn value_pos(&self, entry: &IfdEntry) -> Option<usize> {
+        let size = format_size(entry.format).checked_mul(entry.count as usize)?;
+        if size <= 4 {
+            Some(entry.pos + 8)
+        } else {
+            self.u32_at(entry.pos + 8).map(|o| o as usize)
+        }
+    }
+
+    pub fn value_bytes(&self, entry: &IfdEntry) -> Option<&'a [u8]> {
+        let size = format_size(entry.format).checked_mul(entry.count as usize)?;
+        let pos = self.value_pos(entry)?;
+        self.data.get(pos..pos.checked_add(size)?)
+    }
+
+    /// First value as an unsigned number (offsets, counts...).
+    pub fn value_u32(&self, entry: &IfdEntry) -> Option<u32> {
+        let pos = self.value_pos(entry)?;
+        match entry.format {
+            1 | 7 => self.data.get(pos).map(|&b| b as u32),
+            3 => self.u16_at(pos).map(|v| v as u32),
+            4 | 13 => self.u32_at(pos),
+            _ => None,
+        }
+    }
+
+    pub fn rational_at(&self, pos: usize, signed: bool) -> Option<f64> {
+        let n = self.u32_at(pos)?;
+        let d = self.u32_at(pos + 4)?;
+        if d == 0 {
+            return None;
+        }
+        Some(if signed {
+            n as i32 as f64 / d as i32 as f64
+        } else {
+            n as f64 / d as f64
+        })
+    }
+
+    /// Generic readable form of any entry value.
+    pub fn format_value(&self, entry: &IfdEntry) -> String {
+        let Some(bytes) = self.value_bytes(entry) else {
+            return "(invalid)".to_string();
+        };
+        let count = entry.count as usize;
+        let shown = count.min(16);
+        let more = if count > shown { ", …" } else { "" };
+
+        let list = |f: &dyn Fn(usize) -> Option<String>| -> String {
+            let items: Vec<String> = (0..shown).filter_map(f).collect();
+            items.join(", ") + more
+        };
+        let pos = self.value_pos(entry).unwrap_or(0);
+
+        match entry.format {
+            2 => String::from_utf8_lossy(bytes)
+                .trim_end_matches('\0')
+                .trim()
+                .to_string(),
+            1 | 7 => {
+                let text = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+                if !text.is_empty() && text.iter().all(|&b| b == b' ' || b.is_ascii_graphic()) {
+                    String::from_utf8_lossy(text).into_owned()
+                } else if count <= 16 {
+                    bytes
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                } else {
+                    format!("({} bytes)", count)
+                }
+            }
+            6 => list(&|i| bytes.get(i).map(|&b| (b as i8).to_string())),
+            3 => list(&|i| self.u16_at(pos + i * 2).map(|v| v.to_string())),
+            8 => list(&|i| self.u16_at(pos + i * 2).map(|v| (v as i16).to_string())),
+            4 | 13 => list(&|i| self.u32_at(pos + i * 4).map(|v| v.to_string())),
+            9 => list(&|i| self.u32_at(pos + i * 4).map(|v| (v as i32).to_string())),
+            5 | 10 => list(&|i| {
+                let n = self.u32_at(pos + i * 8)?;
+                let d = self.u32_at(pos + i * 8 + 4)?;
+                Some(if entry.format == 10 {
+                    format!("{}/{}", n as i32, d as i32)
+                } else {
+                    format!("{}/{}", n, d)
+                })
+            }),
+            11 => list(&|i| {
+                self.u32_at(pos + i * 4)
+                    .map(|v| f32::from_bits(v).to_string())
+            }),
+            12 => list(&|i| {
+                let hi = self.u32_at(pos + i * 8)? as u64;
+                let lo = self.u32_at(pos + i * 8 + 4)? as u64;
+                let bits = if self.le {
+                    (lo << 32) | hi
+                } else {
+                    (hi << 32) | lo
+                };
+                Some(f64::from_bits(bits).to_string())
+            }),
+            _ => format!("({} values of unknown type {})", count, entry.format),
+        }
+    }
+}
+
+// ----- EXIF ------
+
+pub const TAG_EXIF_IFD: u16 = 0x8769;
+pub const TAG_GPS_IFD: u16 = 0x8825;
+pub const TAG_INTEROP_IFD: u16 = 0xA005;
+pub const TAG_MAKER_NOTE: u16 = 0x927C;
+pub const TAG_MAKE: u16 = 0x010F;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Group {
+    Ifd0,
+    Ifd1,
+    Exif,
+    Gps,
+    Interop,
+}
+
+impl Group {
+    fn title(self) -> &'static str {
+        match self {
+            Group::Ifd0 => "IFD0 (Main Image)",
+            Group::Ifd1 => "IFD1 (Thumbnail)",
+            Group::Exif => "Exif",
+            Group::Gps => "GPS",
+            Group::Interop => "Interoperability",
+        }
+    }
+
+    fn from_rexif(kind: IfdKind) -> Option<Group> {
+        match kind {
+            IfdKind::Ifd0 => Some(Group::Ifd0),
+            IfdKind::Ifd1 => Some(Group::Ifd1),
+            IfdKind::Exif => Some(Group::Exif),
+            IfdKind::Gps => Some(Group::Gps),
+            IfdKind::Interoperability => Some(Group::Interop),
+            IfdKind::Makernote => None,
+        }
+    }
+}
+
+/// Names for tags rexif does not parse (IFD1, Interoperability).
+fn extra_tag_name(tag: u16) -> Option<&'static str> {
+    Some(match tag {
+        0x0001 => "InteroperabilityIndex",
+        0x0002 => "InteroperabilityVersion",
+        0x00FE => "NewSubfileType",
+        0x0100 => "ImageWidth",
+        0x0101 => "ImageLength",
+        0x0102 => "BitsPerSample",
+        0x0103 => "Compression",
+        0x0106 => "PhotometricInterpretation",
+        0x0111 => "StripOffsets",
+        0x0112 => "Orientation",
+        0x0115 => "SamplesPerPixel",
+        0x0116 => "RowsPerStrip",
+        0x0117 => "StripByteCounts",
+        0x011A => "XResolution",
+        0x011B => "YResolution",
+        0x011C => "PlanarConfiguration",
+        0x0128 => "ResolutionUnit",
+        0x013D => "Predictor",
+        0x0140 => "ColorMap",
+        0x0142 => "TileWidth",
+        0x0143 => "TileLength",
+        0x0144 => "TileOffsets",
+        0x0145 => "TileByteCounts",
+        0x0152 => "ExtraSamples",
+        0x0153 => "SampleFormat",
+        0x0201 => "ThumbnailOffset",
+        0x0202 => "ThumbnailLength",
+        0x0213 => "YCbCrPositioning",
+        0x1000 => "RelatedImageFileFormat",
+        0x1001 => "RelatedImageWidth",
+        0x1002 => "RelatedImageLength",
+        0x02BC => "XMP",
+        0x83BB => "IPTC-NAA",
+        0x8649 => "PhotoshopSettings",
+        0x8773 => "ICCProfile",
+        0x9C9B => "XPTitle",
+        0x9C9C => "XPComment",
+        0x9C9D => "XPAuthor",
+        0x9C9E => "XPKeywords",
+        0x9C9F => "XPSubject",
+        _ => return None,
+    })
+}
+
+fn exif_nodes(exif: &[u8]) -> Vec<MetadataNode> {
+    let Some(tiff) = Tiff::new(exif) else {
+        return vec![MetadataNode::leaf("Error", None, "Invalid EXIF block")];
+    };
+
+    // Names and readable values of the standard tags
+    let mut known: HashMap<(Group, u16), ExifEntry> = HashMap::new();
+    if let Ok(parsed) = rexif::parse_buffer_quiet(exif).0 {
+        for entry in parsed.entries {
+            if let Some(group) = Group::from_rexif(entry.kind) {
+                known.insert((group, entry.ifd.tag), entry);
+            }
+        }
+    }
+
+    let mut visited = HashSet::new();
+    let mut groups: Vec<(Group, Vec<MetadataNode>)> = Vec::new();
+    let mut maker_notes = Vec::new();
+    let mut make = None;
+
+    // (group, offset) still to walk
+    let mut pending = Vec::new();
+    if let Some(offset) = tiff.first_ifd() {
+        pending.push((Group::Ifd0, offset));
+    }
+
+    while let Some((group, offset)) = pending.pop() {
+        if !visited.insert(offset) {
+            continue;
+        }
+        let Some(ifd) = tiff.read_ifd(offset) else {
+            continue;
+        };
+
+        if group == Group::Ifd0 && ifd.next != 0 {
+            pending.push((Group::Ifd1, ifd.next as usize));
+        }
+
+        let mut nodes = Vec::new();
+        for entry in &ifd.entries {
+            let sub = match entry.tag {
+                TAG_EXIF_IFD => Some(Group::Exif),
+                TAG_GPS_IFD => Some(Group::Gps),
+                TAG_INTEROP_IFD => Some(Group::Interop),
+                _ => None,
+            };
+            if let Some(sub) = sub {
+                if let Some(o) = tiff.value_u32(entry) {
+                    pending.push((sub, o as usize));
+                }
+                continue;
+            }
+
+            if entry.tag == TAG_MAKE && group == Group::Ifd0 {
+                make = Some(tiff.format_value(entry));
+            }
+            if entry.tag == TAG_MAKER_NOTE {
+                maker_notes.push(entry.pos);
+                continue;
+            }
+
+            let id = Some(format!("0x{:04X}", entry.tag));
+            let node = match known.get(&(group, entry.tag)) {
+                Some(k) if k.tag != ExifTag::UnknownToMe => MetadataNode::leaf(
+                    format!("{:?}", k.tag),
+                    id,
+                    k.value_more_readable.to_string(),
+                ),
+                _ => {
+                    let name = extra_tag_name(entry.tag)
+                        .map(str::to_string)
+                        .unwrap_or_else(|| format!("Tag 0x{:04X}", entry.tag));
+                    MetadataNode::leaf(name, id, tiff.format_value(entry))
+                }
+            };
+            nodes.push(node);
+        }
+
+        match groups.iter_mut().find(|(g, _)| *g == group) {
+            Some((_, existing)) => existing.extend(nodes),
+            None => groups.push((group, nodes)),
+        }
+    }
+
+    let order = [
+        Group::Ifd0,
+        Group::Exif,
+        Group::Gps,
+        Group::Interop,
+        Group::Ifd1,
+    ];
+    let mut out: Vec<MetadataNode> = order
+        .iter()
+        .filter_map(|g| groups.iter().find(|(k, _)| k == g))
+        .filter(|(_, nodes)| !nodes.is_empty())
+        .map(|(g, nodes)| MetadataNode::group(g.title(), nodes.clone()))
+        .collect();
+
+    for pos in maker_notes {
+        let entry = IfdEntry {
+            tag: TAG_MAKER_NOTE,
+            format: tiff.u16_at(pos + 2).unwrap_or(7),
+            count: tiff.u32_at(pos + 4).unwrap_or(0),
+            pos,
+        };
+        out.push(maker_note_node(&tiff, &entry, make.as_deref()));
+    }
+
+    out
+}
+
+// ----- Maker notes ------
+
+#[derive(Clone, Copy, PartialEq)]
+enum Brand {
+    Canon,
+    Nikon,
+    Sony,
+    Fujifilm,
+    Olympus,
+    Panasonic,
+    Pentax,
+    Apple,
+}
+
+impl Brand {
+    fn name(self) -> &'static str {
+        match self {
+            Brand::Canon => "Canon",
+            Brand::Nikon => "Nikon",
+            Brand::Sony => "Sony",
+            Brand::Fujifilm => "Fujifilm",
+            Brand::Olympus => "Olympus",
+            Brand::Panasonic => "Panasonic",
+            Brand::Pentax => "Pentax",
+            Brand::Apple => "Apple",
+        }
+    }
+
+    fn tag_name(self, tag: u16) -> Option<&'static str> {
+        Some(match (self, tag) {
+            (Brand::Canon, 0x0001) => "CanonCameraSettings",
+            (Brand::Canon, 0x0002) => "CanonFocalLength",
+            (Brand::Canon, 0x0004) => "CanonShotInfo",
+            (Brand::Canon, 0x0006) => "CanonImageType",
+            (Brand::Canon, 0x0007) => "CanonFirmwareVersion",
+            (Brand::Canon, 0x0008) => "FileNumber",
+            (Brand::Canon, 0x0009) => "OwnerName",
+            (Brand::Canon, 0x000C) => "SerialNumber",
+            (Brand::Canon, 0x0010) => "CanonModelID",
+            (Brand::Canon, 0x0095) => "LensModel",
+            (Brand::Canon, 0x0096) => "InternalSerialNumber",
+
+            (Brand::Nikon, 0x0001) => "MakerNoteVersion",
+            (Brand::Nikon, 0x0002) => "ISO",
+            (Brand::Nikon, 0x0004) => "Quality",
+            (Brand::Nikon, 0x0005) => "WhiteBalance",
+            (Brand::Nikon, 0x0007) => "FocusMode",
+            (Brand::Nikon, 0x000B) => "WhiteBalanceFineTune",
+            (Brand::Nikon, 0x001D) => "SerialNumber",
+            (Brand::Nikon, 0x0022) => "ActiveD-Lighting",
+            (Brand::Nikon, 0x0083) => "LensType",
+            (Brand::Nikon, 0x0084) => "Lens",
+            (Brand::Nikon, 0x0093) => "NEFCompression",
+            (Brand::Nikon, 0x00A7) => "ShutterCount",
+
+            (Brand::Sony, 0x0102) => "Quality",
+            (Brand::Sony, 0x0104) => "FlashExposureComp",
+            (Brand::Sony, 0x0115) => "WhiteBalance",
+            (Brand::Sony, 0xB000) => "FileFormat",
+            (Brand::Sony, 0xB001) => "SonyModelID",
+            (Brand::Sony, 0xB027) => "LensType",
+            (Brand::Sony, 0xB041) => "ExposureMode",
+
+            (Brand::Fujifilm, 0x0000) => "Version",
+            (Brand::Fujifilm, 0x0010) => "InternalSerialNumber",
+            (Brand::Fujifilm, 0x1000) => "Quality",
+            (Brand::Fujifilm, 0x1001) => "Sharpness",
+            (Brand::Fujifilm, 0x1002) => "WhiteBalance",
+            (Brand::Fujifilm, 0x1003) => "Saturation",
+            (Brand::Fujifilm, 0x100A) => "WhiteBalanceFineTune",
+            (Brand::Fujifilm, 0x1010) => "FujiFlashMode",
+            (Brand::Fujifilm, 0x1021) => "FocusMode",
+            (Brand::Fujifilm, 0x1401) => "FilmMode",
+            (Brand::Fujifilm, 0x1402) => "DynamicRangeSetting",
+
+            (Brand::Olympus, 0x0200) => "SpecialMode",
+            (Brand::Olympus, 0x0207) => "CameraType",
+            (Brand::Olympus, 0x0209) => "CameraID",
+            (Brand::Olympus, 0x2010) => "Equipment",
+            (Brand::Olympus, 0x2020) => "CameraSettings",
+
+            (Brand::Panasonic, 0x0001) => "ImageQuality",
+            (Brand::Panasonic, 0x0002) => "FirmwareVersion",
+            (Brand::Panasonic, 0x0003) => "WhiteBalance",
+            (Brand::Panasonic, 0x0007) => "FocusMode",
+            (Brand::Panasonic, 0x001A) => "ImageStabilization",
+            (Brand::Panasonic, 0x0025) => "InternalSerialNumber",
+            (Brand::Panasonic, 0x0051) => "LensType",
+            (Brand::Panasonic, 0x0052) => "LensSerialNumber",
+
+            (Brand::Pentax, 0x0000) => "PentaxVersion",
+            (Brand::Pentax, 0x0005) => "PentaxModelID",
+            (Brand::Pentax, 0x0008) => "Quality",
+            (Brand::Pentax, 0x0229) => "SerialNumber",
+
+            (Brand::Apple, 0x0001) => "MakerNoteVersion",
+            (Brand::Apple, 0x000A) => "HDRImageType",
+            (Brand::Apple, 0x0011) => "ContentIdentifier",
+            _ => return None,
+        })
+    }
+}
+
+/// Where a maker note IFD lives and how its offsets are resolved.
+pub struct MakerNoteLayout<'a> {
+    brand: Brand,
+    /// TIFF the IFD offsets are relative to.
+    pub tiff: Tiff<'a>,
+    /// Position of `tiff` inside the main EXIF block.
+    pub base: usize,
+    pub ifd_offset: usize,
+}
+
+impl MakerNoteLayout<'_> {
+    pub fn tag_name(&self, tag: u16) -> Option<&'static str> {
+        self.brand.tag_name(tag)
+    }
+}
+
+pub fn maker_note_layout<'a>(
+    main: &Tiff<'a>,
+    note_pos: usize,
+    note: &'a [u8],
+    make: Option<&str>,
+) -> Option<MakerNoteLayout<'a>> {
+    let make = make.unwrap_or("").to_ascii_lowercase();
+    let same = |brand: Brand, skip: usize| MakerNoteLayout {
+        brand,
+        tiff: Tiff::with_order(main.data, main.le),
+        base: 0,
+        ifd_offset: note_pos + skip,
+    };
+
+    if note.starts_with(b"Nikon\0") {
+        // Nikon type 3: its own TIFF header after a 10 byte prefix
+        let inner = Tiff::new(note.get(10..)?)?;
+        let ifd_offset = inner.first_ifd()?;
+        return Some(MakerNoteLayout {
+            brand: Brand::Nikon,
+            tiff: inner,
+            base: note_pos + 10,
+            ifd_offset,
+        });
+    }
+    if note.starts_with(b"FUJIFILM") {
+        let inner = Tiff::with_order(note, true);
+        let ifd_offset = inner.u32_at(8)? as usize;
+        return Some(MakerNoteLayout {
+            brand: Brand::Fujifilm,
+            tiff: inner,
+            base: note_pos,
+            ifd_offset,
+        });
+    }
+    if note.starts_with(b"OLYMPUS\0") || note.starts_with(b"OM SYSTEM\0") {
+        let header = if note.starts_with(b"OLYMPUS\0") {
+            8
+        } else {
+            12
+        };
+        let le = note.get(header..header + 2)? == b"II";
+        return Some(MakerNoteLayout {
+            brand: Brand::Olympus,
+            tiff: Tiff::with_order(note, le),
+            base: note_pos,
+            ifd_offset: header + 4,
+        });
+    }
+    if note.starts_with(b"Apple iOS\0") {
+        return Some(MakerNoteLayout {
+            brand: Brand::Apple,
+            tiff: Tiff::with_order(note, false),
+            base: note_pos,
+            ifd_offset: 14,
+        });
+    }
+    if note.starts_with(b"OLYMP\0") {
+        return Some(same(Brand::Olympus, 8));
+    }
+    if note.starts_with(b"SONY DSC \0\0\0") || note.starts_with(b"SONY CAM \0\0\0") {
+        return Some(same(Brand::Sony, 12));
+    }
+    if note.starts_with(b"Panasonic\0\0\0") {
+        return Some(same(Brand::Panasonic, 12));
+    }
+    if note.starts_with(b"AOC\0") {
+        return Some(same(Brand::Pentax, 6));
+    }
+    if make.starts_with("canon") {
+        return Some(same(Brand::Canon, 0));
+    }
+    if make.starts_with("sony") {
+        return Some(same(Brand::Sony, 0));
+    }
+
+    None
+}
+
+fn maker_note_node(main: &Tiff, entry: &IfdEntry, make: Option<&str>) -> MetadataNode {
+    let note_pos = main.value_pos(entry).unwrap_or(0);
+    let Some(note) = main.value_bytes(entry) else {
+        return MetadataNode::leaf("Maker Notes", None, "(invalid)");
+    };
+
+    let Some(layout) = maker_note_layout(main, note_pos, note, make) else {
+        return MetadataNode::leaf(
+            "Maker Notes",
+            Some(format!("0x{:04X}", TAG_MAKER_NOTE)),
+            format!("({} bytes, unknown format)", note.len()),
+        );
+    };
+
+    let Some(ifd) = layout.tiff.read_ifd(layout.ifd_offset) else {
+        return MetadataNode::leaf(
+            format!("Maker Notes ({})", layout.brand.name()),
+            None,
+            "(unreadable)",
+        );
+    };
+
+    let children = ifd
+        .entries
+        .iter()
+        .map(|e| {
+            let name = layout
+                .brand
+                .tag_name(e.tag)
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("Tag 0x{:04X}", e.tag));
+            MetadataNode::leaf(
+                name,
+                Some(format!("0x{:04X}", e.tag)),
+                layout.tiff.format_value(e),
+            )
+        })
+        .collect();
+
+    MetadataNode::group(format!("Maker Notes ({})", layout.brand.name()), children)
+}
+
+// ----- IPTC ------
+
+/// One IPTC-IIM dataset.
+pub struct IptcDataset<'a> {
+    pub record: u8,
+    pub dataset: u8,
+    pub data: &'a [u8],
+}
+
+pub fn iptc_datasets(mut data: &[u8]) -> Vec<IptcDataset<'_>> {
+    let mut out = Vec::new();
+
+    while data.len() >= 5 && data[0] == 0x1C {
+        let record = data[1];
+        let dataset = data[2];
+        let mut len = u16::from_be_bytes([data[3], data[4]]) as usize;
+        let mut start = 5;
+
+        // Extended dataset: the low bits give the size of the length field
+        if len & 0x8000 != 0 {
+            let n = len & 0x7FFF;
+            let Some(bytes) = data.get(5..5 + n) else {
+                break;
+            };
+            len = bytes.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+            start += n;
+        }
+
+        let Some(end) = start.checked_add(len) else {
+            break;
+        };
+        let Some(body) = data.get(start..end) else {
+            break;
+        };
+        out.push(IptcDataset {
+            record,
+            dataset,
+            data: body,
+        });
+        data = &data[end..];
+    }
+
+    out
+}
+
+pub fn iptc_name(record: u8, dataset: u8) -> Option<&'static str> {
+    Some(match (record, dataset) {
+        (1, 90) => "CodedCharacterSet",
+        (2, 0) => "RecordVersion",
+        (2, 5) => "ObjectName",
+        (2, 7) => "EditStatus",
+        (2, 10) => "Urgency",
+        (2, 15) => "Category",
+        (2, 20) => "SupplementalCategories",
+        (2, 25) => "Keywords",
+        (2, 40) => "SpecialInstructions",
+        (2, 55) => "DateCreated",
+        (2, 60) => "TimeCreated",
+        (2, 65) => "OriginatingProgram",
+        (2, 80) => "By-line",
+        (2, 85) => "By-lineTitle",
+        (2, 90) => "City",
+        (2, 92) => "Sub-location",
+        (2, 95) => "Province-State",
+        (2, 100) => "Country-PrimaryLocationCode",
+        (2, 101) => "Country-PrimaryLocationName",
+        (2, 103) => "OriginalTransmissionReference",
+        (2, 105) => "Headline",
+        (2, 110) => "Credit",
+        (2, 115) => "Source",
+        (2, 116) => "CopyrightNotice",
+        (2, 118) => "Contact",
+        (2, 120) => "Caption-Abstract",
+        (2, 122) => "Writer-Editor",
+        _ => return None,
+    })
+}
+
+fn iptc_nodes(iptc: &[u8]) -> Vec<MetadataNode> {
+    let mut nodes: Vec<MetadataNode> = Vec::new();
+
+    for ds in iptc_datasets(iptc) {
+        let name = iptc_name(ds.record, ds.dataset)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("Dataset {}:{}", ds.record, ds.dataset));
+        let value = if ds.record == 1 && ds.dataset == 90 {
+            ds.data
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ")
+        } else {
+            String::from_utf8_lossy(ds.data).trim().to_string()
+        };
+
+        // Repeatable datasets (keywords...) are gathered into one node
+        if let Some(existing) = nodes.iter_mut().find(|n| n.name == name) {
+            let prev = existing.value.take().unwrap_or_default();
+            existing.value = Some(format!("{}; {}", prev, value));
+            continue;
+        }
+
+        nodes.push(MetadataNode::leaf(
+            name,
+            Some(format!("{}:{}", ds.record, ds.dataset)),
+            value,
+        ));
+    }
+
+    nodes
+}
+
+// ----- XMP ------
+
+pub const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+fn qualified_name(node: roxmltree::Node, ns: Option<&str>, local: &str) -> String {
+    match ns.and_then(|ns| node.lookup_prefix(ns)) {
+        Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, local),
+        _ => local.to_string(),
+    }
+}
+
+/// One XMP property element, turned into a node.
+fn xmp_property(node: roxmltree::Node) -> MetadataNode {
+    let tag = node.tag_name();
+    let name = qualified_name(node, tag.namespace(), tag.name());
+
+    // rdf:resource="..." shorthand
+    if let Some(res) = node.attribute((RDF_NS, "resource")) {
+        return MetadataNode::leaf(name, None, res);
+    }
+
+    let elements: Vec<_> = node.children().filter(|c| c.is_element()).collect();
+
+    if elements.is_empty() {
+        // Struct written as attributes: <ns:Prop ns:a="1" ns:b="2"/>
+        let attrs: Vec<MetadataNode> = xmp_attributes(node);
+        if !attrs.is_empty() {
+            return MetadataNode::group(name, attrs);
+        }
+        return MetadataNode::leaf(name, None, node.text().unwrap_or("").trim());
+    }
+
+    let mut children = Vec::new();
+    for el in elements {
+        let is_rdf = el.tag_name().namespace() == Some(RDF_NS);
+        match el.tag_name().name() {
+            "Bag" | "Seq" | "Alt" if is_rdf => {
+                for (i, li) in el.children().filter(|c| c.is_element()).enumerate() {
+                    let mut item = xmp_property(li);
+                    item.name = match li.attribute((XML_NS, "lang")) {
+                        Some(lang) => lang.to_string(),
+                        None => format!("[{}]", i + 1),
+                    };
+                    children.push(item);
+                }
+            }
+            "Description" if is_rdf => {
+                children.extend(xmp_attributes(el));
+                children.extend(el.children().filter(|c| c.is_element()).map(xmp_property));
+            }
+            _ => children.push(xmp_property(el)),
+        }
+    }
+    children.extend(xmp_attributes(node));
+
+    // A single-item Alt (the usual x-default title) reads better inline
+    if children.len() == 1 && children[0].children.is_empty() {
+        let only = &children[0];
+        let value = only.value.clone().unwrap_or_default();
+        return MetadataNode::leaf(name, None, value);
+    }
+
+    MetadataNode::group(name, children)
+}
+
+/// Non-RDF attributes of an element, as leaf properties.
+fn xmp_attributes(node: roxmltree::Node) -> Vec<MetadataNode> {
+    node.attributes()
+        .filter(|a| a.namespace() != Some(RDF_NS) && a.namespace() != Some(XML_NS))
+        .map(|a| {
+            MetadataNode::leaf(
+                qualified_name(node, a.namespace(), a.name()),
+                None,
+                a.value(),
+            )
+        })
+        .collect()
+}
+
+pub fn xmp_nodes(xmp: &str) -> Result<Vec<MetadataNode>, String> {
+    let doc = roxmltree::Document::parse(xmp.trim_end_matches('\0').trim())
+        .map_err(|e| format!("Invalid XMP: {}", e))?;
+
+    let mut properties = Vec::new();
+    for desc in doc
+        .descendants()
+        .filter(|n| n.has_tag_name((RDF_NS, "Description")))
+    {
+        // Nested descriptions are handled as struct values
+        if desc
+            .ancestors()
+            .skip(1)
+            .any(|a| a.has_tag_name((RDF_NS, "Description")))
+        {
+            continue;
+        }
+        properties.extend(xmp_attributes(desc));
+        properties.extend(desc.children().filter(|c| c.is_element()).map(xmp_property));
+    }
+
+    // Group by namespace prefix
+    let mut groups: Vec<MetadataNode> = Vec::new();
+    for prop in properties {
+        let prefix = prop
+            .name
+            .split_once(':')
+            .map(|(p, _)| p.to_string())
+            .unwrap_or_default();
+        match groups.iter_mut().find(|g| g.name == prefix) {
+            Some(g) => g.children.push(prop),
+            None => groups.push(MetadataNode::group(prefix, vec![prop])),
+        }
+    }
+
+    Ok(groups)
+}
+
+// ----- Command ------
+
+pub fn build_tree(raw: &RawMetadata) -> MetadataTree {
+    let mut sections = Vec::new();
+
+    if let Some(exif) = &raw.exif {
+        sections.push(MetadataNode::group("EXIF", exif_nodes(exif)));
+    }
+
+    if let Some(iptc) = &raw.iptc {
+        sections.push(MetadataNode::group("IPTC", iptc_nodes(iptc)));
+    }
+
+    if let Some(xmp) = &raw.xmp {
+        let children =
+            xmp_nodes(xmp).unwrap_or_else(|e| vec![MetadataNode::leaf("Error", None, e)]);
+        sections.push(MetadataNode::group("XMP", children));
+    }
+
+    MetadataTree {
+        sections,
+        xmp_raw: raw.xmp.clone(),
+    }
+}
+
+#[tauri::command]
+pub async fn load_metadata_tree(path: String) -> Result<MetadataTree, String> {
+    // reads the whole file
+    tauri::async_runtime::spawn_blocking(move || {
+        let raw = read_raw(&path)?;
+        Ok(build_tree(&raw))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Little endian TIFF with IFD0 holding the width, strip, make and XMP
+    /// and an Exif IFD holding the exposure time, followed by pixel data.
+    fn tiff_file() -> Vec<u8> {
+        let entry = |tag: u16, format: u16, count: u32, value: u32| {
+            [
+                &tag.to_le_bytes()[..],
+                &format.to_le_bytes(),
+                &count.to_le_bytes(),
+                &value.to_le_bytes(),
+            ]
+            .concat()
+        };
+        let mut out = b"II*\0".to_vec();
+        out.extend(8u32.to_le_bytes());
+
+        // IFD0 at 8, 5 entries, values from 74
+        out.extend(5u16.to_le_bytes());
+        out.extend(entry(0x0100, 3, 1, 2));
+        out.extend(entry(TAG_MAKE, 2, 6, 74));
+        out.extend(entry(0x0111, 4, 1, 120));
+        out.extend(entry(TAG_XMP, 1, 6, 80));
+        out.extend(entry(TAG_EXIF_IFD, 4, 1, 86));
+        out.extend(0u32.to_le_bytes());
+        out.extend(b"Canon\0<x:x/>");
+
+        // Exif IFD at 86, value at 104
+        out.extend(1u16.to_le_bytes());
+        out.extend(entry(0x829A, 5, 1, 104));
+        out.extend(0u32.to_le_bytes());
+        out.extend(1u32.to_le_bytes());
+        out.extend(250u32.to_le_bytes());
+
+        out.resize(120, 0);
+        out.extend([0xAB; 64]);
+        out
+    }
+
+    fn tags(tiff: &Tiff, offset: usize) -> Vec<u16> {
+        let ifd = tiff.read_ifd(offset).unwrap();
+        ifd.entries.iter().map(|e| e.tag).collect()
+    }
+
+    #[test]
+    fn exif_of_a_tiff_file_holds_only_the_metadata() {
+        let data = tiff_file();
+        let raw = extract_raw(&data);
+        assert_eq!(raw.xmp.as_deref(), Some("<x:x/>"));
+
+        let exif = raw.exif.unwrap();
+        assert!(exif.len() < 100);
+        assert!(!exif.windows(4).any(|w| w == [0xAB; 4]));
+
+        let tiff = Tiff::new(&exif).unwrap();
+        let ifd0 = tiff.first_ifd().unwrap();
+        assert_eq!(tags(&tiff, ifd0), [TAG_MAKE, TAG_EXIF_IFD]);
+
+        let ifd = tiff.read_ifd(ifd0).unwrap();
+        assert_eq!(tiff.format_value(&ifd.entries[0]), "Canon");
+        let sub = tiff.value_u32(&ifd.entries[1]).unwrap() as usize;
+        assert_eq!(tags(&tiff, sub), [0x829A]);
+        let exposure = &tiff.read_ifd(sub).unwrap().entries[0];
+        assert_eq!(tiff.value_bytes(exposure), Some(&data[104..112]));
+    }
+
+    #[test]
+    fn iptc_lengths_past_the_end_stop_the_datasets() {
+        let mut data = vec![0x1C, 2, 5, 0, 3, b'a', b'b', b'c'];
+        // extended dataset of usize::MAX bytes
+        data.extend([0x1C, 2, 120, 0x80, 8]);
+        data.extend([0xFF; 8]);
+        let datasets = iptc_datasets(&data);
+        assert_eq!(datasets.len(), 1);
+        assert_eq!(datasets[0].data, b"abc");
+    }
+}
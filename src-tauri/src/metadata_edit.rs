@@ -0,0 +1,1648 @@
+// ---------------- METADATA EDITING ----------------
+//
+// Strips or rewrites the metadata blocks of JPEG, PNG, WebP and TIFF files.
+// Pixel data is copied over byte for byte, nothing is re-encoded.
+//
+// EXIF tags are removed by rewriting their IFD in place and zeroing their
+// values, so every other offset in the block (thumbnail, maker notes) stays
+// valid. The ICC profile and the orientation are always kept, they are
+// needed to show the image correctly and say nothing about who took it.
+
+use crate::metadata::{
+    self, format_size, Block, IfdEntry, RawMetadata, Tiff, JPEG_EXIF, JPEG_PHOTOSHOP, JPEG_XMP,
+    PHOTOSHOP_IPTC, PNG_SIGNATURE, PNG_XMP_KEYWORD, RDF_NS, STRUCTURAL_TAGS, TAG_EXIF_IFD,
+    TAG_GPS_IFD, TAG_INTEROP_IFD, TAG_IPTC, TAG_MAKE, TAG_MAKER_NOTE, TAG_XMP,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct StripOptions {
+    /// Everything except the orientation and the color profile.
+    all: bool,
+    gps: bool,
+    /// Body, lens and maker note serial numbers.
+    serial: bool,
+    /// Software, processing software and host computer.
+    software: bool,
+}
+
+/// Descriptive fields. `None` leaves a field alone, an empty value removes it.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct MetadataEdits {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub copyright: Option<String>,
+    pub keywords: Option<Vec<String>>,
+    /// xmp:Rating, -1 (rejected) to 5. 0 removes it.
+    pub rating: Option<i8>,
+    /// xmp:Label, the color label.
+    pub label: Option<String>,
+    /// digiKam:PickLabel, 1 rejected, 2 pending, 3 accepted. 0 removes it.
+    pub pick: Option<u8>,
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+impl MetadataEdits {
+    fn keywords(&self) -> Vec<&str> {
+        self.keywords
+            .iter()
+            .flatten()
+            .map(|k| k.trim())
+            .filter(|k| !k.is_empty())
+            .collect()
+    }
+}
+
+// ----- EXIF ------
+
+const TAG_ORIENTATION: u16 = 0x0112;
+
+const SOFTWARE_TAGS: [u16; 3] = [
+    0x000B, // ProcessingSoftware
+    0x0131, // Software
+    0x013C, // HostComputer
+];
+
+const SERIAL_TAGS: [u16; 3] = [
+    0xA431, // BodySerialNumber
+    0xA435, // LensSerialNumber
+    0xC62F, // CameraSerialNumber (DNG)
+];
+
+/// IFD0 tags that duplicate the edited fields. They are removed so that
+/// readers do not show the old value next to the new XMP / IPTC one.
+fn edited_exif_tags(edits: &MetadataEdits) -> Vec<u16> {
+    let mut tags = Vec::new();
+    if edits.title.is_some() {
+        tags.push(0x9C9B); // XPTitle
+    }
+    if edits.description.is_some() {
+        tags.extend([0x010E, 0x9C9C]); // ImageDescription, XPComment
+    }
+    if edits.author.is_some() {
+        tags.extend([0x013B, 0x9C9D]); // Artist, XPAuthor
+    }
+    if edits.copyright.is_some() {
+        tags.push(0x8298);
+    }
+    if edits.keywords.is_some() {
+        tags.push(0x9C9E); // XPKeywords
+    }
+    if edits.rating.is_some() {
+        tags.extend([0x4746, 0x4749]); // Rating, RatingPercent
+    }
+    tags
+}
+
+fn put_u16(data: &mut [u8], pos: usize, value: u16, le: bool) {
+    let bytes = if le {
+        value.to_le_bytes()
+    } else {
+        value.to_be_bytes()
+    };
+    data[pos..pos + 2].copy_from_slice(&bytes);
+}
+
+fn put_u32(data: &mut [u8], pos: usize, value: u32, le: bool) {
+    let bytes = if le {
+        value.to_le_bytes()
+    } else {
+        value.to_be_bytes()
+    };
+    data[pos..pos + 4].copy_from_slice(&bytes);
+}
+
+/// Remove the entries matching `remove` from the IFD at `offset`, relative
+/// to `base`, and zero their values. Returns the tag and first value of
+/// every removed entry, so that sub-IFDs can be cleared as well. Fails if
+/// the IFD is cut off.
+fn remove_entries(
+    data: &mut [u8],
+    base: usize,
+    le: bool,
+    offset: usize,
+    remove: &dyn Fn(u16) -> bool,
+) -> Result<Vec<(u16, Option<u32>)>, String> {
+    let Some(tiff_data) = data.get(base..) else {
+        return Ok(Vec::new());
+    };
+    let tiff = Tiff::with_order(tiff_data, le);
+    let Some(ifd) = tiff.read_ifd(offset) else {
+        return Ok(Vec::new());
+    };
+
+    let mut kept = Vec::new();
+    let mut removed = Vec::new();
+    let mut values = Vec::new();
+
+    for entry in &ifd.entries {
+        if remove(entry.tag) {
+            removed.push((entry.tag, tiff.value_u32(entry)));
+            if let (Some(pos), Some(bytes)) = (tiff.value_pos(entry), tiff.value_bytes(entry)) {
+                if bytes.len() > 4 {
+                    values.push(pos..pos + bytes.len());
+                }
+            }
+        } else {
+            kept.push(entry.pos);
+        }
+    }
+
+    if removed.is_empty() {
+        return Ok(removed);
+    }
+
+    let data = &mut data[base..];
+    let cut_off = || format!("The IFD at offset {} is cut off", offset);
+    let entries: Vec<[u8; 12]> = kept
+        .iter()
+        .map(|&pos| data.get(pos..pos + 12).and_then(|e| e.try_into().ok()))
+        .collect::<Option<_>>()
+        .ok_or_else(cut_off)?;
+
+    // count, entries and the offset of the next IFD
+    let table = data
+        .get_mut(offset..offset + 2 + ifd.entries.len() * 12 + 4)
+        .ok_or_else(cut_off)?;
+    put_u16(table, 0, entries.len() as u16, le);
+    for (i, entry) in entries.iter().enumerate() {
+        let pos = 2 + i * 12;
+        table[pos..pos + 12].copy_from_slice(entry);
+    }
+    let next_pos = 2 + entries.len() * 12;
+    put_u32(table, next_pos, ifd.next, le);
+    table[next_pos + 4..].fill(0);
+
+    for range in values {
+        let end = range.end.min(data.len());
+        if range.start < end {
+            data[range.start..end].fill(0);
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Remove every entry of the IFD at `offset` and of the IFDs it points to.
+fn clear_ifd(data: &mut [u8], le: bool, offset: usize) -> Result<(), String> {
+    for (tag, value) in remove_entries(data, 0, le, offset, &|_| true)? {
+        if matches!(tag, TAG_EXIF_IFD | TAG_GPS_IFD | TAG_INTEROP_IFD) {
+            if let Some(sub) = value {
+                clear_ifd(data, le, sub as usize)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Offset of the Exif sub-IFD of IFD0.
+fn exif_ifd(tiff: &Tiff) -> Option<usize> {
+    let ifd0 = tiff.read_ifd(tiff.first_ifd()?)?;
+    let entry = ifd0.entries.iter().find(|e| e.tag == TAG_EXIF_IFD)?;
+    tiff.value_u32(entry).map(|o| o as usize)
+}
+
+/// (base, little endian, IFD offset, serial number tags) of the maker note.
+fn maker_note_serials(tiff: &Tiff) -> Option<(usize, bool, usize, Vec<u16>)> {
+    let ifd0 = tiff.read_ifd(tiff.first_ifd()?)?;
+    let make = ifd0
+        .entries
+        .iter()
+        .find(|e| e.tag == TAG_MAKE)
+        .map(|e| tiff.format_value(e));
+
+    let exif = tiff.read_ifd(exif_ifd(tiff)?)?;
+    let entry = exif.entries.iter().find(|e| e.tag == TAG_MAKER_NOTE)?;
+    let note = tiff.value_bytes(entry)?;
+    let layout = metadata::maker_note_layout(tiff, tiff.value_pos(entry)?, note, make.as_deref())?;
+
+    let serials = layout
+        .tiff
+        .read_ifd(layout.ifd_offset)?
+        .entries
+        .iter()
+        .filter(|e| {
+            layout
+                .tag_name(e.tag)
+                .is_some_and(|n| n.ends_with("SerialNumber"))
+        })
+        .map(|e| e.tag)
+        .collect();
+
+    Some((
+        layout.base,
+        layout.tiff.little_endian(),
+        layout.ifd_offset,
+        serials,
+    ))
+}
+
+/// Apply the selective options and edits to a TIFF structure in place.
+/// Returns false if `data` is not a readable TIFF structure.
+fn strip_exif(data: &mut [u8], options: &StripOptions, edited: &[u16]) -> Result<bool, String> {
+    let (le, ifd0, exif, note) = {
+        let Some(tiff) = Tiff::new(data) else {
+            return Ok(false);
+        };
+        let Some(ifd0) = tiff.first_ifd() else {
+            return Ok(false);
+        };
+        (
+            tiff.little_endian(),
+            ifd0,
+            exif_ifd(&tiff),
+            maker_note_serials(&tiff),
+        )
+    };
+
+    if options.gps {
+        for (_, gps) in remove_entries(data, 0, le, ifd0, &|t| t == TAG_GPS_IFD)? {
+            if let Some(gps) = gps {
+                clear_ifd(data, le, gps as usize)?;
+            }
+        }
+    }
+
+    if options.serial {
+        remove_entries(data, 0, le, ifd0, &|t| SERIAL_TAGS.contains(&t))?;
+        if let Some(exif) = exif {
+            remove_entries(data, 0, le, exif, &|t| SERIAL_TAGS.contains(&t))?;
+        }
+        if let Some((base, note_le, offset, serials)) = note {
+            remove_entries(data, base, note_le, offset, &|t| serials.contains(&t))?;
+        }
+    }
+
+    if options.software {
+        remove_entries(data, 0, le, ifd0, &|t| SOFTWARE_TAGS.contains(&t))?;
+    }
+
+    if !edited.is_empty() {
+        remove_entries(data, 0, le, ifd0, &|t| edited.contains(&t))?;
+    }
+
+    Ok(true)
+}
+
+fn orientation_entry(tiff: &Tiff) -> Option<IfdEntry> {
+    let ifd0 = tiff.read_ifd(tiff.first_ifd()?)?;
+    ifd0.entries.into_iter().find(|e| e.tag == TAG_ORIENTATION)
+}
+
+/// EXIF orientation, 1 to 8.
+pub fn exif_orientation(exif: &[u8]) -> Option<u16> {
+    let tiff = Tiff::new(exif)?;
+    let orientation = tiff.value_u32(&orientation_entry(&tiff)?)? as u16;
+    (1..=8).contains(&orientation).then_some(orientation)
+}
+
+/// EXIF block holding only the orientation, if it is not the default one.
+fn minimal_exif(exif: &[u8]) -> Option<Vec<u8>> {
+    let orientation = exif_orientation(exif).filter(|&o| o != 1)?;
+
+    let mut out = b"II*\0".to_vec();
+    out.extend(8u32.to_le_bytes());
+    out.extend(1u16.to_le_bytes());
+    out.extend(TAG_ORIENTATION.to_le_bytes());
+    out.extend(3u16.to_le_bytes());
+    out.extend(1u32.to_le_bytes());
+    out.extend(orientation.to_le_bytes());
+    out.extend([0, 0]);
+    out.extend(0u32.to_le_bytes());
+    Some(out)
+}
+
+/// Remove everything but the image structure from every IFD of a TIFF file.
+fn strip_tiff_all(data: &mut [u8]) -> Result<(), String> {
+    let Some(tiff) = Tiff::new(data) else {
+        return Ok(());
+    };
+    let le = tiff.little_endian();
+    let mut next = tiff.first_ifd();
+    let mut visited = Vec::new();
+
+    while let Some(offset) = next.filter(|&o| o != 0 && !visited.contains(&o)) {
+        visited.push(offset);
+
+        let removed = remove_entries(data, 0, le, offset, &|t| !STRUCTURAL_TAGS.contains(&t))?;
+        for (tag, value) in removed {
+            if matches!(tag, TAG_EXIF_IFD | TAG_GPS_IFD) {
+                if let Some(sub) = value {
+                    clear_ifd(data, le, sub as usize)?;
+                }
+            }
+        }
+
+        let tiff = Tiff::with_order(data, le);
+        next = tiff
+            .u16_at(offset)
+            .and_then(|count| tiff.u32_at(offset + 2 + count as usize * 12))
+            .map(|o| o as usize);
+    }
+    Ok(())
+}
+
+/// Set an IFD0 tag of a TIFF file, appending the value at the end of the
+/// file. IFD0 is moved to the end as well if the tag is new.
+fn set_ifd0_tag(data: &mut Vec<u8>, tag: u16, format: u16, value: &[u8]) -> Result<(), String> {
+    let tiff = Tiff::new(data).ok_or("Invalid TIFF file")?;
+    let le = tiff.little_endian();
+    let offset = tiff.first_ifd().ok_or("Invalid TIFF file")?;
+    let ifd = tiff.read_ifd(offset).ok_or("Invalid TIFF file")?;
+
+    let existing = ifd.entries.iter().position(|e| e.tag == tag);
+    let mut entries: Vec<[u8; 12]> = ifd
+        .entries
+        .iter()
+        .map(|e| data.get(e.pos..e.pos + 12).and_then(|e| e.try_into().ok()))
+        .collect::<Option<_>>()
+        .ok_or("IFD0 is cut off")?;
+    let next = ifd.next;
+
+    if existing.is_some() {
+        remove_entries(data, 0, le, offset, &|t| t == tag)?;
+    }
+
+    // Value first, at an even offset
+    if data.len() % 2 == 1 {
+        data.push(0);
+    }
+    let mut field = [0u8; 4];
+    if value.len() <= 4 {
+        field[..value.len()].copy_from_slice(value);
+    } else {
+        let pos = u32::try_from(data.len()).map_err(|_| "File is too large")?;
+        put_u32(&mut field, 0, pos, le);
+        data.extend_from_slice(value);
+        if data.len() % 2 == 1 {
+            data.push(0);
+        }
+    }
+
+    let count = (value.len() / format_size(format).max(1)) as u32;
+    let mut entry = [0u8; 12];
+    put_u16(&mut entry, 0, tag, le);
+    put_u16(&mut entry, 2, format, le);
+    put_u32(&mut entry, 4, count, le);
+    entry[8..].copy_from_slice(&field);
+
+    let get_tag = |e: &[u8; 12]| {
+        if le {
+            u16::from_le_bytes([e[0], e[1]])
+        } else {
+            u16::from_be_bytes([e[0], e[1]])
+        }
+    };
+    entries.retain(|e| get_tag(e) != tag);
+    let at = entries.partition_point(|e| get_tag(e) < tag);
+    entries.insert(at, entry);
+
+    // Clear the old IFD0 and write the new one at the end
+    let old_len = 2 + ifd.entries.len() * 12 + 4;
+    data.get_mut(offset..offset + old_len)
+        .ok_or("IFD0 is cut off")?
+        .fill(0);
+
+    let new_offset = u32::try_from(data.len()).map_err(|_| "File is too large")?;
+    let mut buf = [0u8; 4];
+    put_u16(&mut buf, 0, entries.len() as u16, le);
+    data.extend_from_slice(&buf[..2]);
+    for e in &entries {
+        data.extend_from_slice(e);
+    }
+    put_u32(&mut buf, 0, next, le);
+    data.extend_from_slice(&buf);
+
+    put_u32(data, 4, new_offset, le);
+    Ok(())
+}
+
+// ----- XMP ------
+
+pub const NS_DC: &str = "http://purl.org/dc/elements/1.1/";
+pub const NS_XMP: &str = "http://ns.adobe.com/xap/1.0/";
+pub const NS_DIGIKAM: &str = "http://www.digikam.org/ns/1.0/";
+const NS_XMP_MM: &str = "http://ns.adobe.com/xap/1.0/mm/";
+const NS_EXIF: &str = "http://ns.adobe.com/exif/1.0/";
+const NS_EXIF_EX: &str = "http://cipa.jp/exif/1.0/";
+const NS_AUX: &str = "http://ns.adobe.com/exif/1.0/aux/";
+const NS_TIFF: &str = "http://ns.adobe.com/tiff/1.0/";
+
+fn xmp_removes(ns: &str, name: &str, options: &StripOptions, edits: &MetadataEdits) -> bool {
+    match ns {
+        NS_DC => match name {
+            "title" => edits.title.is_some(),
+            "description" => edits.description.is_some(),
+            "creator" => edits.author.is_some(),
+            "rights" => edits.copyright.is_some(),
+            "subject" => edits.keywords.is_some(),
+            _ => false,
+        },
+        NS_EXIF => options.gps && name.starts_with("GPS"),
+        NS_AUX | NS_EXIF_EX => options.serial && name.ends_with("SerialNumber"),
+        NS_TIFF => options.software && name == "Software",
+        NS_XMP => match name {
+            "CreatorTool" => options.software,
+            "Rating" => edits.rating.is_some(),
+            "Label" => edits.label.is_some(),
+            _ => false,
+        },
+        NS_DIGIKAM => name == "PickLabel" && edits.pick.is_some(),
+        NS_XMP_MM => options.software && name == "History",
+        _ => false,
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// New `rdf:Description` holding the edited Dublin Core fields and marks.
+fn edited_description(edits: &MetadataEdits) -> Option<String> {
+    let alt = |name: &str, value: &str| {
+        format!(
+            "<dc:{0}><rdf:Alt><rdf:li xml:lang=\"x-default\">{1}</rdf:li></rdf:Alt></dc:{0}>",
+            name,
+            escape_xml(value)
+        )
+    };
+    let list = |name: &str, kind: &str, values: &[&str]| {
+        let items: String = values
+            .iter()
+            .map(|v| format!("<rdf:li>{}</rdf:li>", escape_xml(v)))
+            .collect();
+        format!("<dc:{0}><rdf:{1}>{2}</rdf:{1}></dc:{0}>", name, kind, items)
+    };
+
+    let mut props = String::new();
+    if let Some(title) = non_empty(&edits.title) {
+        props += &alt("title", title);
+    }
+    if let Some(description) = non_empty(&edits.description) {
+        props += &alt("description", description);
+    }
+    if let Some(author) = non_empty(&edits.author) {
+        props += &list("creator", "Seq", &[author]);
+    }
+    if let Some(copyright) = non_empty(&edits.copyright) {
+        props += &alt("rights", copyright);
+    }
+    let keywords = edits.keywords();
+    if !keywords.is_empty() {
+        props += &list("subject", "Bag", &keywords);
+    }
+    if let Some(rating) = edits.rating.filter(|&r| r != 0) {
+        props += &format!("<xmp:Rating>{}</xmp:Rating>", rating);
+    }
+    if let Some(label) = non_empty(&edits.label) {
+        props += &format!("<xmp:Label>{}</xmp:Label>", escape_xml(label));
+    }
+    if let Some(pick) = edits.pick.filter(|&p| p != 0) {
+        props += &format!("<digiKam:PickLabel>{}</digiKam:PickLabel>", pick);
+    }
+
+    if props.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "<rdf:Description rdf:about=\"\" xmlns:rdf=\"{}\" xmlns:dc=\"{}\" xmlns:xmp=\"{}\" \
+         xmlns:digiKam=\"{}\">{}</rdf:Description>",
+        RDF_NS, NS_DC, NS_XMP, NS_DIGIKAM, props
+    ))
+}
+
+fn new_xmp_packet(description: &str) -> String {
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF xmlns:rdf=\"{}\">{}</rdf:RDF></x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>",
+        RDF_NS, description
+    )
+}
+
+/// New XMP packet, `None` if the file should not have one.
+pub fn rewrite_xmp(
+    xmp: Option<&str>,
+    options: &StripOptions,
+    edits: &MetadataEdits,
+) -> Result<Option<String>, String> {
+    let added = edited_description(edits);
+    let xmp = match xmp {
+        Some(xmp) if !options.all => xmp.trim_end_matches('\0'),
+        _ => return Ok(added.as_deref().map(new_xmp_packet)),
+    };
+
+    let doc = match roxmltree::Document::parse(xmp) {
+        Ok(doc) => doc,
+        // An unreadable packet cannot be cleaned, so it is dropped, but it
+        // is not silently replaced when only editing.
+        Err(e) if added.is_some() => return Err(format!("Cannot edit the XMP block: {}", e)),
+        Err(_) => return Ok(None),
+    };
+
+    // (range, replacement), applied back to front
+    let mut splices: Vec<(std::ops::Range<usize>, String)> = Vec::new();
+
+    for desc in doc
+        .descendants()
+        .filter(|n| n.has_tag_name((RDF_NS, "Description")))
+        .filter(|n| {
+            n.parent_element()
+                .is_some_and(|p| p.has_tag_name((RDF_NS, "RDF")))
+        })
+    {
+        let mut removed = Vec::new();
+        let mut kept = 0;
+
+        for attr in desc.attributes() {
+            match attr.namespace() {
+                Some(ns) if xmp_removes(ns, attr.name(), options, edits) => {
+                    removed.push(attr.range())
+                }
+                Some(RDF_NS) => {}
+                _ => kept += 1,
+            }
+        }
+        for prop in desc.children().filter(|c| c.is_element()) {
+            let tag = prop.tag_name();
+            match tag.namespace() {
+                Some(ns) if xmp_removes(ns, tag.name(), options, edits) => {
+                    removed.push(prop.range())
+                }
+                _ => kept += 1,
+            }
+        }
+
+        // a description left empty is dropped, so repeated edits don't pile them up
+        if kept == 0 && !removed.is_empty() {
+            splices.push((desc.range(), String::new()));
+        } else {
+            splices.extend(removed.into_iter().map(|r| (r, String::new())));
+        }
+    }
+
+    if let Some(added) = added {
+        let rdf = doc
+            .descendants()
+            .find(|n| n.has_tag_name((RDF_NS, "RDF")))
+            .ok_or("XMP block has no rdf:RDF element")?;
+        let range = rdf.range();
+
+        if xmp[range.clone()].ends_with("/>") {
+            let rdf = format!("<rdf:RDF xmlns:rdf=\"{}\">{}</rdf:RDF>", RDF_NS, added);
+            splices.push((range, rdf));
+        } else {
+            let close = xmp[..range.end]
+                .rfind("</")
+                .ok_or("Invalid rdf:RDF element")?;
+            splices.push((close..close, added));
+        }
+    }
+
+    let mut out = xmp.to_string();
+    splices.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    for (range, text) in splices {
+        out.replace_range(range, &text);
+    }
+
+    Ok(Some(out))
+}
+
+// ----- IPTC ------
+
+/// ESC % G, the "UTF-8" coded character set.
+const IPTC_UTF8: &[u8] = b"\x1b%G";
+
+fn iptc_dataset(out: &mut Vec<u8>, record: u8, dataset: u8, data: &[u8]) {
+    out.extend([0x1C, record, dataset]);
+    if data.len() < 0x8000 {
+        out.extend((data.len() as u16).to_be_bytes());
+    } else {
+        out.extend([0x80, 0x04]);
+        out.extend((data.len() as u32).to_be_bytes());
+    }
+    out.extend_from_slice(data);
+}
+
+/// New IPTC block, `None` if nothing is left in it.
+fn rewrite_iptc(iptc: &[u8], options: &StripOptions, edits: &MetadataEdits) -> Option<Vec<u8>> {
+    if options.all {
+        return None;
+    }
+
+    let removes = |record: u8, dataset: u8| match (record, dataset) {
+        (2, 5) => edits.title.is_some(),
+        (2, 120) => edits.description.is_some(),
+        (2, 80) => edits.author.is_some(),
+        (2, 116) => edits.copyright.is_some(),
+        (2, 25) => edits.keywords.is_some(),
+        (2, 65) | (2, 70) => options.software,
+        _ => false,
+    };
+
+    let mut datasets: Vec<(u8, u8, Vec<u8>)> = metadata::iptc_datasets(iptc)
+        .into_iter()
+        .filter(|d| !removes(d.record, d.dataset))
+        .map(|d| (d.record, d.dataset, d.data.to_vec()))
+        .collect();
+
+    let mut added: Vec<(u8, &str)> = Vec::new();
+    if let Some(title) = non_empty(&edits.title) {
+        added.push((5, title));
+    }
+    if let Some(description) = non_empty(&edits.description) {
+        added.push((120, description));
+    }
+    if let Some(author) = non_empty(&edits.author) {
+        added.push((80, author));
+    }
+    if let Some(copyright) = non_empty(&edits.copyright) {
+        added.push((116, copyright));
+    }
+    for keyword in edits.keywords() {
+        added.push((25, keyword));
+    }
+
+    if added.iter().any(|(_, v)| !v.is_ascii()) {
+        datasets.retain(|(r, d, _)| (*r, *d) != (1, 90));
+        datasets.push((1, 90, IPTC_UTF8.to_vec()));
+    }
+    datasets.extend(
+        added
+            .into_iter()
+            .map(|(d, v)| (2, d, v.as_bytes().to_vec())),
+    );
+
+    // Records must be in ascending order
+    datasets.sort_by_key(|(record, _, _)| *record);
+    if !datasets.iter().any(|(r, _, _)| *r == 2) {
+        return None;
+    }
+
+    let mut out = Vec::new();
+    for (record, dataset, data) in &datasets {
+        iptc_dataset(&mut out, *record, *dataset, data);
+    }
+    Some(out)
+}
+
+/// Photoshop resources with the IPTC block replaced. The IPTC digest
+/// (0x0425) is dropped, it would no longer match.
+fn rewrite_photoshop(resources: &[u8], iptc: Option<&[u8]>) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for res in metadata::photoshop_resources(resources) {
+        let id = u16::from_be_bytes([res.kind[0], res.kind[1]]);
+        if id != PHOTOSHOP_IPTC && id != 0x0425 {
+            out.extend_from_slice(&resources[res.range]);
+        }
+    }
+
+    if let Some(iptc) = iptc {
+        out.extend_from_slice(b"8BIM");
+        out.extend(PHOTOSHOP_IPTC.to_be_bytes());
+        out.extend([0, 0]);
+        out.extend((iptc.len() as u32).to_be_bytes());
+        out.extend_from_slice(iptc);
+        if iptc.len() % 2 == 1 {
+            out.push(0);
+        }
+    }
+
+    out
+}
+
+// ----- Containers ------
+
+/// New content of every metadata block, `None` removes it.
+struct Blocks {
+    exif: Option<Vec<u8>>,
+    xmp: Option<String>,
+    iptc: Option<Vec<u8>>,
+}
+
+fn jpeg_segment(out: &mut Vec<u8>, marker: u8, parts: &[&[u8]]) -> Result<(), String> {
+    let len: usize = parts.iter().map(|p| p.len()).sum::<usize>() + 2;
+    if len > 0xFFFF {
+        return Err("Metadata block is too large for a JPEG segment".to_string());
+    }
+    out.extend([0xFF, marker]);
+    out.extend((len as u16).to_be_bytes());
+    for part in parts {
+        out.extend_from_slice(part);
+    }
+    Ok(())
+}
+
+/// Position after the EOI marker of the image whose scans start at `pos`,
+/// `None` if the image is cut off before it.
+fn jpeg_image_end(data: &[u8], mut pos: usize) -> Option<usize> {
+    while pos + 2 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        match marker {
+            0xFF => pos += 1,
+            0xD9 => return Some(pos + 2),
+            0xD0..=0xD7 | 0x01 => pos += 2,
+            _ => {
+                let len = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]);
+                pos += 2 + len as usize;
+                if marker == 0xDA {
+                    // entropy coded data, up to the next marker that isn't
+                    // a stuffed byte or a restart
+                    while pos + 1 < data.len()
+                        && (data[pos] != 0xFF || matches!(data[pos + 1], 0x00 | 0xD0..=0xD7))
+                    {
+                        pos += 1;
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+fn write_jpeg(data: &[u8], blocks: &Blocks, options: &StripOptions) -> Result<Vec<u8>, String> {
+    const XMP_EXTENSION: &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+    const ICC_PROFILE: &[u8] = b"ICC_PROFILE\0";
+    const MPF: &[u8] = b"MPF\0";
+
+    // Whatever follows the image (MPF previews, the video of motion photos)
+    // has metadata of its own, often the same location.
+    let drop_trailer = options.all || options.gps;
+
+    let (segments, end) = metadata::jpeg_segments(data);
+    let mut out = data[..2].to_vec();
+    let mut inserted = false;
+    let mut iptc_written = false;
+
+    for seg in segments {
+        let marker = seg.kind[0];
+        let body = &data[seg.body.clone()];
+
+        // New EXIF and XMP go right after the JFIF header
+        if !inserted && marker != 0xE0 {
+            if let Some(exif) = &blocks.exif {
+                jpeg_segment(&mut out, 0xE1, &[JPEG_EXIF, exif])?;
+            }
+            if let Some(xmp) = &blocks.xmp {
+                jpeg_segment(&mut out, 0xE1, &[JPEG_XMP, xmp.as_bytes()])?;
+            }
+            inserted = true;
+        }
+
+        let keep = match marker {
+            0xE1 if body.starts_with(JPEG_EXIF)
+                || body.starts_with(JPEG_XMP)
+                || body.starts_with(XMP_EXTENSION) =>
+            {
+                false
+            }
+            0xED if body.starts_with(JPEG_PHOTOSHOP) => {
+                if !options.all {
+                    let resources = rewrite_photoshop(
+                        &body[JPEG_PHOTOSHOP.len()..],
+                        blocks.iptc.as_deref().filter(|_| !iptc_written),
+                    );
+                    iptc_written = true;
+                    if !resources.is_empty() {
+                        jpeg_segment(&mut out, 0xED, &[JPEG_PHOTOSHOP, &resources])?;
+                    }
+                }
+                false
+            }
+            0xE2 if drop_trailer && body.starts_with(MPF) => false,
+            _ if options.all => match marker {
+                0xE0 => !body.starts_with(b"JFXX"),
+                0xE2 => body.starts_with(ICC_PROFILE),
+                0xE3..=0xEF | 0xE1 | 0xFE => marker == 0xEE,
+                _ => true,
+            },
+            _ => true,
+        };
+
+        if keep {
+            out.extend_from_slice(&data[seg.range]);
+        }
+    }
+
+    if !inserted {
+        if let Some(exif) = &blocks.exif {
+            jpeg_segment(&mut out, 0xE1, &[JPEG_EXIF, exif])?;
+        }
+        if let Some(xmp) = &blocks.xmp {
+            jpeg_segment(&mut out, 0xE1, &[JPEG_XMP, xmp.as_bytes()])?;
+        }
+    }
+
+    match jpeg_image_end(data, end) {
+        Some(image_end) if drop_trailer => out.extend_from_slice(&data[end..image_end]),
+        _ => out.extend_from_slice(&data[end..]),
+    }
+    Ok(out)
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(body);
+
+    out.extend((body.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    out.extend(crc.finalize().to_be_bytes());
+}
+
+/// Uncompressed iTXt chunk body.
+fn png_itxt(keyword: &[u8], text: &str) -> Vec<u8> {
+    let mut body = keyword.to_vec();
+    body.extend([0, 0, 0, 0, 0]);
+    body.extend_from_slice(text.as_bytes());
+    body
+}
+
+fn write_png(
+    data: &[u8],
+    blocks: &Blocks,
+    options: &StripOptions,
+    edits: &MetadataEdits,
+) -> Result<Vec<u8>, String> {
+    let chunks = metadata::png_chunks(data);
+    if chunks.last().map(|c| &c.kind) != Some(b"IEND") {
+        return Err("The PNG file is damaged".to_string());
+    }
+
+    // PNG's own text keywords for the edited fields
+    let text_fields = [
+        ("Title", &edits.title),
+        ("Description", &edits.description),
+        ("Author", &edits.author),
+        ("Copyright", &edits.copyright),
+    ];
+
+    let mut out = PNG_SIGNATURE.to_vec();
+    let mut inserted = false;
+
+    for chunk in &chunks {
+        let body = &data[chunk.body.clone()];
+
+        if !inserted && (&chunk.kind == b"IDAT" || &chunk.kind == b"IEND") {
+            if let Some(exif) = &blocks.exif {
+                png_chunk(&mut out, b"eXIf", exif);
+            }
+            if let Some(xmp) = &blocks.xmp {
+                png_chunk(&mut out, b"iTXt", &png_itxt(PNG_XMP_KEYWORD, xmp));
+            }
+            for (keyword, value) in &text_fields {
+                if let Some(value) = non_empty(value) {
+                    png_chunk(&mut out, b"iTXt", &png_itxt(keyword.as_bytes(), value));
+                }
+            }
+            inserted = true;
+        }
+
+        let keep = match &chunk.kind {
+            b"eXIf" => false,
+            b"tEXt" | b"zTXt" | b"iTXt" => {
+                let keyword = body.split(|&b| b == 0).next().unwrap_or_default();
+                let edited = text_fields
+                    .iter()
+                    .any(|(k, v)| v.is_some() && k.as_bytes() == keyword);
+
+                !(options.all
+                    || keyword == PNG_XMP_KEYWORD
+                    || edited
+                    || (options.software && keyword == b"Software"))
+            }
+            b"tIME" => !options.all,
+            _ => true,
+        };
+
+        if keep {
+            out.extend_from_slice(&data[chunk.range.clone()]);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Canvas size and alpha flag of a simple (non-VP8X) WebP image.
+fn webp_canvas(chunks: &[Block], data: &[u8]) -> Option<(u32, u32, bool)> {
+    for chunk in chunks {
+        let body = &data[chunk.body.clone()];
+        match &chunk.kind {
+            b"VP8L" if body.len() >= 5 && body[0] == 0x2F => {
+                let bits = u32::from_le_bytes(body[1..5].try_into().unwrap());
+                let width = (bits & 0x3FFF) + 1;
+                let height = ((bits >> 14) & 0x3FFF) + 1;
+                return Some((width, height, bits >> 28 & 1 == 1));
+            }
+            b"VP8 " if body.len() >= 10 && body[3..6] == [0x9D, 0x01, 0x2A] => {
+                let width = u16::from_le_bytes([body[6], body[7]]) as u32 & 0x3FFF;
+                let height = u16::from_le_bytes([body[8], body[9]]) as u32 & 0x3FFF;
+                return Some((width, height, false));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn write_webp(data: &[u8], blocks: &Blocks) -> Result<Vec<u8>, String> {
+    const FLAG_ALPHA: u8 = 0x10;
+    const FLAG_EXIF: u8 = 0x08;
+    const FLAG_XMP: u8 = 0x04;
+
+    let chunks = metadata::webp_chunks(data);
+    if chunks.last().map(|c| c.range.end) != Some(data.len()) {
+        return Err("The WebP file is damaged".to_string());
+    }
+
+    let mut body: Vec<u8> = Vec::new();
+    let mut flags = 0u8;
+    if blocks.exif.is_some() {
+        flags |= FLAG_EXIF;
+    }
+    if blocks.xmp.is_some() {
+        flags |= FLAG_XMP;
+    }
+
+    let has_vp8x = chunks.first().map(|c| &c.kind) == Some(b"VP8X");
+    if !has_vp8x && flags != 0 {
+        // The metadata chunks need the extended format
+        let (width, height, alpha) =
+            webp_canvas(&chunks, data).ok_or("Cannot read the WebP image size")?;
+        let mut vp8x = vec![flags | if alpha { FLAG_ALPHA } else { 0 }, 0, 0, 0];
+        vp8x.extend(&(width - 1).to_le_bytes()[..3]);
+        vp8x.extend(&(height - 1).to_le_bytes()[..3]);
+        riff_chunk(&mut body, b"VP8X", &vp8x);
+    }
+
+    for chunk in &chunks {
+        match &chunk.kind {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" => {
+                let mut vp8x = data[chunk.body.clone()].to_vec();
+                if let Some(f) = vp8x.first_mut() {
+                    *f = (*f & !(FLAG_EXIF | FLAG_XMP)) | flags;
+                }
+                riff_chunk(&mut body, b"VP8X", &vp8x);
+            }
+            _ => body.extend_from_slice(&data[chunk.range.clone()]),
+        }
+    }
+
+    if let Some(exif) = &blocks.exif {
+        riff_chunk(&mut body, b"EXIF", exif);
+    }
+    if let Some(xmp) = &blocks.xmp {
+        riff_chunk(&mut body, b"XMP ", xmp.as_bytes());
+    }
+
+    let mut out = b"RIFF".to_vec();
+    out.extend(((body.len() + 4) as u32).to_le_bytes());
+    out.extend_from_slice(b"WEBP");
+    out.extend(body);
+    Ok(out)
+}
+
+fn riff_chunk(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(kind);
+    out.extend((body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+fn write_tiff(
+    data: &[u8],
+    options: &StripOptions,
+    edits: &MetadataEdits,
+) -> Result<Vec<u8>, String> {
+    let raw = metadata::extract_raw(data);
+    let xmp = rewrite_xmp(raw.xmp.as_deref(), options, edits)?;
+    let iptc = raw
+        .iptc
+        .as_deref()
+        .and_then(|i| rewrite_iptc(i, options, edits));
+
+    let mut out = data.to_vec();
+    if options.all {
+        strip_tiff_all(&mut out)?;
+    } else if !strip_exif(&mut out, options, &edited_exif_tags(edits))? {
+        return Err("Invalid TIFF file".to_string());
+    }
+
+    let le = Tiff::new(&out).ok_or("Invalid TIFF file")?.little_endian();
+    let ifd0 = Tiff::new(&out).and_then(|t| t.first_ifd()).unwrap_or(0);
+
+    if xmp != raw.xmp || options.all {
+        match &xmp {
+            Some(xmp) => set_ifd0_tag(&mut out, TAG_XMP, 1, xmp.as_bytes())?,
+            None => {
+                remove_entries(&mut out, 0, le, ifd0, &|t| t == TAG_XMP)?;
+            }
+        }
+    }
+
+    let ifd0 = Tiff::new(&out).and_then(|t| t.first_ifd()).unwrap_or(0);
+    if iptc != raw.iptc || options.all {
+        match &iptc {
+            Some(iptc) => set_ifd0_tag(&mut out, TAG_IPTC, 7, iptc)?,
+            None => {
+                remove_entries(&mut out, 0, le, ifd0, &|t| t == TAG_IPTC)?;
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn rewrite(data: &[u8], options: &StripOptions, edits: &MetadataEdits) -> Result<Vec<u8>, String> {
+    let is_jpeg = data.starts_with(&[0xFF, 0xD8]);
+    let is_png = data.starts_with(PNG_SIGNATURE);
+    let is_webp = data.len() > 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP";
+
+    if !is_jpeg && !is_png && !is_webp {
+        if Tiff::new(data).is_some() {
+            return write_tiff(data, options, edits);
+        }
+        return Err("Editing metadata is not supported for this format".to_string());
+    }
+
+    let raw = metadata::extract_raw(data);
+
+    let exif = match raw.exif {
+        Some(exif) if options.all => minimal_exif(&exif),
+        // An EXIF block that cannot be read cannot be cleaned either
+        Some(mut exif) => strip_exif(&mut exif, options, &edited_exif_tags(edits))?.then_some(exif),
+        None => None,
+    };
+
+    let blocks = Blocks {
+        exif,
+        xmp: rewrite_xmp(raw.xmp.as_deref(), options, edits)?,
+        iptc: raw
+            .iptc
+            .as_deref()
+            .and_then(|i| rewrite_iptc(i, options, edits)),
+    };
+
+    if is_jpeg {
+        write_jpeg(data, &blocks, options)
+    } else if is_png {
+        write_png(data, &blocks, options, edits)
+    } else {
+        write_webp(data, &blocks)
+    }
+}
+
+/// Copy the EXIF and XMP of `source` into a freshly encoded JPEG, PNG or
+/// WebP image. The encoded pixels are upright, so the orientation is reset
+/// to normal. Other formats are returned as they are.
+pub fn embed_metadata(encoded: Vec<u8>, source: &RawMetadata) -> Result<Vec<u8>, String> {
+    let mut exif = source.exif.clone();
+    if let Some(exif) = exif.as_mut() {
+        let entry = Tiff::new(exif).and_then(|t| Some((orientation_entry(&t)?, t.little_endian())));
+        if let Some((entry, le)) = entry.filter(|(e, _)| e.format == 3 && e.count == 1) {
+            put_u16(exif, entry.pos + 8, 1, le);
+        }
+    }
+
+    let blocks = Blocks {
+        exif,
+        xmp: source.xmp.clone(),
+        iptc: None,
+    };
+    let options = StripOptions::default();
+
+    if encoded.starts_with(&[0xFF, 0xD8]) {
+        write_jpeg(&encoded, &blocks, &options)
+    } else if encoded.starts_with(PNG_SIGNATURE) {
+        write_png(&encoded, &blocks, &options, &MetadataEdits::default())
+    } else if encoded.len() > 12 && &encoded[..4] == b"RIFF" && &encoded[8..12] == b"WEBP" {
+        write_webp(&encoded, &blocks)
+    } else {
+        Ok(encoded)
+    }
+}
+
+/// Rewrite `path` through a temporary file next to it, so a failure never
+/// leaves a half written image behind. Files with other hard links are
+/// written in place.
+pub fn rewrite_file(
+    path: &str,
+    options: &StripOptions,
+    edits: &MetadataEdits,
+) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to open: {}", e))?;
+    let out = rewrite(&data, options, edits)?;
+    replace_file(path, &out)
+}
+
+/// Replace the content of `path` with `data`, keeping its permissions.
+/// Read-only files are left alone.
+fn replace_file(path: &str, data: &[u8]) -> Result<(), String> {
+    let meta = fs::metadata(path).map_err(|e| format!("Failed to open: {}", e))?;
+    let permissions = meta.permissions();
+    if permissions.readonly() {
+        return Err("The file is read-only".to_string());
+    }
+
+    // Renaming over a file with other hard links would split them, so the
+    // new content is written into it instead.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if meta.nlink() > 1 {
+            return fs::write(path, data).map_err(|e| format!("Failed to write: {}", e));
+        }
+    }
+
+    let tmp = format!("{}.metadata.tmp", path);
+    let written = fs::write(&tmp, data)
+        .and_then(|()| fs::set_permissions(&tmp, permissions))
+        .and_then(|()| fs::rename(&tmp, path));
+    written.map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        format!("Failed to replace the file: {}", e)
+    })
+}
+
+/// Set the EXIF orientation of `path`, 1 to 8, leaving the pixels alone.
+/// JPEG, PNG and WebP files without EXIF get a block holding only it.
+pub fn set_orientation(path: &str, orientation: u16) -> Result<(), String> {
+    if !(1..=8).contains(&orientation) {
+        return Err(format!("Invalid orientation: {}", orientation));
+    }
+    let data = fs::read(path).map_err(|e| format!("Failed to open: {}", e))?;
+
+    let is_jpeg = data.starts_with(&[0xFF, 0xD8]);
+    let is_png = data.starts_with(PNG_SIGNATURE);
+    let is_webp = data.len() > 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP";
+
+    let set = |tiff: &mut Vec<u8>| {
+        let le = Tiff::new(tiff).ok_or("Invalid EXIF data")?.little_endian();
+        let value = match le {
+            true => orientation.to_le_bytes(),
+            false => orientation.to_be_bytes(),
+        };
+        set_ifd0_tag(tiff, TAG_ORIENTATION, 3, &value)
+    };
+
+    let out = if is_jpeg || is_png || is_webp {
+        let raw = metadata::extract_raw(&data);
+        let exif = match raw.exif {
+            Some(mut exif) => {
+                set(&mut exif)?;
+                exif
+            }
+            // an IFD0 with only the orientation
+            None => [
+                b"II*\0".as_slice(),
+                &8u32.to_le_bytes(),
+                &1u16.to_le_bytes(),
+                &TAG_ORIENTATION.to_le_bytes(),
+                &3u16.to_le_bytes(),
+                &1u32.to_le_bytes(),
+                &orientation.to_le_bytes(),
+                &[0; 2],
+                &0u32.to_le_bytes(),
+            ]
+            .concat(),
+        };
+
+        let blocks = Blocks {
+            exif: Some(exif),
+            xmp: raw.xmp,
+            iptc: raw.iptc,
+        };
+        let options = StripOptions::default();
+        if is_jpeg {
+            write_jpeg(&data, &blocks, &options)?
+        } else if is_png {
+            write_png(&data, &blocks, &options, &MetadataEdits::default())?
+        } else {
+            write_webp(&data, &blocks)?
+        }
+    } else if Tiff::new(&data).is_some() {
+        let mut out = data;
+        set(&mut out)?;
+        out
+    } else {
+        return Err("Rotating is not supported for this format".to_string());
+    };
+
+    replace_file(path, &out)
+}
+
+// ----- Reading the editable fields ------
+
+pub fn xmp_field(doc: &roxmltree::Document, name: &str) -> Option<Vec<String>> {
+    let prop = doc.descendants().find(|n| n.has_tag_name((NS_DC, name)))?;
+
+    let items: Vec<String> = prop
+        .descendants()
+        .filter(|n| n.has_tag_name((RDF_NS, "li")))
+        .filter_map(|n| n.text())
+        .map(str::to_string)
+        .collect();
+
+    if items.is_empty() {
+        prop.text().map(|t| vec![t.trim().to_string()])
+    } else {
+        Some(items)
+    }
+}
+
+fn editable_fields(raw: &metadata::RawMetadata) -> MetadataEdits {
+    let mut edits = MetadataEdits::default();
+
+    // EXIF first, IPTC and then XMP override it
+    if let Some(tiff) = raw.exif.as_deref().and_then(Tiff::new) {
+        if let Some(ifd0) = tiff.first_ifd().and_then(|o| tiff.read_ifd(o)) {
+            for entry in &ifd0.entries {
+                let value = || Some(tiff.format_value(entry));
+                match entry.tag {
+                    0x010E => edits.description = value(),
+                    0x013B => edits.author = value(),
+                    0x8298 => edits.copyright = value(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    if let Some(iptc) = &raw.iptc {
+        let mut keywords = Vec::new();
+        for ds in metadata::iptc_datasets(iptc) {
+            let value = || Some(String::from_utf8_lossy(ds.data).trim().to_string());
+            match (ds.record, ds.dataset) {
+                (2, 5) => edits.title = value(),
+                (2, 120) => edits.description = value(),
+                (2, 80) => edits.author = value(),
+                (2, 116) => edits.copyright = value(),
+                (2, 25) => keywords.extend(value()),
+                _ => {}
+            }
+        }
+        if !keywords.is_empty() {
+            edits.keywords = Some(keywords);
+        }
+    }
+
+    if let Some(doc) = raw
+        .xmp
+        .as_deref()
+        .and_then(|x| roxmltree::Document::parse(x.trim_end_matches('\0')).ok())
+    {
+        let first = |name| xmp_field(&doc, name).and_then(|v| v.into_iter().next());
+        if let Some(v) = first("title") {
+            edits.title = Some(v);
+        }
+        if let Some(v) = first("description") {
+            edits.description = Some(v);
+        }
+        if let Some(v) = xmp_field(&doc, "creator") {
+            edits.author = Some(v.join("; "));
+        }
+        if let Some(v) = first("rights") {
+            edits.copyright = Some(v);
+        }
+        if let Some(v) = xmp_field(&doc, "subject") {
+            edits.keywords = Some(v);
+        }
+    }
+
+    edits
+}
+
+// ----- Commands ------
+//
+// Every one of these reads the whole file, so they run off the async runtime.
+
+#[tauri::command]
+pub async fn strip_metadata(path: String, options: StripOptions) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        rewrite_file(&path, &options, &MetadataEdits::default())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn edit_metadata(path: String, edits: MetadataEdits) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        rewrite_file(&path, &StripOptions::default(), &edits)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn load_editable_metadata(path: String) -> Result<MetadataEdits, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let raw = metadata::read_raw(&path)?;
+        Ok(editable_fields(&raw))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Little endian TIFF structure with one IFD holding `entries`, each
+    /// (tag, format, count, inline value).
+    fn tiff(entries: &[(u16, u16, u32, [u8; 4])]) -> Vec<u8> {
+        let mut out = b"II*\0".to_vec();
+        out.extend(8u32.to_le_bytes());
+        out.extend((entries.len() as u16).to_le_bytes());
+        for (tag, format, count, value) in entries {
+            out.extend(tag.to_le_bytes());
+            out.extend(format.to_le_bytes());
+            out.extend(count.to_le_bytes());
+            out.extend(value);
+        }
+        out.extend(0u32.to_le_bytes());
+        out
+    }
+
+    fn tags(data: &[u8]) -> Vec<u16> {
+        let tiff = Tiff::new(data).unwrap();
+        let ifd = tiff.read_ifd(tiff.first_ifd().unwrap()).unwrap();
+        ifd.entries.iter().map(|e| e.tag).collect()
+    }
+
+    #[test]
+    fn removes_entries_and_keeps_the_rest() {
+        let mut data = tiff(&[
+            (TAG_ORIENTATION, 3, 1, [6, 0, 0, 0]),
+            (0x0131, 2, 4, *b"abc\0"),
+        ]);
+        let options = StripOptions {
+            software: true,
+            ..Default::default()
+        };
+        assert_eq!(strip_exif(&mut data, &options, &[]), Ok(true));
+        assert_eq!(tags(&data), [TAG_ORIENTATION]);
+        assert_eq!(exif_orientation(&data), Some(6));
+    }
+
+    /// JPEG with an EXIF block, a scan and `trailer` after the EOI.
+    fn jpeg(trailer: &[u8]) -> Vec<u8> {
+        let exif = tiff(&[(TAG_ORIENTATION, 3, 1, [6, 0, 0, 0])]);
+        let mut out = vec![0xFF, 0xD8];
+        jpeg_segment(&mut out, 0xE1, &[JPEG_EXIF, &exif]).unwrap();
+        jpeg_segment(&mut out, 0xE2, &[b"MPF\0", b"MM\0*"]).unwrap();
+        jpeg_segment(&mut out, 0xDA, &[&[1, 1, 0, 0, 0x3F, 0]]).unwrap();
+        out.extend([0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD0, 0x56, 0xFF, 0xD9]);
+        out.extend_from_slice(trailer);
+        out
+    }
+
+    fn blocks() -> Blocks {
+        Blocks {
+            exif: None,
+            xmp: None,
+            iptc: None,
+        }
+    }
+
+    fn contains(data: &[u8], part: &[u8]) -> bool {
+        data.windows(part.len()).any(|w| w == part)
+    }
+
+    #[test]
+    fn stripping_drops_what_follows_the_jpeg() {
+        let preview = b"\xFF\xD8\xFF\xE1\x00\x0AExif\0\0GPS\xFF\xD9";
+        let data = jpeg(preview);
+
+        for options in [
+            StripOptions {
+                all: true,
+                ..Default::default()
+            },
+            StripOptions {
+                gps: true,
+                ..Default::default()
+            },
+        ] {
+            let out = write_jpeg(&data, &blocks(), &options).unwrap();
+            assert!(out.ends_with(&[0x56, 0xFF, 0xD9]));
+            assert!(!contains(&out, b"GPS"));
+            assert!(!contains(&out, b"MPF"));
+        }
+
+        let options = StripOptions {
+            serial: true,
+            ..Default::default()
+        };
+        let out = write_jpeg(&data, &blocks(), &options).unwrap();
+        assert!(out.ends_with(preview));
+        assert!(contains(&out, b"MPF"));
+    }
+
+    fn titled(title: &str) -> MetadataEdits {
+        MetadataEdits {
+            title: Some(title.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn jpeg_edits_keep_the_orientation_and_the_scan() {
+        let data = jpeg(b"");
+        let out = rewrite(&data, &StripOptions::default(), &titled("Harbour")).unwrap();
+
+        let raw = metadata::extract_raw(&out);
+        assert!(raw.xmp.unwrap().contains("Harbour"));
+        assert_eq!(exif_orientation(&raw.exif.unwrap()), Some(6));
+        assert!(out.ends_with(&[0x56, 0xFF, 0xD9]));
+    }
+
+    fn png(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut out = PNG_SIGNATURE.to_vec();
+        for (kind, body) in chunks {
+            png_chunk(&mut out, kind, body);
+        }
+        out
+    }
+
+    fn png_kinds(data: &[u8]) -> Vec<[u8; 4]> {
+        metadata::png_chunks(data).iter().map(|c| c.kind).collect()
+    }
+
+    #[test]
+    fn png_metadata_goes_before_the_image_data() {
+        let exif = tiff(&[
+            (TAG_ORIENTATION, 3, 1, [6, 0, 0, 0]),
+            (0x0131, 2, 4, *b"abc\0"),
+        ]);
+        let data = png(&[
+            (b"IHDR", &[0; 13]),
+            (b"tEXt", b"Software\0abc"),
+            (b"tEXt", b"Comment\0kept"),
+            (b"eXIf", &exif),
+            (b"IDAT", &[1, 2, 3]),
+            (b"IEND", &[]),
+        ]);
+        let options = StripOptions {
+            software: true,
+            ..Default::default()
+        };
+        let out = rewrite(&data, &options, &titled("Harbour")).unwrap();
+
+        assert_eq!(
+            png_kinds(&out),
+            [*b"IHDR", *b"tEXt", *b"eXIf", *b"iTXt", *b"iTXt", *b"IDAT", *b"IEND"]
+        );
+        assert!(!contains(&out, b"Software"));
+        assert!(contains(&out, b"Comment\0kept"));
+        assert!(contains(&out, b"Title\0\0\0\0\0Harbour"));
+
+        let raw = metadata::extract_raw(&out);
+        assert!(raw.xmp.unwrap().contains("Harbour"));
+        assert_eq!(tags(&raw.exif.unwrap()), [TAG_ORIENTATION]);
+
+        let options = StripOptions {
+            all: true,
+            ..Default::default()
+        };
+        let out = rewrite(&data, &options, &MetadataEdits::default()).unwrap();
+        assert_eq!(png_kinds(&out), [*b"IHDR", *b"eXIf", *b"IDAT", *b"IEND"]);
+    }
+
+    /// Lossless WebP without a VP8X chunk.
+    fn webp(width: u32, height: u32) -> Vec<u8> {
+        let bits = (width - 1) | (height - 1) << 14;
+        let mut vp8l = vec![0x2F];
+        vp8l.extend(bits.to_le_bytes());
+        let mut body = Vec::new();
+        riff_chunk(&mut body, b"VP8L", &vp8l);
+
+        let mut out = b"RIFF".to_vec();
+        out.extend(((body.len() + 4) as u32).to_le_bytes());
+        out.extend_from_slice(b"WEBP");
+        out.extend(body);
+        out
+    }
+
+    #[test]
+    fn webp_gains_an_extended_header_for_metadata() {
+        let data = webp(300, 200);
+        let out = rewrite(&data, &StripOptions::default(), &titled("Harbour")).unwrap();
+
+        let size = u32::from_le_bytes(out[4..8].try_into().unwrap()) as usize;
+        assert_eq!(size, out.len() - 8);
+
+        let chunks = metadata::webp_chunks(&out);
+        let kinds: Vec<_> = chunks.iter().map(|c| c.kind).collect();
+        assert_eq!(kinds, [*b"VP8X", *b"VP8L", *b"XMP "]);
+
+        let vp8x = &out[chunks[0].body.clone()];
+        assert_eq!(vp8x[0], 0x04);
+        assert_eq!(vp8x[4..7], (300u32 - 1).to_le_bytes()[..3]);
+        assert_eq!(vp8x[7..10], (200u32 - 1).to_le_bytes()[..3]);
+        assert!(metadata::extract_raw(&out).xmp.unwrap().contains("Harbour"));
+
+        // Nothing to add, nothing to change
+        let out = rewrite(&data, &StripOptions::default(), &MetadataEdits::default()).unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn tiff_edits_move_ifd0_to_the_end() {
+        let data = tiff(&[
+            (TAG_ORIENTATION, 3, 1, [6, 0, 0, 0]),
+            (0x0131, 2, 4, *b"abc\0"),
+        ]);
+        let options = StripOptions {
+            software: true,
+            ..Default::default()
+        };
+        let out = rewrite(&data, &options, &titled("Harbour")).unwrap();
+
+        assert_eq!(tags(&out), [TAG_ORIENTATION, TAG_XMP]);
+        assert_eq!(exif_orientation(&out), Some(6));
+        assert!(metadata::extract_raw(&out).xmp.unwrap().contains("Harbour"));
+
+        let options = StripOptions {
+            all: true,
+            ..Default::default()
+        };
+        let out = rewrite(&out, &options, &MetadataEdits::default()).unwrap();
+        assert_eq!(tags(&out), [TAG_ORIENTATION]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replacing_keeps_permissions_and_links() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("metadata-edit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("image.jpg");
+        let link = dir.join("link.jpg");
+        let path = file.to_str().unwrap();
+
+        fs::write(&file, b"old").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        replace_file(path, b"new").unwrap();
+        assert_eq!(fs::read(&file).unwrap(), b"new");
+        let mode = fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        fs::hard_link(&file, &link).unwrap();
+        replace_file(path, b"newer").unwrap();
+        assert_eq!(fs::read(&link).unwrap(), b"newer");
+
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o444)).unwrap();
+        assert!(replace_file(path, b"newest").is_err());
+        assert_eq!(fs::read(&file).unwrap(), b"newer");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cut_off_ifd_is_an_error() {
+        let mut data = tiff(&[
+            (TAG_ORIENTATION, 3, 1, [6, 0, 0, 0]),
+            (0x0131, 2, 4, *b"abc\0"),
+        ]);
+        // no offset of the next IFD
+        data.truncate(data.len() - 4);
+        let options = StripOptions {
+            software: true,
+            ..Default::default()
+        };
+        assert!(strip_exif(&mut data, &options, &[]).is_err());
+    }
+
+    #[test]
+    fn cut_off_entry_is_an_error() {
+        let mut data = tiff(&[(0x0131, 2, 4, *b"abc\0"), (0x8298, 2, 4, *b"xyz\0")]);
+        // no offset of the next IFD and half the value of the last entry
+        data.truncate(data.len() - 6);
+        let options = StripOptions {
+            software: true,
+            ..Default::default()
+        };
+        assert!(strip_exif(&mut data.clone(), &options, &[]).is_err());
+        assert!(set_ifd0_tag(&mut data, TAG_XMP, 1, b"<x/>").is_err());
+    }
+}
@@ -0,0 +1,329 @@
+// ---------------- RATINGS, LABELS AND TAGS ----------------
+//
+// Culling marks: a star rating, a color label, a pick / reject flag and
+// free-form tags. They are stored as XMP so that Lightroom, darktable and
+// digiKam see them: xmp:Rating (-1 for rejected), xmp:Label,
+// digiKam:PickLabel and dc:subject.
+//
+// JPEG, PNG, WebP and TIFF files get the marks embedded, other formats a
+// `photo.jpg.xmp` sidecar (darktable and digiKam naming). Sidecars can also
+// be forced for every file, to leave the originals untouched. An existing
+// sidecar always wins, also under Lightroom's `photo.xmp` name.
+
+use crate::library::{self, Selection};
+use crate::metadata::{self, RDF_NS};
+use crate::metadata_edit::{self, MetadataEdits, StripOptions, NS_DIGIKAM, NS_XMP};
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Color labels, in Lightroom's order and spelling.
+const LABELS: [&str; 5] = ["Red", "Yellow", "Green", "Blue", "Purple"];
+
+/// digiKam:PickLabel values.
+const PICK_REJECTED: u8 = 1;
+const PICK_ACCEPTED: u8 = 3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Flag {
+    #[default]
+    None,
+    Pick,
+    Reject,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Marks {
+    /// 0 to 5 stars.
+    pub rating: u8,
+    /// One of `LABELS`.
+    pub label: Option<String>,
+    pub flag: Flag,
+    pub tags: Vec<String>,
+    /// Read from or written to a sidecar rather than the file itself.
+    pub sidecar: bool,
+}
+
+/// Sidecar names, the one written by default first.
+pub fn sidecar_paths(path: &str) -> [PathBuf; 2] {
+    [
+        PathBuf::from(format!("{}.xmp", path)),
+        Path::new(path).with_extension("xmp"),
+    ]
+}
+
+pub fn existing_sidecar(path: &str) -> Option<PathBuf> {
+    sidecar_paths(path).into_iter().find(|p| p.is_file())
+}
+
+/// Formats metadata_edit can write XMP into.
+fn can_embed(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| {
+            matches!(
+                ext.to_lowercase().as_str(),
+                "jpg" | "jpeg" | "jfif" | "png" | "webp" | "tif" | "tiff"
+            )
+        })
+}
+
+/// Value of a simple property, written either as an attribute of
+/// rdf:Description (darktable) or as an element (Lightroom, digiKam).
+fn xmp_value(doc: &roxmltree::Document, ns: &str, name: &str) -> Option<String> {
+    doc.descendants()
+        .filter(|n| n.has_tag_name((RDF_NS, "Description")))
+        .find_map(|desc| {
+            desc.attribute((ns, name)).map(str::to_string).or_else(|| {
+                desc.children()
+                    .find(|c| c.has_tag_name((ns, name)))
+                    .and_then(|c| c.text())
+                    .map(|t| t.trim().to_string())
+            })
+        })
+}
+
+fn parse_marks(xmp: &str) -> Marks {
+    let mut marks = Marks::default();
+    let Ok(doc) = roxmltree::Document::parse(xmp.trim_end_matches('\0')) else {
+        return marks;
+    };
+
+    // Lightroom writes whole numbers, some tools "3.0"
+    let rating = xmp_value(&doc, NS_XMP, "Rating")
+        .and_then(|r| r.parse::<f64>().ok())
+        .map(|r| r.round() as i32);
+    match rating {
+        Some(r) if r < 0 => marks.flag = Flag::Reject,
+        Some(r) => marks.rating = r.min(5) as u8,
+        None => {}
+    }
+
+    marks.label = xmp_value(&doc, NS_XMP, "Label")
+        .and_then(|l| LABELS.iter().find(|n| n.eq_ignore_ascii_case(&l)))
+        .map(|l| l.to_string());
+
+    match xmp_value(&doc, NS_DIGIKAM, "PickLabel").and_then(|p| p.parse::<u8>().ok()) {
+        Some(PICK_REJECTED) => marks.flag = Flag::Reject,
+        Some(PICK_ACCEPTED) => marks.flag = Flag::Pick,
+        _ => {}
+    }
+
+    marks.tags = metadata_edit::xmp_field(&doc, "subject").unwrap_or_default();
+    marks
+}
+
+pub fn read_marks(path: &str) -> Marks {
+    if let Some(sidecar) = existing_sidecar(path) {
+        let mut marks = fs::read_to_string(sidecar)
+            .map(|xmp| parse_marks(&xmp))
+            .unwrap_or_default();
+        marks.sidecar = true;
+        return marks;
+    }
+
+    if !can_embed(path) {
+        return Marks::default();
+    }
+    metadata::read_raw_quick(path)
+        .ok()
+        .and_then(|raw| raw.xmp)
+        .map(|xmp| parse_marks(&xmp))
+        .unwrap_or_default()
+}
+
+/// Edits that store `marks`. The keywords are only written when the tags
+/// differ from `stored`, IPTC and XPKeywords can hold more than dc:subject.
+fn edits_for(marks: &Marks, stored: &[String]) -> Result<MetadataEdits, String> {
+    if marks.rating > 5 {
+        return Err(format!("Invalid rating: {}", marks.rating));
+    }
+    let label = match marks.label.as_deref().map(str::trim) {
+        None | Some("") => String::new(),
+        Some(label) => LABELS
+            .iter()
+            .find(|n| n.eq_ignore_ascii_case(label))
+            .ok_or_else(|| format!("Unknown color label: {}", label))?
+            .to_string(),
+    };
+
+    let (rating, pick) = match marks.flag {
+        // the stars are dropped, Lightroom and darktable keep only the -1
+        Flag::Reject => (-1, PICK_REJECTED),
+        Flag::Pick => (marks.rating as i8, PICK_ACCEPTED),
+        Flag::None => (marks.rating as i8, 0),
+    };
+
+    Ok(MetadataEdits {
+        rating: Some(rating),
+        label: Some(label),
+        pick: Some(pick),
+        keywords: (marks.tags != stored).then(|| marks.tags.clone()),
+        ..Default::default()
+    })
+}
+
+fn write_sidecar(sidecar: &Path, edits: &MetadataEdits) -> Result<(), String> {
+    let existing = match fs::read_to_string(sidecar) {
+        Ok(xmp) => Some(xmp),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to read the sidecar: {}", e)),
+    };
+
+    let Some(xmp) =
+        metadata_edit::rewrite_xmp(existing.as_deref(), &StripOptions::default(), edits)?
+    else {
+        // nothing to store and no sidecar yet
+        return Ok(());
+    };
+
+    let tmp = sidecar.with_extension("xmp.tmp");
+    fs::write(&tmp, xmp).map_err(|e| format!("Failed to write the sidecar: {}", e))?;
+    fs::rename(&tmp, sidecar).map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        format!("Failed to replace the sidecar: {}", e)
+    })
+}
+
+/// Current marks of `path`.
+#[tauri::command]
+pub async fn get_image_marks(path: String) -> Marks {
+    read_marks(&path)
+}
+
+/// Store `marks` for `path`, replacing the previous ones. `sidecar` writes
+/// a sidecar even when the format could hold the XMP itself.
+pub fn write_marks(path: &str, marks: &Marks, sidecar: bool) -> Result<Marks, String> {
+    let target = match existing_sidecar(path) {
+        Some(existing) => Some(existing),
+        None if sidecar || !can_embed(path) => Some(sidecar_paths(path)[0].clone()),
+        None => None,
+    };
+
+    // a new sidecar holds no tags yet, whatever the file has
+    let stored = match &target {
+        Some(sidecar) if !sidecar.is_file() => Vec::new(),
+        _ => read_marks(path).tags,
+    };
+    let edits = edits_for(marks, &stored)?;
+
+    match target {
+        Some(sidecar) => write_sidecar(&sidecar, &edits)?,
+        None => metadata_edit::rewrite_file(path, &StripOptions::default(), &edits)?,
+    }
+
+    Ok(read_marks(path))
+}
+
+#[tauri::command]
+pub async fn set_image_marks(path: String, marks: Marks, sidecar: bool) -> Result<Marks, String> {
+    // rewrites the whole file when the marks go into it
+    tauri::async_runtime::spawn_blocking(move || write_marks(&path, &marks, sidecar))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// ----- Filtering and sorting folders ------
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct MarksFilter {
+    /// At least this many stars.
+    min_rating: u8,
+    /// Any of these labels, empty for no condition.
+    labels: Vec<String>,
+    /// Only files with this flag.
+    flag: Option<Flag>,
+    /// Leave out rejected files.
+    hide_rejected: bool,
+    /// Every one of these tags, ignoring case.
+    tags: Vec<String>,
+}
+
+impl MarksFilter {
+    /// The conditions on the index rows, labels and tags ignoring case.
+    pub fn select(&self, selection: &mut Selection) {
+        selection.filter(
+            "rating >= ?".to_string(),
+            vec![Value::from(self.min_rating as i64)],
+        );
+        if !self.labels.is_empty() {
+            let any = vec!["?"; self.labels.len()].join(", ");
+            let labels = self
+                .labels
+                .iter()
+                .map(|l| Value::from(l.to_ascii_lowercase()));
+            selection.filter(format!("lower(label) IN ({})", any), labels.collect());
+        }
+        if let Some(flag) = self.flag {
+            selection.filter(
+                "flag = ?".to_string(),
+                vec![Value::from(library::flag_name(flag).to_string())],
+            );
+        }
+        if self.hide_rejected {
+            selection.filter("flag != 'reject'".to_string(), Vec::new());
+        }
+        for tag in &self.tags {
+            selection.filter(
+                "EXISTS (SELECT 1 FROM json_each(images.tags) WHERE lower(value) = ?)".to_string(),
+                vec![Value::from(tag.trim().to_ascii_lowercase())],
+            );
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum MarksSort {
+    /// Most stars first.
+    Rating,
+    /// In `LABELS` order, unlabeled files last.
+    Label,
+    /// Picks first, rejects last.
+    Flag,
+}
+
+impl MarksSort {
+    /// The order of the index rows, ties keep the listing order.
+    pub fn select(self, selection: &mut Selection) {
+        let order = match self {
+            MarksSort::Rating => "rating DESC".to_string(),
+            MarksSort::Label => {
+                let positions = LABELS.iter().enumerate();
+                let cases: Vec<String> = positions
+                    .map(|(i, label)| format!("WHEN '{}' THEN {}", label, i))
+                    .collect();
+                format!("CASE label {} ELSE {} END", cases.join(" "), LABELS.len())
+            }
+            MarksSort::Flag => {
+                "CASE flag WHEN 'pick' THEN 0 WHEN 'reject' THEN 2 ELSE 1 END".to_string()
+            }
+        };
+        selection.order_by(order);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_are_only_written_when_the_tags_change() {
+        let marks = Marks {
+            rating: 3,
+            tags: vec!["beach".to_string()],
+            ..Default::default()
+        };
+        let edits = edits_for(&marks, &marks.tags).unwrap();
+        assert_eq!(edits.rating, Some(3));
+        assert!(edits.keywords.is_none());
+
+        let edits = edits_for(&marks, &[]).unwrap();
+        assert_eq!(edits.keywords, Some(vec!["beach".to_string()]));
+    }
+}
@@ -0,0 +1,625 @@
+// ---------------- BATCH RENAME ----------------
+//
+// Renames a list of files from a name template, then an optional regex
+// find / replace and a case change. The extension is kept, optionally
+// lower cased.
+//
+// Every rename is planned before anything is touched: names that clash
+// with each other or with files outside the batch stop the whole batch.
+// Swaps and chains (a → b while b → c) are fine, those files go through a
+// temporary name first. XMP sidecars move along with their image, and if
+// any step fails everything done so far is undone.
+
+use crate::decode::DecodeState;
+use crate::file_ops;
+use crate::journal::{JournalState, Operation};
+use crate::library::{self, LibraryState, Record};
+use crate::ratings;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+// ----- Templates ------
+
+/// Characters no file name may contain on any platform.
+const FORBIDDEN: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+fn clean_value(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| {
+            if FORBIDDEN.contains(&c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Year, month, day, hour, minute and second.
+type DateParts = [i64; 6];
+
+fn parse_date(date: &str) -> Option<DateParts> {
+    // "2026-09-14 18:03:22", or just the date
+    let digits: Vec<i64> = date
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|p| !p.is_empty())
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+
+    let mut parts = [0; 6];
+    for (part, value) in parts.iter_mut().zip(&digits) {
+        *part = *value;
+    }
+    (digits.len() >= 3).then_some(parts)
+}
+
+fn modified_parts(millis: i64) -> Option<DateParts> {
+    let secs = millis.div_euclid(1000);
+    let mut parts = parse_date(&library::civil_date(secs))?;
+    let time = secs.rem_euclid(86_400);
+    parts[3] = time / 3600;
+    parts[4] = time / 60 % 60;
+    parts[5] = time % 60;
+    Some(parts)
+}
+
+/// strftime-like: %Y %y %m %d %H %M %S and %%.
+fn format_date(parts: DateParts, format: &str) -> Result<String, String> {
+    let [year, month, day, hour, minute, second] = parts;
+    let mut out = String::new();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", year)),
+            Some('y') => out.push_str(&format!("{:02}", year % 100)),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('H') => out.push_str(&format!("{:02}", hour)),
+            Some('M') => out.push_str(&format!("{:02}", minute)),
+            Some('S') => out.push_str(&format!("{:02}", second)),
+            Some('%') => out.push('%'),
+            other => {
+                return Err(format!(
+                    "Unknown date format %{}",
+                    other.map(String::from).unwrap_or_default()
+                ))
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Expand a name template for `record`. Fields are written `{field}` or
+/// `{field:format}`:
+///
+///   {name} {ext} {folder}        the file's name, extension and folder
+///   {counter} {counter:04}       position in the batch, zero padded
+///   {date_taken:%Y%m%d}          EXIF date, else the modification time
+///   {modified:%Y-%m-%d_%H%M%S}   file modification time (UTC)
+///   {camera} {lens} {iso} {width} {height}
+///
+/// Missing values expand to nothing.
+pub fn expand_template(template: &str, record: &Record, counter: usize) -> Result<String, String> {
+    let path = Path::new(&record.path);
+    let part = |p: Option<&std::ffi::OsStr>| {
+        p.and_then(|s| s.to_str())
+            .map(str::to_string)
+            .unwrap_or_default()
+    };
+
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|e| start + e)
+            .ok_or("Unclosed { in the name template")?;
+
+        let field = &rest[start + 1..end];
+        let (name, format) = match field.split_once(':') {
+            Some((name, format)) => (name, Some(format)),
+            None => (field, None),
+        };
+
+        let value = match (name, format) {
+            ("name", None) => part(path.file_stem()),
+            ("ext", None) => part(path.extension()),
+            ("folder", None) => part(path.parent().and_then(|p| p.file_name())),
+            ("counter", None) => counter.to_string(),
+            ("counter", Some(width)) => {
+                let width: usize = width
+                    .parse()
+                    .map_err(|_| format!("Invalid counter width: {}", width))?;
+                format!("{:0width$}", counter, width = width)
+            }
+            ("date_taken" | "date", format) => {
+                let parts = record
+                    .date
+                    .as_deref()
+                    .filter(|d| d.len() > 10)
+                    .and_then(parse_date)
+                    .or_else(|| modified_parts(record.stamp.modified));
+                match parts {
+                    Some(parts) => format_date(parts, format.unwrap_or("%Y-%m-%d"))?,
+                    None => String::new(),
+                }
+            }
+            ("modified", format) => match modified_parts(record.stamp.modified) {
+                Some(parts) => format_date(parts, format.unwrap_or("%Y-%m-%d"))?,
+                None => String::new(),
+            },
+            ("camera", None) => clean_value(record.camera.as_deref().unwrap_or("")),
+            ("lens", None) => clean_value(record.lens.as_deref().unwrap_or("")),
+            ("iso", None) => record.iso.map(|i| i.to_string()).unwrap_or_default(),
+            ("width", None) => record.width.map(|w| w.to_string()).unwrap_or_default(),
+            ("height", None) => record.height.map(|h| h.to_string()).unwrap_or_default(),
+            _ => return Err(format!("Unknown name field: {{{}}}", field)),
+        };
+        out.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+// ----- Planning ------
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Case {
+    #[default]
+    Keep,
+    Lower,
+    Upper,
+    /// First letter of every word upper case.
+    Title,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct RenameOptions {
+    /// See `expand_template`.
+    template: String,
+    /// Regex applied to the expanded name, empty for none.
+    find: String,
+    /// Replacement, `$1` for groups.
+    replace: String,
+    case: Case,
+    /// Lower case the extensions as well.
+    lower_ext: bool,
+    /// Counter of the first file.
+    start: usize,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        RenameOptions {
+            template: "{name}".to_string(),
+            find: String::new(),
+            replace: String::new(),
+            case: Case::Keep,
+            lower_ext: false,
+            start: 1,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PlannedRename {
+    from: String,
+    to: String,
+    /// Why this file can't be renamed, which stops the batch.
+    error: Option<String>,
+    /// Its new name is taken by another file of the batch, it goes
+    /// through a temporary name.
+    chained: bool,
+}
+
+fn change_case(name: &str, case: Case) -> String {
+    match case {
+        Case::Keep => name.to_string(),
+        Case::Lower => name.to_lowercase(),
+        Case::Upper => name.to_uppercase(),
+        Case::Title => {
+            let mut out = String::new();
+            let mut word_start = true;
+            for c in name.chars() {
+                if word_start {
+                    out.extend(c.to_uppercase());
+                } else {
+                    out.extend(c.to_lowercase());
+                }
+                word_start = !c.is_alphanumeric();
+            }
+            out
+        }
+    }
+}
+
+/// A name, without extension, that can be used in any folder.
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() || name == "." || name == ".." {
+        return Err("The new name is empty".to_string());
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| FORBIDDEN.contains(c) || c.is_control())
+    {
+        return Err(format!("The new name contains {:?}", c));
+    }
+    file_ops::check_file_name(name).map_err(|e| e.to_string())
+}
+
+fn new_name(
+    record: &Record,
+    counter: usize,
+    options: &RenameOptions,
+    find: Option<&Regex>,
+) -> Result<String, String> {
+    let mut name = expand_template(&options.template, record, counter)?;
+    if let Some(find) = find {
+        name = find
+            .replace_all(&name, options.replace.as_str())
+            .into_owned();
+    }
+    let name = change_case(name.trim(), options.case);
+    check_name(&name)?;
+
+    let ext = Path::new(&record.path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+    Ok(match ext {
+        "" => name,
+        ext if options.lower_ext => format!("{}.{}", name, ext.to_lowercase()),
+        ext => format!("{}.{}", name, ext),
+    })
+}
+
+/// Key for comparing paths the way the file system does.
+fn path_key(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if cfg!(any(windows, target_os = "macos")) {
+        path.to_lowercase()
+    } else {
+        path.to_string()
+    }
+}
+
+/// The file and its sidecars, as (old, new) pairs.
+fn moves_of(from: &str, to: &str) -> Vec<(PathBuf, PathBuf)> {
+    let mut moves = vec![(PathBuf::from(from), PathBuf::from(to))];
+    let sidecars = ratings::sidecar_paths(from)
+        .into_iter()
+        .zip(ratings::sidecar_paths(to));
+    moves.extend(sidecars.filter(|(old, _)| old.is_file()));
+    moves
+}
+
+fn plan(
+    paths: &[String],
+    options: &RenameOptions,
+    library: &LibraryState,
+    limits: &DecodeState,
+) -> Result<Vec<PlannedRename>, String> {
+    let find = match options.find.as_str() {
+        "" => None,
+        pattern => Some(Regex::new(pattern).map_err(|e| format!("Invalid regex: {}", e))?),
+    };
+
+    let limits = *limits.0.lock().unwrap();
+    let records: HashMap<String, Record> = library::records_of(library, paths.to_vec(), &limits)
+        .into_iter()
+        .map(|r| (r.path.clone(), r))
+        .collect();
+
+    // a broken template is one error, not one per file
+    if let Some(record) = records.values().next() {
+        expand_template(&options.template, record, options.start)?;
+    }
+
+    let mut planned: Vec<PlannedRename> = paths
+        .iter()
+        .enumerate()
+        .map(|(i, from)| {
+            let result = match records.get(from) {
+                Some(record) => new_name(record, options.start + i, options, find.as_ref()),
+                None => Err("The file is gone".to_string()),
+            };
+            let (to, error) = match result {
+                Ok(name) => {
+                    let dir = Path::new(from).parent().unwrap_or(Path::new(""));
+                    (dir.join(name).to_string_lossy().to_string(), None)
+                }
+                Err(e) => (from.clone(), Some(e)),
+            };
+            PlannedRename {
+                from: from.clone(),
+                to,
+                error,
+                chained: false,
+            }
+        })
+        .collect();
+
+    // what the batch moves away, sidecars included
+    let leaving: HashSet<String> = planned
+        .iter()
+        .filter(|p| p.error.is_none() && p.from != p.to)
+        .flat_map(|p| moves_of(&p.from, &p.to))
+        .map(|(old, _)| path_key(&old))
+        .collect();
+
+    let mut claimed: HashMap<String, String> = HashMap::new();
+    for p in planned.iter_mut() {
+        if p.error.is_some() || p.from == p.to {
+            continue;
+        }
+
+        for (old, new) in moves_of(&p.from, &p.to) {
+            let key = path_key(&new);
+            if let Some(other) = claimed.get(&key) {
+                p.error = Some(format!("Same new name as {}", other));
+            } else if leaving.contains(&key) && path_key(&old) != key {
+                p.chained = true;
+            } else if new.exists() && !file_ops::same_file(&old, &new) {
+                p.error = Some(format!("{} already exists", new.display()));
+            }
+            claimed.insert(key, p.from.clone());
+        }
+    }
+    Ok(planned)
+}
+
+// ----- Renaming ------
+
+/// Rename without replacing anything. A case-only rename of the same file
+/// is allowed.
+fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    file_ops::move_no_replace(from, to).map_err(|e| e.to_string())
+}
+
+/// Moves done so far, undone in reverse order after `error` stopped the
+/// rename. Returns the message for it, with the files that stay moved.
+fn undo_moves(done: &[(PathBuf, PathBuf)], error: String) -> String {
+    let stuck: Vec<String> = done
+        .iter()
+        .rev()
+        .filter_map(|(from, to)| {
+            let e = fs::rename(to, from).err()?;
+            Some(format!(
+                "{} is still at {}: {}",
+                from.display(),
+                to.display(),
+                e
+            ))
+        })
+        .collect();
+    match stuck.is_empty() {
+        true => format!("{}, nothing was changed", error),
+        false => format!(
+            "{}, and not everything could be put back: {}",
+            error,
+            stuck.join("; ")
+        ),
+    }
+}
+
+/// Preview of a rename: the new name of every file and what stops it.
+#[tauri::command]
+pub async fn plan_batch_rename(
+    paths: Vec<String>,
+    options: RenameOptions,
+    library: State<'_, LibraryState>,
+    limits: State<'_, DecodeState>,
+) -> Result<Vec<PlannedRename>, String> {
+    plan(&paths, &options, &library, &limits)
+}
+
+/// Rename `paths`, all of them or, if anything fails, none. Returns the
+/// (old, new) path of every renamed file. The renames are one journal
+/// step, undone together.
+#[tauri::command]
+pub async fn batch_rename(
+    paths: Vec<String>,
+    options: RenameOptions,
+    library: State<'_, LibraryState>,
+    limits: State<'_, DecodeState>,
+    journal: State<'_, JournalState>,
+) -> Result<Vec<(String, String)>, String> {
+    let planned = plan(&paths, &options, &library, &limits)?;
+
+    let blocked = planned.iter().filter(|p| p.error.is_some()).count();
+    if blocked > 0 {
+        return Err(format!(
+            "{} file{} can't be renamed, nothing was changed",
+            blocked,
+            if blocked == 1 { "" } else { "s" }
+        ));
+    }
+
+    let planned: Vec<&PlannedRename> = planned.iter().filter(|p| p.from != p.to).collect();
+    let mut done: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut parked: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    // chained files step aside first, so their new names are free
+    for (i, p) in planned.iter().enumerate().filter(|(_, p)| p.chained) {
+        for (k, (old, new)) in moves_of(&p.from, &p.to).into_iter().enumerate() {
+            let dir = old.parent().unwrap_or(Path::new(""));
+            let tmp = dir.join(format!(".rename-{}-{}-{}.tmp", std::process::id(), i, k));
+            if let Err(e) = move_file(&old, &tmp) {
+                return Err(undo_moves(&done, e));
+            }
+            done.push((old, tmp.clone()));
+            parked.push((tmp, new));
+        }
+    }
+
+    let direct = planned
+        .iter()
+        .filter(|p| !p.chained)
+        .flat_map(|p| moves_of(&p.from, &p.to));
+    for (from, to) in direct.chain(parked) {
+        if let Err(e) = move_file(&from, &to) {
+            return Err(undo_moves(&done, e));
+        }
+        done.push((from, to));
+    }
+
+    // sidecars go along when the step is undone, as they did here
+    let ops = planned
+        .iter()
+        .map(|p| Operation::Move {
+            from: p.from.clone(),
+            to: p.to.clone(),
+        })
+        .collect();
+    if let Some(step) = Operation::group(ops) {
+        journal.0.lock().unwrap().record(step);
+    }
+
+    Ok(planned
+        .iter()
+        .map(|p| (p.from.clone(), p.to.clone()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::DecodeLimits;
+    use crate::library::Stamp;
+    use std::sync::Mutex;
+
+    fn record(path: &str, date: Option<&str>) -> Record {
+        Record {
+            path: path.to_string(),
+            stamp: Stamp {
+                size: 0,
+                // 2026-09-15 07:04:05 UTC
+                modified: 1_789_455_845_000,
+                sidecar_modified: None,
+            },
+            width: Some(6000),
+            height: None,
+            camera: Some("Canon: EOS/R5".to_string()),
+            lens: None,
+            date: date.map(str::to_string),
+            iso: Some(400.0),
+            has_exif: false,
+            has_xmp: false,
+            has_iptc: false,
+            has_gps: false,
+            marks: Default::default(),
+            content_hash: None,
+            perceptual: None,
+        }
+    }
+
+    #[test]
+    fn expands_template_fields() {
+        let taken = record("/photos/trip/IMG_1.JPG", Some("2026-09-14 18:03:22"));
+        let expand = |template: &str| expand_template(template, &taken, 7);
+
+        assert_eq!(expand("{folder}-{name}.{ext}").unwrap(), "trip-IMG_1.JPG");
+        assert_eq!(expand("{name}_{counter:03}").unwrap(), "IMG_1_007");
+        assert_eq!(
+            expand("{date_taken:%Y%m%d_%H%M%S}").unwrap(),
+            "20260914_180322"
+        );
+        assert_eq!(
+            expand("{modified:%y-%m-%d %H.%M}").unwrap(),
+            "26-09-15 07.04"
+        );
+        assert_eq!(
+            expand("{camera} {iso} {width}x{height}").unwrap(),
+            "Canon_ EOS_R5 400 6000x"
+        );
+        assert_eq!(expand("100%").unwrap(), "100%");
+
+        // without a time taken, the modification time stands in
+        let undated = record("/photos/IMG_2.JPG", Some("2026-09-14"));
+        assert_eq!(
+            expand_template("{date}", &undated, 1).unwrap(),
+            "2026-09-15"
+        );
+
+        assert!(expand("{name").is_err());
+        assert!(expand("{title}").is_err());
+        assert!(expand("{counter:wide}").is_err());
+        assert!(expand("{date:%Q}").is_err());
+    }
+
+    #[test]
+    fn plans_chains_and_finds_clashes() {
+        let dir = std::env::temp_dir().join(format!("rename-plan-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str| {
+            let path = dir.join(name);
+            fs::write(&path, b"").unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let (x1, x2, x3) = (file("x1.jpg"), file("x2.jpg"), file("x3.jpg"));
+
+        let library = LibraryState(Mutex::new(None));
+        let limits = DecodeState(Mutex::new(DecodeLimits::default()));
+        let numbered = |start| RenameOptions {
+            template: "x{counter}".to_string(),
+            start,
+            ..Default::default()
+        };
+
+        // x1 → x2 → x3 → x4, each taking the name the next one leaves
+        let paths = [x1.clone(), x2.clone(), x3.clone()];
+        let planned = plan(&paths, &numbered(2), &library, &limits).unwrap();
+        let chained: Vec<_> = planned.iter().map(|p| p.chained).collect();
+        assert_eq!(chained, [true, true, false]);
+        assert!(planned.iter().all(|p| p.error.is_none()));
+        assert!(planned[2].to.ends_with("x4.jpg"));
+
+        // x1 → x3 while x3 stays
+        let planned = plan(std::slice::from_ref(&x1), &numbered(3), &library, &limits).unwrap();
+        assert!(planned[0]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("already exists"));
+
+        // two files with the same new name
+        let same = RenameOptions {
+            template: "same".to_string(),
+            ..Default::default()
+        };
+        let planned = plan(&[x1.clone(), x2], &same, &library, &limits).unwrap();
+        assert!(planned[0].error.is_none());
+        assert!(planned[1]
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("Same new name"));
+
+        // x1 → x9 would take the name of a sidecar left behind
+        file("x1.xmp");
+        file("x9.xmp");
+        let planned = plan(&[x1], &numbered(9), &library, &limits).unwrap();
+        assert!(planned[0]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("x9.xmp already exists"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
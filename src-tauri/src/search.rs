@@ -0,0 +1,569 @@
+// ---------------- SEARCH ----------------
+//
+// Query language for the folder list. A query is a list of terms and a
+// file must match all of them:
+//
+//   width>3000  height<=1080  mp>=24  size>2MB  iso>=1600  rating>=4
+//   camera:"X-T4"  lens:35mm  format:png  date:2026-09  date>=2026
+//   has:gps  label:red  is:pick  tag:beach  name:IMG_*.jpg  /^DSC\d+/
+//
+// Text fields match a substring, ignoring case. `date` is the EXIF date
+// taken, or the modification date (UTC) without one, and is compared on
+// as many characters as the query gives, so `date:2026-09` is September
+// 2026. A bare word matches the file name, as a glob when it has `*` or
+// `?`, and `/.../` is a regex on the name. A leading `-` negates a term.
+//
+// `sort:date` orders the matches by name, date, size, width, height, mp,
+// rating or camera, `sort:-date` the other way round.
+//
+// Files are matched with SQL on the library index, so only new or changed
+// files are read. The terms on the file name are matched here after.
+
+use crate::decode::DecodeState;
+use crate::library::{self, LibraryState, Selection};
+use crate::ratings::{Flag, MarksFilter, MarksSort};
+use rayon::prelude::*;
+use regex::Regex;
+use rusqlite::types::Value;
+use std::path::Path;
+use tauri::State;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn sql(self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Number {
+    Width,
+    Height,
+    Megapixels,
+    Size,
+    Iso,
+    Rating,
+}
+
+#[derive(Clone, Copy)]
+enum Text {
+    Camera,
+    Lens,
+    Label,
+    Tag,
+}
+
+enum NameMatch {
+    Contains(String),
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+enum Term {
+    Name(NameMatch),
+    Number(Number, Op, f64),
+    Text(Text, String),
+    Format(String),
+    Date(Op, String),
+    Has(String),
+    Is(Flag),
+}
+
+struct Clause {
+    negate: bool,
+    term: Term,
+}
+
+/// Split on whitespace, keeping quoted parts together.
+fn tokenize(query: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unclosed quote in the search".to_string());
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+/// Bytes, with an optional K, M or G (KB, MB, GB) suffix.
+fn parse_size(value: &str) -> Option<f64> {
+    let upper = value.to_uppercase();
+    let number = upper.trim_end_matches('B');
+    let (digits, factor) = match number.chars().last()? {
+        'K' => (&number[..number.len() - 1], 1024.0),
+        'M' => (&number[..number.len() - 1], 1024.0 * 1024.0),
+        'G' => (&number[..number.len() - 1], 1024.0 * 1024.0 * 1024.0),
+        _ => (number, 1.0),
+    };
+    digits.parse::<f64>().ok().map(|n| n * factor)
+}
+
+fn name_match(value: &str) -> Result<NameMatch, String> {
+    if value.len() > 1 && value.starts_with('/') && value.ends_with('/') {
+        let pattern = &value[1..value.len() - 1];
+        return Regex::new(&format!("(?i){}", pattern))
+            .map(NameMatch::Regex)
+            .map_err(|e| format!("Invalid regex /{}/: {}", pattern, e));
+    }
+    if value.contains(['*', '?', '[']) {
+        return glob::Pattern::new(value)
+            .map(NameMatch::Glob)
+            .map_err(|e| format!("Invalid pattern {}: {}", value, e));
+    }
+    Ok(NameMatch::Contains(value.to_lowercase()))
+}
+
+/// jpeg and jfif are searched as jpg, tiff as tif.
+fn normalize_format(ext: &str) -> String {
+    match ext.to_lowercase().as_str() {
+        "jpeg" | "jfif" => "jpg".to_string(),
+        "tiff" => "tif".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// YYYY, YYYY-MM or YYYY-MM-DD.
+fn is_date(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    parts.len() <= 3
+        && parts
+            .iter()
+            .zip([4, 2, 2])
+            .all(|(part, len)| part.len() == len && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn parse_term(token: &str) -> Result<Term, String> {
+    let field_len = token
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(token.len());
+    let (field, rest) = token.split_at(field_len);
+
+    const OPS: [(&str, Op); 7] = [
+        (">=", Op::Ge),
+        ("<=", Op::Le),
+        ("!=", Op::Ne),
+        (">", Op::Gt),
+        ("<", Op::Lt),
+        (":", Op::Eq),
+        ("=", Op::Eq),
+    ];
+    let operator = OPS
+        .iter()
+        .find_map(|(text, op)| rest.strip_prefix(text).map(|v| (*op, v)))
+        .filter(|_| !field.is_empty());
+
+    // no "field op" in front, the whole token is matched against the name
+    let Some((op, value)) = operator else {
+        return name_match(token).map(Term::Name);
+    };
+
+    let field = field.to_lowercase();
+    let text_only = |term: Term| {
+        if matches!(op, Op::Eq) {
+            Ok(term)
+        } else {
+            Err(format!("{} can only be matched with {}:", field, field))
+        }
+    };
+    let number = |kind: Number| {
+        let parsed = match kind {
+            Number::Size => parse_size(value),
+            _ => value.parse::<f64>().ok(),
+        };
+        parsed
+            .map(|n| Term::Number(kind, op, n))
+            .ok_or_else(|| format!("Not a number: {}", token))
+    };
+
+    match field.as_str() {
+        "width" | "w" => number(Number::Width),
+        "height" | "h" => number(Number::Height),
+        "mp" | "megapixels" => number(Number::Megapixels),
+        "size" => number(Number::Size),
+        "iso" => number(Number::Iso),
+        "rating" | "stars" => number(Number::Rating),
+        "camera" => text_only(Term::Text(Text::Camera, value.to_lowercase())),
+        "lens" => text_only(Term::Text(Text::Lens, value.to_lowercase())),
+        "label" => text_only(Term::Text(Text::Label, value.to_lowercase())),
+        "tag" => text_only(Term::Text(Text::Tag, value.to_lowercase())),
+        "format" | "ext" | "type" => text_only(Term::Format(normalize_format(value))),
+        "name" => text_only(Term::Name(name_match(value)?)),
+        "date" => {
+            if is_date(value) {
+                Ok(Term::Date(op, value.to_string()))
+            } else {
+                Err(format!(
+                    "Dates are written YYYY, YYYY-MM or YYYY-MM-DD: {}",
+                    token
+                ))
+            }
+        }
+        "has" => match value.to_lowercase().as_str() {
+            v @ ("gps" | "exif" | "xmp" | "iptc" | "rating" | "label" | "tags") => {
+                text_only(Term::Has(v.to_string()))
+            }
+            v => Err(format!("Unknown has:{}", v)),
+        },
+        "is" => match value.to_lowercase().as_str() {
+            "pick" | "picked" => text_only(Term::Is(Flag::Pick)),
+            "reject" | "rejected" => text_only(Term::Is(Flag::Reject)),
+            "unflagged" => text_only(Term::Is(Flag::None)),
+            v => Err(format!("Unknown is:{}", v)),
+        },
+        _ => Err(format!("Unknown search field: {}", field)),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum SortKey {
+    Name,
+    Date,
+    Size,
+    Width,
+    Height,
+    Megapixels,
+    Rating,
+    Camera,
+}
+
+fn parse_sort(value: &str) -> Result<(SortKey, bool), String> {
+    let (descending, key) = match value.strip_prefix('-') {
+        Some(key) => (true, key),
+        None => (false, value),
+    };
+    let key = match key.to_lowercase().as_str() {
+        "name" => SortKey::Name,
+        "date" => SortKey::Date,
+        "size" => SortKey::Size,
+        "width" | "w" => SortKey::Width,
+        "height" | "h" => SortKey::Height,
+        "mp" | "megapixels" => SortKey::Megapixels,
+        "rating" | "stars" => SortKey::Rating,
+        "camera" => SortKey::Camera,
+        other => return Err(format!("Unknown sort:{}", other)),
+    };
+    Ok((key, descending))
+}
+
+struct Query {
+    clauses: Vec<Clause>,
+    /// Key and whether it is descending.
+    sort: Option<(SortKey, bool)>,
+}
+
+fn parse_query(query: &str) -> Result<Query, String> {
+    let mut parsed = Query {
+        clauses: Vec::new(),
+        sort: None,
+    };
+
+    for token in tokenize(query)? {
+        if token
+            .get(..5)
+            .is_some_and(|p| p.eq_ignore_ascii_case("sort:"))
+        {
+            parsed.sort = Some(parse_sort(&token[5..])?);
+            continue;
+        }
+
+        let (negate, token) = match token.strip_prefix('-') {
+            Some(rest) if !rest.is_empty() => (true, rest),
+            _ => (false, token.as_str()),
+        };
+        parsed.clauses.push(Clause {
+            negate,
+            term: parse_term(token)?,
+        });
+    }
+    Ok(parsed)
+}
+
+// ----- Matching ------
+
+fn file_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path)
+}
+
+/// SQL for `term` on the index rows, `None` for the terms on the file name.
+fn condition(term: &Term) -> Option<(String, Vec<Value>)> {
+    let text = |value: &String| vec![Value::from(value.clone())];
+    let (sql, params) = match term {
+        Term::Name(_) | Term::Format(_) => return None,
+        Term::Number(kind, op, value) => {
+            let column = match kind {
+                Number::Width => "width",
+                Number::Height => "height",
+                Number::Megapixels => "width * height / 1000000.0",
+                Number::Size => "size",
+                Number::Iso => "iso",
+                Number::Rating => "rating",
+            };
+            (
+                format!("{} {} ?", column, op.sql()),
+                vec![Value::from(*value)],
+            )
+        }
+        Term::Text(Text::Camera, value) => ("instr(lower(camera), ?) > 0".to_string(), text(value)),
+        Term::Text(Text::Lens, value) => ("instr(lower(lens), ?) > 0".to_string(), text(value)),
+        Term::Text(Text::Label, value) => ("instr(lower(label), ?) > 0".to_string(), text(value)),
+        Term::Text(Text::Tag, value) => (
+            "EXISTS (SELECT 1 FROM json_each(images.tags) WHERE instr(lower(value), ?) > 0)"
+                .to_string(),
+            text(value),
+        ),
+        // on as many characters as the query has
+        Term::Date(op, value) => (
+            format!("substr(date, 1, ?) {} ?", op.sql()),
+            vec![Value::from(value.len() as i64), Value::from(value.clone())],
+        ),
+        Term::Has(what) => {
+            let sql = match what.as_str() {
+                "gps" => "has_gps",
+                "exif" => "has_exif",
+                "xmp" => "has_xmp",
+                "iptc" => "has_iptc",
+                "rating" => "rating > 0",
+                "label" => "label IS NOT NULL",
+                _ => "tags != '[]'",
+            };
+            (sql.to_string(), Vec::new())
+        }
+        Term::Is(flag) => (
+            "flag = ?".to_string(),
+            vec![Value::from(library::flag_name(*flag).to_string())],
+        ),
+    };
+    Some((sql, params))
+}
+
+/// Whether the name of `path` matches a name or format term.
+fn matches_name(path: &str, term: &Term) -> bool {
+    let name = file_name(path);
+    match term {
+        Term::Name(NameMatch::Contains(text)) => name.to_lowercase().contains(text),
+        Term::Name(NameMatch::Glob(pattern)) => pattern.matches_with(
+            name,
+            glob::MatchOptions {
+                case_sensitive: false,
+                ..Default::default()
+            },
+        ),
+        Term::Name(NameMatch::Regex(regex)) => regex.is_match(name),
+        Term::Format(format) => Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| normalize_format(e) == *format),
+        _ => true,
+    }
+}
+
+/// ORDER BY for `sort:`, missing values last either way.
+fn order(key: SortKey, descending: bool) -> String {
+    let (value, collation) = match key {
+        SortKey::Name => ("path", " COLLATE file_name"),
+        SortKey::Date => ("date", ""),
+        SortKey::Size => ("size", ""),
+        SortKey::Width => ("width", ""),
+        SortKey::Height => ("height", ""),
+        SortKey::Megapixels => ("width * height", ""),
+        SortKey::Rating => ("rating", ""),
+        SortKey::Camera => ("camera", " COLLATE NOCASE"),
+    };
+    let direction = if descending { " DESC" } else { "" };
+    format!("{} IS NULL, {}{}{}", value, value, collation, direction)
+}
+
+/// Like `get_folder_images`, narrowed down by `query`. With `recursive`
+/// the subfolders of the current folder are searched too.
+#[tauri::command]
+pub async fn search_images(
+    current_path: String,
+    query: String,
+    recursive: bool,
+    filter: Option<MarksFilter>,
+    sort: Option<MarksSort>,
+    library: State<'_, LibraryState>,
+    limits: State<'_, DecodeState>,
+) -> Result<(Vec<String>, usize), String> {
+    let query = parse_query(&query)?;
+    let limits = *limits.0.lock().unwrap();
+    let dir = Path::new(&current_path)
+        .parent()
+        .ok_or("The image has no folder")?;
+
+    let images = if recursive {
+        let mut images = Vec::new();
+        crate::duplicates::collect_images(dir, true, &mut images);
+        images.sort_by(|a, b| natord::compare(a, b));
+        images
+    } else {
+        crate::folder_image_list(dir)
+    };
+
+    // the marks order first, as in an unsearched folder
+    let mut selection = Selection::default();
+    if let Some(filter) = &filter {
+        filter.select(&mut selection);
+    }
+    if let Some(sort) = sort {
+        sort.select(&mut selection);
+    }
+    let mut names = Vec::new();
+    for clause in &query.clauses {
+        match condition(&clause.term) {
+            Some((sql, params)) if clause.negate => {
+                selection.filter(format!("NOT coalesce({}, 0)", sql), params)
+            }
+            Some((sql, params)) => selection.filter(sql, params),
+            None => names.push(clause),
+        }
+    }
+    if let Some((key, descending)) = query.sort {
+        selection.order_by(order(key, descending));
+    }
+
+    let images: Vec<String> = library::select(&library, images, true, &selection, &limits)?
+        .into_par_iter()
+        .filter(|path| {
+            names
+                .iter()
+                .all(|c| matches_name(path, &c.term) != c.negate)
+        })
+        .collect();
+
+    let index = images.iter().position(|p| p == &current_path).unwrap_or(0);
+    Ok((images, index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_terms_and_sorting() {
+        assert_eq!(
+            tokenize(r#"camera:"X T4"  -tag:beach"#).unwrap(),
+            ["camera:X T4", "-tag:beach"]
+        );
+        assert!(tokenize(r#"camera:"X T4"#).is_err());
+        assert_eq!(parse_size("2MB"), Some(2.0 * 1024.0 * 1024.0));
+        assert_eq!(parse_size("10k"), Some(10240.0));
+        assert_eq!(parse_size("lots"), None);
+
+        let query = parse_query("width>=3000 -is:pick IMG_*.jpg sort:-date").unwrap();
+        assert!(matches!(query.sort, Some((SortKey::Date, true))));
+        assert!(matches!(
+            query.clauses[..],
+            [
+                Clause {
+                    negate: false,
+                    term: Term::Number(Number::Width, Op::Ge, 3000.0)
+                },
+                Clause {
+                    negate: true,
+                    term: Term::Is(Flag::Pick)
+                },
+                Clause {
+                    negate: false,
+                    term: Term::Name(NameMatch::Glob(_))
+                },
+            ]
+        ));
+
+        for date in ["date:2026", "date>=2026-09", "date<2026-09-14"] {
+            assert!(parse_term(date).is_ok(), "{}", date);
+        }
+        for bad in [
+            "date:",
+            "date>=",
+            "date:-",
+            "date:26",
+            "date:2026-9",
+            "date:2026-09-14-1",
+            "camera>x",
+            "width:wide",
+            "has:faces",
+            "is:maybe",
+            "colour:red",
+            "/[/",
+        ] {
+            assert!(parse_term(bad).is_err(), "{}", bad);
+        }
+        assert!(parse_query("sort:colour").is_err());
+    }
+
+    #[test]
+    fn translates_terms_to_sql() {
+        let sql = |token: &str| condition(&parse_term(token).unwrap());
+
+        assert_eq!(
+            sql("date>=2026-09"),
+            Some((
+                "substr(date, 1, ?) >= ?".to_string(),
+                vec![Value::from(7), Value::from("2026-09".to_string())]
+            ))
+        );
+        assert_eq!(
+            sql("mp>24"),
+            Some((
+                "width * height / 1000000.0 > ?".to_string(),
+                vec![Value::from(24.0)]
+            ))
+        );
+        assert_eq!(
+            sql("camera:Fuji"),
+            Some((
+                "instr(lower(camera), ?) > 0".to_string(),
+                vec![Value::from("fuji".to_string())]
+            ))
+        );
+        assert_eq!(sql("has:gps"), Some(("has_gps".to_string(), Vec::new())));
+        assert_eq!(sql("format:jpeg"), None);
+        assert_eq!(sql("holiday"), None);
+
+        let term = |token: &str| parse_term(token).unwrap();
+        assert!(matches_name("/p/IMG_1.JPEG", &term("format:jpg")));
+        assert!(matches_name("/p/img_12.jpg", &term("IMG_?2.*")));
+        assert!(matches_name("/p/DSC0042.nef", &term(r"/^dsc\d+/")));
+        assert!(!matches_name("/p/DSC0042.nef", &term("holiday")));
+
+        assert_eq!(
+            order(SortKey::Camera, true),
+            "camera IS NULL, camera COLLATE NOCASE DESC"
+        );
+    }
+}
@@ -0,0 +1,520 @@
+// ---------------- TILED PYRAMID ----------------
+//
+// Very large images (gigapixel PNG / TIFF) are decoded once, downscaled into
+// a pyramid of half-size levels and then served to the viewer as small PNG
+// tiles. Level 0 is the full resolution, every following level halves both
+// dimensions until the whole image fits into a single tile.
+//
+// Opening returns as soon as there is a preview: the EXIF thumbnail of a
+// JPEG, else a thumbnail of the decoded image. The pyramid is finished on
+// a thread of its own, `wait_tiled_image` tells when the tiles are there.
+// Opening an image that is still being built waits for that build.
+
+use crate::color::{self, ColorSettings, ColorState};
+use crate::decode::{self, DecodeLimits, DecodeState};
+use crate::metadata;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageFormat, ImageReader, RgbaImage};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use tauri::{AppHandle, Manager, State};
+
+pub const TILE_SIZE: u32 = 256;
+
+/// Longest side of the preview sent together with the pyramid info.
+const PREVIEW_MAX_SIDE: u32 = 1024;
+
+/// 2 GiB of decoded RGBA pixels by default.
+const DEFAULT_MEMORY_LIMIT: u64 = 2 * 1024 * 1024 * 1024;
+
+struct Pyramid {
+    path: String,
+    width: u32,
+    height: u32,
+    levels: Vec<RgbaImage>,
+}
+
+impl Pyramid {
+    fn level(&self, level: usize) -> Option<&RgbaImage> {
+        self.levels.get(level)
+    }
+
+    fn memory(&self) -> u64 {
+        self.levels.iter().map(|l| l.as_raw().len() as u64).sum()
+    }
+}
+
+/// Where a pyramid being built is at.
+#[derive(Default)]
+struct Progress {
+    /// Set once there is a preview, or the build failed before one.
+    info: Option<Result<TiledImageInfo, String>>,
+    /// Set once the pyramid is in the cache, or the build failed.
+    done: Option<Result<(), String>>,
+}
+
+/// A pyramid being built, for the requests that come in meanwhile.
+#[derive(Default)]
+struct Build {
+    progress: Mutex<Progress>,
+    changed: Condvar,
+}
+
+impl Build {
+    fn update(&self, change: impl FnOnce(&mut Progress)) {
+        change(&mut self.progress.lock().unwrap());
+        self.changed.notify_all();
+    }
+
+    /// Block until `get` finds what it's after.
+    fn wait<T>(&self, get: impl Fn(&Progress) -> Option<T>) -> T {
+        let mut progress = self.progress.lock().unwrap();
+        loop {
+            if let Some(value) = get(&progress) {
+                return value;
+            }
+            progress = self.changed.wait(progress).unwrap();
+        }
+    }
+
+    fn failed(&self) -> bool {
+        matches!(self.progress.lock().unwrap().done, Some(Err(_)))
+    }
+}
+
+pub struct TileCache {
+    memory_limit: u64,
+    /// Least recently used first.
+    pyramids: Vec<Arc<Pyramid>>,
+    /// Pyramids being built, and those that failed to, by path.
+    builds: HashMap<String, Arc<Build>>,
+}
+
+impl Default for TileCache {
+    fn default() -> Self {
+        TileCache {
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            pyramids: Vec::new(),
+            builds: HashMap::new(),
+        }
+    }
+}
+
+impl TileCache {
+    fn get(&mut self, path: &str) -> Option<Arc<Pyramid>> {
+        let pos = self.pyramids.iter().position(|p| p.path == path)?;
+        let pyramid = self.pyramids.remove(pos);
+        self.pyramids.push(pyramid.clone());
+        Some(pyramid)
+    }
+
+    fn insert(&mut self, pyramid: Pyramid) -> Arc<Pyramid> {
+        self.pyramids.retain(|p| p.path != pyramid.path);
+        let pyramid = Arc::new(pyramid);
+        self.pyramids.push(pyramid.clone());
+        self.evict();
+        pyramid
+    }
+
+    /// Evict least recently used pyramids until the cache fits the limit.
+    /// The most recent one is never evicted, it was already shrunk to fit.
+    fn evict(&mut self) {
+        while self.pyramids.len() > 1 && self.memory() > self.memory_limit {
+            self.pyramids.remove(0);
+        }
+    }
+
+    fn memory(&self) -> u64 {
+        self.pyramids.iter().map(|p| p.memory()).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.pyramids.clear();
+        self.builds.clear();
+    }
+}
+
+pub struct TileState(pub Mutex<TileCache>);
+
+#[derive(Serialize, Clone)]
+pub struct TiledImageInfo {
+    width: u32,
+    height: u32,
+    tile_size: u32,
+    level_sizes: Vec<(u32, u32)>,
+    /// Finest level the preview is as sharp as.
+    preview_level: usize,
+    preview: Vec<u8>,
+}
+
+/// Box filter halving of both dimensions (rounded up).
+fn halve(src: &RgbaImage) -> RgbaImage {
+    let (sw, sh) = src.dimensions();
+    let (dw, dh) = (sw.div_ceil(2), sh.div_ceil(2));
+
+    RgbaImage::from_fn(dw, dh, |x, y| {
+        let x0 = x * 2;
+        let y0 = y * 2;
+        let x1 = (x0 + 1).min(sw - 1);
+        let y1 = (y0 + 1).min(sh - 1);
+
+        let mut sum = [0u32; 4];
+        for (px, py) in [(x0, y0), (x1, y0), (x0, y1), (x1, y1)] {
+            let p = src.get_pixel(px, py).0;
+            for c in 0..4 {
+                sum[c] += p[c] as u32;
+            }
+        }
+
+        image::Rgba(sum.map(|s| ((s + 2) / 4) as u8))
+    })
+}
+
+/// Size of every level, from the full size down to a single tile.
+fn level_sizes(width: u32, height: u32) -> Vec<(u32, u32)> {
+    let mut sizes = vec![(width, height)];
+    while let Some(&(w, h)) = sizes
+        .last()
+        .filter(|(w, h)| *w > TILE_SIZE || *h > TILE_SIZE)
+    {
+        sizes.push((w.div_ceil(2), h.div_ceil(2)));
+    }
+    sizes
+}
+
+/// Finest level whose longest side stays within `side`, or the coarsest
+/// one if none does.
+fn level_within(level_sizes: &[(u32, u32)], side: u32) -> usize {
+    level_sizes
+        .iter()
+        .position(|(w, h)| (*w).max(*h) <= side)
+        .unwrap_or(level_sizes.len() - 1)
+}
+
+/// The EXIF thumbnail of a JPEG, decoded.
+fn embedded_thumbnail(path: &str, limits: &DecodeLimits) -> Option<DynamicImage> {
+    let exif = metadata::read_raw_quick(path).ok()?.exif?;
+    let jpeg = metadata::exif_thumbnail(&exif)?;
+
+    let mut reader = ImageReader::with_format(Cursor::new(jpeg), ImageFormat::Jpeg);
+    reader.limits(limits.to_image_limits());
+    reader.decode().ok()
+}
+
+fn encode_png(img: &RgbaImage) -> Result<Vec<u8>, String> {
+    let mut buf = Vec::new();
+    img.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(buf)
+}
+
+fn image_info(
+    (width, height): (u32, u32),
+    level_sizes: &[(u32, u32)],
+    preview: &RgbaImage,
+) -> Result<TiledImageInfo, String> {
+    Ok(TiledImageInfo {
+        width,
+        height,
+        tile_size: TILE_SIZE,
+        level_sizes: level_sizes.to_vec(),
+        preview_level: level_within(level_sizes, preview.width().max(preview.height())),
+        preview: encode_png(preview)?,
+    })
+}
+
+fn pyramid_info(pyramid: &Pyramid) -> Result<TiledImageInfo, String> {
+    let level_sizes: Vec<(u32, u32)> = pyramid.levels.iter().map(|l| l.dimensions()).collect();
+    let preview = pyramid
+        .level(level_within(&level_sizes, PREVIEW_MAX_SIDE))
+        .ok_or("Missing preview level")?;
+    image_info((pyramid.width, pyramid.height), &level_sizes, preview)
+}
+
+/// Decode `path` into a pyramid, handing `build` the preview as soon as
+/// there is one.
+fn build_pyramid(
+    path: &str,
+    limit: u64,
+    limits: &DecodeLimits,
+    colors: &ColorSettings,
+    build: &Build,
+) -> Result<Pyramid, String> {
+    let header = decode::read_header(path, limits)?;
+    limits.check_dimensions(header.width, header.height)?;
+
+    // The pyramid is of the upright image, as the webview shows the file
+    let orientation = decode::orientation(path);
+    let turned = matches!(
+        orientation,
+        Some(
+            Orientation::Rotate90
+                | Orientation::Rotate270
+                | Orientation::Rotate90FlipH
+                | Orientation::Rotate270FlipH
+        )
+    );
+    let (width, height) = match turned {
+        true => (header.height, header.width),
+        false => (header.width, header.height),
+    };
+    let upright = |mut img: DynamicImage| {
+        if let Some(orientation) = orientation {
+            img.apply_orientation(orientation);
+        }
+        img
+    };
+
+    // every level is kept, a third more than the full size
+    let sizes = level_sizes(width, height);
+    let memory: u64 = sizes.iter().map(|(w, h)| *w as u64 * *h as u64 * 4).sum();
+    if memory > limit {
+        return Err(format!(
+            "Image is {} × {} ({} MB decoded with its smaller levels), above the {} MB memory limit",
+            width,
+            height,
+            memory / (1024 * 1024),
+            limit / (1024 * 1024)
+        ));
+    }
+
+    let publish = |preview: &RgbaImage| {
+        let info = image_info((width, height), &sizes, preview);
+        build.update(|p| p.info = Some(info));
+    };
+
+    // shown before anything else is decoded
+    let thumbnail = match header.format {
+        Some(ImageFormat::Jpeg) => embedded_thumbnail(path, limits),
+        _ => None,
+    };
+    let shown = match thumbnail {
+        Some(thumbnail) => {
+            let thumbnail = upright(thumbnail);
+            publish(&color::convert(thumbnail, header.icc.as_deref(), colors)?.into_rgba8());
+            true
+        }
+        None => false,
+    };
+
+    // The memory limit above is what bounds a tiled image; the decoder's
+    // own allocation limit is meant for images decoded whole.
+    let decode_limits = DecodeLimits {
+        max_alloc: limit,
+        ..*limits
+    };
+    let (img, icc) = decode::decode_with_profile(path, &decode_limits)?;
+    let base = color::convert(upright(img), icc.as_deref(), colors)?.into_rgba8();
+    if !shown {
+        let (w, h) = sizes[level_within(&sizes, PREVIEW_MAX_SIDE)];
+        publish(&image::imageops::thumbnail(&base, w, h));
+    }
+
+    let mut levels = vec![base];
+    while levels.len() < sizes.len() {
+        let next = halve(levels.last().unwrap());
+        levels.push(next);
+    }
+
+    Ok(Pyramid {
+        path: path.to_string(),
+        width,
+        height,
+        levels,
+    })
+}
+
+/// Put the pyramid of a build in the cache, unless it was closed meanwhile.
+/// A failed build stays, for `wait_tiled_image` to report.
+fn finish(app: &AppHandle, path: &str, build: &Arc<Build>, result: Result<Pyramid, String>) {
+    let state = app.state::<TileState>();
+    let done = {
+        let mut cache = state.0.lock().unwrap();
+        let current = cache
+            .builds
+            .get(path)
+            .is_some_and(|b| Arc::ptr_eq(b, build));
+        match result {
+            Ok(pyramid) if current => {
+                cache.builds.remove(path);
+                cache.insert(pyramid);
+                Ok(())
+            }
+            Ok(_) => Err("The image was closed".to_string()),
+            Err(e) => Err(e),
+        }
+    };
+
+    build.update(|p| {
+        // failed before there was a preview
+        if let Err(e) = &done {
+            p.info.get_or_insert(Err(e.clone()));
+        }
+        p.done = Some(done);
+    });
+}
+
+fn open(app: &AppHandle, path: &str) -> Result<TiledImageInfo, String> {
+    let limits = *app.state::<DecodeState>().0.lock().unwrap();
+    let colors = app.state::<ColorState>().0.lock().unwrap().clone();
+    let state = app.state::<TileState>();
+
+    let build = {
+        let mut cache = state.0.lock().unwrap();
+        if let Some(pyramid) = cache.get(path) {
+            return pyramid_info(&pyramid);
+        }
+        match cache.builds.get(path).filter(|b| !b.failed()) {
+            Some(build) => build.clone(),
+            None => {
+                let build = Arc::new(Build::default());
+                cache.builds.insert(path.to_string(), build.clone());
+
+                let limit = cache.memory_limit;
+                let (app, file, building) = (app.clone(), path.to_string(), build.clone());
+                let started = thread::Builder::new()
+                    .name("tiles".to_string())
+                    .spawn(move || {
+                        let result = build_pyramid(&file, limit, &limits, &colors, &building);
+                        finish(&app, &file, &building, result);
+                    });
+                if let Err(e) = started {
+                    cache.builds.remove(path);
+                    return Err(format!("Failed to start building the tiles: {}", e));
+                }
+                build
+            }
+        }
+    };
+
+    build.wait(|p| p.info.clone())
+}
+
+/// Size and preview of `path`, as soon as there is a preview. The tiles
+/// follow once `wait_tiled_image` returns.
+#[tauri::command]
+pub async fn open_tiled_image(app: AppHandle, path: String) -> Result<TiledImageInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || open(&app, &path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Wait until the tiles of `path` can be had, or the build failed.
+#[tauri::command]
+pub async fn wait_tiled_image(app: AppHandle, path: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<TileState>();
+        let build = {
+            let mut cache = state.0.lock().unwrap();
+            if cache.get(&path).is_some() {
+                return Ok(());
+            }
+            cache
+                .builds
+                .get(&path)
+                .cloned()
+                .ok_or("Image is not opened in tiled mode")?
+        };
+        build.wait(|p| p.done.clone())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_tile(
+    path: String,
+    level: usize,
+    x: u32,
+    y: u32,
+    state: State<'_, TileState>,
+) -> Result<Vec<u8>, String> {
+    let pyramid = state
+        .0
+        .lock()
+        .unwrap()
+        .get(&path)
+        .ok_or("Image is not opened in tiled mode")?;
+
+    let img = pyramid
+        .level(level)
+        .ok_or_else(|| format!("Level {} is not available", level))?;
+
+    let (lw, lh) = img.dimensions();
+    let left = x * TILE_SIZE;
+    let top = y * TILE_SIZE;
+
+    if left >= lw || top >= lh {
+        return Err(format!("Tile {}/{} is outside level {}", x, y, level));
+    }
+
+    let w = TILE_SIZE.min(lw - left);
+    let h = TILE_SIZE.min(lh - top);
+    let tile = image::imageops::crop_imm(img, left, top, w, h).to_image();
+
+    encode_png(&tile)
+}
+
+#[tauri::command]
+pub fn close_tiled_image(path: String, state: State<TileState>) {
+    let mut cache = state.0.lock().unwrap();
+    cache.pyramids.retain(|p| p.path != path);
+    cache.builds.remove(&path);
+}
+
+#[tauri::command]
+pub fn set_tile_memory_limit(bytes: u64, state: State<TileState>) {
+    let mut cache = state.0.lock().unwrap();
+    cache.memory_limit = bytes;
+    cache.evict();
+}
+
+#[tauri::command]
+pub fn get_tile_memory_limit(state: State<TileState>) -> u64 {
+    state.0.lock().unwrap().memory_limit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_halve_down_to_one_tile() {
+        let sizes = level_sizes(3000, 1000);
+        assert_eq!(
+            sizes,
+            [(3000, 1000), (1500, 500), (750, 250), (375, 125), (188, 63)]
+        );
+        assert_eq!(level_within(&sizes, PREVIEW_MAX_SIDE), 2);
+        assert_eq!(level_within(&sizes, 160), 4);
+        assert_eq!(level_sizes(200, 100), [(200, 100)]);
+    }
+
+    #[test]
+    fn tiled_images_may_exceed_the_decoder_allocation_limit() {
+        let dir = std::env::temp_dir().join(format!("tiles-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("large.png");
+        RgbaImage::new(600, 300).save(&file).unwrap();
+        let path = file.to_str().unwrap();
+
+        let limits = DecodeLimits {
+            max_alloc: 100_000,
+            ..Default::default()
+        };
+        assert!(decode::decode_with_profile(path, &limits).is_err());
+
+        let build = Build::default();
+        let colors = ColorSettings::default();
+        let pyramid = build_pyramid(path, 10_000_000, &limits, &colors, &build).unwrap();
+        assert_eq!(pyramid.levels.len(), 3);
+        assert!(build.progress.lock().unwrap().info.is_some());
+
+        // but not the memory limit
+        assert!(build_pyramid(path, 500_000, &limits, &colors, &Build::default()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
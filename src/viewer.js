@@ -1,8 +1,9 @@
 const { getCurrentWebviewWindow, WebviewWindow } = window.__TAURI__.webviewWindow;
 const { invoke, convertFileSrc } = window.__TAURI__.core;
 const { shell } = window.__TAURI__;
-const { open } = window.__TAURI__.dialog;
+const { open, save } = window.__TAURI__.dialog;
 const { readDir } = window.__TAURI__.fs;
+const { listen } = window.__TAURI__.event;
 
 const webview = getCurrentWebviewWindow();
 
@@ -87,6 +88,7 @@ const ZOOM_STEP = 1.25; // 25% per click (adjust if you want)
 const zoomInBtn = document.getElementById("zoomInBtn");
 const zoomOutBtn = document.getElementById("zoomOutBtn");
 const openWithContextMenu = document.getElementById("openWithContextMenu");
+const openWithOtherItem = openWithContextMenu.querySelector('[data-action="openWithDialog"]');
 
 const fileMenuContextMenu = document.getElementById('fileMenuContextMenu');
 
@@ -170,7 +172,8 @@ async function showImage() {
   imgSize.textContent = '';
   zoomValue.textContent = '';
 
-  resetGifUI(); 
+  resetGifUI();
+  resetTiledUI();
   showLoading();
 
   if (!images.length) {
@@ -189,30 +192,41 @@ async function showImage() {
   gifBar.classList.add("hidden");
   img.classList.remove("hidden");
 
-  if (ext === "ico") {
-    showLoading();
-    await nextImgFrame(); 
-    icoFrames = await invoke("load_ico_frames", { path });
-    icoIndex = 0;
-    icoBar.classList.remove("hidden");
-    showIcoFrame();
-  }
-  else if (ext === "tif" || ext === "tiff") {
-    showLoading();
-    const data = await invoke("load_image", { path });
-    const blob = new Blob([new Uint8Array(data)], { type: "image/png" });
-    img.src = URL.createObjectURL(blob);
-  } 
-  else if (ext === "gif") {
-    showLoading();
-    img.classList.add("hidden");
-    gifCanvas.classList.remove("hidden");
-    icoBar.classList.add("hidden");
-    gifBar.classList.remove("hidden");
-    await loadGIF(path);
+  try {
+    if (await isHugeImage(path, ext)) {
+      showLoading();
+      img.classList.add("hidden");
+      await loadTiled(path);
+    }
+    else if (ext === "ico") {
+      showLoading();
+      await nextImgFrame(); 
+      icoFrames = await invoke("load_ico_frames", { path });
+      icoIndex = 0;
+      icoBar.classList.remove("hidden");
+      showIcoFrame();
+    }
+    else if (ext === "tif" || ext === "tiff") {
+      await showDecodedImage(path);
+    } 
+    else if (ext === "gif") {
+      showLoading();
+      img.classList.add("hidden");
+      gifCanvas.classList.remove("hidden");
+      icoBar.classList.add("hidden");
+      gifBar.classList.remove("hidden");
+      await loadGIF(path);
+    }
+    else if (await needsColorConversion(path)) {
+      await showDecodedImage(path);
+    }
+    else {
+      img.src = fileSrc(path);
+    }
   }
-  else {
-    img.src = convertFileSrc(path);
+  catch (err) {
+    // decoder limits, timeouts and broken files end up here
+    showDecodeError(err);
   }
 
   // Apply rotation (which is reset to 0 here)
@@ -220,6 +234,21 @@ async function showImage() {
 
   await webview.setTitle(`${getFileName(path)} (${index + 1}/${images.length}) - Better Image Viewer`);
   imgAmount.textContent = `${index + 1}/${images.length}`;
+
+  refreshInspectors(path);
+  refreshMarks(path);
+}
+
+// Decoded (and color converted) by Rust, sent back as PNG
+async function showDecodedImage(path) {
+  showLoading();
+  img.src = await decodedImageUrl(path);
+}
+
+async function decodedImageUrl(path) {
+  const data = await invoke("load_image", { path });
+  const blob = new Blob([new Uint8Array(data)], { type: "image/png" });
+  return URL.createObjectURL(blob);
 }
 
 function nextImgFrame() {
@@ -231,9 +260,7 @@ function nextImgFrame() {
   const path = await invoke("get_opened_image");
   if (!path) return;
 
-  const [list, startIndex] = await invoke("get_folder_images", {
-    currentPath: path
-  });
+  const [list, startIndex] = await folderImages(path);
 
   images = list;
   index = startIndex;
@@ -271,14 +298,23 @@ document.addEventListener("keydown", (e) => {
   }
 });
 
-function reloadCurrentImage() {
-  const img = document.getElementById("imgViewer");
-  if (!img.src) return;
+// Files changed on disk get the time in their URL, or the webview keeps
+// showing the old version
+const fileChangedAt = new Map(); // key: filePath, value: Date.now()
+
+function fileSrc(path) {
+  const changed = fileChangedAt.get(path);
+  return convertFileSrc(path) + (changed ? "?reload=" + changed : "");
+}
 
-  const originalSrc = img.src.split("?")[0]; // remove previous cache buster if any
-  img.src = originalSrc + "?reload=" + Date.now();
+// Show the current image again from its file, e.g. after an editor saved it
+async function reloadCurrentImage() {
+  if (!images.length) return;
 
-  console.log("img reloaded")
+  const path = images[index];
+  fileChangedAt.set(path, Date.now());
+  await invoke("close_tiled_image", { path }); // tiles of the old version
+  await showImage();
 }
 
 
@@ -319,7 +355,16 @@ async function showOpenWithMenu(x, y) {
   }
   openWithImagePath = path;
   const apps = await invoke("get_open_with_apps", { path });
+  await renderOpenWithApps(apps);
+  openWithOtherItem.style.display = "";
+
+  openWithContextMenu.style.left = `${x}px`;
+  openWithContextMenu.style.top = `${y}px`;
+  openWithContextMenu.style.display = "block";
+}
 
+// Desktop entries (Linux) come with a plain PNG icon, Windows ones are BGRA
+async function renderOpenWithApps(apps) {
   openWithContextMenu.querySelectorAll(".ctx-item.app").forEach(e => e.remove());
 
   for (const [exe, label, iconBytes] of apps) {
@@ -332,7 +377,9 @@ async function showOpenWithMenu(x, y) {
     iconContainer.className = "app-icon";
 
     if (iconBytes && iconBytes.length > 0) {
-      const iconUrl = await fixIconColors(iconBytes);
+      const iconUrl = exe.endsWith(".desktop")
+        ? URL.createObjectURL(new Blob([new Uint8Array(iconBytes)], { type: "image/png" }))
+        : await fixIconColors(iconBytes);
       const imgIcon = document.createElement("img");
       imgIcon.src = iconUrl;
       imgIcon.className = "app-icon-img";
@@ -352,12 +399,16 @@ async function showOpenWithMenu(x, y) {
     item.appendChild(iconContainer);
     item.appendChild(textSpan);
 
+    const shortcut = externalAppOf(exe)?.shortcut;
+    if (shortcut) {
+      const hint = document.createElement("span");
+      hint.className = "app-shortcut";
+      hint.textContent = shortcut;
+      item.appendChild(hint);
+    }
+
     openWithContextMenu.insertBefore(item, openWithSeparator);
   }
-
-  openWithContextMenu.style.left = `${x}px`;
-  openWithContextMenu.style.top = `${y}px`;
-  openWithContextMenu.style.display = "block";
 }
 
 async function fixIconColors(iconBytes) {
@@ -409,10 +460,21 @@ openWithContextMenu.addEventListener("click", async (e) => {
     }
 
     if (action === "openWithDialog") {
+      // no system dialog on Linux: list every other app in the menu
+      const others = await invoke("get_other_open_with_apps", {
+        path: openWithImagePath,
+      });
+      if (others) {
+        await renderOpenWithApps(others);
+        openWithOtherItem.style.display = "none";
+        return;
+      }
       await invoke("open_with_dialog", {
         path: openWithImagePath,
       });
     }
+
+    if (action === "editExternalApps") openExternalAppsDialog();
   } 
   catch (err) {
     console.error("Error invoking command:", err);
@@ -497,11 +559,10 @@ async function openFileSelect() {
 
   // --- KEY POINT: Update backend stored path ---
   await invoke("set_opened_image", { path });
+  clearSearch();
 
   // --- Regenerate folder list based on new path ---
-  const [list, startIndex] = await invoke("get_folder_images", {
-    currentPath: path
-  });
+  const [list, startIndex] = await folderImages(path);
 
   images = list;
   index = startIndex;
@@ -559,10 +620,9 @@ async function openFolderSelect() {
   }
   
   // update images list
+  clearSearch();
   try {
-    const [list, startIndex] = await invoke("get_folder_images", { 
-      currentPath: firstImage 
-    });
+    const [list, startIndex] = await folderImages(firstImage);
     
     images = list;
     index = startIndex;
@@ -576,1169 +636,4813 @@ async function openFolderSelect() {
 }
 
 function showLoading() {
+  loadingText.textContent = "Loading…";
   loadingText.classList.add("visible");
   img.classList.add("loading");
 }
 
-function hideLoading() {
-  loadingText.classList.remove("visible");
-  img.classList.remove("loading");
+function showDecodeError(err) {
+  console.error("Decode failed:", err);
+  img.classList.add("hidden");
+  loadingText.textContent = `Can't open this image: ${err}`;
+  loadingText.classList.add("visible");
 }
 
-img.addEventListener("load", hideLoading);
-img.addEventListener("error", hideLoading);
-
-// -------------------- NEW GIF SYSTEM ----------------------
+// Optional decoder limits, same shape as DecodeLimits in decode.rs
+const savedDecodeLimits = localStorage.getItem("decodeLimits");
+if (savedDecodeLimits) {
+  invoke("set_decode_limits", { limits: JSON.parse(savedDecodeLimits) })
+    .catch(console.error);
+}
 
-gifPlayPause.onclick = () => playing ? pause() : play();
+// -------------------- COLOR MANAGEMENT ----------------------
 
-gifPrev.onclick = () => {
-  pause();
-  const target = (currentFrame - 1 + frameCount) % frameCount;
-  resetAndDecodeTo(target);
-};
+const colorDlg = document.getElementById("colorDlg");
+const colorManaged = document.getElementById("colorManaged");
+const colorDisplay = document.getElementById("colorDisplay");
+const colorFileRow = document.getElementById("colorFileRow");
+const colorFilePath = document.getElementById("colorFilePath");
+const colorStatus = document.getElementById("colorStatus");
+const colorBadge = document.getElementById("colorBadge");
+const colorToggleItem = document.getElementById("colorToggleItem");
 
-gifNext.onclick = () => {
-  pause();
-  const target = (currentFrame + 1) % frameCount;
-  resetAndDecodeTo(target);
-};
+// Same shape as ColorSettings in color.rs
+const DEFAULT_COLOR_SETTINGS = { managed: true, display: { kind: "srgb" } };
+let colorSettings = DEFAULT_COLOR_SETTINGS;
+const colorSettingsLoaded = restoreColorSettings();
 
-function stopGifPlayback() {
-  playing = false;
+async function restoreColorSettings() {
+  const saved = localStorage.getItem("colorSettings");
+  if (!saved) return;
 
-  if (timer) {
-    clearTimeout(timer);
-    timer = null;
+  try {
+    const settings = JSON.parse(saved);
+    await invoke("set_color_settings", { settings });
+    colorSettings = settings;
+  } catch (err) {
+    // e.g. the chosen ICC file was deleted
+    console.error("Color settings:", err);
   }
+  updateColorUI();
 }
 
-function step() {
-  if (!gifReader || frameCount === 0) return;
-  decodeFrame(currentFrame, true); // dispose old frame
-  currentFrame = (currentFrame + 1) % frameCount;
-  decodeFrame(currentFrame, false); // show new frame
-  draw();
-  if (playing) {
-    timer = setTimeout(step, frameDelays[currentFrame]);
-  }
+async function needsColorConversion(path) {
+  await colorSettingsLoaded;
+  return invoke("needs_color_conversion", { path }).catch(() => false);
 }
 
-function play() {
-  if (playing) return;
-  //updateEQSliderFill(slider);
-  playing = true;
-  paused = false;
-  gifPlayPause.textContent = "⏸";
-  timer = setTimeout(step, frameDelays[currentFrame]);
+async function applyColorSettings(settings) {
+  try {
+    await invoke("set_color_settings", { settings });
+  } catch (err) {
+    colorStatus.textContent = String(err);
+    updateColorUI();
+    return;
+  }
+
+  colorSettings = settings;
+  colorStatus.textContent = "";
+  localStorage.setItem("colorSettings", JSON.stringify(settings));
+  updateColorUI();
+
+  if (images.length) await showImage();
 }
 
-function pause() {
-  //updateEQSliderFill(slider);
-  playing = false;
-  paused = true;
-  gifPlayPause.textContent = "▶";
-  clearTimeout(timer);
+function toggleColorManagement() {
+  return applyColorSettings({ ...colorSettings, managed: !colorSettings.managed });
 }
 
-slider.oninput = e => {
-    if (paused) {
-      pause();
-    }
-    resetAndDecodeTo(+e.target.value);
-    if (!paused) {
-      play();
-    }
-};
+function updateColorUI() {
+  const { managed, display } = colorSettings;
 
-// ---- LOAD GIF -------------------------------------------------
+  colorBadge.classList.toggle("hidden", managed);
+  colorToggleItem.textContent = managed ? "View Unmanaged Colors" : "View Managed Colors";
 
-async function loadGIF(path) {
-  const token = ++gifLoadToken;
-  const url = convertFileSrc(path);
-  const res = await fetch(url);
-  if (token !== gifLoadToken) return;
-  const buf = await res.arrayBuffer();
-  if (token !== gifLoadToken) return;
+  colorManaged.checked = managed;
+  colorDisplay.value = display.kind;
+  colorFileRow.style.display = display.kind === "file" ? "flex" : "none";
+  colorFilePath.textContent = display.path || "No file chosen";
+  colorFilePath.title = display.path || "";
+}
 
-  gifReader = new GifReader(new Uint8Array(buf));
+function openColorDialog() {
+  colorStatus.textContent = "";
+  updateColorUI();
+  colorDlg.style.display = "flex";
+}
 
-  gifWidth = gifReader.width;
-  gifHeight = gifReader.height;
-  imgLabel.style.display = 'block';
-  imgSize.textContent = `${gifWidth} × ${gifHeight}`;
+function closeColorDialog() {
+  colorDlg.style.display = "none";
+}
 
-  computeBaseScaleForGIF();
+async function browseDisplayProfile() {
+  const selected = await open({
+    multiple: false,
+    filters: [{ name: "ICC Profile", extensions: ["icc", "icm"] }]
+  });
+  if (!selected) return;
 
-  frameCount = gifReader.numFrames();
+  const path = Array.isArray(selected) ? selected[0] : selected;
+  await applyColorSettings({ ...colorSettings, display: { kind: "file", path } });
+}
 
-  currentFrame = 0;
-  paused = true;
+colorManaged.onchange = () => {
+  applyColorSettings({ ...colorSettings, managed: colorManaged.checked });
+};
 
-  keyframes.clear();
+colorDisplay.onchange = () => {
+  const kind = colorDisplay.value;
 
-  //gifCanvas.width = gifWidth;
-  //gifCanvas.height = gifHeight;
-  gifCanvas.width = middleFrame.clientWidth;
-  gifCanvas.height = middleFrame.clientHeight;
+  if (kind === "file") {
+    // Nothing to apply until a file is picked
+    colorFileRow.style.display = "flex";
+    browseDisplayProfile();
+    return;
+  }
+  applyColorSettings({ ...colorSettings, display: { kind } });
+};
 
-  slider.min = 0;
-  slider.max = frameCount - 1;
-  slider.step = 1;
-  slider.value = 0;
+document.getElementById("colorBrowseBtn").onclick = browseDisplayProfile;
 
-  updateEQSliderFill(slider);
+document.getElementById("closeColor").onclick =
+document.getElementById("colorCloseBtn").onclick = closeColorDialog;
 
-  composited = new Uint8Array(gifWidth * gifHeight * 4);
-  composited.fill(0);
+colorDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeColorDialog();
+});
 
-  keyframes.set(0, composited.slice());
+document.addEventListener("keydown", e => {
+  if (e.target.closest("input, textarea, select")) return;
+  if (e.key.toLowerCase() === "c" && !e.ctrlKey && !e.metaKey) {
+    toggleColorManagement();
+  }
+});
 
-  imageData = ctx.createImageData(gifWidth, gifHeight);
+// -------------------- PIXEL INSPECTION ----------------------
+
+// Histogram, loupe and channel isolation. The numbers come from the file
+// itself (inspect.rs), not from the color managed pixels on screen.
+
+const histDlg = document.getElementById("histDlg");
+const histCanvas = document.getElementById("histCanvas");
+const histCtx = histCanvas.getContext("2d");
+const histMode = document.getElementById("histMode");
+const histLog = document.getElementById("histLog");
+const histStatus = document.getElementById("histStatus");
+const histClipping = document.querySelector("#histClipping tbody");
+
+const loupe = document.getElementById("loupe");
+const loupeCanvas = document.getElementById("loupeCanvas");
+const loupeCtx = loupeCanvas.getContext("2d");
+const loupeReadout = document.getElementById("loupeReadout");
+const loupeToggleItem = document.getElementById("loupeToggleItem");
+
+const channelItem = document.getElementById("channelItem");
+const channelBadge = document.getElementById("channelBadge");
+
+const LOUPE_PIXELS = 11;   // odd, so one pixel sits in the middle
+const LOUPE_CELL = 14;     // screen pixels per image pixel
+const HIST_COLORS = {
+  red: "rgb(210, 0, 0)",
+  green: "rgb(0, 210, 0)",
+  blue: "rgb(0, 0, 210)",
+  luminance: "#ddd",
+  alpha: "#aaa"
+};
+const CHANNELS = [
+  { key: "", name: "All" },
+  { key: "r", name: "Red" },
+  { key: "g", name: "Green" },
+  { key: "b", name: "Blue" },
+  { key: "a", name: "Alpha" }
+];
+
+let histogram = null;
+let histPath = null;
+let loupeOn = false;
+let channelIndex = 0;
+let sampleBusy = false;
+let sampleQueued = null;
+
+function isHistogramOpen() {
+  return histDlg.style.display === "flex";
+}
 
-  frameDelays = [];
+async function openHistogram(path) {
+  histDlg.style.display = "flex";
+  await refreshHistogram(path);
+}
 
-  for (let i = 0; i < frameCount; i++) {
-    frameDelays.push(gifReader.frameInfo(i).delay * 10 || 100);
+async function refreshHistogram(path) {
+  histPath = path;
+  histogram = null;
+  histStatus.textContent = "Computing…";
+  drawHistogram();
+  fillClipping();
+
+  let result;
+  try {
+    result = await invoke("compute_histogram", { path });
+  } catch (err) {
+    if (histPath === path) histStatus.textContent = String(err);
+    return;
   }
+  // the viewer moved on in the meantime
+  if (histPath !== path) return;
 
-  resetAndDecodeTo(0);
-  hideLoading();
-  play();
+  histogram = result;
+  histStatus.textContent = `${result.pixels.toLocaleString()} pixels`;
+  drawHistogram();
+  fillClipping();
 }
 
-function computeBaseScaleForGIF() {
-  const frame = document.getElementById("middleFrame");
+function closeHistogram() {
+  histDlg.style.display = "none";
+  histogram = null;
+  histPath = null;
+  releaseInspectedImage();
+}
 
-  const fw = frame.clientWidth;
-  const fh = frame.clientHeight;
+// Rust keeps the decoded image around only while something uses it
+function releaseInspectedImage() {
+  if (isHistogramOpen() || loupeOn) return;
+  invoke("release_inspected_image").catch(console.error);
+}
 
-  if (!gifWidth || !gifHeight) return;
+function drawHistogram() {
+  const w = histCanvas.width;
+  const h = histCanvas.height;
+  histCtx.clearRect(0, 0, w, h);
+  if (!histogram) return;
+
+  const mode = histMode.value;
+  const series = (mode === "rgb" ? ["red", "green", "blue"] : [mode])
+    .filter(name => histogram[name]);
+
+  if (!series.length) {
+    histCtx.fillStyle = "#888";
+    histCtx.font = "13px sans-serif";
+    histCtx.textAlign = "center";
+    histCtx.fillText("This image has no alpha channel", w / 2, h / 2);
+    return;
+  }
 
-  // compute scale so GIF fits inside frame
-  const scaleX = fw / gifWidth;
-  const scaleY = fh / gifHeight;
+  const value = histLog.checked ? Math.log1p : v => v;
 
-  scale = 1;
-  translateX = 0;
-  translateY = 0;
-  rotationDegrees = 0;
+  // Clipped ends tend to dwarf everything else, so scale to the inner bins
+  let max = 0;
+  for (const name of series) {
+    for (let i = 1; i < 255; i++) max = Math.max(max, value(histogram[name][i]));
+  }
+  max = max || 1;
 
-  updateTransform();
-}
+  const binWidth = w / 256;
 
-window.addEventListener("resize", () => {
-  if (!gifReader) return;
-  gifCanvas.width = middleFrame.clientWidth;
-  gifCanvas.height = middleFrame.clientHeight;
-});
+  // overlapping channels add up, all three together turn gray
+  histCtx.globalCompositeOperation = mode === "rgb" ? "lighter" : "source-over";
 
+  for (const name of series) {
+    histCtx.fillStyle = HIST_COLORS[name];
+    histCtx.beginPath();
+    histCtx.moveTo(0, h);
+    histogram[name].forEach((count, i) => {
+      const y = h - Math.min(1, value(count) / max) * h;
+      histCtx.lineTo(i * binWidth, y);
+      histCtx.lineTo((i + 1) * binWidth, y);
+    });
+    histCtx.lineTo(w, h);
+    histCtx.closePath();
+    histCtx.fill();
+  }
 
-// ---- FRAME DECODING ------------------------------------------
+  histCtx.globalCompositeOperation = "source-over";
+}
 
-let offscreenCanvas = document.createElement("canvas");
-let offscreenCtx = offscreenCanvas.getContext("2d");
+function fillClipping() {
+  histClipping.innerHTML = "";
+  if (!histogram || !histogram.pixels) return;
 
-function clearRect(info) {
-  for (let y = info.y; y < info.y + info.height; y++) {
-    for (let x = info.x; x < info.x + info.width; x++) {
-      const idx = (y * gifWidth + x) * 4;
-      composited[idx + 3] = 0;
+  const rows = [
+    ["Red", histogram.clipping_red],
+    ["Green", histogram.clipping_green],
+    ["Blue", histogram.clipping_blue],
+    ["Luminance", histogram.clipping_luminance]
+  ];
+
+  for (const [label, clip] of rows) {
+    const row = histClipping.insertRow();
+    row.insertCell().textContent = label;
+
+    for (const count of [clip.shadows, clip.highlights]) {
+      const cell = row.insertCell();
+      cell.textContent = `${(count / histogram.pixels * 100).toFixed(2)}%`;
+      cell.title = `${count.toLocaleString()} pixels`;
     }
   }
 }
 
-function decodeFrame(i, applyDisposal = true) {
-  const info = gifReader.frameInfo(i);
+histMode.onchange = drawHistogram;
+histLog.onchange = drawHistogram;
 
-  if (info.disposal === 3) {
-    previous = composited.slice();
-  }
+document.getElementById("closeHist").onclick =
+document.getElementById("histCloseBtn").onclick = closeHistogram;
 
-  gifReader.decodeAndBlitFrameRGBA(i, composited);
+histDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeHistogram();
+});
 
-  // apply disposal ONLY if requested
-  if (applyDisposal) {
-    if (info.disposal === 2) {
-      clearRect(info);
-    } 
-    else if (info.disposal === 3 && previous) {
-      composited.set(previous);
-    }
-  }
+// ----- Loupe ------
 
-  if (i % KEYFRAME_INTERVAL === 0) {
-    keyframes.set(i, composited.slice());
+function toggleLoupe() {
+  loupeOn = !loupeOn;
+  loupeToggleItem.textContent = loupeOn ? "Hide Pixel Loupe" : "Show Pixel Loupe";
+  middleFrame.classList.toggle("loupeActive", loupeOn);
+
+  if (!loupeOn) {
+    loupe.style.display = "none";
+    releaseInspectedImage();
   }
 }
 
-/* function draw() {
-  if (currentFrame > frameCount - 1) {
-    currentFrame = frameCount - 1;
+// Image pixel under a point on screen, null when there is none
+function imagePixelAt(clientX, clientY) {
+  let x, y, width, height;
+
+  if (tiled) {
+    const rect = tileCanvas.getBoundingClientRect();
+    const s = tiledFitScale() * scale;
+    const ox = tileCanvas.width / 2 + translateX - tiled.width * s / 2;
+    const oy = tileCanvas.height / 2 + translateY - tiled.height * s / 2;
+
+    x = Math.floor((clientX - rect.left - ox) / s);
+    y = Math.floor((clientY - rect.top - oy) / s);
+    ({ width, height } = tiled);
+  }
+  else if (!img.classList.contains("hidden") && img.naturalWidth && !icoFrames.length) {
+    width = img.naturalWidth;
+    height = img.naturalHeight;
+
+    // undo translate / scale / rotate around the element's center...
+    const rect = img.getBoundingClientRect();
+    const rad = rotationDegrees * Math.PI / 180;
+    const dx = (clientX - (rect.left + rect.width / 2)) / scale;
+    const dy = (clientY - (rect.top + rect.height / 2)) / scale;
+    const u = dx * Math.cos(rad) + dy * Math.sin(rad);
+    const v = -dx * Math.sin(rad) + dy * Math.cos(rad);
+
+    // ...then object-fit: contain
+    const fit = Math.min(img.clientWidth / width, img.clientHeight / height);
+    x = Math.floor(u / fit + width / 2);
+    y = Math.floor(v / fit + height / 2);
+  }
+  else {
+    return null;
   }
-  imageData.data.set(composited);
-  ctx.putImageData(imageData, 0, 0);
-  slider.value = currentFrame;
-  info.textContent = `Frame: ${currentFrame + 1}/${frameCount}`;
 
-  updateEQSliderFill(slider);
-} */
-function draw() {
-  if (currentFrame > frameCount - 1) {
-    currentFrame = frameCount - 1;
+  if (x < 0 || y < 0 || x >= width || y >= height) return null;
+  return { x, y };
+}
+
+function drawLoupe({ x, y }) {
+  const r = (LOUPE_PIXELS - 1) / 2;
+  const size = LOUPE_PIXELS * LOUPE_CELL;
+
+  loupeCtx.imageSmoothingEnabled = false;
+  loupeCtx.fillStyle = "#000";
+  loupeCtx.fillRect(0, 0, size, size);
+
+  if (tiled) {
+    // what is on screen, so blurry below 100% (the readout stays exact)
+    const s = tiledFitScale() * scale;
+    const ox = tileCanvas.width / 2 + translateX - tiled.width * s / 2;
+    const oy = tileCanvas.height / 2 + translateY - tiled.height * s / 2;
+    loupeCtx.drawImage(
+      tileCanvas,
+      ox + (x - r) * s, oy + (y - r) * s, LOUPE_PIXELS * s, LOUPE_PIXELS * s,
+      0, 0, size, size
+    );
+  } else {
+    loupeCtx.drawImage(img, x - r, y - r, LOUPE_PIXELS, LOUPE_PIXELS, 0, 0, size, size);
   }
 
-  // copy decoded pixels → imageData
-  imageData.data.set(composited);
+  // the sampled pixel
+  const c = r * LOUPE_CELL;
+  loupeCtx.lineWidth = 1;
+  loupeCtx.strokeStyle = "#000";
+  loupeCtx.strokeRect(c - 0.5, c - 0.5, LOUPE_CELL + 1, LOUPE_CELL + 1);
+  loupeCtx.strokeStyle = "#fff";
+  loupeCtx.strokeRect(c + 0.5, c + 0.5, LOUPE_CELL - 1, LOUPE_CELL - 1);
+}
 
-  // update offscreen buffer
-  offscreenCanvas.width = gifWidth;
-  offscreenCanvas.height = gifHeight;
-  offscreenCtx.putImageData(imageData, 0, 0);
+function placeLoupe(clientX, clientY) {
+  const frameRect = middleFrame.getBoundingClientRect();
+  loupe.style.display = "flex";
 
-  // render with transforms
-  renderGifFrame(offscreenCanvas);
+  let left = clientX - frameRect.left + 20;
+  let top = clientY - frameRect.top + 20;
 
-  slider.value = currentFrame;
-  info.textContent = `Frame: ${currentFrame + 1}/${frameCount}`;
+  // flip to the other side of the cursor near the edges
+  if (left + loupe.offsetWidth > frameRect.width) left -= loupe.offsetWidth + 40;
+  if (top + loupe.offsetHeight > frameRect.height) top -= loupe.offsetHeight + 40;
 
-  updateEQSliderFill(slider);
+  loupe.style.left = `${Math.max(0, left)}px`;
+  loupe.style.top = `${Math.max(0, top)}px`;
 }
 
-function renderGifFrame(source) {
-  const canvas = gifCanvas;
-  const ctx = canvas.getContext("2d");
+function updateLoupe(clientX, clientY) {
+  const pixel = images.length && !inGridMode && !isCompareOpen()
+    ? imagePixelAt(clientX, clientY)
+    : null;
+  if (!pixel) {
+    loupe.style.display = "none";
+    return;
+  }
 
-  ctx.clearRect(0, 0, canvas.width, canvas.height);
+  drawLoupe(pixel);
+  placeLoupe(clientX, clientY);
+  requestSample(images[index], pixel.x, pixel.y);
+}
 
-  ctx.save();
+// One sample_pixel call at a time, only the latest position is kept
+function requestSample(path, x, y) {
+  sampleQueued = { path, x, y };
+  if (!sampleBusy) runSamples();
+}
 
-  // Move origin to center of canvas
-  ctx.translate(canvas.width / 2, canvas.height / 2);
+async function runSamples() {
+  sampleBusy = true;
 
-  // Apply viewer transforms
-  ctx.translate(translateX, translateY);
-  ctx.scale(scale, scale);
-  ctx.rotate(rotationDegrees * Math.PI / 180);
+  while (sampleQueued) {
+    const { path, x, y } = sampleQueued;
+    sampleQueued = null;
 
-  // Draw centered
-  ctx.drawImage(
-    source,
-    -gifWidth / 2,
-    -gifHeight / 2
-  );
+    try {
+      const sample = await invoke("sample_pixel", { path, x, y });
+      if (loupeOn && path === images[index]) showSample(sample);
+    } catch (err) {
+      loupeReadout.textContent = String(err);
+    }
+  }
 
-  ctx.restore();
+  sampleBusy = false;
 }
 
+function showSample({ x, y, rgba, channels, original, original_bits }) {
+  const hex = "#" + rgba.slice(0, 3).map(v => v.toString(16).padStart(2, "0")).join("").toUpperCase();
 
-function resetAndDecodeTo(target) {
-  let start = 0;
-  for (const k of keyframes.keys()) {
-    if (k <= target && k > start) start = k;
-  }
+  // native value per RGBA row; gray images repeat L for R, G and B
+  const native = {};
+  channels.forEach((name, i) => {
+    if (name === "L") native.R = native.G = native.B = original[i];
+    else native[name] = original[i];
+  });
+  const format = v => original_bits === 32 ? v.toFixed(4) : String(v);
 
-  if (keyframes.has(start)) {
-    composited.set(keyframes.get(start));
-  } 
-  else {
-    composited.fill(0);
-    keyframes.set(0, composited.slice());
+  const lines = [`${x}, ${y}`, hex];
+  ["R", "G", "B", "A"].forEach((name, i) => {
+    let line = `${name} ${String(rgba[i]).padStart(3)}`;
+    if (original_bits !== 8 && native[name] !== undefined) {
+      line += `  ${format(native[name]).padStart(6)}`;
+    }
+    lines.push(line);
+  });
+  if (original_bits !== 8) {
+    lines.push(original_bits === 32 ? "(32-bit float)" : `(${original_bits}-bit)`);
   }
 
-  previous = null;
+  loupeReadout.textContent = lines.join("\n");
+}
 
-  for (let i = start + 1; i < target; i++) {
-    decodeFrame(i, true);
-  }
+middleFrame.addEventListener("mousemove", e => {
+  if (loupeOn) updateLoupe(e.clientX, e.clientY);
+});
 
-  decodeFrame(target, false);
+middleFrame.addEventListener("wheel", e => {
+  // the image moved under a still cursor
+  if (loupeOn) requestAnimationFrame(() => updateLoupe(e.clientX, e.clientY));
+});
 
-  currentFrame = target;
-  draw();
-}
+middleFrame.addEventListener("mouseleave", () => {
+  loupe.style.display = "none";
+});
 
+// ----- Channel isolation ------
 
-function updateEQSliderFill(slider) {
-  const min = Number(slider.min);
-  const max = Number(slider.max);
-  const val = Number(slider.value);
+function setChannel(i) {
+  channelIndex = i;
+  const { key, name } = CHANNELS[i];
 
-  if (max <= min) {
-    slider.style.background = "#5e5e5eff";
-    return;
-  }
+  if (key) middleFrame.dataset.channel = key;
+  else delete middleFrame.dataset.channel;
 
-  const percent = ((val - min) / (max - min)) * 100;
+  channelItem.textContent = `Channel: ${name}`;
+  channelBadge.textContent = `${name} channel`;
+  channelBadge.classList.toggle("hidden", !key);
+}
 
-  slider.style.background = `
-    linear-gradient(
-      to right,
-      #ee2727ff ${percent}%,
-      #5e5e5eff ${percent}%
-    )
-  `;
+function cycleChannel() {
+  setChannel((channelIndex + 1) % CHANNELS.length);
 }
 
-slider.addEventListener("input", () => {
-  updateEQSliderFill(slider);
+// Called by showImage for the new file
+function refreshInspectors(path) {
+  loupe.style.display = "none";
+  if (isHistogramOpen()) refreshHistogram(path);
+}
+
+document.addEventListener("keydown", e => {
+  if (e.target.closest("input, textarea, select")) return;
+  if (e.ctrlKey || e.metaKey || e.altKey) return;
+
+  const key = e.key.toLowerCase();
+  if (key === "h") {
+    if (isHistogramOpen()) closeHistogram();
+    else if (images.length) openHistogram(images[index]);
+  }
+  if (key === "l") toggleLoupe();
+  if (key === "k") cycleChannel();
 });
 
-function resetGifUI() {
-  // Stop playback completely
-  stopGifPlayback();
+// -------------------- COMPARE ----------------------
+
+// Two or more images with linked zoom and pan. Onion skin, difference and
+// heatmap work on the A / B pair; the diff itself comes from compare.rs.
+
+const compareView = document.getElementById("compareView");
+const comparePanes = document.getElementById("comparePanes");
+const compareMode = document.getElementById("compareMode");
+const compareA = document.getElementById("compareA");
+const compareB = document.getElementById("compareB");
+const compareOpacity = document.getElementById("compareOpacity");
+const compareOpacityRow = document.getElementById("compareOpacityRow");
+const compareThreshold = document.getElementById("compareThreshold");
+const compareThresholdRow = document.getElementById("compareThresholdRow");
+const compareThresholdValue = document.getElementById("compareThresholdValue");
+const compareStats = document.getElementById("compareStats");
+
+let compareFiles = [];
+let compareUrls = new Map();    // path -> displayable URL
+let compareResult = null;       // { difference, heatmap } URLs and numbers
+let compareToken = 0;
+let compareTransform = { scale: 1, x: 0, y: 0 };
+let compareDrag = null;
+
+function isCompareOpen() {
+  return !compareView.classList.contains("hidden");
+}
 
-  // Kill decoder state
-  gifReader = null;
-  frameCount = 0;
-  currentFrame = 0;
-  paused = true;
+// Same decision as showImage: TIFFs and tagged images go through Rust
+async function compareImageUrl(path) {
+  const ext = getExt(path);
+  if (ext === "tif" || ext === "tiff" || await needsColorConversion(path)) {
+    return decodedImageUrl(path);
+  }
+  return convertFileSrc(path);
+}
 
-  keyframes.clear();
-  frameDelays = [];
+async function openCompare(paths) {
+  paths = [...new Set(paths)];
+  if (paths.length < 2) {
+    alert("Choose at least one other image to compare with.");
+    return;
+  }
 
-  composited = null;
-  previous = null;
-  imageData = null;
+  closeCompare();
+  compareFiles = paths;
+  compareTransform = { scale: 1, x: 0, y: 0 };
 
-  // Clear canvas visually
-  ctx.clearRect(0, 0, gifCanvas.width, gifCanvas.height);
+  for (const select of [compareA, compareB]) {
+    select.innerHTML = "";
+    paths.forEach((path, i) => select.add(new Option(getFileName(path), i)));
+  }
+  compareA.value = 0;
+  compareB.value = 1;
 
-  // Shrink canvas so browser drops backing store
-  gifCanvas.width = 1;
-  gifCanvas.height = 1;
+  compareView.classList.remove("hidden");
+  compareStats.textContent = "Loading…";
 
-  // Reset slider + info
-  slider.min = 0;
-  slider.max = 0;
-  slider.value = 0;
-  slider.style.background = "#5e5e5eff";
-  info.textContent = "Frame: 0/0";
+  try {
+    const urls = await Promise.all(paths.map(compareImageUrl));
+    paths.forEach((path, i) => compareUrls.set(path, urls[i]));
+  } catch (err) {
+    compareStats.textContent = String(err);
+    return;
+  }
+
+  compareStats.textContent = "";
+  renderCompare();
 }
 
-// ------------------------- ZOOM AND DRAG ------------------------
+function closeCompare() {
+  compareToken++;
+  compareView.classList.add("hidden");
+  comparePanes.innerHTML = "";
 
-function getActiveViewer() {
-  const img = document.getElementById("imgViewer");
-  const canvas = document.getElementById("gifCanvas");
-  //console.log("img",img.classList.contains("hidden"))
-  //console.log("canvas",canvas.classList.contains("hidden"))
-  // the one that is NOT hidden is the active viewer
-  if (!img.classList.contains("hidden")) return img;
-  if (!canvas.classList.contains("hidden")) return canvas;
+  for (const url of compareUrls.values()) {
+    if (url.startsWith("blob:")) URL.revokeObjectURL(url);
+  }
+  compareUrls.clear();
+  clearCompareResult();
+  compareFiles = [];
+}
 
-  return null;
+function clearCompareResult() {
+  if (compareResult) {
+    URL.revokeObjectURL(compareResult.difference);
+    URL.revokeObjectURL(compareResult.heatmap);
+  }
+  compareResult = null;
 }
 
-img.addEventListener("load", computeBaseScale);
+function addComparePane(label, ...urls) {
+  const pane = document.createElement("div");
+  pane.className = "comparePane";
 
-// Mouse wheel zoom in the middleFrame
+  for (const url of urls) {
+    const image = document.createElement("img");
+    image.className = "compareImg";
+    image.src = url;
+    pane.appendChild(image);
+  }
 
-imgViewerDiv.addEventListener("wheel", e => {
-  if (inGridMode) return;  // <-- Do not block wheel scroll!
-  //if (!img.src) return;
-  e.preventDefault();
+  const caption = document.createElement("div");
+  caption.className = "compareLabel";
+  caption.textContent = label;
+  pane.appendChild(caption);
 
-  const rect = imgViewerDiv.getBoundingClientRect();
+  comparePanes.appendChild(pane);
+  return pane;
+}
 
-  // mouse position relative to frame center
-  const mx = e.clientX - rect.left - rect.width / 2;
-  const my = e.clientY - rect.top  - rect.height / 2;
+function renderCompare() {
+  const mode = compareMode.value;
+  const a = compareFiles[compareA.value];
+  const b = compareFiles[compareB.value];
 
-  // image-local coords BEFORE scaling
-  const ix = (mx - translateX) / scale;
-  const iy = (my - translateY) / scale;
+  comparePanes.innerHTML = "";
+  document.querySelectorAll(".compareAB").forEach(el => el.classList.toggle("hidden", mode === "side"));
+  compareOpacityRow.classList.toggle("hidden", mode !== "onion");
+  compareThresholdRow.classList.toggle("hidden", mode === "side" || mode === "onion");
 
-  let newScale = scale * (1 - e.deltaY * ZOOM_SPEED);
-  newScale = Math.min(MAX_SCALE, Math.max(MIN_SCALE, newScale));
+  if (mode === "side") {
+    compareStats.textContent = "";
+    compareFiles.forEach(path => addComparePane(getFileName(path), compareUrls.get(path)));
+  }
+  else if (mode === "onion") {
+    compareStats.textContent = "";
+    const pane = addComparePane(`${getFileName(a)} / ${getFileName(b)}`, compareUrls.get(a), compareUrls.get(b));
+    pane.querySelectorAll(".compareImg")[1].style.opacity = compareOpacity.value / 100;
+  }
+  else if (compareResult) {
+    addComparePane(`${getFileName(a)} − ${getFileName(b)}`, compareResult[mode]);
+  }
+  else {
+    computeCompareDiff();
+  }
 
-  if (newScale === scale) return;
+  applyCompareTransform();
+}
 
-  // keep pixel under cursor stable
-  translateX = mx - ix * newScale;
-  translateY = my - iy * newScale;
+async function computeCompareDiff() {
+  const token = ++compareToken;
+  const a = compareFiles[compareA.value];
+  const b = compareFiles[compareB.value];
+  const threshold = Number(compareThreshold.value);
 
-  scale = newScale;
-  updateTransform();
-}, { passive: false });
+  if (a === b) {
+    compareStats.textContent = "Pick two different images as A and B.";
+    return;
+  }
 
-// ---Click + drag pan----
+  compareStats.textContent = "Comparing…";
 
-img.addEventListener("mousedown", e => {
-  if (e.button !== 0) return;
-  if (scale === 1) return;
+  let result;
+  try {
+    result = await invoke("compare_images", { a, b, threshold });
+  } catch (err) {
+    if (token === compareToken) compareStats.textContent = String(err);
+    return;
+  }
+  if (token !== compareToken) return;
+
+  const toUrl = bytes => URL.createObjectURL(new Blob([new Uint8Array(bytes)], { type: "image/png" }));
+  clearCompareResult();
+  compareResult = {
+    difference: toUrl(result.difference),
+    heatmap: toUrl(result.heatmap)
+  };
+
+  const pixels = result.width * result.height;
+  const percent = (result.changed_pixels / pixels * 100).toFixed(2);
+  compareStats.textContent =
+    `PSNR ${result.psnr === null ? "∞" : result.psnr.toFixed(2)} dB · ` +
+    `SSIM ${result.ssim.toFixed(4)} · ` +
+    `${result.changed_pixels.toLocaleString()} pixels over threshold (${percent}%) · ` +
+    `max difference ${result.max_difference}`;
+
+  renderCompare();
+}
 
-  dragging = true;
-  lastX = e.clientX;
-  lastY = e.clientY;
-  img.classList.add("dragging");
-});
+function applyCompareTransform() {
+  const { scale: s, x, y } = compareTransform;
+  comparePanes.querySelectorAll(".compareImg").forEach(image => {
+    image.style.transform = `translate(${x}px, ${y}px) scale(${s})`;
+  });
+}
 
-window.addEventListener("mousemove", e => {
-  if (!dragging) return;
+compareMode.onchange = renderCompare;
 
-  translateX += e.clientX - lastX;
-  translateY += e.clientY - lastY;
+compareA.onchange = compareB.onchange = () => {
+  compareToken++;
+  clearCompareResult();
+  renderCompare();
+};
 
-  lastX = e.clientX;
-  lastY = e.clientY;
+compareOpacity.oninput = () => {
+  const top = comparePanes.querySelectorAll(".compareImg")[1];
+  if (top) top.style.opacity = compareOpacity.value / 100;
+};
 
-  updateTransform();
-});
+compareThreshold.oninput = () => {
+  compareThresholdValue.textContent = compareThreshold.value;
+};
 
-window.addEventListener("mouseup", () => {
-  dragging = false;
-  img.classList.remove("dragging");
-});
+compareThreshold.onchange = () => {
+  compareToken++;
+  clearCompareResult();
+  renderCompare();
+};
 
-function resetView() {
-  scale = 1;
-  translateX = 0;
-  translateY = 0;
-  updateTransform();
-}
+document.getElementById("compareClose").onclick = closeCompare;
 
-img.addEventListener("load", resetView);
+// Zoom around the cursor in whichever pane it is over, the others follow
+comparePanes.addEventListener("wheel", e => {
+  e.preventDefault();
+  const pane = e.target.closest(".comparePane");
+  if (!pane) return;
 
-img.addEventListener("load", () => {
-  scale = 1;
-  translateX = 0;
-  translateY = 0;
-  isOriginalSize = false;
+  const rect = pane.getBoundingClientRect();
+  const px = e.clientX - (rect.left + rect.width / 2);
+  const py = e.clientY - (rect.top + rect.height / 2);
 
-  originalBtn.title = "Original Size";
-  originalBtn.innerHTML = originalSizeIcon;
+  const t = compareTransform;
+  const newScale = Math.min(MAX_SCALE, Math.max(MIN_SCALE, t.scale * Math.exp(-e.deltaY * ZOOM_SPEED)));
+  const ratio = newScale / t.scale;
 
-  updateTransform();
+  t.x = px - (px - t.x) * ratio;
+  t.y = py - (py - t.y) * ratio;
+  t.scale = newScale;
+  if (newScale === MIN_SCALE) t.x = t.y = 0;
 
-  const w = img.naturalWidth;
-  const h = img.naturalHeight;
-  imgLabel.style.display = 'block';
-  imgSize.textContent = `${w} × ${h}`;
+  applyCompareTransform();
+}, { passive: false });
 
+comparePanes.addEventListener("mousedown", e => {
+  if (e.button !== 0) return;
+  e.preventDefault();
+  compareDrag = { x: e.clientX, y: e.clientY };
+  comparePanes.classList.add("dragging");
 });
 
+window.addEventListener("mousemove", e => {
+  if (!compareDrag) return;
+  compareTransform.x += e.clientX - compareDrag.x;
+  compareTransform.y += e.clientY - compareDrag.y;
+  compareDrag = { x: e.clientX, y: e.clientY };
+  applyCompareTransform();
+});
 
-// ------------- ORIGINAL SIZE ---------------------
-
-const originalSizeIcon = `
-<svg class="barIconSvg" viewBox="0 -960 960 960">
-  <path d="M800-640v-80h-80q-17 0-28.5-11.5T680-760q0-17 11.5-28.5T720-800h80q33 0 56.5 23.5T880-720v80q0 17-11.5 28.5T840-600q-17 0-28.5-11.5T800-640Zm-720 0v-80q0-33 23.5-56.5T160-800h80q17 0 28.5 11.5T280-760q0 17-11.5 28.5T240-720h-80v80q0 17-11.5 28.5T120-600q-17 0-28.5-11.5T80-640Zm720 480h-80q-17 0-28.5-11.5T680-200q0-17 11.5-28.5T720-240h80v-80q0-17 11.5-28.5T840-360q17 0 28.5 11.5T880-320v80q0 33-23.5 56.5T800-160Zm-640 0q-33 0-56.5-23.5T80-240v-80q0-17 11.5-28.5T120-360q17 0 28.5 11.5T160-320v80h80q17 0 28.5 11.5T280-200q0 17-11.5 28.5T240-160h-80Zm80-240v-160q0-33 23.5-56.5T320-640h320q33 0 56.5 23.5T720-560v160q0 33-23.5 56.5T640-320H320q-33 0-56.5-23.5T240-400Zm80 0h320v-160H320v160Zm0 0v-160 160Z"/>
-</svg> `;
+window.addEventListener("mouseup", () => {
+  compareDrag = null;
+  comparePanes.classList.remove("dragging");
+});
 
-const fitScreen = `
-<svg class="barIconSvg" viewBox="0 -960 960 960">
-  <path d="M240-240h-80q-17 0-28.5-11.5T120-280q0-17 11.5-28.5T160-320h120q17 0 28.5 11.5T320-280v120q0 17-11.5 28.5T280-120q-17 0-28.5-11.5T240-160v-80Zm480 0v80q0 17-11.5 28.5T680-120q-17 0-28.5-11.5T640-160v-120q0-17 11.5-28.5T680-320h120q17 0 28.5 11.5T840-280q0 17-11.5 28.5T800-240h-80ZM240-720v-80q0-17 11.5-28.5T280-840q17 0 28.5 11.5T320-800v120q0 17-11.5 28.5T280-640H160q-17 0-28.5-11.5T120-680q0-17 11.5-28.5T160-720h80Zm480 0h80q17 0 28.5 11.5T840-680q0 17-11.5 28.5T800-640H680q-17 0-28.5-11.5T640-680v-120q0-17 11.5-28.5T680-840q17 0 28.5 11.5T720-800v80Z"/>
-</svg>`;
+comparePanes.addEventListener("dblclick", () => {
+  compareTransform = { scale: 1, x: 0, y: 0 };
+  applyCompareTransform();
+});
 
-originalBtn.innerHTML = originalSizeIcon;
+// The viewer's shortcuts (next image, loupe...) don't apply while comparing
+document.addEventListener("keydown", e => {
+  if (!isCompareOpen()) return;
+  if (e.key === "F5" || e.key === "F11") return;
 
-function getOriginalScale() {
-  // rendered size from CSS (fit)
-  const fittedWidth = img.clientWidth;
-  const fittedHeight = img.clientHeight;
+  e.stopImmediatePropagation();
+  if (e.key === "Escape") closeCompare();
+}, true);
 
-  // real image size
-  const naturalWidth = img.naturalWidth;
-  const naturalHeight = img.naturalHeight;
+async function chooseCompareFiles(path) {
+  const folder = path.replace(/[\\/][^\\/]*$/, "");
+  const selected = await open({
+    multiple: true,
+    defaultPath: folder,
+    filters: [
+      {
+        name: "Image",
+        extensions: [
+          "png","jpeg","jpg","gif","bmp","ico","tif","tiff","avif",
+          "webp","cur","svg","jfif"
+        ]
+      }
+    ]
+  });
+  if (!selected) return;
 
-  // scale needed to reach 1:1 pixels
-  return Math.min(
-    naturalWidth / fittedWidth,
-    naturalHeight / fittedHeight
-  );
+  const others = Array.isArray(selected) ? selected : [selected];
+  await openCompare([path, ...others]);
 }
-/* function getOriginalScale() {
-  const imgVisible = !img.classList.contains("hidden");
-  const gifVisible = !gifCanvas.classList.contains("hidden");
+
+function hideLoading() {
+  loadingText.classList.remove("visible");
+  img.classList.remove("loading");
+}
+
+img.addEventListener("load", hideLoading);
+img.addEventListener("error", hideLoading);
+
+// -------------------- NEW GIF SYSTEM ----------------------
+
+gifPlayPause.onclick = () => playing ? pause() : play();
+
+gifPrev.onclick = () => {
+  pause();
+  const target = (currentFrame - 1 + frameCount) % frameCount;
+  resetAndDecodeTo(target);
+};
+
+gifNext.onclick = () => {
+  pause();
+  const target = (currentFrame + 1) % frameCount;
+  resetAndDecodeTo(target);
+};
+
+function stopGifPlayback() {
+  playing = false;
+
+  if (timer) {
+    clearTimeout(timer);
+    timer = null;
+  }
+}
+
+function step() {
+  if (!gifReader || frameCount === 0) return;
+  decodeFrame(currentFrame, true); // dispose old frame
+  currentFrame = (currentFrame + 1) % frameCount;
+  decodeFrame(currentFrame, false); // show new frame
+  draw();
+  if (playing) {
+    timer = setTimeout(step, frameDelays[currentFrame]);
+  }
+}
+
+function play() {
+  if (playing) return;
+  //updateEQSliderFill(slider);
+  playing = true;
+  paused = false;
+  gifPlayPause.textContent = "⏸";
+  timer = setTimeout(step, frameDelays[currentFrame]);
+}
+
+function pause() {
+  //updateEQSliderFill(slider);
+  playing = false;
+  paused = true;
+  gifPlayPause.textContent = "▶";
+  clearTimeout(timer);
+}
+
+slider.oninput = e => {
+    if (paused) {
+      pause();
+    }
+    resetAndDecodeTo(+e.target.value);
+    if (!paused) {
+      play();
+    }
+};
+
+// ---- LOAD GIF -------------------------------------------------
+
+async function loadGIF(path) {
+  const token = ++gifLoadToken;
+  const url = fileSrc(path);
+  const res = await fetch(url);
+  if (token !== gifLoadToken) return;
+  const buf = await res.arrayBuffer();
+  if (token !== gifLoadToken) return;
+
+  gifReader = new GifReader(new Uint8Array(buf));
+
+  gifWidth = gifReader.width;
+  gifHeight = gifReader.height;
+  imgLabel.style.display = 'block';
+  imgSize.textContent = `${gifWidth} × ${gifHeight}`;
+
+  computeBaseScaleForGIF();
+
+  frameCount = gifReader.numFrames();
+
+  currentFrame = 0;
+  paused = true;
+
+  keyframes.clear();
+
+  //gifCanvas.width = gifWidth;
+  //gifCanvas.height = gifHeight;
+  gifCanvas.width = middleFrame.clientWidth;
+  gifCanvas.height = middleFrame.clientHeight;
+
+  slider.min = 0;
+  slider.max = frameCount - 1;
+  slider.step = 1;
+  slider.value = 0;
+
+  updateEQSliderFill(slider);
+
+  composited = new Uint8Array(gifWidth * gifHeight * 4);
+  composited.fill(0);
+
+  keyframes.set(0, composited.slice());
+
+  imageData = ctx.createImageData(gifWidth, gifHeight);
+
+  frameDelays = [];
+
+  for (let i = 0; i < frameCount; i++) {
+    frameDelays.push(gifReader.frameInfo(i).delay * 10 || 100);
+  }
+
+  resetAndDecodeTo(0);
+  hideLoading();
+  play();
+}
+
+function computeBaseScaleForGIF() {
+  const frame = document.getElementById("middleFrame");
+
+  const fw = frame.clientWidth;
+  const fh = frame.clientHeight;
+
+  if (!gifWidth || !gifHeight) return;
+
+  // compute scale so GIF fits inside frame
+  const scaleX = fw / gifWidth;
+  const scaleY = fh / gifHeight;
+
+  scale = 1;
+  translateX = 0;
+  translateY = 0;
+  rotationDegrees = 0;
+
+  updateTransform();
+}
+
+window.addEventListener("resize", () => {
+  if (!gifReader) return;
+  gifCanvas.width = middleFrame.clientWidth;
+  gifCanvas.height = middleFrame.clientHeight;
+});
+
+// -------------------- TILED VIEW (huge images) ----------------------
+
+const tileCanvas = document.getElementById("tileCanvas");
+const tileCtx = tileCanvas.getContext("2d");
+
+const TILED_MIN_PIXELS = 40_000_000; // above this, use the tile pyramid
+const TILE_CACHE_MAX = 512;          // decoded tiles kept in the webview
+
+let tiled = null;                    // info from open_tiled_image, `ready` once the tiles are
+let tiledLoadToken = 0;
+const tileImages = new Map();        // key: "level/x/y", value: { img, url }
+
+// Optional memory limit for the Rust tile pyramid, in MB
+const tileMemoryLimitMB = Number(localStorage.getItem("tileMemoryLimitMB"));
+if (tileMemoryLimitMB > 0) {
+  invoke("set_tile_memory_limit", { bytes: tileMemoryLimitMB * 1024 * 1024 })
+    .catch(console.error);
+}
+
+// throws when the header is over the decoder limits
+async function isHugeImage(path, ext) {
+  if (ext === "svg" || ext === "ico" || ext === "gif" || ext === "cur") return false;
+
+  const dims = await invoke("get_image_dimensions", { path });
+  if (!dims) return false;
+
+  const [w, h] = dims;
+  return w * h > TILED_MIN_PIXELS;
+}
+
+function bytesToImage(bytes) {
+  const blob = new Blob([new Uint8Array(bytes)], { type: "image/png" });
+  const url = URL.createObjectURL(blob);
+
+  return new Promise((resolve, reject) => {
+    const tileImg = new Image();
+    tileImg.onload = () => resolve({ img: tileImg, url });
+    tileImg.onerror = reject;
+    tileImg.src = url;
+  });
+}
+
+async function loadTiled(path) {
+  const token = ++tiledLoadToken;
+
+  let info, preview;
+  try {
+    info = await invoke("open_tiled_image", { path });
+    preview = await bytesToImage(info.preview);
+  }
+  catch (err) {
+    if (token === tiledLoadToken) showDecodeError(err);
+    return;
+  }
+  if (token !== tiledLoadToken) {
+    URL.revokeObjectURL(preview.url);
+    return;
+  }
+
+  tiled = { ...info, path, preview, ready: false };
+
+  tileCanvas.width = middleFrame.clientWidth;
+  tileCanvas.height = middleFrame.clientHeight;
+  tileCanvas.classList.remove("hidden");
+
+  imgLabel.style.display = 'block';
+  imgSize.textContent = `${tiled.width} × ${tiled.height}`;
+
+  hideLoading();
+  resetView();
+
+  // the preview shows while the rest of the pyramid is built
+  try {
+    await invoke("wait_tiled_image", { path });
+  }
+  catch (err) {
+    if (token === tiledLoadToken) showDecodeError(err);
+    return;
+  }
+  if (token !== tiledLoadToken) return;
+
+  tiled.ready = true;
+  renderTiles();
+}
+
+function resetTiledUI() {
+  tiledLoadToken++;
+
+  if (tiled) {
+    invoke("close_tiled_image", { path: tiled.path }).catch(console.error);
+    URL.revokeObjectURL(tiled.preview.url);
+  }
+  tiled = null;
+
+  for (const entry of tileImages.values()) {
+    if (entry && entry.url) URL.revokeObjectURL(entry.url);
+  }
+  tileImages.clear();
+
+  tileCtx.clearRect(0, 0, tileCanvas.width, tileCanvas.height);
+  tileCanvas.width = 1;
+  tileCanvas.height = 1;
+  tileCanvas.classList.add("hidden");
+}
+
+// scale at which the whole image fits the frame
+function tiledFitScale() {
+  return Math.min(
+    middleFrame.clientWidth / tiled.width,
+    middleFrame.clientHeight / tiled.height
+  );
+}
+
+function getTileImage(level, x, y) {
+  const key = `${level}/${x}/${y}`;
+
+  if (tileImages.has(key)) {
+    return tileImages.get(key)?.img || null;
+  }
+
+  tileImages.set(key, null); // loading
+  const path = tiled.path;
+
+  invoke("get_tile", { path, level, x, y })
+    .then(bytesToImage)
+    .then(entry => {
+      if (!tiled || tiled.path !== path || !tileImages.has(key)) {
+        URL.revokeObjectURL(entry.url);
+        return;
+      }
+      tileImages.set(key, entry);
+      trimTileImages();
+      renderTiles();
+    })
+    .catch(err => console.error("Tile failed:", err));
+
+  return null;
+}
+
+function trimTileImages() {
+  for (const [key, entry] of tileImages) {
+    if (tileImages.size <= TILE_CACHE_MAX) break;
+    if (!entry) continue;
+    URL.revokeObjectURL(entry.url);
+    tileImages.delete(key);
+  }
+}
+
+function renderTiles() {
+  if (!tiled) return;
+
+  const cw = tileCanvas.width;
+  const ch = tileCanvas.height;
+  tileCtx.clearRect(0, 0, cw, ch);
+
+  // screen pixels per image pixel
+  const s = tiledFitScale() * scale;
+  const dw = tiled.width * s;
+  const dh = tiled.height * s;
+
+  // top-left corner of the image on the canvas
+  const ox = cw / 2 + translateX - dw / 2;
+  const oy = ch / 2 + translateY - dh / 2;
+
+  // low-res preview underneath, tiles paint over it as they arrive
+  tileCtx.drawImage(tiled.preview.img, ox, oy, dw, dh);
+
+  const lastLevel = tiled.level_sizes.length - 1;
+  let level = Math.floor(Math.log2(1 / s));
+  level = Math.min(lastLevel, Math.max(0, level));
+
+  // preview is already sharp enough, or there are no tiles yet
+  if (level >= tiled.preview_level || !tiled.ready) return;
+
+  const [lw, lh] = tiled.level_sizes[level];
+  const ts = tiled.tile_size;
+
+  // screen pixels per level pixel
+  const sx = dw / lw;
+  const sy = dh / lh;
+
+  const x0 = Math.max(0, Math.floor(-ox / sx / ts));
+  const y0 = Math.max(0, Math.floor(-oy / sy / ts));
+  const x1 = Math.min(Math.ceil(lw / ts) - 1, Math.floor((cw - ox) / sx / ts));
+  const y1 = Math.min(Math.ceil(lh / ts) - 1, Math.floor((ch - oy) / sy / ts));
+
+  for (let ty = y0; ty <= y1; ty++) {
+    for (let tx = x0; tx <= x1; tx++) {
+      const tile = getTileImage(level, tx, ty);
+      if (!tile) continue;
+
+      tileCtx.drawImage(
+        tile,
+        ox + tx * ts * sx,
+        oy + ty * ts * sy,
+        tile.width * sx,
+        tile.height * sy
+      );
+    }
+  }
+}
+
+tileCanvas.addEventListener("mousedown", e => {
+  if (e.button !== 0) return;
+  if (scale === 1) return;
+
+  dragging = true;
+  lastX = e.clientX;
+  lastY = e.clientY;
+  tileCanvas.classList.add("dragging");
+});
+
+window.addEventListener("mouseup", () => {
+  tileCanvas.classList.remove("dragging");
+});
+
+window.addEventListener("resize", () => {
+  if (!tiled) return;
+  tileCanvas.width = middleFrame.clientWidth;
+  tileCanvas.height = middleFrame.clientHeight;
+  updateTransform();
+});
+
+
+// ---- FRAME DECODING ------------------------------------------
+
+let offscreenCanvas = document.createElement("canvas");
+let offscreenCtx = offscreenCanvas.getContext("2d");
+
+function clearRect(info) {
+  for (let y = info.y; y < info.y + info.height; y++) {
+    for (let x = info.x; x < info.x + info.width; x++) {
+      const idx = (y * gifWidth + x) * 4;
+      composited[idx + 3] = 0;
+    }
+  }
+}
+
+function decodeFrame(i, applyDisposal = true) {
+  const info = gifReader.frameInfo(i);
+
+  if (info.disposal === 3) {
+    previous = composited.slice();
+  }
+
+  gifReader.decodeAndBlitFrameRGBA(i, composited);
+
+  // apply disposal ONLY if requested
+  if (applyDisposal) {
+    if (info.disposal === 2) {
+      clearRect(info);
+    } 
+    else if (info.disposal === 3 && previous) {
+      composited.set(previous);
+    }
+  }
+
+  if (i % KEYFRAME_INTERVAL === 0) {
+    keyframes.set(i, composited.slice());
+  }
+}
+
+/* function draw() {
+  if (currentFrame > frameCount - 1) {
+    currentFrame = frameCount - 1;
+  }
+  imageData.data.set(composited);
+  ctx.putImageData(imageData, 0, 0);
+  slider.value = currentFrame;
+  info.textContent = `Frame: ${currentFrame + 1}/${frameCount}`;
+
+  updateEQSliderFill(slider);
+} */
+function draw() {
+  if (currentFrame > frameCount - 1) {
+    currentFrame = frameCount - 1;
+  }
+
+  // copy decoded pixels → imageData
+  imageData.data.set(composited);
+
+  // update offscreen buffer
+  offscreenCanvas.width = gifWidth;
+  offscreenCanvas.height = gifHeight;
+  offscreenCtx.putImageData(imageData, 0, 0);
+
+  // render with transforms
+  renderGifFrame(offscreenCanvas);
+
+  slider.value = currentFrame;
+  info.textContent = `Frame: ${currentFrame + 1}/${frameCount}`;
+
+  updateEQSliderFill(slider);
+}
+
+function renderGifFrame(source) {
+  const canvas = gifCanvas;
+  const ctx = canvas.getContext("2d");
+
+  ctx.clearRect(0, 0, canvas.width, canvas.height);
+
+  ctx.save();
+
+  // Move origin to center of canvas
+  ctx.translate(canvas.width / 2, canvas.height / 2);
+
+  // Apply viewer transforms
+  ctx.translate(translateX, translateY);
+  ctx.scale(scale, scale);
+  ctx.rotate(rotationDegrees * Math.PI / 180);
+
+  // Draw centered
+  ctx.drawImage(
+    source,
+    -gifWidth / 2,
+    -gifHeight / 2
+  );
+
+  ctx.restore();
+}
+
+
+function resetAndDecodeTo(target) {
+  let start = 0;
+  for (const k of keyframes.keys()) {
+    if (k <= target && k > start) start = k;
+  }
+
+  if (keyframes.has(start)) {
+    composited.set(keyframes.get(start));
+  } 
+  else {
+    composited.fill(0);
+    keyframes.set(0, composited.slice());
+  }
+
+  previous = null;
+
+  for (let i = start + 1; i < target; i++) {
+    decodeFrame(i, true);
+  }
+
+  decodeFrame(target, false);
+
+  currentFrame = target;
+  draw();
+}
+
+
+function updateEQSliderFill(slider) {
+  const min = Number(slider.min);
+  const max = Number(slider.max);
+  const val = Number(slider.value);
+
+  if (max <= min) {
+    slider.style.background = "#5e5e5eff";
+    return;
+  }
+
+  const percent = ((val - min) / (max - min)) * 100;
+
+  slider.style.background = `
+    linear-gradient(
+      to right,
+      #ee2727ff ${percent}%,
+      #5e5e5eff ${percent}%
+    )
+  `;
+}
+
+slider.addEventListener("input", () => {
+  updateEQSliderFill(slider);
+});
+
+function resetGifUI() {
+  // Stop playback completely
+  stopGifPlayback();
+
+  // Kill decoder state
+  gifReader = null;
+  frameCount = 0;
+  currentFrame = 0;
+  paused = true;
+
+  keyframes.clear();
+  frameDelays = [];
+
+  composited = null;
+  previous = null;
+  imageData = null;
+
+  // Clear canvas visually
+  ctx.clearRect(0, 0, gifCanvas.width, gifCanvas.height);
+
+  // Shrink canvas so browser drops backing store
+  gifCanvas.width = 1;
+  gifCanvas.height = 1;
+
+  // Reset slider + info
+  slider.min = 0;
+  slider.max = 0;
+  slider.value = 0;
+  slider.style.background = "#5e5e5eff";
+  info.textContent = "Frame: 0/0";
+}
+
+// ------------------------- ZOOM AND DRAG ------------------------
+
+function getActiveViewer() {
+  const img = document.getElementById("imgViewer");
+  const canvas = document.getElementById("gifCanvas");
+  //console.log("img",img.classList.contains("hidden"))
+  //console.log("canvas",canvas.classList.contains("hidden"))
+  // the one that is NOT hidden is the active viewer
+  if (!img.classList.contains("hidden")) return img;
+  if (!canvas.classList.contains("hidden")) return canvas;
+
+  return null;
+}
+
+img.addEventListener("load", computeBaseScale);
+
+// Mouse wheel zoom in the middleFrame
+
+imgViewerDiv.addEventListener("wheel", e => {
+  if (inGridMode) return;  // <-- Do not block wheel scroll!
+  //if (!img.src) return;
+  e.preventDefault();
+
+  const rect = imgViewerDiv.getBoundingClientRect();
+
+  // mouse position relative to frame center
+  const mx = e.clientX - rect.left - rect.width / 2;
+  const my = e.clientY - rect.top  - rect.height / 2;
+
+  // image-local coords BEFORE scaling
+  const ix = (mx - translateX) / scale;
+  const iy = (my - translateY) / scale;
+
+  let newScale = scale * (1 - e.deltaY * ZOOM_SPEED);
+  newScale = Math.min(MAX_SCALE, Math.max(MIN_SCALE, newScale));
+
+  if (newScale === scale) return;
+
+  // keep pixel under cursor stable
+  translateX = mx - ix * newScale;
+  translateY = my - iy * newScale;
+
+  scale = newScale;
+  updateTransform();
+}, { passive: false });
+
+// ---Click + drag pan----
+
+img.addEventListener("mousedown", e => {
+  if (e.button !== 0) return;
+  if (scale === 1) return;
+
+  dragging = true;
+  lastX = e.clientX;
+  lastY = e.clientY;
+  img.classList.add("dragging");
+});
+
+window.addEventListener("mousemove", e => {
+  if (!dragging) return;
+
+  translateX += e.clientX - lastX;
+  translateY += e.clientY - lastY;
+
+  lastX = e.clientX;
+  lastY = e.clientY;
+
+  updateTransform();
+});
+
+window.addEventListener("mouseup", () => {
+  dragging = false;
+  img.classList.remove("dragging");
+});
+
+function resetView() {
+  scale = 1;
+  translateX = 0;
+  translateY = 0;
+  updateTransform();
+}
+
+img.addEventListener("load", resetView);
+
+img.addEventListener("load", () => {
+  scale = 1;
+  translateX = 0;
+  translateY = 0;
+  isOriginalSize = false;
+
+  originalBtn.title = "Original Size";
+  originalBtn.innerHTML = originalSizeIcon;
+
+  updateTransform();
+
+  const w = img.naturalWidth;
+  const h = img.naturalHeight;
+  imgLabel.style.display = 'block';
+  imgSize.textContent = `${w} × ${h}`;
+
+});
+
+
+// ------------- ORIGINAL SIZE ---------------------
+
+const originalSizeIcon = `
+<svg class="barIconSvg" viewBox="0 -960 960 960">
+  <path d="M800-640v-80h-80q-17 0-28.5-11.5T680-760q0-17 11.5-28.5T720-800h80q33 0 56.5 23.5T880-720v80q0 17-11.5 28.5T840-600q-17 0-28.5-11.5T800-640Zm-720 0v-80q0-33 23.5-56.5T160-800h80q17 0 28.5 11.5T280-760q0 17-11.5 28.5T240-720h-80v80q0 17-11.5 28.5T120-600q-17 0-28.5-11.5T80-640Zm720 480h-80q-17 0-28.5-11.5T680-200q0-17 11.5-28.5T720-240h80v-80q0-17 11.5-28.5T840-360q17 0 28.5 11.5T880-320v80q0 33-23.5 56.5T800-160Zm-640 0q-33 0-56.5-23.5T80-240v-80q0-17 11.5-28.5T120-360q17 0 28.5 11.5T160-320v80h80q17 0 28.5 11.5T280-200q0 17-11.5 28.5T240-160h-80Zm80-240v-160q0-33 23.5-56.5T320-640h320q33 0 56.5 23.5T720-560v160q0 33-23.5 56.5T640-320H320q-33 0-56.5-23.5T240-400Zm80 0h320v-160H320v160Zm0 0v-160 160Z"/>
+</svg> `;
+
+const fitScreen = `
+<svg class="barIconSvg" viewBox="0 -960 960 960">
+  <path d="M240-240h-80q-17 0-28.5-11.5T120-280q0-17 11.5-28.5T160-320h120q17 0 28.5 11.5T320-280v120q0 17-11.5 28.5T280-120q-17 0-28.5-11.5T240-160v-80Zm480 0v80q0 17-11.5 28.5T680-120q-17 0-28.5-11.5T640-160v-120q0-17 11.5-28.5T680-320h120q17 0 28.5 11.5T840-280q0 17-11.5 28.5T800-240h-80ZM240-720v-80q0-17 11.5-28.5T280-840q17 0 28.5 11.5T320-800v120q0 17-11.5 28.5T280-640H160q-17 0-28.5-11.5T120-680q0-17 11.5-28.5T160-720h80Zm480 0h80q17 0 28.5 11.5T840-680q0 17-11.5 28.5T800-640H680q-17 0-28.5-11.5T640-680v-120q0-17 11.5-28.5T680-840q17 0 28.5 11.5T720-800v80Z"/>
+</svg>`;
+
+originalBtn.innerHTML = originalSizeIcon;
+
+function getOriginalScale() {
+  if (tiled) return 1 / tiledFitScale();
+
+  // rendered size from CSS (fit)
+  const fittedWidth = img.clientWidth;
+  const fittedHeight = img.clientHeight;
+
+  // real image size
+  const naturalWidth = img.naturalWidth;
+  const naturalHeight = img.naturalHeight;
+
+  // scale needed to reach 1:1 pixels
+  return Math.min(
+    naturalWidth / fittedWidth,
+    naturalHeight / fittedHeight
+  );
+}
+/* function getOriginalScale() {
+  const imgVisible = !img.classList.contains("hidden");
+  const gifVisible = !gifCanvas.classList.contains("hidden");
 
   if (imgVisible) {
     // normal image
     const fittedWidth = img.clientWidth;
     const fittedHeight = img.clientHeight;
 
-    const naturalWidth = img.naturalWidth;
-    const naturalHeight = img.naturalHeight;
+    const naturalWidth = img.naturalWidth;
+    const naturalHeight = img.naturalHeight;
+
+    return Math.min(
+      naturalWidth / fittedWidth,
+      naturalHeight / fittedHeight
+    );
+  }
+
+  if (gifVisible && gifReader) {
+    // GIF: compare canvas fit size and gifWidth/gifHeight
+    const fittedWidth = gifCanvas.clientWidth;
+    const fittedHeight = gifCanvas.clientHeight;
+
+    return Math.min(
+      gifWidth / fittedWidth,
+      gifHeight / fittedHeight
+    );
+  }
+
+  return 1;
+} */
+
+originalBtn.addEventListener("click", () => {
+  if (!img.src && !tiled) return;
+
+  if (!isOriginalSize) {
+    // ➜ original size
+    scale = getOriginalScale();
+    translateX = 0;
+    translateY = 0;
+    isOriginalSize = true;
+
+    originalBtn.title = "Fit to window";
+    originalBtn.innerHTML = fitScreen;
+  } 
+  else {
+    // ➜ fit
+    scale = 1;
+    translateX = 0;
+    translateY = 0;
+    isOriginalSize = false;
+
+    originalBtn.title = "Original Size";
+    originalBtn.innerHTML = originalSizeIcon;
+  }
+
+  updateTransform();
+});
+
+// ---------- ZOOM -------------
+
+function zoomCentered(factor) {
+  let newScale = scale * factor;
+  newScale = Math.min(MAX_SCALE, Math.max(MIN_SCALE, newScale));
+
+  if (newScale === scale) return;
+
+  scale = newScale;
+  isOriginalSize = false;
+  originalBtn.title = "Original Size";
+  originalBtn.innerHTML = fitScreen;
+
+  updateTransform();
+}
+
+
+zoomInBtn.addEventListener("click", () => {
+  if (!img.src && !tiled) return;
+  zoomRelative(ZOOM_STEP);
+});
+
+zoomOutBtn.addEventListener("click", () => {
+  if (!img.src && !tiled) return;
+  zoomRelative(1 / ZOOM_STEP);
+});
+
+
+function zoomRelative(factor) {
+  let newScale = scale * factor;
+  newScale = Math.min(MAX_SCALE, Math.max(MIN_SCALE, newScale));
+
+  if (newScale === scale) return;
+
+  const ratio = newScale / scale;
+
+  // 🔑 preserve current view direction
+  translateX *= ratio;
+  translateY *= ratio;
+
+  scale = newScale;
+
+  // exit original-size mode if active
+  if (isOriginalSize) {
+    isOriginalSize = false;
+    originalBtn.title = "Original Size";
+    originalBtn.innerHTML = fitScreen;
+  }
+
+  updateTransform();
+}
+
+// --------------- DELETE BTN -----------------
+
+let deleting = false;
+
+const deleteBtn = document.getElementById("deleteBtn");
+
+deleteBtn.addEventListener("click", async () => {
+  if (!images.length) return;
+  if (inGridMode && gridSelection.size) {
+    await bulkTrash();
+    return;
+  }
+
+  const path = images[index];
+  const fileName = getFileName(path);
+
+  const confirmed = await confirmDlg(`Send "${fileName}" to the Recycle Bin?`);
+  if (!confirmed) return;
+
+  deleting = true; // 🔑 important
+
+  try {
+    await invoke("trash_file", { path });
+    showOpNote(`Moved ${fileName} to the trash. Ctrl+Z to undo.`);
+
+    images.splice(index, 1);
+
+    if (images.length === 0) {
+      clearViewer();
+      return;
+    }
+
+    if (index >= images.length) {
+      index = images.length - 1;
+    }
+
+    await showImage();
+  } catch (err) {
+    console.error("Trash failed:", err);
+  } 
+  finally {
+    deleting = false;
+  }
+});
+
+document.addEventListener("keydown", (e) => {
+  if (confirmDialogOpen) return;
+
+  const tag = e.target.tagName.toLowerCase();
+  if (tag === "input" || tag === "textarea") return;
+
+  if (e.key === "Delete") {
+    e.preventDefault();
+    deleteBtn.click();
+  }
+});
+
+
+
+// IF NO IMAGE LEFT
+function clearViewer() {
+  img.src = "";
+  img.style.transform = "translate(0px, 0px) scale(1)";
+  img.classList.add("hidden");
+  gifCanvas.classList.add("hidden");
+  gifBar.classList.add("hidden");
+  resetTiledUI();
+  refreshMarks(null);
+}
+
+img.addEventListener("error", () => {
+  if (deleting) return;
+
+  if (deleting) {
+    console.log("Ignoring img error during delete");
+    return;
+  }
+
+  console.warn("Image failed to load, skipping");
+
+  if (images.length > 0) {
+    images.splice(index, 1);
+
+    if (index >= images.length) {
+      index = images.length - 1;
+    }
+
+    showImage();
+  } 
+  else {
+    clearViewer();
+  }
+});
+
+document.addEventListener("keydown", (e) => {
+  // Ignore if user is typing in an input / textarea
+  const tag = e.target.tagName;
+  if (tag === "INPUT" || tag === "TEXTAREA") return;
+
+  if (e.key === "Delete") {
+    e.preventDefault();
+    deleteBtn.click();
+  }
+});
+
+// ----------- CONFIRM DLG ---------------
+
+let confirmDialogOpen = false;
+
+function confirmDlg(message) {
+  return new Promise((resolve) => {
+    confirmDialogOpen = true;
+
+    const dlg = document.getElementById('confirmDlg');
+    const text = document.getElementById('confirmText');
+    const ok = document.getElementById('confirmOk');
+    const cancel = document.getElementById('confirmCancel');
+    const closeBtn = document.getElementById('confirmDlgCloseBtn');
+
+    text.textContent = message;
+    dlg.style.display = 'block';
+
+    // 🔑 focus OK button
+    requestAnimationFrame(() => ok.focus());
+
+    let finished = false;
+
+    const cleanup = (result) => {
+      if (finished) return;
+      finished = true;
+
+      confirmDialogOpen = false;
+      dlg.style.display = 'none';
+
+      ok.onclick = null;
+      cancel.onclick = null;
+      closeBtn.onclick = null;
+
+      document.removeEventListener('keydown', keyHandler);
+
+      resolve(result);
+    };
+
+    ok.onclick = () => cleanup(true);
+    cancel.onclick = () => cleanup(false);
+    closeBtn.onclick = () => cleanup(false);
+
+    const keyHandler = (e) => {
+      if (e.key === 'Escape') {
+        e.preventDefault();
+        cleanup(false);
+      }
+
+      if (e.key === 'Enter') {
+        e.preventDefault();
+        cleanup(true);
+      }
+      
+    };
+
+    document.addEventListener('keydown', keyHandler);
+  });
+}
+
+// ------------ ROTATE IMAGE -------------------
+
+const rotateLeftBtn = document.getElementById("rotateLeft");
+const rotateRightBtn = document.getElementById("rotateRight");
+
+function updateTransform() {
+  clampTranslation();
+  img.style.transform = `
+    translate(${translateX}px, ${translateY}px)
+    scale(${scale})
+    rotate(${rotationDegrees}deg)
+  `;
+  let z = getTrueZoomPercent();
+  if (z) {
+    zoomLabel.style.display = 'block';
+    zoomValue.textContent = z + "%";
+  }
+  if (tiled) renderTiles();
+}
+
+function getTrueZoomPercent() {
+  const fittedScale = getOriginalScale();
+  return Math.round((scale / fittedScale) * 100);
+}
+
+function clampTranslation() {
+  const fw = frame.clientWidth;
+  const fh = frame.clientHeight;
+
+  const iw = (tiled ? tiled.width * tiledFitScale() : img.clientWidth) * scale;
+  const ih = (tiled ? tiled.height * tiledFitScale() : img.clientHeight) * scale;
+
+  const maxX = Math.max(0, (iw - fw) / 2);
+  const maxY = Math.max(0, (ih - fh) / 2);
+
+  translateX = Math.min(maxX, Math.max(-maxX, translateX));
+  translateY = Math.min(maxY, Math.max(-maxY, translateY));
+}
+
+function computeBaseScale() {
+  const cw = frame.clientWidth;
+  const ch = frame.clientHeight;
+
+  let iw = img.naturalWidth;
+  let ih = img.naturalHeight;
+
+  if (rotationDegrees % 180 !== 0) {
+    [iw, ih] = [ih, iw];
+  }
+
+  baseScale = Math.min(cw / iw, ch / ih);
+
+  scale = baseScale;
+  translateX = 0;
+  translateY = 0;
+
+  updateTransform();
+}
+
+
+rotateLeftBtn.addEventListener("click", () => {
+  if (!img.src) return;
+
+  rotationDegrees = (rotationDegrees - 90 + 360) % 360;
+
+  // reset pan on rotate (Windows behavior)
+  translateX = 0;
+  translateY = 0;
+
+  computeBaseScale(); // 🔑 refit AFTER rotation
+});
+
+rotateRightBtn.addEventListener("click", () => {
+  if (!img.src) return;
+
+  rotationDegrees = (rotationDegrees + 90) % 360;
+
+  translateX = 0;
+  translateY = 0;
+
+  computeBaseScale();
+});
+
+// ----------- FILE MENU -------------
+
+document.getElementById("fileMenuContextMenu").addEventListener("click", async (e) => {
+  e.stopPropagation(); // ⬅️ THIS IS THE KEY
+
+  const item = e.target.closest(".ctx-item");
+  if (!item) return;
+
+  const action = item.dataset.action;
+  const currentFilePath = images[index];
+  const fileName = getFileName(currentFilePath);
+
+  try {
+      switch (action) {
+        case "openFile":
+          openFileSelect();
+          break;
+
+        case "openFolder":
+          openFolderSelect();
+          break;
+
+        case "save":
+          //await invoke("save_file", { path: currentFilePath });
+          break;
+
+        case "saveAs":
+          //await invoke("save_file_as", { path: currentFilePath });
+          break;
+
+        case "saveCopy":
+          
+          break;
+
+        case "setWallpaper":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          closeAllMenus();
+          const confirmed = await confirmDlg(`Set "${fileName}" as Desktop Background Image?`);
+          if (!confirmed) return;
+          await invoke("set_desktop_background", { path: currentFilePath });
+          break;
+
+        case "openExplorer":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          await invoke("open_in_explorer", { path: currentFilePath });
+          break;
+
+        case "renameFile":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          inputDlg.style.display = 'block';
+          break;
+
+        case "properties":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          await invoke('show_file_properties', { path: currentFilePath });
+          break;
+
+        case "imageInfo":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          await fillImageInfo(currentFilePath);
+          break;
+
+        case "editMetadata":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          await openMetadataEditor(currentFilePath);
+          break;
+
+        case "toggleColorManagement":
+          await toggleColorManagement();
+          break;
+
+        case "colorSettings":
+          openColorDialog();
+          break;
+
+        case "histogram":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          // fills in when ready, the menu shouldn't wait for it
+          openHistogram(currentFilePath);
+          break;
+
+        case "toggleLoupe":
+          toggleLoupe();
+          break;
+
+        case "cycleChannel":
+          cycleChannel();
+          break;
+
+        case "compare":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          closeAllMenus();
+          await chooseCompareFiles(currentFilePath);
+          break;
+
+        case "findDuplicates":
+          openDuplicateFinder(currentFilePath);
+          break;
+
+        case "libraryIndex":
+          openLibraryDialog(currentFilePath);
+          break;
+
+        case "batchConvert":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          openConvertDialog();
+          break;
+
+        case "favoriteFolders":
+          openFavoritesDialog();
+          break;
+
+        case "copyImage":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          await copyImageToClipboard();
+          break;
+
+        case "copyFile":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          await copyFilesToClipboard();
+          break;
+
+        case "pasteImage":
+          await pasteImage();
+          break;
+
+        case "savePasted":
+          await savePastedImage();
+          break;
+
+        case "batchRename":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          openRenameDialog();
+          break;
+
+        case "editTags":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          openTagsEditor();
+          break;
+
+        case "filterMarks":
+          openFilterDialog();
+          break;
+
+        case "toggleSidecars":
+          setWriteSidecars(!writeSidecars);
+          break;
+
+        case "showMap":
+          if (!currentFilePath) {
+            alert("No image loaded.");
+            return;
+          }
+          await openMap(currentFilePath);
+          break;
+      }
+    } 
+    catch (err) {
+      console.error(err);
+    }
+
+  closeAllMenus(); // ✅ use the centralized closer
+});
+
+function closeAllMenus() {
+  document.querySelectorAll(".context-menu2").forEach(menu => {
+    menu.style.display = "none";
+  });
+  currentOpenMenu = null;
+}
+const fileBtn = document.getElementById("fileMenuBtn");
+const fileMenu = document.getElementById("fileMenuContextMenu");
+
+fileBtn.addEventListener("click", (e) => {
+  e.stopPropagation();
+  if (fileMenu.style.display === 'none' || fileMenu.style.display === '') {
+    const rect = fileBtn.getBoundingClientRect();
+    fileMenu.style.left = `${rect.left}px`;
+    fileMenu.style.top = `${rect.bottom + 4}px`;
+    fileMenu.style.display = "block";
+  }
+  else {
+    fileMenu.style.display = 'none';
+  }
+});
+
+// --------- INPUT DLG ----------
+
+const inputDlg = document.getElementById('inputDlg');
+const inputOk = document.getElementById('inputOk');
+const inputCancel = document.getElementById('inputCancel');
+const inputDlgInput = document.getElementById('inputDlgInput');
+const inputDlgCloseBtn = document.getElementById('inputDlgCloseBtn');
+
+inputOk.addEventListener('click', async function (event) {
+  inputDlg.style.display = 'none';
+  const name = inputDlgInput.value.trim();
+  if (name && images[index]) {
+    await renameCurrentFile(name);
+  }
+});
+
+// A taken name is never replaced, keeping both files is offered instead
+async function renameCurrentFile(name, policy) {
+  const path = images[index];
+  try {
+    const renamed = await invoke("rename_file", { path, newName: name, policy });
+    if (!renamed) return;
+
+    images[index] = renamed;
+    thumbCache.delete(path);
+    await showImage();
+    if (inGridMode) populateGrid();
+    showOpNote(`Renamed to ${getFileName(renamed)}. Ctrl+Z to undo.`);
+  } catch (err) {
+    if (err.kind === "exists" && !policy) {
+      const keepBoth = await confirmDlg(`"${getFileName(err.path)}" already exists. Keep both and add a number to the new name?`);
+      if (keepBoth) await renameCurrentFile(name, "auto_suffix");
+      return;
+    }
+    alert(err.message ?? String(err));
+  }
+}
+
+inputDlgCloseBtn.addEventListener('click', function (event) {
+  inputDlg.style.display = 'none';
+});
+
+inputCancel.addEventListener('click', function (event) {
+  inputDlg.style.display = 'none';
+});
+
+// -------------- GRID VIEW -----------
+
+const thumbCache = new Map(); // key: filePath, value: <div class="thumbWrapper">
+
+const gridViewBtn = document.getElementById("gridViewBtn");
+const gridView = document.getElementById("gridView");
+const gridContainer = document.getElementById("gridContainer");
+
+let inGridMode = false;
+
+gridViewBtn.addEventListener("click", () => {
+  if (!images.length) return;
+
+  if (inGridMode) {
+    exitGridMode();
+  } 
+  else {
+    enterGridMode();
+  }
+});
+
+function enterGridMode() {
+  inGridMode = true;
+  // hide single view UI
+  img.classList.add("hidden");
+  gifCanvas.classList.add("hidden");
+  gifBar.classList.add("hidden");
+  icoBar.classList.add("hidden");
+  tileCanvas.classList.add("hidden");
+  loadingText.classList.remove("visible");
+
+  gridView.classList.remove("hidden");
+
+  populateGrid();
+}
+
+async function populateGrid() {
+  gridContainer.innerHTML = "";
+
+  for (let i = 0; i < images.length; i++) {
+    const path = images[i];
+    const ext = getExt(path);
+
+    let wrap;
+
+    // REUSE FROM CACHE IF EXISTS
+    if (thumbCache.has(path)) {
+      wrap = thumbCache.get(path);
+      wrap.dataset.index = i; // keep index updated
+      gridContainer.appendChild(wrap);
+      continue;
+    }
+
+    // CREATE NEW WRAPPER
+    wrap = document.createElement("div");
+    wrap.className = "thumbWrapper";
+    wrap.dataset.index = i;
+
+    const thumb = document.createElement("img");
+    thumb.className = "gridThumb";
+    wrap.appendChild(thumb);
+
+    // LOAD IMAGE (TIFF OR NORMAL)
+    if (ext === "tif" || ext === "tiff") {
+      try {
+        const data = await invoke("load_image", { path });
+        const blob = new Blob([new Uint8Array(data)], { type: "image/png" });
+
+        // Store blob URL so we don't generate it again
+        const blobURL = URL.createObjectURL(blob);
+        thumb.src = blobURL;
+
+        // Cache the blob URL for possible cleanup later
+        wrap.dataset.blob = blobURL;
+
+      } catch (err) {
+        console.error("TIFF thumbnail failed:", err);
+        thumb.src = "";
+      }
+    } 
+    else {
+      thumb.crossOrigin = "anonymous"; // readable for the drag picture
+      thumb.src = fileSrc(path);
+    }
+
+    // SET TITLE (hover tooltip)
+    const fileName = path.split(/[/\\]/).pop();
+    wrap.title = fileName;
+
+    // CLICK HANDLER, Ctrl and Shift select instead of opening
+    wrap.addEventListener("click", e => {
+      if (dragOutClickBlock) return;
+      const at = Number(wrap.dataset.index);
+      if (e.ctrlKey || e.metaKey || e.shiftKey) {
+        selectThumb(at, e.shiftKey, e.ctrlKey || e.metaKey);
+        return;
+      }
+      index = at;
+      exitGridMode();
+      showImage();
+    });
+
+    // DRAG OUT, with the rest of the selection if selected
+    wrap.addEventListener("mousedown", e => {
+      if (e.button !== 0) return;
+      const at = images[Number(wrap.dataset.index)];
+      const paths = gridSelection.has(at) ? selectedPaths() : [at];
+      pressForDragOut(e, paths, thumb);
+    });
+
+    // STORE IN CACHE + APPEND
+    thumbCache.set(path, wrap);
+    gridContainer.appendChild(wrap);
+  }
+
+  // OPTIONAL: clean orphaned cache entries  
+  for (const cachedPath of thumbCache.keys()) {
+    if (!images.includes(cachedPath)) {
+      // release blob URLs if TIFF cached
+      const oldWrap = thumbCache.get(cachedPath);
+      if (oldWrap.dataset.blob) {
+        URL.revokeObjectURL(oldWrap.dataset.blob);
+      }
+      thumbCache.delete(cachedPath);
+    }
+  }
+
+  renderGridSelection();
+}
+
+function exitGridMode() {
+  inGridMode = false;
+  clearGridSelection();
+  gridView.classList.add("hidden");
+  img.classList.remove("hidden");
+  showImage();
+}
+
+document.addEventListener("keydown", e => {
+  if (e.key.toLowerCase() === "g") {
+    gridViewBtn.click();
+  }
+});
+
+document.getElementById("closeImgInfo").onclick =
+document.getElementById("imgInfoCloseBtn").onclick = () => {
+  document.getElementById("imgInfoDlg").style.display = "none";
+};
+
+async function fillImageInfo(path) {
+  const info = await invoke("load_image_metadata", { path });
+
+  document.getElementById("imgInfoDlg").style.display = "block";
+
+  // BASIC INFO
+  document.getElementById("imgInfoFileName").textContent = info.file_name;
+  document.getElementById("imgInfoFormat").textContent = info.format;
+  document.getElementById("imgInfoDimensions").textContent = `${info.width} × ${info.height}`;
+  document.getElementById("imgInfoFileSize").textContent = formatBytes(info.file_size);
+
+  // PIXEL FORMAT
+  document.getElementById("imgInfoColorModel").textContent = info.color_model;
+  document.getElementById("imgInfoChannels").textContent = info.channels;
+  document.getElementById("imgInfoBitsPerChannel").textContent = info.bits_per_channel;
+  document.getElementById("imgInfoAlpha").textContent = info.alpha ? "Yes" : "No";
+
+  // Format specific rows are hidden when they don't apply
+  document.getElementById("imgInfoSubsamplingRow").style.display = info.subsampling ? "" : "none";
+  document.getElementById("imgInfoSubsampling").textContent = info.subsampling || "";
+
+  document.getElementById("imgInfoPaletteRow").style.display = info.palette_size ? "" : "none";
+  document.getElementById("imgInfoPalette").textContent =
+    info.palette_size ? `${info.palette_size} colors` : "";
+
+  document.getElementById("imgInfoQualityRow").style.display = info.jpeg_quality ? "" : "none";
+  document.getElementById("imgInfoQuality").textContent =
+    info.jpeg_quality ? `≈ ${info.jpeg_quality}` : "";
+
+  document.getElementById("imgInfoScan").textContent =
+    info.progressive ? "Progressive" : info.interlaced ? "Interlaced" : "Sequential";
+
+  const ratio = (info.width / info.height).toFixed(3);
+  document.getElementById("imgInfoAspect").textContent = ratio;
+
+  document.getElementById("imgInfoOrientation").textContent =
+    info.width >= info.height ? "Landscape" : "Portrait";
+
+  // FILE SYSTEM METADATA
+  document.getElementById("imgInfoFullPath").textContent = info.full_path;
+  document.getElementById("imgInfoCreated").textContent = formatUnix(info.created);
+  document.getElementById("imgInfoModified").textContent = formatUnix(info.modified);
+  /* document.getElementById("imgInfoReadOnly").textContent = info.read_only ? "Yes" : "No"; */
+
+  // UI-ONLY  
+  /* document.getElementById("imgInfoZoom").textContent = currentZoom + "%";
+  document.getElementById("imgInfoDisplayedRes").textContent = `${displayWidth} × ${displayHeight}`;
+  document.getElementById("imgInfoScaling").textContent = scalingType; */
+
+  // EXIF DATA
+  const exifBlock = document.getElementById("imgExifBlock");
+
+  const hasExif =
+    info.date_taken ||
+    info.camera ||
+    info.aperture ||
+    info.shutter ||
+    info.iso ||
+    info.focal ||
+    info.color_profile;
+
+  if (hasExif) {
+    exifBlock.style.display = "block";
+
+    document.getElementById("imgInfoDateTaken").textContent = info.date_taken || "-";
+    document.getElementById("imgInfoCamera").textContent = info.camera || "-";
+    document.getElementById("imgInfoAperture").textContent = info.aperture || "-";
+    document.getElementById("imgInfoShutter").textContent = info.shutter || "-";
+    document.getElementById("imgInfoISO").textContent = info.iso || "-";
+    document.getElementById("imgInfoFocal").textContent = info.focal || "-";
+    document.getElementById("imgInfoFlash").textContent = info.flash || "-";
+    document.getElementById("imgInfoColorProfile").textContent = info.icc_profile
+      ? `${info.icc_profile.description} (ICC ${info.icc_profile.version}, ${info.icc_profile.color_space})`
+      : info.color_profile || "-";
+    /* document.getElementById("imgInfoDPI").textContent = "N/A"; */
+  } 
+  else {
+    exifBlock.style.display = "none";
+  }
+
+  // GPS
+  const gpsBlock = document.getElementById("imgGpsBlock");
+
+  if (info.gps) {
+    const gps = info.gps;
+    gpsBlock.style.display = "block";
+
+    document.getElementById("imgInfoLocation").textContent = formatLatLon(gps.latitude, gps.longitude);
+    document.getElementById("imgInfoAltitude").textContent =
+      gps.altitude != null ? `${gps.altitude.toFixed(1)} m` : "-";
+    document.getElementById("imgInfoDirection").textContent =
+      gps.direction != null ? `${gps.direction.toFixed(1)}° ${gps.direction_magnetic ? "M" : "T"}` : "-";
+    document.getElementById("imgInfoMapBtn").onclick = () => openMap(path);
+  }
+  else {
+    gpsBlock.style.display = "none";
+  }
+
+  fillMetadataTree(path);
+}
+
+function formatLatLon(lat, lon) {
+  const ns = lat >= 0 ? "N" : "S";
+  const ew = lon >= 0 ? "E" : "W";
+  return `${Math.abs(lat).toFixed(6)}° ${ns}, ${Math.abs(lon).toFixed(6)}° ${ew}`;
+}
+
+// -------------------- METADATA TREE ----------------------
+
+const imgMetaBlock = document.getElementById("imgMetaBlock");
+const imgMetaTree = document.getElementById("imgMetaTree");
+const imgMetaSearch = document.getElementById("imgMetaSearch");
+const imgXmpRawBlock = document.getElementById("imgXmpRawBlock");
+const imgXmpRaw = document.getElementById("imgXmpRaw");
+
+async function fillMetadataTree(path) {
+  imgMetaTree.innerHTML = "";
+  imgMetaSearch.value = "";
+
+  let tree;
+  try {
+    tree = await invoke("load_metadata_tree", { path });
+  } catch (err) {
+    console.error(err);
+    imgMetaBlock.style.display = "none";
+    return;
+  }
+
+  const hasMeta = tree.sections.length > 0;
+  imgMetaBlock.style.display = hasMeta ? "block" : "none";
+
+  tree.sections.forEach(section => imgMetaTree.appendChild(renderMetaNode(section, true)));
+
+  imgXmpRawBlock.style.display = tree.xmp_raw ? "block" : "none";
+  imgXmpRaw.textContent = tree.xmp_raw || "";
+}
+
+function renderMetaNode(node, open = false) {
+  if (node.children.length === 0) {
+    const row = document.createElement("div");
+    row.className = "metaLeaf";
+
+    const name = document.createElement("span");
+    name.className = "metaName";
+    name.textContent = node.name;
+
+    if (node.id) {
+      const id = document.createElement("span");
+      id.className = "metaId";
+      id.textContent = node.id;
+      name.appendChild(id);
+    }
+
+    const value = document.createElement("span");
+    value.className = "metaValue";
+    value.textContent = node.value ?? "";
+
+    row.append(name, value);
+    row.dataset.search = `${node.name} ${node.id || ""} ${node.value ?? ""}`.toLowerCase();
+    return row;
+  }
+
+  const details = document.createElement("details");
+  details.open = open;
+
+  const summary = document.createElement("summary");
+  summary.textContent = node.name;
+  details.appendChild(summary);
+
+  node.children.forEach(child => details.appendChild(renderMetaNode(child)));
+  return details;
+}
+
+// Show only matching rows and open the groups that contain them
+function filterMetaTree(query) {
+  query = query.trim().toLowerCase();
+
+  const visit = el => {
+    if (el.classList.contains("metaLeaf")) {
+      const match = !query || el.dataset.search.includes(query);
+      el.style.display = match ? "" : "none";
+      return match;
+    }
+
+    const titleMatch = query && el.querySelector(":scope > summary").textContent.toLowerCase().includes(query);
+    let any = false;
+    el.querySelectorAll(":scope > .metaLeaf, :scope > details").forEach(child => {
+      if (titleMatch) {
+        child.style.display = "";
+        child.querySelectorAll(".metaLeaf, details").forEach(c => (c.style.display = ""));
+        any = true;
+      } else if (visit(child)) {
+        any = true;
+      }
+    });
+
+    el.style.display = any || !query ? "" : "none";
+    if (query) el.open = any;
+    return any;
+  };
+
+  imgMetaTree.querySelectorAll(":scope > details").forEach(visit);
+}
+
+imgMetaSearch.addEventListener("input", () => filterMetaTree(imgMetaSearch.value));
+
+// Keep typing in the search box from triggering viewer shortcuts
+imgMetaSearch.addEventListener("keydown", e => e.stopPropagation());
+
+function formatBytes(bytes, decimals = 2) {
+  if (bytes === 0) return "0 B";
+  const k = 1024;
+  const dm = decimals < 0 ? 0 : decimals;
+  const sizes = ["B", "KB", "MB", "GB", "TB"];
+  const i = Math.floor(Math.log(bytes) / Math.log(k));
+  return parseFloat((bytes / Math.pow(k, i)).toFixed(dm)) + " " + sizes[i];
+}
+
+function formatUnix(sec) {
+  if (!sec || sec === 0) return "-";
+  return new Date(sec * 1000).toLocaleString();
+}
+
+// -------------------- METADATA EDITING ----------------------
+
+const metaEditDlg = document.getElementById("metaEditDlg");
+const metaEditStatus = document.getElementById("metaEditStatus");
+const metaFields = {
+  title: document.getElementById("metaTitle"),
+  description: document.getElementById("metaDescription"),
+  author: document.getElementById("metaAuthor"),
+  copyright: document.getElementById("metaCopyright"),
+  keywords: document.getElementById("metaKeywords"),
+};
+let metaEditPath = null;
+let metaEditLoaded = null;
+
+async function openMetadataEditor(path) {
+  metaEditPath = path;
+  metaEditStatus.textContent = "";
+
+  try {
+    metaEditLoaded = await invoke("load_editable_metadata", { path });
+  } catch (err) {
+    metaEditLoaded = {};
+    metaEditStatus.textContent = String(err);
+  }
+
+  metaFields.title.value = metaEditLoaded.title || "";
+  metaFields.description.value = metaEditLoaded.description || "";
+  metaFields.author.value = metaEditLoaded.author || "";
+  metaFields.copyright.value = metaEditLoaded.copyright || "";
+  metaFields.keywords.value = (metaEditLoaded.keywords || []).join(", ");
+
+  metaEditDlg.style.display = "flex";
+  metaFields.title.focus();
+}
+
+function closeMetadataEditor() {
+  metaEditDlg.style.display = "none";
+  metaEditPath = null;
+}
+
+// Only fields that were changed are sent, the others are left untouched
+function collectMetadataEdits() {
+  const edits = {};
+
+  ["title", "description", "author", "copyright"].forEach(key => {
+    const value = metaFields[key].value.trim();
+    if (value !== (metaEditLoaded[key] || "")) edits[key] = value;
+  });
+
+  const keywords = metaFields.keywords.value
+    .split(",")
+    .map(k => k.trim())
+    .filter(Boolean);
+  if (keywords.join(",") !== (metaEditLoaded.keywords || []).join(",")) {
+    edits.keywords = keywords;
+  }
+
+  return edits;
+}
+
+async function afterMetadataChange() {
+  // Refresh the info dialog if it shows this file
+  if (document.getElementById("imgInfoDlg").style.display === "block" && metaEditPath === images[index]) {
+    await fillImageInfo(metaEditPath);
+  }
+  // the keywords are the tags
+  if (metaEditPath === images[index]) await refreshMarks(metaEditPath);
+}
+
+document.getElementById("metaSaveBtn").onclick = async () => {
+  if (!metaEditPath) return;
+
+  const edits = collectMetadataEdits();
+  if (Object.keys(edits).length === 0) {
+    closeMetadataEditor();
+    return;
+  }
+
+  try {
+    await invoke("edit_metadata", { path: metaEditPath, edits });
+    await afterMetadataChange();
+    closeMetadataEditor();
+  } catch (err) {
+    metaEditStatus.textContent = String(err);
+  }
+};
+
+document.getElementById("metaStripBtn").onclick = async () => {
+  if (!metaEditPath) return;
+
+  const options = {
+    all: document.getElementById("stripAll").checked,
+    gps: document.getElementById("stripGps").checked,
+    serial: document.getElementById("stripSerial").checked,
+    software: document.getElementById("stripSoftware").checked,
+  };
+
+  if (!options.all && !options.gps && !options.serial && !options.software) {
+    metaEditStatus.textContent = "Nothing selected.";
+    return;
+  }
+
+  const what = options.all ? "all metadata" : "the selected metadata";
+  if (!confirm(`Remove ${what} from this file? This cannot be undone.`)) return;
+
+  try {
+    await invoke("strip_metadata", { path: metaEditPath, options });
+    await afterMetadataChange();
+    await openMetadataEditor(metaEditPath);
+    metaEditStatus.textContent = "Metadata removed.";
+  } catch (err) {
+    metaEditStatus.textContent = String(err);
+  }
+};
+
+document.getElementById("closeMetaEdit").onclick =
+document.getElementById("metaCancelBtn").onclick = closeMetadataEditor;
+
+// Keep typing in the fields from triggering viewer shortcuts
+metaEditDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeMetadataEditor();
+});
+
+// -------------------- MAP ----------------------
+// Equirectangular projection over the bundled world outline, no tiles.
+
+const mapDlg = document.getElementById("mapDlg");
+const mapCanvas = document.getElementById("mapCanvas");
+const mapCtx = mapCanvas.getContext("2d");
+const mapTooltip = document.getElementById("mapTooltip");
+const mapStatus = document.getElementById("mapStatus");
+
+const MAP_MAX_ZOOM = 2000;
+const MAP_MARKER_RADIUS = 5;
+
+let mapPoints = [];
+let mapCurrentPath = null;
+let mapView = { lon: 0, lat: 0, zoom: 1 };
+let mapHover = null;
+
+async function openMap(path) {
+  mapCurrentPath = path;
+  mapPoints = [];
+  mapHover = null;
+  mapTooltip.style.display = "none";
+
+  const folder = path.replace(/[\\/][^\\/]*$/, "");
+  document.getElementById("mapTitle").textContent = `Map — ${folder.split(/[/\\]/).pop() || folder}`;
+  mapDlg.style.display = "flex";
+  mapStatus.textContent = "Reading locations…";
+  resizeMapCanvas();
+
+  try {
+    mapPoints = await invoke("get_geotagged_images", { folder });
+  } catch (err) {
+    mapStatus.textContent = String(err);
+  }
+
+  const current = mapPoints.find(p => p.path === path);
+  if (current) {
+    mapView = { lon: current.position.longitude, lat: current.position.latitude, zoom: 16 };
+  } else {
+    fitMapToPoints();
+  }
+
+  const count = mapPoints.length;
+  mapStatus.textContent =
+    count === 0 ? "No geotagged images in this folder." :
+    `${count} geotagged image${count === 1 ? "" : "s"} in this folder` +
+    (current ? "" : " (this image has no location)");
+
+  drawMap();
+}
+
+function closeMap() {
+  mapDlg.style.display = "none";
+  mapPoints = [];
+}
+
+function fitMapToPoints() {
+  if (mapPoints.length === 0) {
+    mapView = { lon: 0, lat: 20, zoom: 1 };
+    return;
+  }
+
+  const lons = mapPoints.map(p => p.position.longitude);
+  const lats = mapPoints.map(p => p.position.latitude);
+  const minLon = Math.min(...lons), maxLon = Math.max(...lons);
+  const minLat = Math.min(...lats), maxLat = Math.max(...lats);
+
+  // Leave a margin around the points and don't zoom in further than street level
+  const spanLon = Math.max((maxLon - minLon) * 1.4, 0.02);
+  const spanLat = Math.max((maxLat - minLat) * 1.4, 0.02);
+  const { width, height } = mapSize();
+  const zoom = Math.min((360 / spanLon), (360 * height / width) / spanLat, MAP_MAX_ZOOM);
+
+  mapView = { lon: (minLon + maxLon) / 2, lat: (minLat + maxLat) / 2, zoom: Math.max(zoom, 1) };
+}
+
+function mapSize() {
+  return { width: mapCanvas.clientWidth, height: mapCanvas.clientHeight };
+}
+
+function resizeMapCanvas() {
+  const dpr = window.devicePixelRatio || 1;
+  const { width, height } = mapSize();
+  mapCanvas.width = Math.round(width * dpr);
+  mapCanvas.height = Math.round(height * dpr);
+  mapCtx.setTransform(dpr, 0, 0, dpr, 0, 0);
+}
+
+function mapScale() {
+  return (mapSize().width / 360) * mapView.zoom;
+}
+
+function mapProject(lon, lat) {
+  const { width, height } = mapSize();
+  const scale = mapScale();
+  return [
+    (lon - mapView.lon) * scale + width / 2,
+    (mapView.lat - lat) * scale + height / 2,
+  ];
+}
+
+function mapUnproject(x, y) {
+  const { width, height } = mapSize();
+  const scale = mapScale();
+  return [
+    (x - width / 2) / scale + mapView.lon,
+    mapView.lat - (y - height / 2) / scale,
+  ];
+}
+
+function clampMapView() {
+  mapView.zoom = Math.min(Math.max(mapView.zoom, 1), MAP_MAX_ZOOM);
+  mapView.lon = Math.min(Math.max(mapView.lon, -180), 180);
+  mapView.lat = Math.min(Math.max(mapView.lat, -90), 90);
+}
+
+// Rings are filled one at a time so overlapping outlines never cancel out
+function fillRings(rings, fill, stroke) {
+  mapCtx.fillStyle = fill;
+  mapCtx.strokeStyle = stroke;
+  mapCtx.lineWidth = 1;
+
+  rings.forEach(ring => {
+    mapCtx.beginPath();
+    ring.forEach(([lon, lat], i) => {
+      const [x, y] = mapProject(lon, lat);
+      if (i === 0) mapCtx.moveTo(x, y);
+      else mapCtx.lineTo(x, y);
+    });
+    mapCtx.closePath();
+    mapCtx.fill();
+    mapCtx.stroke();
+  });
+}
+
+function drawMap() {
+  const { width, height } = mapSize();
+
+  mapCtx.fillStyle = "#1b2a3a";
+  mapCtx.fillRect(0, 0, width, height);
+
+  // Land, then inland seas on top
+  fillRings(WORLD_OUTLINE, "#3d4b3c", "#6b7d68");
+  fillRings(WORLD_WATER, "#1b2a3a", "#6b7d68");
+
+  drawGraticule();
+
+  // Markers, the current image last so it stays on top
+  const current = mapPoints.find(p => p.path === mapCurrentPath);
+  mapPoints.forEach(p => {
+    if (p !== current) drawMarker(p, p === mapHover ? "#ffd54a" : "#4aa8ff", MAP_MARKER_RADIUS);
+  });
+  if (current) drawMarker(current, "#ff5a4a", MAP_MARKER_RADIUS + 2);
+}
+
+function drawGraticule() {
+  const { width, height } = mapSize();
+  const [west, north] = mapUnproject(0, 0);
+  const [east, south] = mapUnproject(width, height);
+
+  // Keep roughly 80px or more between lines
+  const steps = [30, 10, 5, 1, 0.5, 0.1, 0.05, 0.01];
+  const step = steps.find(s => s * mapScale() < 160) ?? steps[steps.length - 1];
+
+  mapCtx.strokeStyle = "rgba(255, 255, 255, 0.12)";
+  mapCtx.fillStyle = "rgba(255, 255, 255, 0.45)";
+  mapCtx.font = "10px sans-serif";
+  mapCtx.beginPath();
+
+  for (let lon = Math.ceil(Math.max(west, -180) / step) * step; lon <= Math.min(east, 180); lon += step) {
+    const [x] = mapProject(lon, 0);
+    mapCtx.moveTo(x, 0);
+    mapCtx.lineTo(x, height);
+    mapCtx.fillText(`${+lon.toFixed(2)}°`, x + 2, height - 3);
+  }
+  for (let lat = Math.ceil(Math.max(south, -90) / step) * step; lat <= Math.min(north, 90); lat += step) {
+    const [, y] = mapProject(0, lat);
+    mapCtx.moveTo(0, y);
+    mapCtx.lineTo(width, y);
+    mapCtx.fillText(`${+lat.toFixed(2)}°`, 2, y - 2);
+  }
+  mapCtx.stroke();
+}
+
+function drawMarker(point, color, radius) {
+  const { latitude, longitude, direction } = point.position;
+  const [x, y] = mapProject(longitude, latitude);
+
+  // Which way the camera was facing
+  if (direction != null) {
+    const a = (direction - 90) * Math.PI / 180;
+    mapCtx.beginPath();
+    mapCtx.moveTo(x, y);
+    mapCtx.lineTo(x + Math.cos(a) * radius * 3, y + Math.sin(a) * radius * 3);
+    mapCtx.strokeStyle = color;
+    mapCtx.lineWidth = 2;
+    mapCtx.stroke();
+    mapCtx.lineWidth = 1;
+  }
+
+  mapCtx.beginPath();
+  mapCtx.arc(x, y, radius, 0, Math.PI * 2);
+  mapCtx.fillStyle = color;
+  mapCtx.fill();
+  mapCtx.strokeStyle = "#fff";
+  mapCtx.stroke();
+}
+
+function mapPointAt(x, y) {
+  let best = null;
+  let bestDist = (MAP_MARKER_RADIUS + 4) ** 2;
+
+  mapPoints.forEach(p => {
+    const [px, py] = mapProject(p.position.longitude, p.position.latitude);
+    const dist = (px - x) ** 2 + (py - y) ** 2;
+    if (dist <= bestDist) {
+      best = p;
+      bestDist = dist;
+    }
+  });
+  return best;
+}
+
+function mapEventPos(e) {
+  const rect = mapCanvas.getBoundingClientRect();
+  return [e.clientX - rect.left, e.clientY - rect.top];
+}
+
+let mapDrag = null;
+
+mapCanvas.addEventListener("mousedown", e => {
+  const [x, y] = mapEventPos(e);
+  mapDrag = { x, y, lon: mapView.lon, lat: mapView.lat, moved: false };
+  mapCanvas.classList.add("dragging");
+});
+
+document.addEventListener("mousemove", e => {
+  if (mapDlg.style.display !== "flex") return;
+  const [x, y] = mapEventPos(e);
+
+  if (mapDrag) {
+    const dx = x - mapDrag.x;
+    const dy = y - mapDrag.y;
+    if (Math.abs(dx) + Math.abs(dy) > 3) mapDrag.moved = true;
+
+    const scale = mapScale();
+    mapView.lon = mapDrag.lon - dx / scale;
+    mapView.lat = mapDrag.lat + dy / scale;
+    clampMapView();
+    mapTooltip.style.display = "none";
+    drawMap();
+    return;
+  }
+
+  if (e.target !== mapCanvas) return;
+
+  const hover = mapPointAt(x, y);
+  if (hover !== mapHover) {
+    mapHover = hover;
+    drawMap();
+  }
+
+  if (hover) {
+    const { latitude, longitude } = hover.position;
+    mapTooltip.textContent = `${hover.path.split(/[/\\]/).pop()} — ${formatLatLon(latitude, longitude)}`;
+    mapTooltip.style.left = `${x + 12}px`;
+    mapTooltip.style.top = `${y + 12}px`;
+    mapTooltip.style.display = "block";
+    mapCanvas.style.cursor = "pointer";
+  } else {
+    mapTooltip.style.display = "none";
+    mapCanvas.style.cursor = "";
+  }
+});
+
+document.addEventListener("mouseup", async e => {
+  if (!mapDrag) return;
+  const wasClick = !mapDrag.moved;
+  mapDrag = null;
+  mapCanvas.classList.remove("dragging");
+
+  if (!wasClick || e.target !== mapCanvas) return;
+
+  // Clicking a marker opens that image
+  const [x, y] = mapEventPos(e);
+  const point = mapPointAt(x, y);
+  if (!point) return;
+
+  const i = images.indexOf(point.path);
+  if (i === -1) return;
+
+  index = i;
+  mapCurrentPath = point.path;
+  drawMap();
+  await showImage();
+});
+
+mapCanvas.addEventListener("wheel", e => {
+  e.preventDefault();
+  const [x, y] = mapEventPos(e);
+  const [lon, lat] = mapUnproject(x, y);
+
+  // Zoom around the cursor
+  mapView.zoom *= e.deltaY < 0 ? 1.25 : 0.8;
+  clampMapView();
+  const [nx, ny] = mapUnproject(x, y);
+  mapView.lon += lon - nx;
+  mapView.lat += lat - ny;
+  clampMapView();
+  drawMap();
+}, { passive: false });
+
+window.addEventListener("resize", () => {
+  if (mapDlg.style.display !== "flex") return;
+  resizeMapCanvas();
+  drawMap();
+});
+
+document.getElementById("closeMap").onclick = closeMap;
+
+// -------------------- DUPLICATES ----------------------
+
+const dupDlg = document.getElementById("dupDlg");
+const dupFolder = document.getElementById("dupFolder");
+const dupRecursive = document.getElementById("dupRecursive");
+const dupSimilarity = document.getElementById("dupSimilarity");
+const dupScanBtn = document.getElementById("dupScanBtn");
+const dupStatus = document.getElementById("dupStatus");
+const dupResults = document.getElementById("dupResults");
+const dupTrashBtn = document.getElementById("dupTrashBtn");
+
+let dupFolderPath = null;
+let dupScanning = false;
+let dupBlobUrls = [];
+
+listen("duplicate-scan-progress", ({ payload }) => {
+  if (dupScanning) dupStatus.textContent = `Hashing ${payload.done} / ${payload.total}…`;
+});
+
+function openDuplicateFinder(path) {
+  if (path && !dupFolderPath) setDuplicateFolder(path.replace(/[\\/][^\\/]*$/, ""));
+  dupDlg.style.display = "flex";
+}
+
+function closeDuplicateFinder() {
+  dupDlg.style.display = "none";
+}
+
+function setDuplicateFolder(folder) {
+  dupFolderPath = folder;
+  dupFolder.textContent = folder;
+  dupFolder.title = folder;
+}
+
+function clearDuplicateResults() {
+  dupBlobUrls.forEach(url => URL.revokeObjectURL(url));
+  dupBlobUrls = [];
+  dupResults.innerHTML = "";
+  dupTrashBtn.disabled = true;
+}
+
+async function scanDuplicates() {
+  if (!dupFolderPath || dupScanning) return;
+
+  dupScanning = true;
+  dupScanBtn.disabled = true;
+  clearDuplicateResults();
+  dupStatus.textContent = "Listing images…";
+
+  try {
+    const report = await invoke("find_duplicates", {
+      folder: dupFolderPath,
+      recursive: dupRecursive.checked,
+      maxDistance: Number(dupSimilarity.value)
+    });
+
+    const copies = report.groups.reduce((n, g) => n + g.files.length - 1, 0);
+    dupStatus.textContent = report.groups.length
+      ? `${report.groups.length} groups, ${copies} extra copies in ${report.scanned} images`
+      : `No duplicates in ${report.scanned} images`;
+    if (report.undecodable) {
+      dupStatus.textContent += ` (${report.undecodable} compared by content only)`;
+    }
+
+    report.groups.forEach(renderDuplicateGroup);
+    updateDuplicateTrashButton();
+  } catch (err) {
+    dupStatus.textContent = String(err);
+  } finally {
+    dupScanning = false;
+    dupScanBtn.disabled = false;
+  }
+}
+
+function renderDuplicateGroup(group) {
+  const box = document.createElement("div");
+  box.className = "dupGroup";
+
+  const header = document.createElement("div");
+  header.className = "dupGroupHeader";
+  const title = document.createElement("span");
+  title.textContent = `${group.exact ? "Exact copies" : "Similar"} · ${group.files.length} files`;
+  const compareBtn = document.createElement("button");
+  compareBtn.className = "buttonStyle";
+  compareBtn.type = "button";
+  compareBtn.textContent = "Compare";
+  compareBtn.onclick = () => {
+    closeDuplicateFinder();
+    openCompare(group.files.map(f => f.path));
+  };
+  header.append(title, compareBtn);
+  box.appendChild(header);
+
+  group.files.forEach((file, i) => {
+    const row = document.createElement("label");
+    row.className = "dupFile";
+    row.title = file.path;
+
+    const check = document.createElement("input");
+    check.type = "checkbox";
+    check.dataset.path = file.path;
+    check.checked = i !== group.keep;
+    check.onchange = updateDuplicateTrashButton;
+
+    const thumb = document.createElement("img");
+    thumb.className = "dupThumb";
+    thumb.loading = "lazy";
+    setDuplicateThumb(thumb, file.path);
+
+    const info = document.createElement("div");
+    info.className = "dupInfo";
+    const name = document.createElement("div");
+    name.className = "dupName";
+    name.textContent = file.path;
+    const details = document.createElement("div");
+    details.className = "dupDetails";
+    details.textContent = [
+      file.width ? `${file.width} × ${file.height}` : "not decodable",
+      formatBytes(file.size),
+      formatUnix(file.modified)
+    ].join(" · ");
+    info.append(name, details);
+
+    row.append(check, thumb, info);
+
+    if (i === group.keep) {
+      const keep = document.createElement("span");
+      keep.className = "dupKeep";
+      keep.textContent = "Keep";
+      keep.title = "Most pixels, then largest file, then oldest";
+      row.appendChild(keep);
+    }
+
+    box.appendChild(row);
+  });
+
+  dupResults.appendChild(box);
+}
+
+async function setDuplicateThumb(thumb, path) {
+  const ext = getExt(path);
+  if (ext !== "tif" && ext !== "tiff") {
+    thumb.src = convertFileSrc(path);
+    return;
+  }
+
+  try {
+    const url = await decodedImageUrl(path);
+    dupBlobUrls.push(url);
+    thumb.src = url;
+  } catch (err) {
+    console.error("TIFF thumbnail failed:", err);
+  }
+}
+
+function checkedDuplicates() {
+  return [...dupResults.querySelectorAll("input[type=checkbox]:checked")];
+}
+
+function updateDuplicateTrashButton() {
+  const count = checkedDuplicates().length;
+  dupTrashBtn.disabled = count === 0;
+  dupTrashBtn.textContent = count ? `Move ${count} to Trash` : "Move Checked to Trash";
+}
+
+async function trashCheckedDuplicates() {
+  const checks = checkedDuplicates();
+  if (!checks.length) return;
+
+  // trashing every file of a group would lose the image entirely
+  const emptied = [...dupResults.querySelectorAll(".dupGroup")].filter(group =>
+    [...group.querySelectorAll("input[type=checkbox]")].every(c => c.checked)
+  );
+  const warning = emptied.length
+    ? ` ${emptied.length} group${emptied.length === 1 ? "" : "s"} would have no copy left.`
+    : "";
+
+  const confirmed = await confirmDlg(`Move ${checks.length} file${checks.length === 1 ? "" : "s"} to the Trash?${warning}`);
+  if (!confirmed) return;
+
+  const trashed = [];
+  const failed = [];
+
+  for (const check of checks) {
+    const path = check.dataset.path;
+    try {
+      await invoke("trash_file", { path });
+      trashed.push(path);
+      check.closest(".dupFile").remove();
+    } catch (err) {
+      failed.push(`${getFileName(path)}: ${err}`);
+    }
+  }
+
+  // groups with a single file left are no longer duplicates
+  dupResults.querySelectorAll(".dupGroup").forEach(group => {
+    if (group.querySelectorAll(".dupFile").length < 2) group.remove();
+  });
+
+  dupStatus.textContent = `Moved ${trashed.length} file${trashed.length === 1 ? "" : "s"} to the Trash.` +
+    (failed.length ? ` Failed: ${failed.join("; ")}` : "");
+  updateDuplicateTrashButton();
+
+  await forgetRemovedImages(trashed);
+}
+
+// Drop files that are gone from the viewer's list
+async function forgetRemovedImages(paths) {
+  if (!paths.length || !images.length) return;
+
+  const current = images[index];
+  images = images.filter(p => !paths.includes(p));
+
+  if (!images.length) {
+    clearViewer();
+    return;
+  }
+
+  if (paths.includes(current)) {
+    index = Math.min(index, images.length - 1);
+    await showImage();
+  } else {
+    index = images.indexOf(current);
+    imgAmount.textContent = `${index + 1}/${images.length}`;
+  }
+}
+
+async function browseDuplicateFolder() {
+  const selected = await open({ directory: true, multiple: false, defaultPath: dupFolderPath || undefined });
+  if (!selected) return;
+
+  setDuplicateFolder(Array.isArray(selected) ? selected[0] : selected);
+  clearDuplicateResults();
+  dupStatus.textContent = "";
+}
+
+dupScanBtn.onclick = scanDuplicates;
+dupTrashBtn.onclick = trashCheckedDuplicates;
+document.getElementById("dupBrowseBtn").onclick = browseDuplicateFolder;
+
+document.getElementById("closeDup").onclick =
+document.getElementById("dupCloseBtn").onclick = closeDuplicateFinder;
+
+dupDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeDuplicateFinder();
+});
+
+// -------------------- LIBRARY INDEX ----------------------
+
+// The index (library.rs) fills itself as folders are searched, filtered
+// or scanned for duplicates; indexing ahead makes the first search of a
+// large library fast too.
+
+const libraryDlg = document.getElementById("libraryDlg");
+const libraryStats = document.getElementById("libraryStats");
+const libraryFolder = document.getElementById("libraryFolder");
+const libraryRecursive = document.getElementById("libraryRecursive");
+const libraryHashes = document.getElementById("libraryHashes");
+const libraryIndexBtn = document.getElementById("libraryIndexBtn");
+const libraryStatus = document.getElementById("libraryStatus");
+
+let libraryFolderPath = null;
+let libraryIndexing = false;
+
+listen("library-index-progress", ({ payload }) => {
+  if (libraryIndexing) libraryStatus.textContent = `Reading ${payload.done} / ${payload.total}…`;
+});
+
+async function openLibraryDialog(path) {
+  if (path && !libraryFolderPath) setLibraryFolder(path.replace(/[\\/][^\\/]*$/, ""));
+  libraryDlg.style.display = "flex";
+  await refreshLibraryStats();
+}
+
+function closeLibraryDialog() {
+  libraryDlg.style.display = "none";
+}
+
+function setLibraryFolder(folder) {
+  libraryFolderPath = folder;
+  libraryFolder.textContent = folder;
+  libraryFolder.title = folder;
+}
+
+async function refreshLibraryStats() {
+  try {
+    const info = await invoke("get_library_info");
+    libraryStats.textContent = info.available
+      ? `${info.images} images in ${info.folders} folders · ${formatBytes(info.size)}`
+      : "The index couldn't be opened, folders are read directly.";
+    libraryStats.title = info.path || "";
+    libraryIndexBtn.disabled = !info.available;
+  } catch (err) {
+    libraryStats.textContent = String(err);
+  }
+}
+
+async function indexLibraryFolder() {
+  if (!libraryFolderPath || libraryIndexing) return;
+
+  libraryIndexing = true;
+  libraryIndexBtn.disabled = true;
+  libraryStatus.textContent = "Listing images…";
+
+  try {
+    const read = await invoke("index_folder", {
+      folder: libraryFolderPath,
+      recursive: libraryRecursive.checked,
+      hashes: libraryHashes.checked
+    });
+    libraryStatus.textContent = read
+      ? `Indexed ${read} new or changed image${read === 1 ? "" : "s"}.`
+      : "The index is up to date.";
+  } catch (err) {
+    libraryStatus.textContent = String(err);
+  } finally {
+    libraryIndexing = false;
+    libraryIndexBtn.disabled = false;
+    await refreshLibraryStats();
+  }
+}
+
+async function clearLibraryIndex() {
+  if (libraryIndexing) return;
+  const confirmed = await confirmDlg("Clear the library index? It is rebuilt as folders are opened.");
+  if (!confirmed) return;
+
+  try {
+    await invoke("clear_library_index");
+    libraryStatus.textContent = "Index cleared.";
+  } catch (err) {
+    libraryStatus.textContent = String(err);
+  }
+  await refreshLibraryStats();
+}
+
+async function browseLibraryFolder() {
+  const selected = await open({ directory: true, multiple: false, defaultPath: libraryFolderPath || undefined });
+  if (!selected) return;
+
+  setLibraryFolder(Array.isArray(selected) ? selected[0] : selected);
+  libraryStatus.textContent = "";
+}
+
+libraryIndexBtn.onclick = indexLibraryFolder;
+document.getElementById("libraryClearBtn").onclick = clearLibraryIndex;
+document.getElementById("libraryBrowseBtn").onclick = browseLibraryFolder;
+
+document.getElementById("closeLibrary").onclick =
+document.getElementById("libraryCloseBtn").onclick = closeLibraryDialog;
+
+libraryDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeLibraryDialog();
+});
+
+// -------------------- BATCH CONVERT ----------------------
+
+const convertDlg = document.getElementById("convertDlg");
+const convertScope = document.getElementById("convertScope");
+const convertFormat = document.getElementById("convertFormat");
+const convertQuality = document.getElementById("convertQuality");
+const convertFolder = document.getElementById("convertFolder");
+const convertStatus = document.getElementById("convertStatus");
+const convertErrors = document.getElementById("convertErrors");
+const convertStartBtn = document.getElementById("convertStartBtn");
+const convertStopBtn = document.getElementById("convertStopBtn");
+
+let convertFolderPath = null;
+let converting = false;
+
+listen("batch-convert-progress", ({ payload }) => {
+  if (converting) convertStatus.textContent = `Converting ${payload.done} / ${payload.total}…`;
+});
+
+function openConvertDialog() {
+  const current = images[index];
+  if (current && !convertFolderPath) {
+    const folder = current.replace(/[\\/][^\\/]*$/, "");
+    const sep = current.includes("\\") ? "\\" : "/";
+    setConvertFolder(folder + sep + "converted");
+  }
+
+  updateScopeChoice(convertScope);
+  convertQuality.disabled = convertFormat.value !== "jpg";
+  convertDlg.style.display = "flex";
+}
+
+function closeConvertDialog() {
+  convertDlg.style.display = "none";
+}
+
+function setConvertFolder(folder) {
+  convertFolderPath = folder;
+  convertFolder.textContent = folder;
+  convertFolder.title = folder;
+}
 
-    return Math.min(
-      naturalWidth / fittedWidth,
-      naturalHeight / fittedHeight
-    );
+// Reread the folder list, after files were added to or removed from it
+async function reloadFolderList() {
+  const current = images[index];
+  if (!current) return;
+
+  const [list, startIndex] = await folderImages(current);
+  images = list;
+  index = startIndex;
+  imgAmount.textContent = `${index + 1}/${images.length}`;
+  if (inGridMode) populateGrid();
+}
+
+async function startConversion() {
+  if (converting || !convertFolderPath) return;
+
+  const paths = scopePaths(convertScope.value);
+  if (!paths.length || !paths[0]) return;
+
+  const size = input => input.value ? Math.max(1, Math.round(Number(input.value))) : null;
+  const options = {
+    format: convertFormat.value,
+    quality: Math.min(100, Math.max(1, Number(convertQuality.value) || 90)),
+    max_width: size(document.getElementById("convertMaxWidth")),
+    max_height: size(document.getElementById("convertMaxHeight")),
+    strip_metadata: document.getElementById("convertStrip").checked,
+    output_dir: convertFolderPath,
+    template: document.getElementById("convertTemplate").value.trim() || "{name}",
+    overwrite: document.getElementById("convertOverwrite").checked
+  };
+
+  converting = true;
+  convertStartBtn.disabled = true;
+  convertStopBtn.disabled = false;
+  convertErrors.textContent = "";
+  convertStatus.textContent = `Converting 0 / ${paths.length}…`;
+
+  try {
+    const report = await invoke("batch_convert", { paths, options });
+
+    convertStatus.textContent = `Converted ${report.converted.length} of ${paths.length} images.` +
+      (report.cancelled ? ` Stopped, ${report.cancelled} not converted.` : "") +
+      (report.failed.length ? ` ${report.failed.length} failed:` : "");
+    convertErrors.textContent = report.failed
+      .map(f => `${getFileName(f.path)}: ${f.error}`)
+      .join("\n");
+
+    // new files in the folder being viewed
+    const folder = images[index]?.replace(/[\\/][^\\/]*$/, "");
+    if (report.converted.length && folder === convertFolderPath) await reloadFolderList();
+  } catch (err) {
+    convertStatus.textContent = String(err);
+  } finally {
+    converting = false;
+    convertStartBtn.disabled = false;
+    convertStopBtn.disabled = true;
   }
+}
 
-  if (gifVisible && gifReader) {
-    // GIF: compare canvas fit size and gifWidth/gifHeight
-    const fittedWidth = gifCanvas.clientWidth;
-    const fittedHeight = gifCanvas.clientHeight;
+async function browseConvertFolder() {
+  const selected = await open({ directory: true, multiple: false, defaultPath: convertFolderPath || undefined });
+  if (!selected) return;
+  setConvertFolder(Array.isArray(selected) ? selected[0] : selected);
+}
 
-    return Math.min(
-      gifWidth / fittedWidth,
-      gifHeight / fittedHeight
-    );
+convertFormat.onchange = () => {
+  convertQuality.disabled = convertFormat.value !== "jpg";
+};
+
+convertStartBtn.onclick = startConversion;
+convertStopBtn.onclick = () => invoke("cancel_batch_convert");
+document.getElementById("convertBrowseBtn").onclick = browseConvertFolder;
+
+document.getElementById("closeConvert").onclick =
+document.getElementById("convertCloseBtn").onclick = closeConvertDialog;
+
+convertDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeConvertDialog();
+});
+
+// -------------------- BATCH RENAME ----------------------
+
+// The preview is the plan of rename.rs, redone as the options change. The
+// Rename button only works when no file has a problem.
+
+const renameDlg = document.getElementById("renameDlg");
+const renameScope = document.getElementById("renameScope");
+const renamePreview = document.getElementById("renamePreview");
+const renameStatus = document.getElementById("renameStatus");
+const renameStartBtn = document.getElementById("renameStartBtn");
+
+let renamePlanId = 0;
+let renamePlanTimer = null;
+let renaming = false;
+
+function openRenameDialog() {
+  updateScopeChoice(renameScope);
+  renameDlg.style.display = "flex";
+  planRename();
+}
+
+function closeRenameDialog() {
+  renameDlg.style.display = "none";
+  renamePreview.replaceChildren();
+  renameStatus.textContent = "";
+}
+
+function renamePaths() {
+  return scopePaths(renameScope.value);
+}
+
+function renameOptions() {
+  return {
+    template: document.getElementById("renameTemplate").value || "{name}",
+    find: document.getElementById("renameFind").value,
+    replace: document.getElementById("renameReplace").value,
+    case: document.getElementById("renameCase").value,
+    lower_ext: document.getElementById("renameLowerExt").checked,
+    start: Math.max(0, Math.round(Number(document.getElementById("renameStart").value) || 0))
+  };
+}
+
+function schedulePlanRename() {
+  clearTimeout(renamePlanTimer);
+  renamePlanTimer = setTimeout(planRename, 200);
+}
+
+async function planRename() {
+  const id = ++renamePlanId;
+  const paths = renamePaths();
+  renameStartBtn.disabled = true;
+  if (!paths.length || !paths[0]) return;
+
+  let planned;
+  try {
+    planned = await invoke("plan_batch_rename", { paths, options: renameOptions() });
+  } catch (err) {
+    if (id !== renamePlanId) return;
+    renamePreview.replaceChildren();
+    renameStatus.textContent = String(err);
+    return;
   }
+  if (id !== renamePlanId) return; // options changed meanwhile
+
+  const rows = planned.map(p => {
+    const row = document.createElement("div");
+    row.className = "renameRow" + (p.from === p.to ? " unchanged" : "");
+
+    const from = document.createElement("span");
+    from.textContent = getFileName(p.from);
+    const arrow = document.createElement("span");
+    arrow.textContent = "→";
+    const to = document.createElement("span");
+    to.textContent = getFileName(p.to);
+    row.append(from, arrow, to);
+
+    if (p.error) {
+      const error = document.createElement("span");
+      error.className = "renameError";
+      error.textContent = p.error;
+      row.append(error);
+    }
+    return row;
+  });
+  renamePreview.replaceChildren(...rows);
+
+  const blocked = planned.filter(p => p.error).length;
+  const changed = planned.filter(p => p.from !== p.to).length;
+  renameStatus.textContent = blocked
+    ? `${blocked} of ${planned.length} files can't be renamed.`
+    : `${changed} of ${planned.length} files get a new name.`;
+  renameStartBtn.disabled = renaming || blocked > 0 || changed === 0;
+}
 
-  return 1;
-} */
+async function startRename() {
+  if (renaming) return;
+  const paths = renamePaths();
+  if (!paths.length || !paths[0]) return;
 
-originalBtn.addEventListener("click", () => {
-  if (!img.src) return;
+  renaming = true;
+  renameStartBtn.disabled = true;
+  renameStatus.textContent = "Renaming…";
 
-  if (!isOriginalSize) {
-    // ➜ original size
-    scale = getOriginalScale();
-    translateX = 0;
-    translateY = 0;
-    isOriginalSize = true;
+  try {
+    const renamed = await invoke("batch_rename", { paths, options: renameOptions() });
 
-    originalBtn.title = "Fit to window";
-    originalBtn.innerHTML = fitScreen;
-  } 
-  else {
-    // ➜ fit
-    scale = 1;
-    translateX = 0;
-    translateY = 0;
-    isOriginalSize = false;
+    const moved = new Map(renamed);
+    images = images.map(p => moved.get(p) ?? p);
+    for (const [old, renamedTo] of moved) {
+      thumbCache.delete(old);
+      if (gridSelection.delete(old)) gridSelection.add(renamedTo);
+    }
 
-    originalBtn.title = "Original Size";
-    originalBtn.innerHTML = originalSizeIcon;
+    await showImage();
+    if (inGridMode) populateGrid();
+    renameStatus.textContent = `Renamed ${renamed.length} files. Ctrl+Z to undo.`;
+  } catch (err) {
+    renameStatus.textContent = String(err);
+  } finally {
+    renaming = false;
   }
+  await planRename();
+}
 
-  updateTransform();
+for (const id of ["renameTemplate", "renameFind", "renameReplace", "renameStart"]) {
+  document.getElementById(id).addEventListener("input", schedulePlanRename);
+}
+for (const id of ["renameScope", "renameCase", "renameLowerExt"]) {
+  document.getElementById(id).addEventListener("change", planRename);
+}
+
+renameStartBtn.onclick = startRename;
+
+document.getElementById("closeRename").onclick =
+document.getElementById("renameCloseBtn").onclick = closeRenameDialog;
+
+renameDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeRenameDialog();
 });
 
-// ---------- ZOOM -------------
+// -------------------- UNDO / REDO ----------------------
 
-function zoomCentered(factor) {
-  let newScale = scale * factor;
-  newScale = Math.min(MAX_SCALE, Math.max(MIN_SCALE, newScale));
+// Trash, rename and copy are journaled by the backend (journal.rs), across
+// restarts. Ctrl+Z undoes the last one, Ctrl+Y or Ctrl+Shift+Z redoes it.
 
-  if (newScale === scale) return;
+const opNote = document.getElementById("opNote");
+let opNoteTimer = null;
 
-  scale = newScale;
-  isOriginalSize = false;
-  originalBtn.title = "Original Size";
-  originalBtn.innerHTML = fitScreen;
+function showOpNote(text) {
+  opNote.textContent = text;
+  opNote.title = text;
+  clearTimeout(opNoteTimer);
+  opNoteTimer = setTimeout(() => { opNote.textContent = ""; }, 5000);
+}
 
-  updateTransform();
+// Show a file that came back, within its folder
+async function showRestoredImage(path) {
+  const [list, startIndex] = await folderImages(path);
+  images = list;
+  index = startIndex;
+  await showImage();
+  if (inGridMode) populateGrid();
 }
 
+// Told once, it likely fails the same way on every step
+let journalErrorShown = false;
 
-zoomInBtn.addEventListener("click", () => {
-  if (!img.src) return;
-  zoomRelative(ZOOM_STEP);
+listen("journal-error", ({ payload }) => {
+  if (journalErrorShown) return;
+  journalErrorShown = true;
+  alert(payload);
 });
 
-zoomOutBtn.addEventListener("click", () => {
-  if (!img.src) return;
-  zoomRelative(1 / ZOOM_STEP);
-});
+async function stepJournal(command) {
+  let applied;
+  try {
+    applied = await invoke(command);
+  } catch (err) {
+    showOpNote(String(err));
+    return;
+  }
+  if (!applied) {
+    showOpNote(command === "undo_file_operation" ? "Nothing to undo." : "Nothing to redo.");
+    return;
+  }
 
+  for (const path of applied.removed) thumbCache.delete(path);
+  if (applied.added.length) await showRestoredImage(applied.added[0]);
+  else await forgetRemovedImages(applied.removed);
+  showOpNote(applied.message);
+}
 
-function zoomRelative(factor) {
-  let newScale = scale * factor;
-  newScale = Math.min(MAX_SCALE, Math.max(MIN_SCALE, newScale));
+document.addEventListener("keydown", e => {
+  if (confirmDialogOpen) return;
+  if (e.target.closest("input, textarea, select")) return;
+  if (!(e.ctrlKey || e.metaKey) || e.altKey) return;
 
-  if (newScale === scale) return;
+  const key = e.key.toLowerCase();
+  if (key === "z" && !e.shiftKey) {
+    e.preventDefault();
+    stepJournal("undo_file_operation");
+  }
+  else if (key === "y" || (key === "z" && e.shiftKey)) {
+    e.preventDefault();
+    stepJournal("redo_file_operation");
+  }
+});
 
-  const ratio = newScale / scale;
+// -------------------- FAVORITE FOLDERS ----------------------
 
-  // 🔑 preserve current view direction
-  translateX *= ratio;
-  translateY *= ratio;
+// Nine folders on Shift+1…9, each set to move or copy. Sending the current
+// image there goes on to the next one, for culling a folder by keyboard.
 
-  scale = newScale;
+const favoritesDlg = document.getElementById("favoritesDlg");
+const favoritesList = document.getElementById("favoritesList");
 
-  // exit original-size mode if active
-  if (isOriginalSize) {
-    isOriginalSize = false;
-    originalBtn.title = "Original Size";
-    originalBtn.innerHTML = fitScreen;
+let favoriteFolders = loadFavoriteFolders();
+let sendingToFavorite = false;
+
+function loadFavoriteFolders() {
+  let saved = [];
+  try {
+    saved = JSON.parse(localStorage.getItem("favoriteFolders")) || [];
+  } catch (err) {
+    console.error("Favorite folders:", err);
   }
+  return Array.from({ length: 9 }, (_, i) => ({ path: saved[i]?.path || null, copy: !!saved[i]?.copy }));
+}
 
-  updateTransform();
+function saveFavoriteFolders() {
+  localStorage.setItem("favoriteFolders", JSON.stringify(favoriteFolders));
 }
 
-// --------------- DELETE BTN -----------------
+function renderFavoriteFolders() {
+  const rows = favoriteFolders.map((fav, i) => {
+    const row = document.createElement("div");
+    row.className = "favoriteRow";
+
+    const key = document.createElement("span");
+    key.className = "favoriteKey";
+    key.textContent = `Shift+${i + 1}`;
+
+    const path = document.createElement("span");
+    path.className = "favoritePath";
+    path.textContent = fav.path || "Not set";
+    path.title = fav.path || "";
+
+    const mode = document.createElement("select");
+    mode.className = "inputDlgInput";
+    mode.innerHTML = `<option value="move">Move</option><option value="copy">Copy</option>`;
+    mode.value = fav.copy ? "copy" : "move";
+    mode.onchange = () => {
+      fav.copy = mode.value === "copy";
+      saveFavoriteFolders();
+    };
 
-let deleting = false;
+    const browse = document.createElement("button");
+    browse.className = "buttonStyle";
+    browse.type = "button";
+    browse.textContent = "Browse…";
+    browse.onclick = async () => {
+      const selected = await open({ directory: true, multiple: false, defaultPath: fav.path || undefined });
+      if (!selected) return;
+      fav.path = Array.isArray(selected) ? selected[0] : selected;
+      saveFavoriteFolders();
+      renderFavoriteFolders();
+    };
 
-const deleteBtn = document.getElementById("deleteBtn");
+    const clear = document.createElement("button");
+    clear.className = "buttonStyle";
+    clear.type = "button";
+    clear.textContent = "Clear";
+    clear.disabled = !fav.path;
+    clear.onclick = () => {
+      fav.path = null;
+      saveFavoriteFolders();
+      renderFavoriteFolders();
+    };
 
-deleteBtn.addEventListener("click", async () => {
-  if (!images.length) return;
+    row.append(key, path, mode, browse, clear);
+    return row;
+  });
+  favoritesList.replaceChildren(...rows);
+}
 
-  const path = images[index];
-  const fileName = getFileName(path);
+function openFavoritesDialog() {
+  renderFavoriteFolders();
+  favoritesDlg.style.display = "flex";
+}
 
-  const confirmed = await confirmDlg(`Send "${fileName}" to the Recycle Bin?`);
-  if (!confirmed) return;
+function closeFavoritesDialog() {
+  favoritesDlg.style.display = "none";
+}
 
-  deleting = true; // 🔑 important
+async function sendToFavorite(slot, policy) {
+  const fav = favoriteFolders[slot];
+  const path = images[index];
+  if (!path) return;
+  if (!fav.path) {
+    showOpNote(`No folder on Shift+${slot + 1}, see File > Favorite Folders.`);
+    return;
+  }
 
+  let sent;
   try {
-    await invoke("trash_file", { path });
-
-    images.splice(index, 1);
-
-    if (images.length === 0) {
-      clearViewer();
+    sent = await invoke("send_to_folder", { path, folder: fav.path, copy: fav.copy, policy });
+  } catch (err) {
+    if (err.kind === "exists" && !policy) {
+      const keepBoth = await confirmDlg(`"${getFileName(err.path)}" is already in ${fav.path}. Keep both and add a number to the name?`);
+      if (keepBoth) await sendToFavorite(slot, "auto_suffix");
       return;
     }
+    showOpNote(err.message ?? String(err));
+    return;
+  }
+  if (!sent) return;
+  if (sent.path === path) {
+    showOpNote(`${getFileName(path)} is already in ${fav.path}.`);
+    return;
+  }
 
-    if (index >= images.length) {
-      index = images.length - 1;
+  showOpNote(`${fav.copy ? "Copied" : "Moved"} ${getFileName(path)} to ${fav.path}. Ctrl+Z to undo.`);
+  if (sent.warning) alert(sent.warning);
+
+  if (fav.copy) {
+    if (index < images.length - 1) {
+      index++;
+      await showImage();
     }
+    return;
+  }
 
-    await showImage();
-  } catch (err) {
-    console.error("Trash failed:", err);
-  } 
-  finally {
-    deleting = false;
+  // moved out of the folder, the next image takes its place
+  thumbCache.delete(path);
+  images.splice(index, 1);
+  if (!images.length) {
+    clearViewer();
+    return;
   }
-});
+  index = Math.min(index, images.length - 1);
+  await showImage();
+  if (inGridMode) populateGrid();
+}
 
-document.addEventListener("keydown", (e) => {
-  if (confirmDialogOpen) return;
+document.addEventListener("keydown", async e => {
+  if (confirmDialogOpen || sendingToFavorite) return;
+  if (e.target.closest("input, textarea, select")) return;
+  if (!e.shiftKey || e.ctrlKey || e.metaKey || e.altKey) return;
 
-  const tag = e.target.tagName.toLowerCase();
-  if (tag === "input" || tag === "textarea") return;
+  const digit = /^Digit([1-9])$/.exec(e.code);
+  if (!digit) return;
 
-  if (e.key === "Delete") {
-    e.preventDefault();
-    deleteBtn.click();
+  e.preventDefault();
+  sendingToFavorite = true;
+  try {
+    const slot = Number(digit[1]) - 1;
+    const fav = favoriteFolders[slot];
+    // a grid selection goes as a whole
+    if (inGridMode && gridSelection.size && fav.path) await bulkSend(fav.copy, fav.path);
+    else await sendToFavorite(slot);
+  } finally {
+    sendingToFavorite = false;
   }
 });
 
+document.getElementById("closeFavorites").onclick =
+document.getElementById("favoritesCloseBtn").onclick = closeFavoritesDialog;
 
+favoritesDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeFavoritesDialog();
+});
 
-// IF NO IMAGE LEFT
-function clearViewer() {
-  img.src = "";
-  img.style.transform = "translate(0px, 0px) scale(1)";
-  img.classList.add("hidden");
-  gifCanvas.classList.add("hidden");
-  gifBar.classList.add("hidden");
+// -------------------- GRID SELECTION ----------------------
+
+// Ctrl+click picks single thumbnails, Shift+click a range from the last
+// one picked, Ctrl+A all of them. The bar above the grid runs bulk.rs on
+// the selection, each file on its own, and lists the ones that failed.
+
+const gridBulkBar = document.getElementById("gridBulkBar");
+const gridSelCount = document.getElementById("gridSelCount");
+const bulkMarksDlg = document.getElementById("bulkMarksDlg");
+const bulkMarksStatus = document.getElementById("bulkMarksStatus");
+
+const gridSelection = new Set(); // paths
+let gridAnchor = null;
+let bulkBusy = false;
+let bulkLabel = "";
+
+listen("bulk-progress", ({ payload }) => {
+  if (bulkBusy) showOpNote(`${bulkLabel} ${payload.done} / ${payload.total}…`);
+});
+
+function selectThumb(at, range, add) {
+  const path = images[at];
+  const anchor = images.indexOf(gridAnchor);
+
+  if (range && anchor !== -1) {
+    if (!add) gridSelection.clear();
+    for (let i = Math.min(anchor, at); i <= Math.max(anchor, at); i++) gridSelection.add(images[i]);
+  } else {
+    if (!gridSelection.delete(path)) gridSelection.add(path);
+    gridAnchor = path;
+  }
+  renderGridSelection();
 }
 
-img.addEventListener("error", () => {
-  if (deleting) return;
+function clearGridSelection() {
+  gridSelection.clear();
+  gridAnchor = null;
+  renderGridSelection();
+}
 
-  if (deleting) {
-    console.log("Ignoring img error during delete");
-    return;
+// Also forgets selected files that left the folder list
+function renderGridSelection() {
+  const listed = new Set(images);
+  for (const path of gridSelection) {
+    if (!listed.has(path)) gridSelection.delete(path);
+  }
+  for (const [path, wrap] of thumbCache) {
+    wrap.classList.toggle("selected", gridSelection.has(path));
   }
 
-  console.warn("Image failed to load, skipping");
+  const count = gridSelection.size;
+  gridBulkBar.classList.toggle("hidden", !count);
+  gridSelCount.textContent = `${count} selected`;
+}
 
-  if (images.length > 0) {
-    images.splice(index, 1);
+// In folder list order
+function selectedPaths() {
+  return images.filter(p => gridSelection.has(p));
+}
 
-    if (index >= images.length) {
-      index = images.length - 1;
-    }
+// The images an "Images:" choice of a dialog stands for
+function scopePaths(scope) {
+  if (scope === "current") return [images[index]];
+  if (scope === "selection") return selectedPaths();
+  return [...images];
+}
+
+function updateScopeChoice(select) {
+  const selection = select.querySelector('option[value="selection"]');
+  select.options[0].textContent = `Folder list (${images.length})`;
+  selection.textContent = `Selected images (${gridSelection.size})`;
+  selection.hidden = selection.disabled = !gridSelection.size;
+  if (selection.disabled && select.value === "selection") select.value = "folder";
+}
+
+function forgetThumb(path) {
+  const wrap = thumbCache.get(path);
+  if (wrap?.dataset.blob) URL.revokeObjectURL(wrap.dataset.blob);
+  thumbCache.delete(path);
+}
+
+async function runBulk(label, command, paths, args = {}) {
+  if (bulkBusy || !paths.length) return null;
+
+  bulkBusy = true;
+  bulkLabel = label;
+  for (const button of gridBulkBar.querySelectorAll("button")) button.disabled = true;
+  showOpNote(`${label} 0 / ${paths.length}…`);
+
+  try {
+    return await invoke(command, { paths, ...args });
+  } catch (err) {
+    showOpNote(String(err));
+    return null;
+  } finally {
+    bulkBusy = false;
+    for (const button of gridBulkBar.querySelectorAll("button")) button.disabled = false;
+  }
+}
+
+// Failures and warnings, like sidecars left behind, are listed together
+function reportBulk(note, failed, warned = []) {
+  showOpNote(failed.length ? `${note} ${failed.length} failed.` : note);
+  const listed = [
+    ...failed.map(f => `${getFileName(f.path)}: ${f.error}`),
+    ...warned.map(f => `${getFileName(f.path)}: ${f.warning}`),
+  ];
+  if (listed.length) alert(listed.join("\n"));
+}
+
+async function bulkTrash() {
+  const paths = selectedPaths();
+  if (!paths.length || bulkBusy) return;
+  if (!await confirmDlg(`Send ${paths.length} selected images to the Recycle Bin?`)) return;
+
+  deleting = true;
+  try {
+    const results = await runBulk("Trashing", "bulk_trash", paths);
+    if (!results) return;
+
+    const trashed = results.filter(r => !r.error).map(r => r.path);
+    trashed.forEach(forgetThumb);
+    await forgetRemovedImages(trashed);
+    if (inGridMode) populateGrid();
+    reportBulk(`Moved ${trashed.length} images to the trash. Ctrl+Z to undo.`, results.filter(r => r.error));
+  } finally {
+    deleting = false;
+  }
+}
+
+// Move or copy the selection, or `paths`, into `folder`
+async function bulkSend(copy, folder, paths = selectedPaths(), policy) {
+  if (!folder) {
+    const selected = await open({ directory: true, multiple: false });
+    if (!selected) return;
+    folder = Array.isArray(selected) ? selected[0] : selected;
+  }
+
+  const results = await runBulk(copy ? "Copying" : "Moving", "bulk_send", paths, { folder, copy, policy });
+  if (!results) return;
 
-    showImage();
-  } 
-  else {
-    clearViewer();
+  const sent = results.filter(r => r.new_path && r.new_path !== r.path);
+  if (!copy) {
+    const moved = sent.map(r => r.path);
+    moved.forEach(forgetThumb);
+    await forgetRemovedImages(moved);
+    if (inGridMode) populateGrid();
   }
-});
 
-document.addEventListener("keydown", (e) => {
-  // Ignore if user is typing in an input / textarea
-  const tag = e.target.tagName;
-  if (tag === "INPUT" || tag === "TEXTAREA") return;
+  // names already taken are asked about, not listed
+  const taken = policy ? [] : results.filter(r => r.kind === "exists");
+  reportBulk(
+    `${copy ? "Copied" : "Moved"} ${sent.length} images to ${folder}.` + (sent.length ? " Ctrl+Z to undo." : ""),
+    results.filter(r => r.error && !taken.includes(r)),
+    results.filter(r => r.warning)
+  );
 
-  if (e.key === "Delete") {
-    e.preventDefault();
-    deleteBtn.click();
+  if (taken.length) {
+    const keepBoth = await confirmDlg(`${taken.length} of the images are already in ${folder}. Keep both and add a number to the names?`);
+    if (keepBoth) await bulkSend(copy, folder, taken.map(r => r.path), "auto_suffix");
   }
-});
+}
 
-// ----------- CONFIRM DLG ---------------
+async function bulkRotate(degrees) {
+  const results = await runBulk("Rotating", "bulk_rotate", selectedPaths(), { degrees });
+  if (!results) return;
 
-let confirmDialogOpen = false;
+  const rotated = results.filter(r => !r.error).map(r => r.path);
+  for (const path of rotated) {
+    const wrap = thumbCache.get(path);
+    // TIFF thumbnails are decoded again, the others reloaded
+    if (wrap?.dataset.blob) forgetThumb(path);
+    else if (wrap) wrap.querySelector("img").src = convertFileSrc(path) + "?reload=" + Date.now();
+  }
+  if (inGridMode) populateGrid();
+  reportBulk(`Rotated ${rotated.length} images.`, results.filter(r => r.error));
+}
 
-function confirmDlg(message) {
-  return new Promise((resolve) => {
-    confirmDialogOpen = true;
+// ----- Marks of the selection -----
 
-    const dlg = document.getElementById('confirmDlg');
-    const text = document.getElementById('confirmText');
-    const ok = document.getElementById('confirmOk');
-    const cancel = document.getElementById('confirmCancel');
-    const closeBtn = document.getElementById('confirmDlgCloseBtn');
+function openBulkMarksDialog() {
+  if (!gridSelection.size) return;
+  bulkMarksStatus.textContent = `${gridSelection.size} images selected.`;
+  bulkMarksDlg.style.display = "flex";
+}
 
-    text.textContent = message;
-    dlg.style.display = 'block';
+function closeBulkMarksDialog() {
+  bulkMarksDlg.style.display = "none";
+}
 
-    // 🔑 focus OK button
-    requestAnimationFrame(() => ok.focus());
+async function applyBulkMarks() {
+  const value = id => document.getElementById(id).value;
+  const choice = id => value(id) === "keep" ? null : value(id);
+  const tags = id => value(id).split(",").map(t => t.trim()).filter(Boolean);
+
+  const change = {
+    rating: choice("bulkRating") === null ? null : Number(value("bulkRating")),
+    label: choice("bulkLabel"),
+    flag: choice("bulkFlag"),
+    add_tags: tags("bulkAddTags"),
+    remove_tags: tags("bulkRemoveTags")
+  };
+
+  const results = await runBulk("Saving marks", "bulk_set_marks", selectedPaths(), { change, sidecar: writeSidecars });
+  if (!results) return;
+
+  const saved = results.filter(r => !r.error).length;
+  bulkMarksStatus.textContent = `Saved the marks of ${saved} of ${results.length} images.`;
+  reportBulk(`Saved the marks of ${saved} images.`, results.filter(r => r.error));
+  if (results.some(r => r.path === marksPath)) await refreshMarks(marksPath);
+}
 
-    let finished = false;
+document.getElementById("bulkTrashBtn").onclick = bulkTrash;
+document.getElementById("bulkMoveBtn").onclick = () => bulkSend(false);
+document.getElementById("bulkCopyBtn").onclick = () => bulkSend(true);
+document.getElementById("bulkRotateLeftBtn").onclick = () => bulkRotate(270);
+document.getElementById("bulkRotateRightBtn").onclick = () => bulkRotate(90);
+document.getElementById("bulkMarksBtn").onclick = openBulkMarksDialog;
+document.getElementById("bulkClearBtn").onclick = clearGridSelection;
+
+document.getElementById("bulkRenameBtn").onclick = () => {
+  openRenameDialog();
+  renameScope.value = "selection";
+  planRename();
+};
 
-    const cleanup = (result) => {
-      if (finished) return;
-      finished = true;
+document.getElementById("bulkConvertBtn").onclick = () => {
+  openConvertDialog();
+  convertScope.value = "selection";
+};
 
-      confirmDialogOpen = false;
-      dlg.style.display = 'none';
+document.getElementById("bulkMarksApplyBtn").onclick = applyBulkMarks;
+document.getElementById("closeBulkMarks").onclick =
+document.getElementById("bulkMarksCloseBtn").onclick = closeBulkMarksDialog;
 
-      ok.onclick = null;
-      cancel.onclick = null;
-      closeBtn.onclick = null;
+bulkMarksDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeBulkMarksDialog();
+});
 
-      document.removeEventListener('keydown', keyHandler);
+document.addEventListener("keydown", e => {
+  if (!inGridMode || confirmDialogOpen) return;
+  if (e.target.closest("input, textarea, select")) return;
 
-      resolve(result);
-    };
+  if ((e.ctrlKey || e.metaKey) && e.key.toLowerCase() === "a") {
+    e.preventDefault();
+    images.forEach(p => gridSelection.add(p));
+    renderGridSelection();
+  }
+  else if (e.key === "Escape" && gridSelection.size) {
+    clearGridSelection();
+  }
+});
 
-    ok.onclick = () => cleanup(true);
-    cancel.onclick = () => cleanup(false);
-    closeBtn.onclick = () => cleanup(false);
+// -------------------- CLIPBOARD ----------------------
 
-    const keyHandler = (e) => {
-      if (e.key === 'Escape') {
-        e.preventDefault();
-        cleanup(false);
-      }
+// Ctrl+C copies the pixels of the current image, Ctrl+Shift+C the file
+// itself (the selection, in the grid) and Ctrl+V opens an image from the
+// clipboard. A pasted image waits in the app cache until it is saved.
 
-      if (e.key === 'Enter') {
-        e.preventDefault();
-        cleanup(true);
-      }
-      
-    };
+const pastedImages = new Set();
 
-    document.addEventListener('keydown', keyHandler);
-  });
+async function copyImageToClipboard() {
+  const path = images[index];
+  if (!path) return;
+
+  try {
+    await invoke("copy_image_to_clipboard", { path });
+    showOpNote(`Copied the image ${getFileName(path)}.`);
+  } catch (err) {
+    showOpNote(String(err));
+  }
 }
 
-// ------------ ROTATE IMAGE -------------------
+async function copyFilesToClipboard() {
+  const paths = inGridMode && gridSelection.size ? selectedPaths() : [images[index]].filter(Boolean);
+  if (!paths.length) return;
 
-const rotateLeftBtn = document.getElementById("rotateLeft");
-const rotateRightBtn = document.getElementById("rotateRight");
+  try {
+    await invoke("copy_files_to_clipboard", { paths });
+    showOpNote(paths.length === 1 ? `Copied the file ${getFileName(paths[0])}.` : `Copied ${paths.length} files.`);
+  } catch (err) {
+    showOpNote(String(err));
+  }
+}
 
-function updateTransform() {
-  clampTranslation();
-  img.style.transform = `
-    translate(${translateX}px, ${translateY}px)
-    scale(${scale})
-    rotate(${rotationDegrees}deg)
-  `;
-  let z = getTrueZoomPercent();
-  if (z) {
-    zoomLabel.style.display = 'block';
-    zoomValue.textContent = z + "%";
+async function pasteImage() {
+  let path;
+  try {
+    path = await invoke("paste_image_from_clipboard");
+  } catch (err) {
+    showOpNote(String(err));
+    return;
+  }
+  if (!path) {
+    showOpNote("There is no image on the clipboard.");
+    return;
   }
+
+  pastedImages.add(path);
+  if (inGridMode) exitGridMode();
+  await showRestoredImage(path);
+  showOpNote("Pasted the clipboard image, File > Save Pasted Image… keeps it.");
 }
 
-function getTrueZoomPercent() {
-  const fittedScale = getOriginalScale();
-  return Math.round((scale / fittedScale) * 100);
+async function savePastedImage() {
+  const path = images[index];
+  if (!pastedImages.has(path)) {
+    alert("The current image wasn't pasted from the clipboard.");
+    return;
+  }
+
+  let dest = await save({ defaultPath: "Pasted image.png", filters: [{ name: "PNG image", extensions: ["png"] }] });
+  if (!dest) return;
+  if (!/\.png$/i.test(dest)) dest += ".png";
+
+  try {
+    // the save dialog asked about replacing already
+    const saved = await invoke("copy_file", { src: path, dest, policy: "overwrite" });
+    pastedImages.delete(path);
+    await showRestoredImage(saved.path);
+    showOpNote(`Saved ${getFileName(saved.path)}.`);
+  } catch (err) {
+    alert(`Could not save the image: ${err.message ?? err}`);
+  }
 }
 
-function clampTranslation() {
-  const fw = frame.clientWidth;
-  const fh = frame.clientHeight;
+document.addEventListener("keydown", e => {
+  if (confirmDialogOpen) return;
+  if (e.target.closest("input, textarea, select")) return;
+  if (!(e.ctrlKey || e.metaKey) || e.altKey) return;
 
-  const iw = img.clientWidth * scale;
-  const ih = img.clientHeight * scale;
+  const key = e.key.toLowerCase();
+  if (key === "c" && e.shiftKey) {
+    e.preventDefault();
+    copyFilesToClipboard();
+  }
+  // selected text copies as usual
+  else if (key === "c" && !window.getSelection().toString()) {
+    e.preventDefault();
+    copyImageToClipboard();
+  }
+  else if (key === "v" && !e.shiftKey) {
+    e.preventDefault();
+    pasteImage();
+  }
+});
 
-  const maxX = Math.max(0, (iw - fw) / 2);
-  const maxY = Math.max(0, (ih - fh) / 2);
+// -------------------- DRAG AND DROP ----------------------
 
-  translateX = Math.min(maxX, Math.max(-maxX, translateX));
-  translateY = Math.min(maxY, Math.max(-maxY, translateY));
+// Files and folders dropped on the window are opened: one file with the
+// rest of its folder, several files as a list of just those. Dragging the
+// image while it fits the window (zoomed in, that pans it) or thumbnails
+// in the grid drags the files out to other apps (drag_out.rs).
+
+const DRAG_OUT_DISTANCE = 6; // px the mouse moves before a press drags
+
+let dragOutPress = null; // { x, y, paths, source } while the button is down
+let draggingOut = false;
+let dragOutClickBlock = false;
+
+function pressForDragOut(e, paths, source) {
+  dragOutClickBlock = false;
+  dragOutPress = { x: e.clientX, y: e.clientY, paths, source };
 }
 
-function computeBaseScale() {
-  const cw = frame.clientWidth;
-  const ch = frame.clientHeight;
+// A small PNG of what `source` shows, null when the webview won't give
+// its pixels and the backend makes one
+async function dragIcon(source) {
+  if (!source?.naturalWidth) return null;
 
-  let iw = img.naturalWidth;
-  let ih = img.naturalHeight;
+  const fit = Math.min(1, 128 / Math.max(source.naturalWidth, source.naturalHeight));
+  const canvas = document.createElement("canvas");
+  canvas.width = Math.max(1, Math.round(source.naturalWidth * fit));
+  canvas.height = Math.max(1, Math.round(source.naturalHeight * fit));
 
-  if (rotationDegrees % 180 !== 0) {
-    [iw, ih] = [ih, iw];
+  try {
+    canvas.getContext("2d").drawImage(source, 0, 0, canvas.width, canvas.height);
+    const blob = await new Promise(resolve => canvas.toBlob(resolve, "image/png"));
+    return blob ? Array.from(new Uint8Array(await blob.arrayBuffer())) : null;
+  } catch (err) {
+    console.error("Drag picture:", err);
+    return null;
   }
+}
 
-  baseScale = Math.min(cw / iw, ch / ih);
+async function startFileDrag(paths, source) {
+  draggingOut = true;
+  dragOutClickBlock = true; // letting go ends the drag, it isn't a click
+  try {
+    await invoke("start_file_drag", { paths, icon: await dragIcon(source) });
+  } catch (err) {
+    draggingOut = false;
+    showOpNote(String(err));
+  }
+}
 
-  scale = baseScale;
-  translateX = 0;
-  translateY = 0;
+async function openDropped(paths) {
+  let dropped;
+  try {
+    dropped = await invoke("list_dropped_images", { paths });
+  } catch (err) {
+    showOpNote(String(err));
+    return;
+  }
+  if (!dropped.length) {
+    showOpNote("There are no images in what was dropped.");
+    return;
+  }
 
-  updateTransform();
+  const list = paths.length > 1 ? dropped : null;
+  await invoke("set_opened_image", { path: dropped[0], list });
+  clearSearch();
+  clearGridSelection();
+
+  const [folderList, startIndex] = await folderImages(dropped[0]);
+  images = folderList;
+  index = startIndex;
+  await showImage();
+  if (inGridMode) populateGrid();
+  if (list) showOpNote(`Opened ${dropped.length} dropped images.`);
 }
 
+img.addEventListener("mousedown", e => {
+  if (e.button !== 0 || scale !== 1 || !images[index]) return;
+  pressForDragOut(e, [images[index]], img);
+});
 
-rotateLeftBtn.addEventListener("click", () => {
-  if (!img.src) return;
+// the webview's own image dragging would get in the way
+img.addEventListener("dragstart", e => e.preventDefault());
+gridContainer.addEventListener("dragstart", e => e.preventDefault());
 
-  rotationDegrees = (rotationDegrees - 90 + 360) % 360;
+window.addEventListener("mousemove", e => {
+  if (!dragOutPress) return;
+  if (!(e.buttons & 1)) {
+    dragOutPress = null;
+    return;
+  }
+  if (Math.hypot(e.clientX - dragOutPress.x, e.clientY - dragOutPress.y) < DRAG_OUT_DISTANCE) return;
 
-  // reset pan on rotate (Windows behavior)
-  translateX = 0;
-  translateY = 0;
+  const { paths, source } = dragOutPress;
+  dragOutPress = null;
+  startFileDrag(paths, source);
+});
 
-  computeBaseScale(); // 🔑 refit AFTER rotation
+window.addEventListener("mouseup", () => {
+  dragOutPress = null;
 });
 
-rotateRightBtn.addEventListener("click", () => {
-  if (!img.src) return;
+listen("file-drag-end", () => {
+  draggingOut = false;
+});
 
-  rotationDegrees = (rotationDegrees + 90) % 360;
+listen("tauri://drag-enter", () => {
+  if (!draggingOut) document.body.classList.add("dropping");
+});
 
-  translateX = 0;
-  translateY = 0;
+listen("tauri://drag-leave", () => {
+  document.body.classList.remove("dropping");
+});
 
-  computeBaseScale();
+listen("tauri://drag-drop", async ({ payload }) => {
+  document.body.classList.remove("dropping");
+  // our own files, let go where they came from
+  if (draggingOut) return;
+  await openDropped(payload.paths);
 });
 
-// ----------- FILE MENU -------------
+// -------------------- EXTERNAL APPS ----------------------
 
-document.getElementById("fileMenuContextMenu").addEventListener("click", async (e) => {
-  e.stopPropagation(); // ⬅️ THIS IS THE KEY
+// Programs added to Open With by hand, kept by external_apps.rs in the
+// order given here. {path} in the arguments is the image. An app with a
+// shortcut opens the current image from anywhere but a text field.
 
-  const item = e.target.closest(".ctx-item");
-  if (!item) return;
+const externalAppsDlg = document.getElementById("externalAppsDlg");
+const externalAppsList = document.getElementById("externalAppsList");
+const externalAppsStatus = document.getElementById("externalAppsStatus");
+const EXTERNAL_APP_PREFIX = "external:"; // ID_PREFIX in external_apps.rs
 
-  const action = item.dataset.action;
-  const currentFilePath = images[index];
-  const fileName = getFileName(currentFilePath);
+let externalApps = [];
+let externalAppsDraft = [];
 
-  try {
-      switch (action) {
-        case "openFile":
-          openFileSelect();
-          break;
+invoke("get_external_apps")
+  .then(apps => { externalApps = apps; })
+  .catch(console.error);
 
-        case "openFolder":
-          openFolderSelect();
-          break;
+function externalAppOf(menuId) {
+  if (!menuId.startsWith(EXTERNAL_APP_PREFIX)) return null;
+  const id = menuId.slice(EXTERNAL_APP_PREFIX.length);
+  return externalApps.find(app => app.id === id) || null;
+}
 
-        case "save":
-          //await invoke("save_file", { path: currentFilePath });
-          break;
+// "Ctrl+Alt+G" for the key event, null for a lone modifier
+function shortcutOf(e) {
+  if (["Control", "Shift", "Alt", "Meta"].includes(e.key)) return null;
+
+  let key = e.key.length === 1 ? e.key.toUpperCase() : e.key;
+  if (/^Key[A-Z]$/.test(e.code)) key = e.code.slice(3);
+  else if (/^Digit[0-9]$/.test(e.code)) key = e.code.slice(5);
+
+  const parts = [];
+  if (e.ctrlKey) parts.push("Ctrl");
+  if (e.altKey) parts.push("Alt");
+  if (e.shiftKey) parts.push("Shift");
+  if (e.metaKey) parts.push("Meta");
+  parts.push(key);
+  return parts.join("+");
+}
 
-        case "saveAs":
-          //await invoke("save_file_as", { path: currentFilePath });
-          break;
+function externalAppInput(app, field, placeholder) {
+  const input = document.createElement("input");
+  input.className = "inputDlgInput";
+  input.type = "text";
+  input.spellcheck = false;
+  input.placeholder = placeholder;
+  input.value = app[field] || "";
+  input.oninput = () => { app[field] = input.value; };
+  return input;
+}
 
-        case "saveCopy":
-          
-          break;
+function externalAppButton(text, title, onclick) {
+  const button = document.createElement("button");
+  button.className = "buttonStyle";
+  button.type = "button";
+  button.textContent = text;
+  button.title = title;
+  button.onclick = onclick;
+  return button;
+}
 
-        case "setWallpaper":
-          if (!currentFilePath) {
-            alert("No image loaded.");
-            return;
-          }
-          closeAllMenus();
-          const confirmed = await confirmDlg(`Set "${fileName}" as Desktop Background Image?`);
-          if (!confirmed) return;
-          await invoke("set_desktop_background", { path: currentFilePath });
-          break;
+function moveExternalApp(from, to) {
+  if (to < 0 || to >= externalAppsDraft.length) return;
+  const [app] = externalAppsDraft.splice(from, 1);
+  externalAppsDraft.splice(to, 0, app);
+  renderExternalApps();
+}
 
-        case "openExplorer":
-          if (!currentFilePath) {
-            alert("No image loaded.");
-            return;
-          }
-          await invoke("open_in_explorer", { path: currentFilePath });
-          break;
+function renderExternalApps() {
+  const rows = externalAppsDraft.map((app, i) => {
+    const row = document.createElement("div");
+    row.className = "externalAppRow";
 
-        case "renameFile":
-          if (!currentFilePath) {
-            alert("No image loaded.");
-            return;
-          }
-          inputDlg.style.display = 'block';
-          break;
+    const label = externalAppInput(app, "label", "Name");
+    const path = externalAppInput(app, "path", "Program");
+    const args = externalAppInput(app, "args", "{path}");
 
-        case "properties":
-          if (!currentFilePath) {
-            alert("No image loaded.");
-            return;
-          }
-          await invoke('show_file_properties', { path: currentFilePath });
-          break;
+    const browse = externalAppButton("Browse…", "Choose the program", async () => {
+      const selected = await open({ multiple: false, directory: false });
+      if (!selected) return;
+      app.path = Array.isArray(selected) ? selected[0] : selected;
+      path.value = app.path;
+    });
 
-        case "imageInfo":
-          if (!currentFilePath) {
-            alert("No image loaded.");
-            return;
-          }
-          await fillImageInfo(currentFilePath);
-          break;
+    // pressed, not typed: Backspace clears it
+    const shortcut = externalAppInput(app, "shortcut", "No shortcut");
+    shortcut.readOnly = true;
+    shortcut.title = "Press the keys, Backspace to clear";
+    shortcut.onkeydown = e => {
+      if (e.key === "Escape" || e.key === "Tab") return;
+      e.preventDefault();
+      if ((e.key === "Backspace" || e.key === "Delete") && !e.ctrlKey && !e.altKey && !e.metaKey) {
+        app.shortcut = null;
+        shortcut.value = "";
+        return;
       }
-    } 
-    catch (err) {
-      console.error(err);
-    }
+      const combo = shortcutOf(e);
+      if (!combo) return;
+      if (!e.ctrlKey && !e.altKey && !e.metaKey && !/^F\d+$/.test(e.key)) {
+        externalAppsStatus.textContent = "Shortcuts need Ctrl or Alt, or an F key.";
+        return;
+      }
+      externalAppsStatus.textContent = "";
+      app.shortcut = combo;
+      shortcut.value = combo;
+    };
 
-  closeAllMenus(); // ✅ use the centralized closer
-});
+    const up = externalAppButton("↑", "Move up", () => moveExternalApp(i, i - 1));
+    const down = externalAppButton("↓", "Move down", () => moveExternalApp(i, i + 1));
+    const remove = externalAppButton("✕", "Remove", () => {
+      externalAppsDraft.splice(i, 1);
+      renderExternalApps();
+    });
+    up.disabled = i === 0;
+    down.disabled = i === externalAppsDraft.length - 1;
 
-function closeAllMenus() {
-  document.querySelectorAll(".context-menu2").forEach(menu => {
-    menu.style.display = "none";
+    row.append(label, path, browse, args, shortcut, up, down, remove);
+    return row;
   });
-  currentOpenMenu = null;
+  externalAppsList.replaceChildren(...rows);
 }
-const fileBtn = document.getElementById("fileMenuBtn");
-const fileMenu = document.getElementById("fileMenuContextMenu");
 
-fileBtn.addEventListener("click", (e) => {
-  e.stopPropagation();
-  if (fileMenu.style.display === 'none' || fileMenu.style.display === '') {
-    const rect = fileBtn.getBoundingClientRect();
-    fileMenu.style.left = `${rect.left}px`;
-    fileMenu.style.top = `${rect.bottom + 4}px`;
-    fileMenu.style.display = "block";
+function openExternalAppsDialog() {
+  externalAppsDraft = externalApps.map(app => ({ ...app }));
+  externalAppsStatus.textContent = "";
+  renderExternalApps();
+  externalAppsDlg.style.display = "flex";
+}
+
+function closeExternalAppsDialog() {
+  externalAppsDlg.style.display = "none";
+}
+
+async function saveExternalApps() {
+  try {
+    externalApps = await invoke("set_external_apps", { apps: externalAppsDraft });
+  } catch (err) {
+    externalAppsStatus.textContent = String(err);
+    return;
   }
-  else {
-    fileMenu.style.display = 'none';
+  closeExternalAppsDialog();
+}
+
+async function openWithExternalApp(app) {
+  const path = images[index];
+  if (!path) return;
+  try {
+    await invoke("open_with_app", { app: EXTERNAL_APP_PREFIX + app.id, path });
+  } catch (err) {
+    showOpNote(String(err));
   }
-});
+}
 
-// --------- INPUT DLG ----------
+document.getElementById("externalAppsAddBtn").onclick = () => {
+  externalAppsDraft.push({ id: "", label: "", path: "", args: "", shortcut: null });
+  renderExternalApps();
+  externalAppsList.lastElementChild?.querySelector("input")?.focus();
+};
 
-const inputDlg = document.getElementById('inputDlg');
-const inputOk = document.getElementById('inputOk');
-const inputCancel = document.getElementById('inputCancel');
-const inputDlgInput = document.getElementById('inputDlgInput');
-const inputDlgCloseBtn = document.getElementById('inputDlgCloseBtn');
+document.getElementById("externalAppsSaveBtn").onclick = saveExternalApps;
+document.getElementById("closeExternalApps").onclick =
+document.getElementById("externalAppsCloseBtn").onclick = closeExternalAppsDialog;
 
-inputOk.addEventListener('click', async function (event) {
-  inputDlg.style.display = 'none';
-  const name =  inputDlgInput.value;
-  if (name) {
-    await invoke("rename_file", { path: currentFilePath, newName: name });
-  }
+externalAppsDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeExternalAppsDialog();
 });
 
-inputDlgCloseBtn.addEventListener('click', function (event) {
-  inputDlg.style.display = 'none';
-});
+// ahead of the built-in shortcuts, a user's choice wins
+document.addEventListener("keydown", e => {
+  if (confirmDialogOpen || !externalApps.length) return;
+  if (e.target.closest("input, textarea, select")) return;
 
-inputCancel.addEventListener('click', function (event) {
-  inputDlg.style.display = 'none';
-});
+  const combo = shortcutOf(e);
+  const app = combo && externalApps.find(a => a.shortcut === combo);
+  if (!app) return;
 
-// -------------- GRID VIEW -----------
+  e.preventDefault();
+  e.stopImmediatePropagation();
+  openWithExternalApp(app);
+}, true);
 
-const thumbCache = new Map(); // key: filePath, value: <div class="thumbWrapper">
+// -------------------- EDITS IN OTHER APPS ----------------------
 
-const gridViewBtn = document.getElementById("gridViewBtn");
-const gridView = document.getElementById("gridView");
-const gridContainer = document.getElementById("gridContainer");
+// Images opened with an app from Open With are watched while it runs
+// (editor_watch.rs). Whenever the file was saved there, the image and its
+// thumbnail are shown again.
 
-let inGridMode = false;
+listen("image-edited", async ({ payload }) => {
+  const { path, changed, closed } = payload;
+  if (!changed) return;
 
-gridViewBtn.addEventListener("click", () => {
-  if (!images.length) return;
+  fileChangedAt.set(path, Date.now());
+  await invoke("close_tiled_image", { path });
+  const wrap = thumbCache.get(path);
+  // TIFF thumbnails are decoded again, the others reloaded
+  if (wrap?.dataset.blob) forgetThumb(path);
+  else if (wrap) wrap.querySelector("img").src = fileSrc(path);
 
   if (inGridMode) {
-    exitGridMode();
-  } 
-  else {
-    enterGridMode();
+    if (images.includes(path)) populateGrid();
   }
+  else if (images[index] === path) await reloadCurrentImage();
+
+  showOpNote(`${getFileName(path)} was ${closed ? "saved and closed" : "saved"} in another app.`);
 });
 
-function enterGridMode() {
-  inGridMode = true;
-  // hide single view UI
-  img.classList.add("hidden");
-  gifCanvas.classList.add("hidden");
-  gifBar.classList.add("hidden");
-  icoBar.classList.add("hidden");
-  loadingText.classList.remove("visible");
+// -------------------- SEARCH ----------------------
 
-  gridView.classList.remove("hidden");
+// Query over the folder, see search.rs for the syntax. While a search is
+// active the folder list holds only the matches, so next / prev, the grid
+// and the slideshow stay within them.
 
-  populateGrid();
+const searchInput = document.getElementById("searchInput");
+const searchRecursive = document.getElementById("searchRecursive");
+const searchCount = document.getElementById("searchCount");
+
+let searchQuery = "";
+let searchSubfolders = false;
+
+// The folder list, narrowed by the search and ordered by the marks filter
+function folderImages(path) {
+  if (!searchQuery && !searchSubfolders) {
+    return invoke("get_folder_images", {
+      currentPath: path,
+      filter: marksFilter,
+      sort: marksSort,
+    });
+  }
+  return invoke("search_images", {
+    currentPath: path,
+    query: searchQuery,
+    recursive: searchSubfolders,
+    filter: marksFilter,
+    sort: marksSort,
+  });
 }
 
-async function populateGrid() {
-  gridContainer.innerHTML = "";
+function showSearchError(err) {
+  searchInput.classList.toggle("searchError", !!err);
+  searchCount.textContent = err ? String(err) : "";
+  searchCount.title = err ? String(err) : "";
+}
 
-  for (let i = 0; i < images.length; i++) {
-    const path = images[i];
-    const ext = getExt(path);
+async function runSearch() {
+  const query = searchInput.value.trim();
+  const recursive = searchRecursive.checked;
 
-    let wrap;
+  const path = images[index] || await invoke("get_opened_image");
+  if (!path) return;
 
-    // REUSE FROM CACHE IF EXISTS
-    if (thumbCache.has(path)) {
-      wrap = thumbCache.get(path);
-      wrap.dataset.index = i; // keep index updated
-      gridContainer.appendChild(wrap);
-      continue;
-    }
+  const previous = [searchQuery, searchSubfolders];
+  [searchQuery, searchSubfolders] = [query, recursive];
 
-    // CREATE NEW WRAPPER
-    wrap = document.createElement("div");
-    wrap.className = "thumbWrapper";
-    wrap.dataset.index = i;
+  let list, startIndex;
+  try {
+    [list, startIndex] = await folderImages(path);
+  } catch (err) {
+    // a bad query keeps the current list
+    [searchQuery, searchSubfolders] = previous;
+    showSearchError(err);
+    return;
+  }
 
-    const thumb = document.createElement("img");
-    thumb.className = "gridThumb";
-    wrap.appendChild(thumb);
+  showSearchError(null);
+  if (query || recursive) {
+    searchCount.textContent = `${list.length} ${list.length === 1 ? "match" : "matches"}`;
+  }
 
-    // LOAD IMAGE (TIFF OR NORMAL)
-    if (ext === "tif" || ext === "tiff") {
-      try {
-        const data = await invoke("load_image", { path });
-        const blob = new Blob([new Uint8Array(data)], { type: "image/png" });
+  images = list;
+  index = startIndex;
+  if (inGridMode) populateGrid();
+  else await showImage();
+}
 
-        // Store blob URL so we don't generate it again
-        const blobURL = URL.createObjectURL(blob);
-        thumb.src = blobURL;
+// Opening another file or folder starts without a search
+function clearSearch() {
+  searchQuery = "";
+  searchSubfolders = false;
+  searchInput.value = "";
+  searchRecursive.checked = false;
+  showSearchError(null);
+}
 
-        // Cache the blob URL for possible cleanup later
-        wrap.dataset.blob = blobURL;
+searchInput.addEventListener("keydown", async e => {
+  // arrows and letters belong to the text field here
+  e.stopPropagation();
 
-      } catch (err) {
-        console.error("TIFF thumbnail failed:", err);
-        thumb.src = "";
-      }
-    } 
-    else {
-      thumb.src = convertFileSrc(path);
-    }
+  if (e.key === "Enter") await runSearch();
+  if (e.key === "Escape") {
+    searchInput.value = "";
+    searchInput.blur();
+    if (searchQuery) await runSearch();
+  }
+});
 
-    // SET TITLE (hover tooltip)
-    const fileName = path.split(/[/\\]/).pop();
-    wrap.title = fileName;
+searchRecursive.addEventListener("change", runSearch);
+
+// -------------------- RATINGS, LABELS AND TAGS ----------------------
+
+// Stars, color label, pick / reject flag and tags of the current image,
+// stored as XMP by ratings.rs. Keys follow Lightroom: 0-5 stars, 6-9 red to
+// blue label, P pick, X reject, U unflag. T edits the tags.
+
+const marksBadge = document.getElementById("marksBadge");
+const tagsDlg = document.getElementById("tagsDlg");
+const tagsInput = document.getElementById("tagsInput");
+const tagsStatus = document.getElementById("tagsStatus");
+const filterDlg = document.getElementById("filterDlg");
+const filterRating = document.getElementById("filterRating");
+const filterLabels = document.getElementById("filterLabels");
+const filterFlag = document.getElementById("filterFlag");
+const filterTags = document.getElementById("filterTags");
+const filterSort = document.getElementById("filterSort");
+const filterItem = document.getElementById("filterItem");
+const sidecarToggleItem = document.getElementById("sidecarToggleItem");
+
+const LABEL_KEYS = { "6": "Red", "7": "Yellow", "8": "Green", "9": "Blue" };
+const LABEL_COLORS = {
+  Red: "#e53935",
+  Yellow: "#fdd835",
+  Green: "#43a047",
+  Blue: "#1e88e5",
+  Purple: "#8e24aa",
+};
 
-    // CLICK HANDLER
-    wrap.addEventListener("click", () => {
-      index = i;
-      exitGridMode();
-      showImage();
-    });
+let marksPath = null;
+let currentMarks = null;   // null until loaded for marksPath
+let marksWrite = Promise.resolve();
+let marksFilter = null;
+let marksSort = null;
+let writeSidecars = localStorage.getItem("writeSidecars") === "true";
 
-    // STORE IN CACHE + APPEND
-    thumbCache.set(path, wrap);
-    gridContainer.appendChild(wrap);
+setWriteSidecars(writeSidecars);
+
+function setWriteSidecars(on) {
+  writeSidecars = on;
+  localStorage.setItem("writeSidecars", String(on));
+  sidecarToggleItem.textContent = on ? "Embed Marks in Files" : "Write Marks to XMP Sidecars";
+}
+
+// Called by showImage for the new file, null when nothing is shown
+async function refreshMarks(path) {
+  marksPath = path;
+  currentMarks = null;
+  renderMarksBadge();
+  if (!path) return;
+
+  try {
+    const marks = await invoke("get_image_marks", { path });
+    if (marksPath !== path) return;
+    currentMarks = marks;
+    renderMarksBadge();
+  } catch (err) {
+    console.error(err);
   }
+}
 
-  // OPTIONAL: clean orphaned cache entries  
-  for (const cachedPath of thumbCache.keys()) {
-    if (!images.includes(cachedPath)) {
-      // release blob URLs if TIFF cached
-      const oldWrap = thumbCache.get(cachedPath);
-      if (oldWrap.dataset.blob) {
-        URL.revokeObjectURL(oldWrap.dataset.blob);
-      }
-      thumbCache.delete(cachedPath);
-    }
+function renderMarksBadge() {
+  const marks = currentMarks;
+  const empty = !marks || (!marks.rating && !marks.label && marks.flag === "none");
+  marksBadge.classList.toggle("hidden", empty);
+  marksBadge.innerHTML = "";
+  if (empty) return;
+
+  const add = (className, text) => {
+    const span = document.createElement("span");
+    span.className = className;
+    span.textContent = text;
+    marksBadge.appendChild(span);
+    return span;
+  };
+
+  if (marks.flag === "reject") add("marksReject", "✕ Rejected");
+  else if (marks.rating) add("marksStars", "★".repeat(marks.rating) + "☆".repeat(5 - marks.rating));
+  if (marks.flag === "pick") add("", "⚑ Picked");
+  if (marks.label) {
+    const dot = add("marksLabel", "");
+    dot.style.background = LABEL_COLORS[marks.label];
+    dot.title = marks.label;
   }
 }
 
-function exitGridMode() {
-  inGridMode = false;
-  gridView.classList.add("hidden");
-  img.classList.remove("hidden");
-  showImage();
+// Shown right away, written in order so quick key presses don't race
+function updateMarks(change) {
+  if (!currentMarks) return;
+
+  const path = marksPath;
+  const marks = { ...currentMarks, ...change };
+  currentMarks = marks;
+  renderMarksBadge();
+
+  marksWrite = marksWrite.then(async () => {
+    try {
+      await invoke("set_image_marks", { path, marks, sidecar: writeSidecars });
+    } catch (err) {
+      alert(`Could not save the rating: ${err}`);
+      if (marksPath === path) await refreshMarks(path);
+    }
+  });
 }
 
 document.addEventListener("keydown", e => {
-  if (e.key.toLowerCase() === "g") {
-    gridViewBtn.click();
+  if (e.target.closest("input, textarea, select")) return;
+  if (e.ctrlKey || e.metaKey || e.altKey || e.shiftKey) return;
+  if (!currentMarks) return;
+
+  const key = e.key.toLowerCase();
+  const flag = currentMarks.flag;
+
+  if (key.length === 1 && key >= "0" && key <= "5") {
+    // stars take a file out of the rejects
+    updateMarks({ rating: Number(key), flag: flag === "reject" ? "none" : flag });
+  }
+  else if (LABEL_KEYS[key]) {
+    const label = LABEL_KEYS[key];
+    updateMarks({ label: currentMarks.label === label ? null : label });
+  }
+  else if (key === "p") updateMarks({ flag: flag === "pick" ? "none" : "pick" });
+  else if (key === "x") updateMarks({ flag: flag === "reject" ? "none" : "reject", rating: 0 });
+  else if (key === "u") updateMarks({ flag: "none" });
+  else if (key === "t") {
+    e.preventDefault();
+    openTagsEditor();
   }
 });
 
-document.getElementById("closeImgInfo").onclick =
-document.getElementById("imgInfoCloseBtn").onclick = () => {
-  document.getElementById("imgInfoDlg").style.display = "none";
-};
+// ----- Tags -----
 
-async function fillImageInfo(path) {
-  const info = await invoke("load_image_metadata", { path });
+function openTagsEditor() {
+  if (!currentMarks) return;
+  tagsInput.value = currentMarks.tags.join(", ");
+  tagsStatus.textContent = currentMarks.sidecar ? "Stored in an XMP sidecar." : "";
+  tagsDlg.style.display = "flex";
+  tagsInput.focus();
+}
 
-  document.getElementById("imgInfoDlg").style.display = "block";
+function closeTagsEditor() {
+  tagsDlg.style.display = "none";
+}
 
-  // BASIC INFO
-  document.getElementById("imgInfoFileName").textContent = info.file_name;
-  document.getElementById("imgInfoFormat").textContent = info.format;
-  document.getElementById("imgInfoDimensions").textContent = `${info.width} × ${info.height}`;
-  document.getElementById("imgInfoFileSize").textContent = formatBytes(info.file_size);
+function saveTags() {
+  const tags = tagsInput.value
+    .split(",")
+    .map(t => t.trim())
+    .filter(Boolean);
+  updateMarks({ tags });
+  closeTagsEditor();
+}
 
-  // FIXED FIELD NAMES
-  document.getElementById("imgInfoColorMode").textContent = info.color_mode;
-  document.getElementById("imgInfoBitDepth").textContent = info.bit_depth;
-  document.getElementById("imgInfoAlpha").textContent = info.alpha ? "Yes" : "No";
+document.getElementById("tagsSaveBtn").onclick = saveTags;
+document.getElementById("closeTags").onclick =
+document.getElementById("tagsCancelBtn").onclick = closeTagsEditor;
 
-  const ratio = (info.width / info.height).toFixed(3);
-  document.getElementById("imgInfoAspect").textContent = ratio;
+tagsDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeTagsEditor();
+  if (e.key === "Enter") saveTags();
+});
 
-  document.getElementById("imgInfoOrientation").textContent =
-    info.width >= info.height ? "Landscape" : "Portrait";
+// ----- Filter and sort -----
 
-  // FILE SYSTEM METADATA
-  document.getElementById("imgInfoFullPath").textContent = info.full_path;
-  document.getElementById("imgInfoCreated").textContent = formatUnix(info.created);
-  document.getElementById("imgInfoModified").textContent = formatUnix(info.modified);
-  /* document.getElementById("imgInfoReadOnly").textContent = info.read_only ? "Yes" : "No"; */
+function openFilterDialog() {
+  const filter = marksFilter || {};
+  filterRating.value = String(filter.min_rating || 0);
+  filterLabels.querySelectorAll("input").forEach(box => {
+    box.checked = (filter.labels || []).includes(box.value);
+  });
+  filterFlag.value = filter.hide_rejected ? "notrejected" : (filter.flag || "");
+  filterTags.value = (filter.tags || []).join(", ");
+  filterSort.value = marksSort || "";
+  filterDlg.style.display = "flex";
+}
 
-  // UI-ONLY  
-  /* document.getElementById("imgInfoZoom").textContent = currentZoom + "%";
-  document.getElementById("imgInfoDisplayedRes").textContent = `${displayWidth} × ${displayHeight}`;
-  document.getElementById("imgInfoScaling").textContent = scalingType; */
+function closeFilterDialog() {
+  filterDlg.style.display = "none";
+}
 
-  // EXIF DATA
-  const exifBlock = document.getElementById("imgExifBlock");
+function readFilterDialog() {
+  const filter = {
+    min_rating: Number(filterRating.value),
+    labels: [...filterLabels.querySelectorAll("input:checked")].map(box => box.value),
+    flag: ["pick", "none", "reject"].includes(filterFlag.value) ? filterFlag.value : null,
+    hide_rejected: filterFlag.value === "notrejected",
+    tags: filterTags.value.split(",").map(t => t.trim()).filter(Boolean),
+  };
+  const active = filter.min_rating || filter.labels.length || filter.flag ||
+    filter.hide_rejected || filter.tags.length;
+  return active ? filter : null;
+}
 
-  const hasExif =
-    info.date_taken ||
-    info.camera ||
-    info.aperture ||
-    info.shutter ||
-    info.iso ||
-    info.focal ||
-    info.color_profile;
+async function applyMarksFilter(filter, sort) {
+  marksFilter = filter;
+  marksSort = sort || null;
+  filterItem.textContent = marksFilter || marksSort ? "Filter and Sort… (on)" : "Filter and Sort…";
 
-  if (hasExif) {
-    exifBlock.style.display = "block";
+  // the current image may be filtered out, the opened one is the fallback
+  const path = images[index] || await invoke("get_opened_image");
+  if (!path) return;
 
-    document.getElementById("imgInfoDateTaken").textContent = info.date_taken || "-";
-    document.getElementById("imgInfoCamera").textContent = info.camera || "-";
-    document.getElementById("imgInfoAperture").textContent = info.aperture || "-";
-    document.getElementById("imgInfoShutter").textContent = info.shutter || "-";
-    document.getElementById("imgInfoISO").textContent = info.iso || "-";
-    document.getElementById("imgInfoFocal").textContent = info.focal || "-";
-    document.getElementById("imgInfoFlash").textContent = info.flash || "-";
-    document.getElementById("imgInfoColorProfile").textContent = info.color_profile || "-";
-    /* document.getElementById("imgInfoDPI").textContent = "N/A"; */
-  } 
-  else {
-    exifBlock.style.display = "none";
-  }
+  showLoading();
+  const [list, startIndex] = await folderImages(path);
+  images = list;
+  index = startIndex;
+  if (inGridMode) populateGrid();
+  else await showImage();
 }
 
-function formatBytes(bytes, decimals = 2) {
-  if (bytes === 0) return "0 B";
-  const k = 1024;
-  const dm = decimals < 0 ? 0 : decimals;
-  const sizes = ["B", "KB", "MB", "GB", "TB"];
-  const i = Math.floor(Math.log(bytes) / Math.log(k));
-  return parseFloat((bytes / Math.pow(k, i)).toFixed(dm)) + " " + sizes[i];
-}
+document.getElementById("filterApplyBtn").onclick = async () => {
+  closeFilterDialog();
+  await applyMarksFilter(readFilterDialog(), filterSort.value);
+};
 
-function formatUnix(sec) {
-  if (!sec || sec === 0) return "-";
-  return new Date(sec * 1000).toLocaleString();
-}
+document.getElementById("filterClearBtn").onclick = async () => {
+  closeFilterDialog();
+  await applyMarksFilter(null, null);
+};
+
+document.getElementById("closeFilter").onclick =
+document.getElementById("filterCancelBtn").onclick = closeFilterDialog;
+
+filterDlg.addEventListener("keydown", e => {
+  e.stopPropagation();
+  if (e.key === "Escape") closeFilterDialog();
+});
 
 //DRAG WINDOW SYSTEM------------------------------------
 
-const draggableIds = ['dragImgInfo'];
+const draggableIds = ['dragImgInfo', 'dragMetaEdit', 'dragMap', 'dragColor', 'dragHist', 'dragDup', 'dragTags', 'dragFilter', 'dragLibrary', 'dragConvert', 'dragRename', 'dragFavorites', 'dragBulkMarks', 'dragExternalApps'];
 let draggableElements = [];
 
 draggableIds.forEach((id) => {
@@ -0,0 +1,244 @@
+// ---------------- WORLD OUTLINE ----------------
+//
+// Coarse coastlines for the offline map, as [longitude, latitude] rings.
+// Accurate to about a degree, which is enough to tell where a photo was
+// taken without any tiles or network access.
+
+const WORLD_OUTLINE = [
+  // North America
+  [[-168,66],[-162,70],[-156,71.3],[-141,69.6],[-130,70],[-117,69],[-108,68.5],[-95,68],
+   [-90,68.5],[-85,69.5],[-82,66],[-88,64],[-93,61],[-94,58.7],[-92,57],[-85,55.2],
+   [-82,52.5],[-79.5,51.5],[-78.5,55],[-77,58],[-78,62.3],[-73,62],[-69,58.8],[-64.5,60.3],
+   [-61.5,56],[-57,52],[-59,48],[-64.5,48.8],[-66,45],[-70,43.8],[-70,41.7],[-74,40.5],
+   [-76,37],[-75.5,35.2],[-81,31.5],[-80,27],[-80.3,25.2],[-81.8,26.7],[-83,29.5],[-85,29.7],
+   [-89,30.3],[-90,29],[-94,29.6],[-97.3,27.5],[-97.5,24],[-97.5,21.5],[-96,19],[-94.5,18.2],
+   [-91,18.6],[-90.5,21],[-87,21.5],[-87.8,18],[-88.3,15.8],[-84,15.9],[-83.5,11],[-81.5,9],
+   [-79.5,9.6],[-77.5,8.5],[-78.4,7.5],[-80,7.3],[-82.5,8.2],[-85.7,10],[-87.5,13],
+   [-91.5,14],[-94.5,16.2],[-98,16],[-102,17.8],[-105.5,20.5],[-105.5,23],[-109,26],
+   [-112.5,30],[-114.5,31.5],[-112.5,28.5],[-110.5,24.2],[-109.5,23],[-112,25],[-114,27.5],
+   [-116,30.5],[-117.2,32.6],[-120.5,34.5],[-122.5,37.5],[-124,40.3],[-124.5,43],[-124,46.3],
+   [-124.7,48.4],[-123,49],[-127.5,51],[-130.5,54.5],[-134,58],[-137.5,59],[-141,60],
+   [-146,60.8],[-151,59.5],[-154,57.5],[-158,56.5],[-162,55],[-164,55],[-158,57.5],
+   [-157,58.7],[-162,58.6],[-165,60.5],[-165,62.5],[-161,64.5],[-166,65.3],[-168,66]],
+
+  // Greenland
+  [[-73,78],[-67,80.5],[-50,82.2],[-32,83.5],[-20,82],[-18,79],[-19,76],[-22,72],[-24,70],
+   [-32,68],[-39,65.5],[-43,60],[-48,61],[-51,64],[-53,67],[-55,70],[-55,72.5],[-58,75.5],
+   [-66,76.5],[-73,78]],
+
+  // South America
+  [[-77.5,8.5],[-76,9.5],[-75,11],[-72,12],[-71.5,10.8],[-68,10.5],[-64,10.6],[-61,10.5],
+   [-60,8.5],[-57,6],[-54,5.8],[-51.5,4.2],[-50,1.8],[-48.5,-1],[-44.5,-2.5],[-40,-2.8],
+   [-35.2,-5.3],[-35,-9],[-37,-11.5],[-39,-14],[-39,-18],[-40.5,-21.5],[-42,-23],[-45,-23.8],
+   [-48.5,-26],[-48.8,-28.5],[-51,-31],[-53.5,-34],[-55,-35],[-57.5,-35.5],[-57,-38.2],
+   [-62,-39],[-62.5,-41],[-65,-42.5],[-65.5,-45],[-67.5,-46.5],[-66,-48],[-69,-51],
+   [-68.5,-52.5],[-71.5,-54],[-74.5,-52.5],[-75.5,-48],[-74,-44],[-73.5,-40],[-73.5,-37],
+   [-71.5,-32],[-71.5,-28],[-70.5,-23],[-70.2,-18.5],[-72,-17],[-76,-14],[-78.3,-10],
+   [-80,-7.5],[-81.3,-5],[-80.3,-3.5],[-81,-1],[-80,1],[-78.8,1.5],[-77.5,3.5],[-77.4,6.5],
+   [-77.5,8.5]],
+
+  // Africa
+  [[-5.9,35.8],[-2,35.1],[3,36.8],[10.2,37.2],[11,35.5],[10.5,34],[15.5,32],[19.5,30.5],
+   [20.5,32.5],[25,32],[29,30.9],[32.3,31.3],[34,27.8],[35.5,24],[37.2,21],[38.5,18],
+   [39.7,15.2],[42.5,12.8],[43.3,11.9],[44.5,10.4],[51.2,11.8],[51,10],[49,6],[46,2],
+   [43,-1],[40,-3],[39.2,-6],[39.5,-10],[40.5,-15],[37,-18],[35.5,-21],[35.5,-24],
+   [32.5,-26],[32.8,-28.8],[30.5,-31.3],[27.5,-33.5],[25,-34],[22,-34.4],[19.8,-34.8],
+   [18.3,-33.8],[17.8,-31],[15.2,-26.5],[14.5,-22.5],[12,-18],[11.8,-15.5],[13.6,-11.8],
+   [13,-9],[12,-6],[11.8,-3.5],[9.5,-1],[9.5,2.5],[9.7,4],[8.5,4.5],[6,4.3],[3.5,6.3],
+   [1,5.9],[-2,4.8],[-4.5,5.2],[-7.5,4.4],[-9.5,5.6],[-13,8],[-15,11],[-16.8,13.2],
+   [-17.5,14.7],[-16.5,16.5],[-16,19],[-17,21],[-16,23.7],[-14.5,26],[-13,27.7],
+   [-9.8,29.8],[-9.7,32.3],[-6.8,34],[-5.9,35.8]],
+
+  // Madagascar
+  [[49.3,-12],[50.5,-15.5],[49.5,-17.5],[48,-22],[47,-25],[45,-25.5],[43.7,-23.5],
+   [43.3,-21.5],[44.5,-19],[44,-17],[46,-15.7],[48,-13.5],[49.3,-12]],
+
+  // Eurasia
+  [[-9,43],[-8.8,41.5],[-9.5,39],[-8.9,37],[-6.5,36.8],[-5.6,36],[-4.5,36.7],[-2,36.8],
+   [-0.5,38.3],[0.2,39.8],[0.8,41],[3.2,42],[3.1,43.3],[4.8,43.4],[6.5,43.1],[8.8,44.4],
+   [10.5,43],[12.5,41.5],[15.7,40],[16,38],[17,39],[18.5,40.2],[16,41.5],[14,42.5],
+   [12.3,44.5],[13.5,45.7],[14.5,45],[17.5,43],[19.5,41.8],[19.4,40.3],[21.2,37.8],
+   [22.5,36.5],[23.2,38],[22.6,40.3],[24,40.8],[26.2,40.9],[26.2,39.5],[26.6,38.3],
+   [27.3,37],[28.5,36.7],[31,36.8],[34,36.3],[36,36.8],[35.9,35],[35,32.8],[34.4,31.5],
+   [32.3,31.3],[34,27.8],[35,28],[36.5,26],[39,21.5],[42.5,15.8],[43.5,12.7],[45,12.8],
+   [49,14],[52.2,15.6],[55.5,17.6],[57.8,18.9],[59.8,22.5],[58.7,23.6],[56.4,24.9],
+   [56,26.2],[54,24.1],[51.6,24.3],[51.5,26],[50,26.7],[48.5,28.5],[48,30],[50.2,30.2],
+   [51.5,27.9],[54.7,26.5],[56.8,27.1],[57.3,25.8],[61.5,25.2],[66.5,25.4],[67.3,24],
+   [68.5,23.3],[70.3,21],[72.7,21],[73,19],[73.5,16],[74.8,12.8],[76.3,9.5],[77.5,8.1],
+   [78.2,8.9],[79.5,10.5],[80.2,13],[80.1,15.5],[81.3,16.4],[82.4,17],[84.8,19.2],
+   [86.8,20.5],[87,21.5],[88.5,21.6],[90.5,22.5],[91.8,22.3],[92.3,20.7],[94.2,18.5],
+   [94.5,16],[95.5,15.8],[97.6,16.5],[98.2,13],[98.6,10],[98.3,8],[100.3,5.5],
+   [101.3,2.9],[103.5,1.3],[104.2,1.4],[103.4,4.2],[102.2,6.2],[100.4,7.8],[99.2,9.2],
+   [99.3,10.9],[100,12.7],[100.9,13.4],[102.5,12.2],[103,11],[104.8,8.6],[106.4,9.5],
+   [109,11.6],[109.3,13.6],[108.8,15.5],[106.5,17.7],[105.7,19],[106.7,20.7],[108.5,21.5],
+   [110.3,20.6],[110.5,21.3],[113.3,22.2],[116.5,22.9],[118.7,24.6],[119.7,26],[121.5,28.5],
+   [122,30.4],[121.9,31.7],[120.8,33.3],[119.2,34.6],[120.3,36.1],[122.5,37],[121,37.6],
+   [118.9,37.4],[118,38.5],[117.7,39],[119.3,39.4],[121.6,40.8],[121.8,39],[122.7,39.8],
+   [124.3,39.9],[125.1,38],[126.7,37.8],[126.4,34.5],[127.8,34.7],[129.4,35.6],[129.5,37],
+   [128.5,38.5],[127.5,39.8],[129.7,40.9],[129.8,42.3],[131.8,43],[133.2,42.8],[135.5,43.9],
+   [137.7,45.8],[140.4,48.5],[141.4,52.2],[139.6,54.2],[137.4,54],[137,54.8],[141.3,58.6],
+   [143.5,59.4],[148.5,59.3],[152.3,59],[155,59.3],[156.7,61.5],[159.3,61.8],[160.2,60.7],
+   [163.2,62.5],[162.8,61.7],[160.3,59.9],[158.5,58],[156.7,57],[155.9,53.9],[156.6,51.1],
+   [158.3,51.9],[160,53.2],[160.3,54.5],[162.1,56.1],[163.3,56.2],[162.7,57.8],[163.6,59.9],
+   [166,60.3],[170.3,60],[172.2,61],[177.3,62.5],[179.2,62.3],[178.4,63.5],[178.9,64.5],
+   [177.4,64.7],[179.9,65],[180,68.9],[176.9,69.6],[170.5,70.1],[168.4,69.8],[160.9,69.6],
+   [159.7,70.8],[152.6,70.8],[150,71.6],[144,72.5],[139.9,71.5],[136,71.6],[131.3,70.8],
+   [129,72.8],[126,73.5],[123.2,73],[119,73.1],[113.9,73.6],[112,73.8],[109,73.5],
+   [105.3,72.8],[110,74.4],[112.7,75.9],[108,76.7],[104.3,77.7],[99.5,76.5],[94,76.1],
+   [88.6,75.4],[86,74.5],[87,73.9],[80.5,73.6],[80.7,72.3],[78.5,72.4],[75.5,72.3],
+   [74,73.1],[72,72.8],[72.8,70.4],[73,68.4],[71.3,66.3],[69.3,66.9],[67,68.5],[68.7,69.6],
+   [68,72.6],[66,71],[61.5,69.8],[58.5,68.9],[55,68.4],[53.7,68.9],[49.1,67.6],[45,66.8],
+   [44,68.4],[40.4,67.7],[41.1,66.8],[38.7,65.5],[36.3,64.4],[34.6,64.8],[33.1,66.5],
+   [35,67],[40.3,66.5],[41,67.5],[39,68.5],[33,69.4],[28,71],[23.7,70.7],[18.5,69.8],
+   [15,68.5],[13,66.5],[12.3,65],[10.2,63.5],[5.3,62.5],[5,60.5],[5.6,58.6],[7,58],
+   [8.5,58.3],[10.5,59.3],[11.3,58.5],[12.2,56.5],[12.9,55.4],[14.3,56],[16.5,56.6],
+   [16.5,58.4],[18.8,59.8],[17.3,61.3],[17.9,62.8],[21,64.5],[22.4,65.8],[25.4,65],
+   [25,64],[21.5,62.8],[21.4,60.6],[22.8,59.9],[25.8,60.4],[28,60.5],[29.9,60],
+   [28.1,59.5],[23.4,59.3],[23.5,58.3],[24.4,57.3],[21.6,57.5],[21,56.1],[21.1,55.3],
+   [19.6,54.4],[17.6,54.8],[14.6,53.9],[12.5,54.5],[11,54],[9.9,54.8],[8.6,55.5],
+   [8.1,56.6],[9.5,57.1],[10.6,57.7],[10.3,56.2],[10.9,56.4],[10.6,55.3],[9.5,55],
+   [8.9,53.9],[7,53.6],[5,53.3],[4.2,52],[3.3,51.4],[1.6,50.9],[1.4,50],[-1.3,49.6],
+   [-1.9,48.7],[-4.6,48.6],[-4.4,47.9],[-2.2,47.2],[-1.2,46.2],[-1.4,44.5],[-1.8,43.4],
+   [-4.3,43.4],[-8,43.7],[-9,43]],
+
+  // Great Britain
+  [[-5.7,50.1],[-3.6,50.3],[-1.1,50.8],[1.4,51.2],[1.7,52.7],[0.3,53.4],[-0.4,54.4],
+   [-1.5,55.3],[-2.5,56],[-1.8,57.6],[-3.2,58.6],[-5,58.6],[-6.2,57.5],[-5.6,56.2],
+   [-5,55],[-3.5,54.9],[-3.3,54.2],[-3,53.3],[-4.6,53.3],[-4.2,52.3],[-5.2,51.8],
+   [-3.4,51.4],[-5.7,50.1]],
+
+  // Ireland
+  [[-6,52.2],[-6.2,53.9],[-5.7,54.7],[-7,55.3],[-8.5,54.7],[-10,54.1],[-9.6,53.2],
+   [-10.3,51.9],[-9.5,51.5],[-8,51.7],[-6,52.2]],
+
+  // Iceland
+  [[-22.5,63.9],[-24,64.9],[-22.5,65.5],[-24.3,65.6],[-22,66.4],[-16.5,66.5],[-14.5,66.2],
+   [-13.6,65.1],[-15,64.3],[-18.7,63.4],[-22.5,63.9]],
+
+  // Honshu, Shikoku and Kyushu
+  [[130,31.3],[131.3,31.4],[132,33.2],[133.5,33.4],[134.7,33.8],[135.5,34.6],[136.8,34.3],
+   [137.5,34.7],[139,34.9],[140.3,35.2],[140.8,36.5],[141.1,38.3],[142,39.5],[141.4,41.4],
+   [140,40.8],[139.8,39.4],[139.2,38],[136.8,37.2],[136,35.7],[133.4,35.5],[131.4,34.4],
+   [129.8,33.4],[130.2,32.1],[130,31.3]],
+
+  // Hokkaido
+  [[140,41.5],[141.2,41.8],[143.3,42],[145.6,43.3],[145.2,44.2],[143.5,44.3],[141.8,45.4],
+   [141.6,43.5],[140.3,43.2],[140,41.5]],
+
+  // Sakhalin
+  [[142,46],[143.5,46.2],[143,49],[144.6,49],[143.2,51.5],[143.3,53.2],[142.6,54.3],
+   [142.3,52.4],[141.7,48.8],[142,46]],
+
+  // Taiwan
+  [[120.1,23],[120.7,22],[121.5,22.8],[121.9,25],[121.2,25.2],[120.2,23.8],[120.1,23]],
+
+  // Hainan
+  [[108.6,19.2],[109.5,18.2],[110.5,18.7],[111,19.7],[110.1,20.1],[108.7,19.8],[108.6,19.2]],
+
+  // Sri Lanka
+  [[79.8,8],[80,6.2],[81.3,6.2],[81.9,7.5],[80.3,9.8],[79.8,8]],
+
+  // Sumatra
+  [[95.3,5.6],[97.5,5.2],[100.4,2.2],[103.7,-1],[106,-3.2],[105.8,-5.8],[104.5,-5.9],
+   [102.3,-4],[100.9,-2.1],[99.2,0.2],[98.6,1.7],[97.2,3.3],[95.3,5.6]],
+
+  // Java
+  [[105.2,-6.8],[106.1,-6],[108.3,-6.3],[111,-6.4],[112.6,-6.9],[114.6,-7.8],[114.4,-8.7],
+   [111.4,-8.3],[108.3,-7.8],[106.4,-7.4],[105.2,-6.8]],
+
+  // Borneo
+  [[109,1.8],[109.6,2],[111.2,2.7],[113,3.2],[115.5,5.2],[117,6.9],[117.7,6],[119.1,5.4],
+   [118.4,4.9],[117.9,4.1],[117.8,1.8],[119,0.9],[117.5,0.1],[116.6,-1.5],[116.5,-3],
+   [116,-4],[114.5,-3.5],[111.7,-3],[110.2,-2.9],[110,-1.5],[109,-0.4],[109,1.8]],
+
+  // Sulawesi
+  [[119.4,-5.5],[120.4,-5.5],[120.4,-2.9],[121.4,-4.6],[122.8,-4.5],[121.4,-1.9],[123.3,-0.9],
+   [121.5,-0.9],[120.2,0.3],[121,1.3],[122.9,0.9],[124.8,1.5],[124.4,0.4],[120.6,0.5],
+   [120,-0.5],[119.7,-2.2],[118.8,-2.8],[119.4,-5.5]],
+
+  // Philippines, Luzon and Mindanao
+  [[120.5,18.5],[122.2,18.5],[122,16.5],[121.6,15],[122.5,14],[124,13.8],[124,12.6],
+   [123.3,13],[121.8,13.8],[120.6,13.9],[120.6,15.5],[119.8,16.3],[120.5,18.5]],
+  [[122,7],[123.5,7.8],[125.5,9.8],[126.5,7.4],[126.1,6.4],[125.4,5.6],[124.2,6.2],
+   [122,7]],
+
+  // New Guinea
+  [[131,-1.4],[134,-0.9],[136,-2],[138,-1.6],[141,-2.6],[144.5,-3.8],[146,-5.4],[147.8,-6.3],
+   [148.3,-8.5],[150,-10.3],[148,-10.2],[146,-8],[144.3,-7.6],[143.3,-9],[141,-9.1],
+   [139,-8.1],[138,-8.4],[137.7,-5.2],[135.2,-4.5],[133,-4.1],[132,-2.8],[131,-1.4]],
+
+  // Australia
+  [[113.4,-22],[114.2,-26.3],[115,-30],[115.7,-33.5],[115,-34.3],[118,-35],[121.5,-33.8],
+   [124,-33],[127,-32.3],[131,-31.5],[134.2,-32.6],[135.8,-34.8],[137.8,-33],[137.5,-35.4],
+   [138.5,-35.6],[140,-37.5],[143,-38.8],[146.3,-39],[148,-37.8],[150,-37.5],[150.2,-35.5],
+   [151.3,-33.6],[153.1,-30.8],[153.6,-28],[153,-25.2],[150.6,-22.5],[149,-20.5],
+   [146.3,-18.9],[145.3,-15],[143.5,-14],[142.5,-10.7],[141.5,-13.5],[141.5,-16.5],
+   [140,-17.7],[137,-15.9],[135.5,-15],[136.8,-12.2],[135,-12.2],[132.6,-11.5],[131,-12.2],
+   [129.5,-14.9],[128,-14.9],[126.2,-14.2],[125,-15.6],[123.5,-17.5],[122.2,-18.2],
+   [121,-19.5],[118,-20.3],[116.7,-20.6],[114.5,-21.8],[113.4,-22]],
+
+  // Tasmania
+  [[144.6,-40.7],[148.3,-40.9],[148,-43.2],[146.8,-43.6],[145.2,-42.2],[144.6,-40.7]],
+
+  // New Zealand
+  [[172.7,-34.4],[174.3,-35.3],[175.6,-37.2],[178.5,-37.7],[177.5,-39.2],[176,-41.4],
+   [174.6,-41.3],[175.3,-40],[173.8,-39.2],[174.6,-38],[172.7,-34.4]],
+  [[172.7,-40.5],[174.3,-41.2],[173.5,-43],[172.8,-43.8],[171.2,-44.5],[170.6,-45.9],
+   [169,-46.6],[166.5,-46],[166.6,-45.2],[168.4,-44],[170.6,-43],[172.7,-40.5]],
+
+  // Cuba
+  [[-85,21.9],[-83,22.9],[-81,23.1],[-77.5,21.8],[-75.7,21.1],[-74.2,20.2],[-77.4,19.9],
+   [-78,20.8],[-81.5,22.2],[-84.3,21.8],[-85,21.9]],
+
+  // Hispaniola
+  [[-74.4,18.5],[-72.8,19.9],[-70,19.7],[-68.3,18.6],[-71.4,17.6],[-74.4,18.5]],
+
+  // Newfoundland
+  [[-59.3,47.6],[-55.9,51.6],[-55.5,49.4],[-52.7,47.5],[-53.6,46.7],[-56,47.6],[-59.3,47.6]],
+
+  // Baffin Island
+  [[-62,66.6],[-66,61.9],[-71.5,63.5],[-74.5,64.8],[-78,64.5],[-73,67.5],[-76,68.6],
+   [-80,70],[-88.5,70.5],[-89,73.5],[-80,73.7],[-76,72.4],[-70,70.6],[-67,69.5],[-62,66.6]],
+
+  // Victoria Island and the western Arctic islands
+  [[-118,71],[-117,69],[-110,68.5],[-101,69.5],[-102,72.7],[-108,73.2],[-114,73],
+   [-119,72.3],[-118,71]],
+
+  // Ellesmere Island
+  [[-89,76.5],[-80,76.2],[-75,78.5],[-70,80],[-62,82],[-70,83],[-86,82],[-92,81],
+   [-90,79],[-89,76.5]],
+
+  // Svalbard
+  [[11,78.6],[16.5,76.6],[22,77.5],[20,79],[25,79.8],[27,80.2],[18,80.5],[11,79.8],
+   [11,78.6]],
+
+  // Novaya Zemlya
+  [[52.5,71.4],[55.8,71],[57.5,70.7],[55.5,72.8],[58,74.8],[63,76.2],[68.5,76.9],
+   [64,75.3],[60,74.7],[56.5,73.2],[52.5,71.4]],
+
+  // Severnaya Zemlya
+  [[95,79],[100,78.3],[105,78.8],[102,80.2],[96,81.2],[95,79]],
+
+  // New Siberian Islands
+  [[137.5,75],[142,73.9],[148,75],[146,75.6],[140,76.2],[137.5,75]],
+
+  // Antarctica
+  [[-180,-90],[-180,-78],[-160,-78],[-148,-76.5],[-135,-74.5],[-120,-73.8],[-100,-73],
+   [-90,-73],[-80,-73],[-75,-71],[-68,-68],[-60,-63.5],[-57,-64],[-62,-70],[-62,-74],
+   [-60,-77],[-45,-78],[-35,-77.5],[-28,-76],[-20,-73.5],[-12,-71.5],[0,-70],[15,-70],
+   [30,-69.5],[40,-68.8],[50,-67],[60,-67],[70,-68],[80,-67.2],[90,-66.5],[100,-66],
+   [110,-66],[120,-66.8],[135,-66],[145,-67],[155,-69],[165,-70.5],[170,-71.5],[170,-77],
+   [165,-78],[180,-78],[180,-90],[0,-90],[-180,-90]],
+];
+
+// Large inland seas drawn over the land.
+const WORLD_WATER = [
+  // Caspian Sea
+  [[47,44.8],[49.3,46.6],[53,46.8],[53.2,45.3],[51.3,44.5],[52.6,41.8],[54,40.6],
+   [53.8,37.3],[50.3,37.3],[49,38.5],[49.5,40.5],[48,42],[47,44.8]],
+
+  // Black Sea
+  [[27.5,42.5],[28.5,44.3],[29.7,45.3],[31,46.6],[33.5,46],[33.5,44.5],[36.5,45.2],
+   [38.2,46.9],[39.5,47],[38,44.5],[41.5,41.5],[39,41],[35.5,41.7],[33,42],[29,41.2],
+   [27.5,42.5]],
+];